@@ -0,0 +1,9 @@
+#![no_main]
+
+use h3::fuzzing;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (u64, &[u8])| {
+    let (max_size, data) = input;
+    let _ = fuzzing::decode_field_section(data, max_size);
+});
@@ -0,0 +1,10 @@
+#![no_main]
+
+use bytes::Bytes;
+use h3::{fuzzing, proto::frame::Frame};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (Frame<Bytes>, Vec<usize>)| {
+    let (frame, lens) = input;
+    fuzzing::round_trip(frame, &lens);
+});
@@ -0,0 +1,9 @@
+#![no_main]
+
+use h3::fuzzing;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (&[u8], Vec<usize>)| {
+    let (data, lens) = input;
+    let _ = fuzzing::decode_frames(data, &lens);
+});
@@ -0,0 +1,8 @@
+#![no_main]
+
+use h3::fuzzing;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = fuzzing::decode_settings(data);
+});
@@ -15,12 +15,13 @@ use std::{
 use bytes::{Buf, Bytes, BytesMut};
 
 use futures::{
+    future::Shared,
     ready,
     stream::{self, BoxStream},
-    StreamExt,
+    FutureExt, StreamExt,
 };
-use quinn::ReadDatagram;
 pub use quinn::{self, AcceptBi, AcceptUni, Endpoint, OpenBi, OpenUni, VarInt, WriteError};
+use quinn::{ReadDatagram, ZeroRttAccepted};
 
 use h3::{
     ext::Datagram,
@@ -38,6 +39,7 @@ pub struct Connection {
     incoming_uni: BoxStream<'static, <AcceptUni<'static> as Future>::Output>,
     opening_uni: Option<BoxStream<'static, <OpenUni<'static> as Future>::Output>>,
     datagrams: BoxStream<'static, <ReadDatagram<'static> as Future>::Output>,
+    zero_rtt: Option<Shared<ZeroRttAccepted>>,
 }
 
 impl Connection {
@@ -56,6 +58,17 @@ impl Connection {
             datagrams: Box::pin(stream::unfold(conn, |conn| async {
                 Some((conn.read_datagram().await, conn))
             })),
+            zero_rtt: None,
+        }
+    }
+
+    /// Create a [`Connection`] from a [`quinn::Connection`] still in its 0-RTT phase
+    ///
+    /// Takes the parts returned by [`quinn::Connecting::into_0rtt()`].
+    pub fn new_0rtt(conn: quinn::Connection, accepted: ZeroRttAccepted) -> Self {
+        Self {
+            zero_rtt: Some(accepted.shared()),
+            ..Self::new(conn)
         }
     }
 }
@@ -219,7 +232,9 @@ where
         OpenStreams {
             conn: self.conn.clone(),
             opening_bi: None,
+            opened_bi: None,
             opening_uni: None,
+            zero_rtt: self.zero_rtt.clone(),
         }
     }
 
@@ -265,6 +280,38 @@ impl quic::RecvDatagramExt for Connection {
     }
 }
 
+impl quic::EarlyDataExt for Connection {
+    fn is_early_data(&self) -> bool {
+        is_early_data(&self.zero_rtt)
+    }
+
+    fn poll_handshake(&mut self, cx: &mut task::Context<'_>) -> Poll<bool> {
+        poll_handshake(&mut self.zero_rtt, cx)
+    }
+}
+
+fn is_early_data(zero_rtt: &Option<Shared<ZeroRttAccepted>>) -> bool {
+    zero_rtt
+        .as_ref()
+        .map_or(false, |accepted| accepted.clone().now_or_never().is_none())
+}
+
+fn poll_handshake(
+    zero_rtt: &mut Option<Shared<ZeroRttAccepted>>,
+    cx: &mut task::Context<'_>,
+) -> Poll<bool> {
+    match zero_rtt {
+        Some(accepted) => accepted.poll_unpin(cx),
+        None => Poll::Ready(true),
+    }
+}
+
+impl From<Connection> for quic::BoxedConnection {
+    fn from(conn: Connection) -> Self {
+        Self::with_extensions(conn)
+    }
+}
+
 /// Stream opener backed by a Quinn connection
 ///
 /// Implements [`quic::OpenStreams`] using [`quinn::Connection`],
@@ -272,7 +319,26 @@ impl quic::RecvDatagramExt for Connection {
 pub struct OpenStreams {
     conn: quinn::Connection,
     opening_bi: Option<BoxStream<'static, <OpenBi<'static> as Future>::Output>>,
+    // Opened by `poll_ready_bidi()`, for the next `poll_open_bidi()`
+    opened_bi: Option<(quinn::SendStream, quinn::RecvStream)>,
     opening_uni: Option<BoxStream<'static, <OpenUni<'static> as Future>::Output>>,
+    zero_rtt: Option<Shared<ZeroRttAccepted>>,
+}
+
+impl OpenStreams {
+    fn poll_next_bi(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<<OpenBi<'static> as Future>::Output> {
+        if self.opening_bi.is_none() {
+            self.opening_bi = Some(Box::pin(stream::unfold(self.conn.clone(), |conn| async {
+                Some((conn.open_bi().await, conn))
+            })));
+        }
+
+        let opening = self.opening_bi.as_mut().expect("set above");
+        Poll::Ready(ready!(opening.poll_next_unpin(cx)).expect("the unfold stream never ends"))
+    }
 }
 
 impl<B> quic::OpenStreams<B> for OpenStreams
@@ -288,14 +354,10 @@ where
         &mut self,
         cx: &mut task::Context<'_>,
     ) -> Poll<Result<Self::BidiStream, Self::Error>> {
-        if self.opening_bi.is_none() {
-            self.opening_bi = Some(Box::pin(stream::unfold(self.conn.clone(), |conn| async {
-                Some((conn.open_bi().await, conn))
-            })));
-        }
-
-        let (send, recv) =
-            ready!(self.opening_bi.as_mut().unwrap().poll_next_unpin(cx)).unwrap()?;
+        let (send, recv) = match self.opened_bi.take() {
+            Some(opened) => opened,
+            None => ready!(self.poll_next_bi(cx))?,
+        };
         Poll::Ready(Ok(Self::BidiStream {
             send: Self::SendStream::new(send),
             recv: Self::RecvStream::new(recv),
@@ -316,6 +378,19 @@ where
         Poll::Ready(Ok(Self::SendStream::new(send)))
     }
 
+    /// Opens a stream and keeps it for the next `poll_open_bidi()`
+    ///
+    /// Quinn cannot tell whether a stream can be opened without opening it, so this reserves one
+    /// of the streams the peer allows, until it is used. Cloning the opener does not carry the
+    /// reserved stream over.
+    fn poll_ready_bidi(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Quinn opens a stream once the peer allows it, keep it for `poll_open_bidi()`
+        if self.opened_bi.is_none() {
+            self.opened_bi = Some(ready!(self.poll_next_bi(cx))?);
+        }
+        Poll::Ready(Ok(()))
+    }
+
     fn close(&mut self, code: h3::error::Code, reason: &[u8]) {
         self.conn.close(
             VarInt::from_u64(code.value()).expect("error code VarInt"),
@@ -329,11 +404,29 @@ impl Clone for OpenStreams {
         Self {
             conn: self.conn.clone(),
             opening_bi: None,
+            opened_bi: None,
             opening_uni: None,
+            zero_rtt: self.zero_rtt.clone(),
         }
     }
 }
 
+impl quic::EarlyDataExt for OpenStreams {
+    fn is_early_data(&self) -> bool {
+        is_early_data(&self.zero_rtt)
+    }
+
+    fn poll_handshake(&mut self, cx: &mut task::Context<'_>) -> Poll<bool> {
+        poll_handshake(&mut self.zero_rtt, cx)
+    }
+}
+
+impl From<OpenStreams> for quic::BoxedOpenStreams {
+    fn from(opener: OpenStreams) -> Self {
+        Self::with_early_data(opener)
+    }
+}
+
 /// Quinn-backed bidirectional stream
 ///
 /// Implements [`quic::BidiStream`] which allows the stream to be split
@@ -417,6 +510,12 @@ where
     }
 }
 
+impl From<BidiStream<Bytes>> for quic::BoxedBidiStream {
+    fn from(stream: BidiStream<Bytes>) -> Self {
+        Self::new(stream)
+    }
+}
+
 /// Quinn-backed receive stream
 ///
 /// Implements a [`quic::RecvStream`] backed by a [`quinn::RecvStream`].
@@ -482,6 +581,12 @@ impl quic::RecvStream for RecvStream {
     }
 }
 
+impl From<RecvStream> for quic::BoxedRecvStream {
+    fn from(stream: RecvStream) -> Self {
+        Self::new(stream)
+    }
+}
+
 /// The error type for [`RecvStream`]
 ///
 /// Wraps errors that occur when reading from a receive stream.
@@ -535,6 +640,10 @@ impl Error for ReadError {
             _ => None,
         }
     }
+
+    fn is_stream_reset(&self) -> bool {
+        matches!(self.0, quinn::ReadError::Reset(_))
+    }
 }
 
 /// Quinn-backed send stream
@@ -670,6 +779,12 @@ where
     }
 }
 
+impl From<SendStream<Bytes>> for quic::BoxedSendStream {
+    fn from(stream: SendStream<Bytes>) -> Self {
+        Self::new(stream)
+    }
+}
+
 /// The error type for [`SendStream`]
 ///
 /// Wraps errors that can happen writing to or polling a send stream.
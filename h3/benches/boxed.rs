@@ -0,0 +1,77 @@
+//! Requests over the in-memory transport, as is and erased behind `BoxedConnection`
+//!
+//! Boxing costs one dynamic call per poll of a connection or stream, which should show as a
+//! small, constant overhead per request. Run with `cargo bench -p h3 --features test-helpers`.
+
+use std::time::{Duration, Instant};
+
+use bytes::{Buf, Bytes};
+use futures_util::{
+    future::{self, Either},
+    pin_mut,
+};
+use h3::{client, quic, server, test};
+use http::{Request, Response};
+
+const REQUESTS: u32 = 2_000;
+
+async fn requests<C: quic::Connection<Bytes>>(client_conn: C, server_conn: C) -> Duration {
+    let client_fut = async {
+        let (mut driver, mut send_request) = client::new(client_conn).await.unwrap();
+        let requests = async {
+            let start = Instant::now();
+            for _ in 0..REQUESTS {
+                let mut stream = send_request
+                    .send_request(Request::get("http://localhost/").body(()).unwrap())
+                    .await
+                    .unwrap();
+                stream.finish().await.unwrap();
+                stream.recv_response().await.unwrap();
+                while let Some(mut data) = stream.recv_data().await.unwrap() {
+                    data.advance(data.remaining());
+                }
+            }
+            start.elapsed()
+        };
+        let drive = future::poll_fn(|cx| driver.poll_close(cx));
+        pin_mut!(requests, drive);
+        match future::select(requests, drive).await {
+            Either::Left((elapsed, _)) => elapsed,
+            Either::Right((res, _)) => panic!("closed: {:?}", res),
+        }
+    };
+
+    let server_fut = async {
+        let mut incoming = server::Connection::new(server_conn).await.unwrap();
+        while let Some((_, mut stream)) = incoming.accept().await.unwrap() {
+            stream.send_response(Response::new(())).await.unwrap();
+            stream
+                .send_data(Bytes::from_static(b"hypertext"))
+                .await
+                .unwrap();
+            stream.finish().await.unwrap();
+        }
+    };
+
+    pin_mut!(client_fut, server_fut);
+    match future::select(client_fut, server_fut).await {
+        Either::Left((elapsed, _)) => elapsed,
+        Either::Right(_) => panic!("server done first"),
+    }
+}
+
+fn main() {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .unwrap();
+
+    let (client_conn, server_conn) = test::pair();
+    let plain = rt.block_on(requests(client_conn, server_conn));
+    let (client_conn, server_conn) = test::boxed_pair();
+    let boxed = rt.block_on(requests(client_conn, server_conn));
+
+    for (name, elapsed) in [("plain", plain), ("boxed", boxed)] {
+        println!("{}: {:?} per request", name, elapsed / REQUESTS);
+    }
+}
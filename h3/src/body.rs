@@ -0,0 +1,204 @@
+//! Sending and receiving message bodies as [`http_body::Body`]
+//!
+//! The body types built on a request stream, here and in the client and server modules, are
+//! `Unpin` whatever the stream: it is never pinned, only polled through `&mut` methods.
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::{Buf, Bytes};
+use futures_util::{future, ready};
+use http::{header, HeaderMap, HeaderValue, StatusCode};
+use http_body::{Body, Frame, SizeHint};
+
+use crate::{
+    connection::{ConnectionState, RequestStream},
+    error::Code,
+    proto::frame,
+    quic, Error,
+};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Receiving side of a message body: the data, then the trailers
+pub(crate) struct RecvBody {
+    state: State,
+}
+
+enum State {
+    Data,
+    Trailers,
+    Done,
+}
+
+impl RecvBody {
+    pub(crate) fn new() -> Self {
+        Self { state: State::Data }
+    }
+
+    /// A body known to be empty, only trailers can still be received
+    pub(crate) fn empty() -> Self {
+        Self {
+            state: State::Trailers,
+        }
+    }
+
+    pub(crate) fn stop(&mut self) {
+        self.state = State::Done;
+    }
+
+    pub(crate) fn poll_frame<S, B>(
+        &mut self,
+        stream: &mut RequestStream<S, B>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, Error>>>
+    where
+        S: quic::RecvStream,
+        B: Buf,
+    {
+        loop {
+            match self.state {
+                State::Data => match ready!(stream.poll_recv_data(cx)) {
+                    Ok(Some(mut data)) => {
+                        return Poll::Ready(Some(Ok(Frame::data(
+                            data.copy_to_bytes(data.remaining()),
+                        ))))
+                    }
+                    Ok(None) => self.state = State::Trailers,
+                    Err(e) => {
+                        self.state = State::Done;
+                        return Poll::Ready(Some(Err(e)));
+                    }
+                },
+                State::Trailers => {
+                    let trailers = ready!(stream.poll_recv_trailers(cx));
+                    self.state = State::Done;
+                    return Poll::Ready(trailers.transpose().map(|t| t.map(Frame::trailers)));
+                }
+                State::Done => return Poll::Ready(None),
+            }
+        }
+    }
+
+    /// Whether all the data was received, the trailers may still be to come
+    pub(crate) fn is_data_received(&self) -> bool {
+        !matches!(self.state, State::Data)
+    }
+
+    pub(crate) fn is_end_stream(&self) -> bool {
+        matches!(self.state, State::Done)
+    }
+
+    pub(crate) fn size_hint(&self) -> SizeHint {
+        match self.state {
+            State::Data => SizeHint::default(),
+            State::Trailers | State::Done => SizeHint::with_exact(0),
+        }
+    }
+}
+
+//= https://www.rfc-editor.org/rfc/rfc9110#section-6.4.1
+//# Responses to the HEAD request method (Section 9.3.2) never include
+//# content; the associated response header fields indicate only what
+//# their values would have been if the request method had been GET
+//# (Section 9.3.1).  2xx (Successful) responses to a CONNECT request
+//# method (Section 9.3.6) switch the connection to tunnel mode instead
+//# of having content.  All 1xx (Informational), 204 (No Content), and
+//# 304 (Not Modified) responses do not include content.
+pub(crate) fn response_has_body(head: bool, status: StatusCode) -> bool {
+    !(head
+        || status.is_informational()
+        || status == StatusCode::NO_CONTENT
+        || status == StatusCode::NOT_MODIFIED)
+}
+
+/// Set the `content-length` header from the exact size of a body, unless already present
+pub(crate) fn set_content_length(headers: &mut HeaderMap, size_hint: &SizeHint) {
+    if let Some(len) = size_hint.exact() {
+        headers
+            .entry(header::CONTENT_LENGTH)
+            .or_insert_with(|| HeaderValue::from(len));
+    }
+}
+
+/// Send all of `body` on `stream`, followed by its trailers if any, then finish the stream
+///
+/// The stream is reset with `H3_REQUEST_CANCELLED` if the body fails.
+pub(crate) async fn send<S, B, T>(stream: &mut RequestStream<S, B>, body: T) -> Result<(), Error>
+where
+    S: quic::SendStream<B>,
+    B: Buf,
+    T: Body<Data = B>,
+    T::Error: Into<BoxError>,
+{
+    let mut body = SendBody::new(body);
+    future::poll_fn(|cx| body.poll_send(stream, cx)).await
+}
+
+/// Sending side of a message body, streamed as it is polled
+///
+/// Like [`send()`], the body is followed by its trailers if any, then the stream is finished.
+pub(crate) struct SendBody<T, B> {
+    body: Pin<Box<T>>,
+    // Taken out once handed to the stream
+    frame: Option<frame::Frame<B>>,
+    // No more frames are left to send, the stream is to be finished
+    ended: bool,
+}
+
+impl<T, B> SendBody<T, B>
+where
+    T: Body<Data = B>,
+    T::Error: Into<BoxError>,
+    B: Buf,
+{
+    pub(crate) fn new(body: T) -> Self {
+        Self {
+            body: Box::pin(body),
+            frame: None,
+            ended: false,
+        }
+    }
+
+    /// Polls for the whole body to be sent, and the stream finished
+    pub(crate) fn poll_send<S>(
+        &mut self,
+        stream: &mut RequestStream<S, B>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Error>>
+    where
+        S: quic::SendStream<B>,
+    {
+        loop {
+            ready!(stream.stream_mut().poll_send_frame(cx, &mut self.frame))
+                .map_err(|e| stream.maybe_conn_err(e))?;
+            if self.ended {
+                return stream.poll_finish(cx);
+            }
+
+            let frame = match ready!(self.body.as_mut().poll_frame(cx)) {
+                Some(Ok(frame)) => frame,
+                Some(Err(e)) => {
+                    stream.stop_stream(Code::H3_REQUEST_CANCELLED);
+                    return Poll::Ready(Err(Code::H3_REQUEST_CANCELLED.with_cause(e)));
+                }
+                None => {
+                    self.ended = true;
+                    continue;
+                }
+            };
+            match frame.into_data() {
+                Ok(data) if data.has_remaining() => self.frame = Some(frame::Frame::Data(data)),
+                Ok(_) => (),
+                Err(frame) => {
+                    if let Ok(trailers) = frame.into_trailers() {
+                        self.frame = Some(stream.trailers_frame(trailers)?);
+                        self.ended = true;
+                    }
+                }
+            }
+        }
+    }
+}
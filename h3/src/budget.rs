@@ -0,0 +1,85 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+/// A limit on the bytes buffered by several streams together, such as all those of a connection
+///
+/// Per-stream flow control bounds what each stream buffers, yet a peer opening many streams can
+/// still hold a lot of memory in total. Streams share the budget through
+/// [`FrameStream::with_buffer_budget()`].
+///
+/// [`FrameStream::with_buffer_budget()`]: crate::frame::FrameStream::with_buffer_budget
+#[derive(Debug)]
+pub struct BufferBudget {
+    max: usize,
+    used: AtomicUsize,
+}
+
+impl BufferBudget {
+    /// A budget of `max` bytes
+    pub fn new(max: usize) -> Self {
+        Self {
+            max,
+            used: AtomicUsize::new(0),
+        }
+    }
+
+    /// Number of bytes currently buffered by the streams sharing the budget
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Acquire)
+    }
+
+    /// Number of bytes which can still be buffered
+    pub fn available(&self) -> usize {
+        self.max.saturating_sub(self.used())
+    }
+
+    fn try_reserve(&self, len: usize) -> bool {
+        self.used
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+                used.checked_add(len).filter(|used| *used <= self.max)
+            })
+            .is_ok()
+    }
+
+    fn release(&self, len: usize) {
+        self.used.fetch_sub(len, Ordering::AcqRel);
+    }
+}
+
+/// The part of a [`BufferBudget`] held by a stream, given back when dropped
+pub(crate) struct BudgetReservation {
+    budget: Arc<BufferBudget>,
+    reserved: usize,
+}
+
+impl BudgetReservation {
+    pub(crate) fn new(budget: Arc<BufferBudget>) -> Self {
+        Self {
+            budget,
+            reserved: 0,
+        }
+    }
+
+    pub(crate) fn reserve(&mut self, len: usize) -> bool {
+        if !self.budget.try_reserve(len) {
+            return false;
+        }
+        self.reserved += len;
+        true
+    }
+
+    /// Releases what is reserved beyond `buffered` bytes
+    pub(crate) fn release_above(&mut self, buffered: usize) {
+        let released = self.reserved.saturating_sub(buffered);
+        self.budget.release(released);
+        self.reserved -= released;
+    }
+}
+
+impl Drop for BudgetReservation {
+    fn drop(&mut self) {
+        self.budget.release(self.reserved);
+    }
+}
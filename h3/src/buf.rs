@@ -1,28 +1,32 @@
 use std::collections::VecDeque;
 use std::io::IoSlice;
 
-use bytes::{Buf, Bytes};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 
+/// A queue of buffers, read as a single contiguous [`Buf`]
 #[derive(Debug)]
-pub(crate) struct BufList<T> {
+pub struct BufList<T> {
     bufs: VecDeque<T>,
 }
 
 impl<T: Buf> BufList<T> {
-    pub(crate) fn new() -> BufList<T> {
+    /// Create an empty list
+    pub fn new() -> BufList<T> {
         BufList {
             bufs: VecDeque::new(),
         }
     }
 
+    /// Append `buf` to the end of the list, unless it is empty
     #[inline]
     #[allow(dead_code)]
-    pub(crate) fn push(&mut self, buf: T) {
-        debug_assert!(buf.has_remaining());
-        self.bufs.push_back(buf);
+    pub fn push(&mut self, buf: T) {
+        if buf.has_remaining() {
+            self.bufs.push_back(buf);
+        }
     }
 
-    pub fn cursor(&self) -> Cursor<T> {
+    pub(crate) fn cursor(&self) -> Cursor<T> {
         Cursor {
             buf: self,
             pos_total: 0,
@@ -32,12 +36,18 @@ impl<T: Buf> BufList<T> {
     }
 }
 
+impl<T: Buf> Default for BufList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl BufList<Bytes> {
-    pub fn take_first_chunk(&mut self) -> Option<Bytes> {
+    pub(crate) fn take_first_chunk(&mut self) -> Option<Bytes> {
         self.bufs.pop_front()
     }
 
-    pub fn take_chunk(&mut self, max_len: usize) -> Option<Bytes> {
+    pub(crate) fn take_chunk(&mut self, max_len: usize) -> Option<Bytes> {
         let chunk = self
             .bufs
             .front_mut()
@@ -51,7 +61,12 @@ impl BufList<Bytes> {
         chunk
     }
 
-    pub fn push_bytes<T>(&mut self, buf: &mut T)
+    /// The chunk pushed last, if still buffered
+    pub(crate) fn last_chunk(&self) -> Option<&Bytes> {
+        self.bufs.back()
+    }
+
+    pub(crate) fn push_bytes<T>(&mut self, buf: &mut T)
     where
         T: Buf,
     {
@@ -98,6 +113,25 @@ impl<T: Buf> Buf for BufList<T> {
         }
     }
 
+    fn copy_to_bytes(&mut self, len: usize) -> Bytes {
+        assert!(len <= self.remaining(), "`len` greater than remaining");
+
+        // Share the memory of the front buffer if it holds all the bytes
+        if let Some(front) = self.bufs.front_mut() {
+            if front.remaining() >= len {
+                let bytes = front.copy_to_bytes(len);
+                if !front.has_remaining() {
+                    self.bufs.pop_front();
+                }
+                return bytes;
+            }
+        }
+
+        let mut bytes = BytesMut::with_capacity(len);
+        bytes.put(self.take(len));
+        bytes.freeze()
+    }
+
     #[inline]
     fn chunks_vectored<'t>(&'t self, dst: &mut [IoSlice<'t>]) -> usize {
         if dst.is_empty() {
@@ -0,0 +1,192 @@
+//! Request and response bodies as [`http_body::Body`]
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::{Buf, Bytes};
+use futures_util::{future, pin_mut, ready, Future};
+use http::{Method, Request, Response};
+use http_body::{Body, Frame, SizeHint};
+use tracing::trace;
+
+use crate::{
+    body::{RecvBody, SendBody},
+    error::Code,
+    quic, Error,
+};
+
+use super::{RequestStream, SendRequest};
+
+/// The body of a response, read from the receiving side of a [`RequestStream`]
+///
+/// Data is yielded as it arrives, followed by the trailers if the server sent any. When returned
+/// by [`SendRequest::send()`], polling it also sends what is left of the request body: once the
+/// response ends, or if this is dropped, the rest of the request is cancelled.
+pub struct ResponseBody<S, B> {
+    stream: RequestStream<S, B>,
+    recv: RecvBody,
+    upload: Option<Box<dyn Upload + Send>>,
+}
+
+/// The sending side of a request, with the body left to send
+trait Upload {
+    fn poll_upload(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>>;
+}
+
+impl<S, B, T> Upload for (RequestStream<S, B>, SendBody<T, B>)
+where
+    S: quic::SendStream<B>,
+    B: Buf,
+    T: Body<Data = B>,
+    T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    fn poll_upload(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        let (stream, body) = self;
+        body.poll_send(&mut stream.inner, cx)
+    }
+}
+
+impl<S, B> ResponseBody<S, B> {
+    /// Sends the request body as long as it is polled, until it is over
+    fn poll_upload(&mut self, cx: &mut Context<'_>) {
+        if let Some(upload) = &mut self.upload {
+            if let Poll::Ready(res) = upload.poll_upload(cx) {
+                if let Err(e) = res {
+                    // Whatever the server answers is still received
+                    trace!("request body not sent: {}", e);
+                }
+                self.upload = None;
+            }
+        }
+    }
+}
+
+impl<S, B> ResponseBody<S, B> {
+    /// Returns the underlying stream
+    pub fn into_inner(self) -> RequestStream<S, B> {
+        self.stream
+    }
+}
+
+impl<S, B> ResponseBody<S, B>
+where
+    S: quic::RecvStream,
+    B: Buf,
+{
+    /// Tell the server to stop sending the body
+    pub fn stop_sending(&mut self, error_code: Code) {
+        self.stream.stop_sending(error_code);
+        self.recv.stop();
+    }
+}
+
+impl<S, B> Unpin for ResponseBody<S, B> {}
+
+impl<S, B> Body for ResponseBody<S, B>
+where
+    S: quic::RecvStream,
+    B: Buf,
+{
+    type Data = Bytes;
+    type Error = Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+        this.poll_upload(cx);
+        let frame = ready!(this.recv.poll_frame(&mut this.stream.inner, cx));
+        if frame.is_none() {
+            // The response is complete, the server does not need the rest of the request
+            this.upload = None;
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.recv.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.recv.size_hint()
+    }
+}
+
+impl<T, B> SendRequest<T, B>
+where
+    T: quic::OpenStreams<B>,
+    T::BidiStream: quic::BidiStream<B>,
+    B: Buf,
+{
+    /// Send a whole request, and receive the response
+    ///
+    /// The body is streamed in DATA frames followed by its trailers, with a `content-length`
+    /// header when its exact size is known, then the stream is finished. If the body fails,
+    /// the stream is reset with [`Code::H3_REQUEST_CANCELLED`].
+    ///
+    /// The returned future resolves once the response headers are received, the request body
+    /// being sent meanwhile. What is left of it is sent while the response body is polled, so
+    /// the server can stream its response while it receives the request. The response body is
+    /// empty if the request method is HEAD, or if the status forbids content.
+    pub async fn send<R>(
+        &mut self,
+        req: Request<R>,
+    ) -> Result<Response<ResponseBody<<T::BidiStream as quic::BidiStream<B>>::RecvStream, B>>, Error>
+    where
+        R: Body<Data = B> + Send + 'static,
+        R::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        B: Send + 'static,
+        <T::BidiStream as quic::BidiStream<B>>::SendStream: Send + 'static,
+    {
+        let head = req.method() == Method::HEAD;
+        let (mut parts, body) = req.into_parts();
+        if !body.is_end_stream() {
+            crate::body::set_content_length(&mut parts.headers, &body.size_hint());
+        }
+
+        let (send, mut recv) = self
+            .send_request(Request::from_parts(parts, ()))
+            .await?
+            .split();
+        let mut upload: Option<Box<dyn Upload + Send>> =
+            Some(Box::new((send, SendBody::new(body))));
+        let mut upload_err = None;
+        let response = {
+            let response = recv.recv_response();
+            pin_mut!(response);
+            future::poll_fn(|cx| {
+                if let Some(sending) = &mut upload {
+                    if let Poll::Ready(res) = sending.poll_upload(cx) {
+                        upload_err = res.err();
+                        upload = None;
+                    }
+                }
+                response.as_mut().poll(cx)
+            })
+            .await
+        };
+
+        let response = match (response, upload_err) {
+            //= https://www.rfc-editor.org/rfc/rfc9114#section-4.1
+            //# Clients MUST NOT discard
+            //# complete responses as a result of having their request terminated
+            //# abruptly.
+            (Ok(response), _) => response,
+            (Err(_), Some(e)) | (Err(e), None) => return Err(e),
+        };
+
+        let state = if crate::body::response_has_body(head, response.status()) {
+            RecvBody::new()
+        } else {
+            RecvBody::empty()
+        };
+        Ok(response.map(|()| ResponseBody {
+            stream: recv,
+            recv: state,
+            upload,
+        }))
+    }
+}
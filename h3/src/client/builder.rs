@@ -2,6 +2,7 @@
 
 use std::{
     marker::PhantomData,
+    ops::ControlFlow,
     sync::{atomic::AtomicUsize, Arc},
     task::Poll,
 };
@@ -10,13 +11,15 @@ use bytes::{Buf, Bytes};
 use futures_util::future;
 
 use crate::{
-    config::Config,
+    config::{Config, UnknownStreamAction},
     connection::{ConnectionInner, SharedStateRef},
-    error::Error,
-    quic::{self},
+    error::{Code, Error},
+    ext::UniStream,
+    proto::varint::VarInt,
+    quic::{self, StreamId},
 };
 
-use super::connection::{Connection, SendRequest};
+use super::connection::{Connection, InFlight, SendRequest};
 
 /// Start building a new HTTP/3 client
 pub fn builder() -> Builder {
@@ -60,12 +63,14 @@ where
 /// ```
 pub struct Builder {
     config: Config,
+    max_concurrent_requests: Option<usize>,
 }
 
 impl Builder {
     pub(super) fn new() -> Self {
         Builder {
             config: Default::default(),
+            max_concurrent_requests: None,
         }
     }
 
@@ -94,6 +99,74 @@ impl Builder {
         self
     }
 
+    /// Decide what to do with incoming unidirectional streams of unknown type
+    ///
+    /// The handler is given the stream type. By default, reading is aborted with
+    /// `H3_STREAM_CREATION_ERROR`.
+    pub fn unknown_uni_stream(
+        &mut self,
+        handler: impl Fn(u64) -> UnknownStreamAction + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.config.unknown_uni_stream = Arc::new(handler);
+        self
+    }
+
+    /// Receive the frames of unknown type arriving on request streams
+    ///
+    /// The handler is given the stream id, the frame type and its payload, once the whole frame
+    /// is received. Returning `Break` stops the stream with the given code, and the request
+    /// fails. Frames of reserved types are passed too, which the handler can tell apart with
+    /// [`FrameType::is_reserved()`](crate::codec::FrameType::is_reserved). Without a handler,
+    /// these frames are skipped. It is called from the task reading the request stream, so it
+    /// must not block.
+    pub fn on_extension_frame(
+        &mut self,
+        handler: impl Fn(StreamId, u64, Bytes) -> ControlFlow<Code> + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.config.on_extension_frame = Some(Arc::new(handler));
+        self
+    }
+
+    /// Hand the incoming unidirectional streams of type `ty` to `handler`
+    ///
+    /// This lets an extension define its own stream types. The handler is given the stream id
+    /// and the stream, as soon as its type is received. It is called from the task driving the
+    /// connection, so it must not block: reading the stream is left to a task it spawns or
+    /// notifies. The connection receives the data of these streams while it is driven, so they
+    /// are only read as long as the connection is.
+    ///
+    /// Streams of a registered type are not subject to [`Builder::unknown_uni_stream()`], and
+    /// take precedence over types implemented here as extensions, such as WebTransport streams.
+    /// Registering the control, push or QPACK stream types fails.
+    pub fn register_uni_stream_handler(
+        &mut self,
+        ty: VarInt,
+        handler: impl Fn(StreamId, UniStream) + Send + Sync + 'static,
+    ) -> Result<&mut Self, Error> {
+        self.config.register_uni_stream_handler(ty, handler)?;
+        Ok(self)
+    }
+
+    /// Limit the number of unidirectional streams of unknown type discarded at once
+    ///
+    /// This counts the streams left open by [`UnknownStreamAction::Discard`] until they end,
+    /// stopped streams do not count. Past this, the connection is closed with
+    /// `H3_EXCESSIVE_LOAD`. Defaults to 64.
+    pub fn max_unknown_uni_streams(&mut self, value: usize) -> &mut Self {
+        self.config.max_unknown_uni_streams = value;
+        self
+    }
+
+    /// Limit the number of requests in flight at once
+    ///
+    /// A request is in flight until its [`RequestStream`](super::RequestStream) and both its
+    /// halves are dropped. Past this, [`SendRequest::poll_ready()`] waits for one to be over.
+    /// Unlimited by default, leaving the server's limit on streams as the only one.
+    pub fn max_concurrent_requests(&mut self, value: usize) -> &mut Self {
+        self.max_concurrent_requests = Some(value);
+        self
+    }
+
     /// Create a new HTTP/3 client from a `quic` connection
     pub async fn build<C, O, B>(
         &mut self,
@@ -106,14 +179,16 @@ impl Builder {
     {
         let open = quic.opener();
         let conn_state = SharedStateRef::default();
+        let in_flight = Arc::new(InFlight::new(self.max_concurrent_requests));
 
         let conn_waker = Some(future::poll_fn(|cx| Poll::Ready(cx.waker().clone())).await);
 
         Ok((
             Connection {
-                inner: ConnectionInner::new(quic, conn_state.clone(), self.config).await?,
+                inner: ConnectionInner::new(quic, conn_state.clone(), self.config.clone()).await?,
                 sent_closing: None,
                 recv_closing: None,
+                in_flight: in_flight.clone(),
             },
             SendRequest {
                 open,
@@ -121,7 +196,9 @@ impl Builder {
                 conn_waker,
                 max_field_section_size: self.config.settings.max_field_section_size,
                 sender_count: Arc::new(AtomicUsize::new(1)),
+                in_flight,
                 send_grease_frame: self.config.send_grease,
+                on_extension_frame: self.config.on_extension_frame.clone(),
                 _buf: PhantomData,
             },
         ))
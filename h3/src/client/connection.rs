@@ -2,26 +2,38 @@
 
 use std::{
     marker::PhantomData,
-    sync::{atomic::AtomicUsize, Arc},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
     task::{Context, Poll, Waker},
 };
 
-use bytes::{Buf, BytesMut};
-use futures_util::future;
+use bytes::{Buf, Bytes, BytesMut};
+use futures_util::{
+    future, ready,
+    task::{noop_waker_ref, AtomicWaker},
+};
 use http::request;
 use tracing::{info, trace};
 
 use crate::{
-    connection::{self, ConnectionInner, ConnectionState, SharedStateRef},
+    connection::{self, ConnectionInner, ConnectionState, Goaway, RequestEnd, SharedStateRef},
     error::{Code, Error, ErrorLevel},
-    frame::FrameStream,
-    proto::{frame::Frame, headers::Header, push::PushId},
+    ext::AllowEarlyData,
+    frame::{EncodedRequest, ExtensionHandler, FrameStream},
+    proto::{
+        frame::Frame,
+        headers::Header,
+        push::{PushId, PushIdLimit},
+        varint::VarInt,
+    },
     qpack,
     quic::{self, StreamId},
     stream::{self, BufRecvStream},
 };
 
-use super::stream::RequestStream;
+use super::stream::{Early, RequestStream};
 
 /// HTTP/3 request sender
 ///
@@ -31,11 +43,14 @@ use super::stream::RequestStream;
 /// to call [`RequestStream::finish()`] to let the server know the request transfer is complete.
 /// This includes the cases where no body is sent at all.
 ///
-/// This struct is cloneable so multiple requests can be sent concurrently.
+/// This struct is cloneable so multiple requests can be sent concurrently. It is `Send` and
+/// `'static` whenever `T` and `B` are, as are the [`RequestStream`]s it returns and their
+/// split halves, so each request can be moved to a task of its own.
 ///
 /// Existing instances are atomically counted internally, so whenever all of them have been
 /// dropped, the connection will be automatically closed whith HTTP/3 connection error code
-/// `HTTP_NO_ERROR = 0`.
+/// `HTTP_NO_ERROR = 0`. Requests still in flight at this point are carried on, the connection
+/// driver then closes the connection once the last of them is over.
 ///
 /// # Examples
 ///
@@ -110,9 +125,11 @@ where
     pub(super) max_field_section_size: u64, // maximum size for a header we receive
     // counts instances of SendRequest to close the connection when the last is dropped.
     pub(super) sender_count: Arc<AtomicUsize>,
+    pub(super) in_flight: Arc<InFlight>,
     pub(super) conn_waker: Option<Waker>,
     pub(super) _buf: PhantomData<fn(B)>,
     pub(super) send_grease_frame: bool,
+    pub(super) on_extension_frame: Option<ExtensionHandler>,
 }
 
 impl<T, B> SendRequest<T, B>
@@ -120,20 +137,119 @@ where
     T: quic::OpenStreams<B>,
     B: Buf,
 {
+    /// Polls until a new request can be started without waiting
+    ///
+    /// This accounts for the limit on requests in flight set with
+    /// [`Builder::max_concurrent_requests()`](super::Builder::max_concurrent_requests), shared by
+    /// all the clones of this handle, and for the number of streams the server allows. It fails
+    /// once the connection is closing, such as after the server sent a GOAWAY.
+    ///
+    /// The room found under the limit on requests in flight is not reserved: a request started
+    /// from another handle in between can use it up. A stream may be though, depending on the
+    /// QUIC implementation, see [`quic::OpenStreams::poll_ready_bidi()`]: it is then kept by
+    /// this handle for its next request.
+    pub fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        self.check_closing()?;
+        ready!(self.in_flight.poll_capacity(cx));
+        self.open
+            .poll_ready_bidi(cx)
+            .map_err(|e| self.maybe_conn_err(e))
+    }
+
+    fn check_closing(&self) -> Result<(), Error> {
+        if self.conn_state.read("poll ready lock state").closing {
+            return Err(Error::closing());
+        }
+        Ok(())
+    }
+
     /// Send a HTTP/3 request to the server
+    ///
+    /// This waits for [`SendRequest::poll_ready()`], then for the request headers to be sent.
     pub async fn send_request(
         &mut self,
         req: http::Request<()>,
     ) -> Result<RequestStream<T::BidiStream, B>, Error> {
-        let (peer_max_field_section_size, closing) = {
-            let state = self.conn_state.read("send request lock state");
-            (state.peer_config.max_field_section_size, state.closing)
-        };
+        let headers = self.encode_request(req)?;
+        self.send_encoded(headers).await
+    }
 
-        if closing {
-            return Err(Error::closing());
+    /// Sends a request whose headers are already encoded, see [`SendRequest::send_request()`]
+    async fn send_encoded(
+        &mut self,
+        headers: Bytes,
+    ) -> Result<RequestStream<T::BidiStream, B>, Error> {
+        let mut stream = self.open_request().await?;
+        stream::write(&mut stream, Frame::Headers(headers))
+            .await
+            .map_err(|e| self.maybe_conn_err(e))?;
+
+        Ok(self.request_stream(stream))
+    }
+
+    /// Opens the stream of a new request, once [`SendRequest::poll_ready()`] resolves
+    async fn open_request(&mut self) -> Result<T::BidiStream, Error> {
+        future::poll_fn(|cx| self.poll_ready(cx)).await?;
+
+        //= https://www.rfc-editor.org/rfc/rfc9114#section-4.1
+        //= type=implication
+        //# A
+        //# client MUST send only a single request on a given stream.
+        future::poll_fn(|cx| self.open.poll_open_bidi(cx))
+            .await
+            .map_err(|e| self.maybe_conn_err(e))
+    }
+
+    /// Send a HTTP/3 request to the server if it can be done without waiting
+    ///
+    /// Fails with [`Error::is_not_ready()`] if [`SendRequest::poll_ready()`] would not resolve
+    /// right away, and the request is not sent. This may also happen just after `poll_ready()`
+    /// resolved, if the QUIC implementation does not override
+    /// [`quic::OpenStreams::poll_ready_bidi()`], as a stream may then not be available after all.
+    /// Otherwise the request headers are handed to the stream, which sends them along with
+    /// whatever is sent next on the [`RequestStream`].
+    ///
+    /// Until then they may stay buffered: the request body must be sent, or the stream finished,
+    /// before waiting for the response with [`RequestStream::recv_response()`].
+    pub fn try_send_request(
+        &mut self,
+        req: http::Request<()>,
+    ) -> Result<RequestStream<T::BidiStream, B>, Error> {
+        let headers = self.encode_request(req)?;
+        // Unlike `poll_ready()`, nothing waits for the capacity, so no waker is registered for it
+        self.check_closing()?;
+        if !self.in_flight.has_capacity() {
+            return Err(Error::not_ready());
+        }
+        let mut cx = Context::from_waker(noop_waker_ref());
+        match self.open.poll_ready_bidi(&mut cx) {
+            Poll::Ready(res) => res.map_err(|e| self.maybe_conn_err(e))?,
+            Poll::Pending => return Err(Error::not_ready()),
         }
 
+        //= https://www.rfc-editor.org/rfc/rfc9114#section-4.1
+        //= type=implication
+        //# A
+        //# client MUST send only a single request on a given stream.
+        let mut stream = match self.open.poll_open_bidi(&mut cx) {
+            Poll::Ready(res) => res.map_err(|e| self.maybe_conn_err(e))?,
+            Poll::Pending => return Err(Error::not_ready()),
+        };
+
+        quic::SendStream::send_data(&mut stream, Frame::Headers(headers))
+            .map_err(|e| self.maybe_conn_err(e))?;
+
+        Ok(self.request_stream(stream))
+    }
+
+    /// Encodes the header of a request, checking the server accepts its size
+    fn encode_request(&self, req: http::Request<()>) -> Result<Bytes, Error> {
+        let peer_max_field_section_size = self
+            .conn_state
+            .read("send request lock state")
+            .peer_config
+            .max_field_section_size;
+
         let (parts, _) = req.into_parts();
         let request::Parts {
             method,
@@ -144,14 +260,6 @@ where
         } = parts;
         let headers = Header::request(method, uri, headers, extensions)?;
 
-        //= https://www.rfc-editor.org/rfc/rfc9114#section-4.1
-        //= type=implication
-        //# A
-        //# client MUST send only a single request on a given stream.
-        let mut stream = future::poll_fn(|cx| self.open.poll_open_bidi(cx))
-            .await
-            .map_err(|e| self.maybe_conn_err(e))?;
-
         //= https://www.rfc-editor.org/rfc/rfc9114#section-4.2
         //= type=TODO
         //# Characters in field names MUST be
@@ -175,22 +283,113 @@ where
             return Err(Error::header_too_big(mem_size, peer_max_field_section_size));
         }
 
-        stream::write(&mut stream, Frame::Headers(block.freeze()))
-            .await
-            .map_err(|e| self.maybe_conn_err(e))?;
+        Ok(block.freeze())
+    }
 
-        let request_stream = RequestStream {
+    /// Wraps a stream the request headers were sent on
+    fn request_stream(&mut self, stream: T::BidiStream) -> RequestStream<T::BidiStream, B> {
+        let mut stream = FrameStream::new(BufRecvStream::new(stream));
+        stream.set_extension_handler(self.on_extension_frame.clone());
+        // No MAX_PUSH_ID frame is ever sent, the server may not push anything
+        stream.set_push_id_limit(PushIdLimit::default());
+        let mut request_stream = RequestStream {
             inner: connection::RequestStream::new(
-                FrameStream::new(BufRecvStream::new(stream)),
+                stream,
                 self.max_field_section_size,
                 self.conn_state.clone(),
                 self.send_grease_frame,
             ),
+            early: None,
         };
+        request_stream
+            .inner
+            .track_end(Arc::new(self.in_flight.start()));
+        request_stream.inner.headers_sent();
         // send the grease frame only once
         self.send_grease_frame = false;
+        request_stream
+    }
+}
+
+impl<T, B> SendRequest<T, B>
+where
+    T: quic::OpenStreams<B> + quic::EarlyDataExt,
+    B: Buf,
+{
+    /// Send a HTTP/3 request to the server, in 0-RTT if the handshake is still in progress
+    ///
+    /// The [`RequestStream`] is returned as soon as the request headers are sent, so the body
+    /// can be sent in 0-RTT as well. The server is free to reject early data though: pass the
+    /// stream to [`SendRequest::confirm_early()`] before receiving the response, to learn
+    /// whether the request must be sent again. Receiving from a stream which was not replayed
+    /// fails once early data is rejected, see [`Error::is_early_data_rejected()`].
+    ///
+    /// Early data can be replayed by an attacker, so only idempotent requests are accepted.
+    /// Insert the [`AllowEarlyData`](crate::ext::AllowEarlyData) extension into a request to
+    /// send it early regardless of its method.
+    pub async fn send_request_early(
+        &mut self,
+        req: http::Request<()>,
+    ) -> Result<RequestStream<T::BidiStream, B>, Error>
+    where
+        T: Clone + Send + 'static,
+    {
+        //= https://www.rfc-editor.org/rfc/rfc9114#section-10.9
+        //# The anti-replay mitigations in [HTTP-REPLAY] MUST be applied when
+        //# using HTTP/3 with 0-RTT.
+        if !req.method().is_idempotent() && req.extensions().get::<AllowEarlyData>().is_none() {
+            return Err(Code::H3_REQUEST_REJECTED.with_reason(
+                "non-idempotent request refused in 0-RTT",
+                ErrorLevel::StreamError,
+            ));
+        }
+
+        if !self.open.is_early_data() {
+            return self.send_request(req).await;
+        }
+
+        let headers = self.encode_request(req)?;
+        let request = EncodedRequest::from_frames(&[Frame::Headers(headers.clone())]);
+        let mut request_stream = self.send_encoded(headers).await?;
+        let mut open = self.open.clone();
+        request_stream.early = Some(Early {
+            request,
+            handshake: Box::new(move |cx| open.poll_handshake(cx)),
+        });
         Ok(request_stream)
     }
+
+    /// Waits for the handshake to complete, replaying the request of `stream` if its early data
+    /// was rejected
+    ///
+    /// Resolves to `true` if the request headers were sent again, as encoded the first time, on
+    /// a new stream which replaces `stream`. Whatever followed them in 0-RTT, such as the body,
+    /// was discarded by the server and must be sent again. Resolves to `false` if early data
+    /// was accepted, or if the request was not sent in 0-RTT.
+    pub async fn confirm_early(
+        &mut self,
+        stream: &mut RequestStream<T::BidiStream, B>,
+    ) -> Result<bool, Error>
+    where
+        T::BidiStream: quic::SendStream<Bytes>,
+    {
+        let request = match stream.early.take() {
+            Some(early) => early.request,
+            None => return Ok(false),
+        };
+        if future::poll_fn(|cx| self.open.poll_handshake(cx)).await {
+            return Ok(false);
+        }
+
+        trace!("early data rejected, replaying request");
+        let mut replay = self.open_request().await?;
+        request
+            .send_to(&mut replay)
+            .await
+            .map_err(|e| self.maybe_conn_err(e))?;
+        *stream = self.request_stream(replay);
+        Ok(true)
+    }
 }
 
 impl<T, B> ConnectionState for SendRequest<T, B>
@@ -217,9 +416,11 @@ where
             conn_state: self.conn_state.clone(),
             max_field_section_size: self.max_field_section_size,
             sender_count: self.sender_count.clone(),
+            in_flight: self.in_flight.clone(),
             conn_waker: self.conn_waker.clone(),
             _buf: PhantomData,
             send_grease_frame: self.send_grease_frame,
+            on_extension_frame: self.on_extension_frame.clone(),
         }
     }
 }
@@ -238,18 +439,99 @@ where
             if let Some(w) = Option::take(&mut self.conn_waker) {
                 w.wake()
             }
+            self.in_flight.orphaned.store(true, Ordering::SeqCst);
+            if self.in_flight.requests.load(Ordering::SeqCst) != 0 {
+                // The driver closes the connection once the last request is over
+                self.in_flight.driver.wake();
+                return;
+            }
             self.shared_state().write("SendRequest drop").error = Some(Error::closed());
             self.open.close(Code::H3_NO_ERROR, b"");
         }
     }
 }
 
+/// Requests in flight, which keep the connection open after all [`SendRequest`] are dropped
+#[derive(Default)]
+pub(super) struct InFlight {
+    // `requests` and `orphaned` are each written then the other read, by the last request and
+    // the last `SendRequest`: SeqCst keeps both from missing the other's write
+    requests: AtomicUsize,
+    // The last `SendRequest` has been dropped
+    orphaned: AtomicBool,
+    driver: AtomicWaker,
+    max_requests: Option<usize>,
+    // `SendRequest::poll_ready()` calls waiting for a request to be over
+    capacity_wakers: Mutex<Vec<Waker>>,
+}
+
+impl InFlight {
+    pub(super) fn new(max_requests: Option<usize>) -> Self {
+        Self {
+            max_requests,
+            ..Self::default()
+        }
+    }
+
+    /// Counts a new request in flight, until its end is reported
+    fn start(self: &Arc<Self>) -> RequestEnd {
+        self.requests.fetch_add(1, Ordering::SeqCst);
+        let in_flight = self.clone();
+        RequestEnd::new(move || in_flight.end())
+    }
+
+    fn end(&self) {
+        if self.requests.fetch_sub(1, Ordering::SeqCst) == 1 && self.orphaned.load(Ordering::SeqCst)
+        {
+            self.driver.wake();
+        }
+        if self.max_requests.is_some() {
+            let wakers = std::mem::take(&mut *self.capacity_wakers.lock().unwrap());
+            wakers.into_iter().for_each(Waker::wake);
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        self.orphaned.load(Ordering::SeqCst) && self.requests.load(Ordering::SeqCst) == 0
+    }
+
+    /// Whether fewer requests than the limit are in flight
+    fn has_capacity(&self) -> bool {
+        match self.max_requests {
+            Some(max) => self.requests.load(Ordering::Acquire) < max,
+            None => true,
+        }
+    }
+
+    /// Resolves once fewer requests than the limit are in flight
+    fn poll_capacity(&self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.has_capacity() {
+            return Poll::Ready(());
+        }
+
+        let mut wakers = self.capacity_wakers.lock().unwrap();
+        if !wakers.iter().any(|w| w.will_wake(cx.waker())) {
+            wakers.push(cx.waker().clone());
+        }
+        // A request may have ended before the waker was registered
+        if self.has_capacity() {
+            return Poll::Ready(());
+        }
+        Poll::Pending
+    }
+}
+
 /// Client connection driver
 ///
 /// Maintains the internal state of an HTTP/3 connection, including control and QPACK.
-/// It needs to be polled continously via [`poll_close()`]. On connection closure, this
-/// will resolve to `Ok(())` if the peer sent `HTTP_NO_ERROR`, or `Err()` if a connection-level
-/// error occured.
+/// It needs to be polled continously via [`poll_drive()`], independently from the
+/// [`SendRequest`] handles: the peer's SETTINGS and GOAWAY frames only get processed while it
+/// is polled, so it is best spawned on its own task with [`drive()`]. On connection closure,
+/// this will resolve to `Ok(())` if the peer sent `HTTP_NO_ERROR`, or `Err()` if a
+/// connection-level error occured.
+///
+/// Dropping the driver before the connection is closed tears it down: the peer is sent
+/// `HTTP_NO_ERROR`, and ongoing requests as well as [`SendRequest`] handles fail with an error.
 ///
 /// [`shutdown()`] initiates a graceful shutdown of this connection. After calling it, no request
 /// initiation will be further allowed. Then [`poll_close()`] will resolve when all ongoing requests
@@ -262,10 +544,9 @@ where
 ///
 /// ```rust
 /// # use bytes::Buf;
-/// # use futures_util::future;
 /// # use h3::{client::*, quic};
 /// # use tokio::task::JoinHandle;
-/// # async fn doc<C, B>(mut connection: Connection<C, B>)
+/// # async fn doc<C, B>(connection: Connection<C, B>)
 /// #    -> JoinHandle<Result<(), Box<dyn std::error::Error + Send + Sync>>>
 /// # where
 /// #    C: quic::Connection<B> + Send + 'static,
@@ -275,7 +556,7 @@ where
 /// # {
 /// // Run the driver on a different task
 /// tokio::spawn(async move {
-///     future::poll_fn(|cx| connection.poll_close(cx)).await?;
+///     connection.drive().await?;
 ///     Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
 /// })
 /// # }
@@ -326,6 +607,8 @@ where
 /// driver.await?
 /// # }
 /// ```
+/// [`poll_drive()`]: struct.Connection.html#method.poll_drive
+/// [`drive()`]: struct.Connection.html#method.drive
 /// [`poll_close()`]: struct.Connection.html#method.poll_close
 /// [`shutdown()`]: struct.Connection.html#method.shutdown
 pub struct Connection<C, B>
@@ -338,6 +621,7 @@ where
     pub(super) sent_closing: Option<PushId>,
     // Has a GOAWAY frame been received? If so, this is StreamId the last the remote will accept.
     pub(super) recv_closing: Option<StreamId>,
+    pub(super) in_flight: Arc<InFlight>,
 }
 
 impl<C, B> Connection<C, B>
@@ -351,13 +635,43 @@ where
         self.inner.shutdown(&mut self.sent_closing, PushId(0)).await
     }
 
+    /// Sends a frame of extension type `frame_type` on the control stream, followed by `payload`
+    ///
+    /// See [`ExtensionFrame::new()`](crate::codec::ExtensionFrame::new) for the types allowed.
+    pub async fn send_extension_frame(
+        &mut self,
+        frame_type: VarInt,
+        payload: Bytes,
+    ) -> Result<(), Error> {
+        self.inner.send_extension_frame(frame_type, payload).await
+    }
+
     /// Wait until the connection is closed
     pub async fn wait_idle(&mut self) -> Result<(), Error> {
-        future::poll_fn(|cx| self.poll_close(cx)).await
+        future::poll_fn(|cx| self.poll_drive(cx)).await
+    }
+
+    /// Drive the connection until it is closed, meant to be spawned on its own task
+    pub async fn drive(mut self) -> Result<(), Error> {
+        future::poll_fn(|cx| self.poll_drive(cx)).await
     }
 
     /// Maintain the connection state until it is closed
+    ///
+    /// This is the same as [`poll_drive()`](Self::poll_drive).
     pub fn poll_close(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        self.poll_drive(cx)
+    }
+
+    /// Process the control stream and QPACK streams until the connection is closed
+    pub fn poll_drive(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        self.in_flight.driver.register(cx.waker());
+        if self.in_flight.is_idle() {
+            self.inner.shared.write("poll_drive idle").error = Some(Error::closed());
+            self.inner.conn.close(Code::H3_NO_ERROR, b"");
+            return Poll::Ready(Ok(()));
+        }
+
         while let Poll::Ready(result) = self.inner.poll_control(cx) {
             match result {
                 //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.4.2
@@ -380,6 +694,9 @@ where
                 //# Once a server has provided new settings,
                 //# clients MUST comply with those values.
                 Ok(Frame::Settings(_)) => trace!("Got settings"),
+                Ok(Frame::Origin { entries }) => {
+                    trace!("Got ORIGIN frame with {} origins", entries.len())
+                }
                 Ok(Frame::Goaway(id)) => {
                     //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.6
                     //# The GOAWAY frame is always sent on the control stream.  In the
@@ -387,15 +704,20 @@ where
                     //# initiated bidirectional stream encoded as a variable-length integer.
                     //# A client MUST treat receipt of a GOAWAY frame containing a stream ID
                     //# of any other type as a connection error of type H3_ID_ERROR.
-                    if !StreamId::from(id).is_request() {
-                        return Poll::Ready(Err(Code::H3_ID_ERROR.with_reason(
-                            format!("non-request StreamId in a GoAway frame: {}", id),
-                            ErrorLevel::ConnectionError,
-                        )));
-                    }
+                    let id = Goaway::new(id).as_stream_id()?;
                     self.inner.process_goaway(&mut self.recv_closing, id)?;
 
-                    info!("Server initiated graceful shutdown, last: StreamId({})", id);
+                    info!("Server initiated graceful shutdown, last: {}", id);
+                }
+                Ok(Frame::CancelPush(id)) => {
+                    //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.3
+                    //# If a CANCEL_PUSH frame is received that
+                    //# references a push ID greater than currently allowed on the
+                    //# connection, this MUST be treated as a connection error of type
+                    //# H3_ID_ERROR.
+                    self.inner.push_id_limit.check(id)?;
+                    // Server push is not implemented, there is nothing to cancel
+                    trace!("Got CANCEL_PUSH frame for {}", id);
                 }
 
                 //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.5
@@ -413,11 +735,11 @@ where
                     )))
                 }
                 Err(e) => {
-                    let connection_error = self.inner.shared.read("poll_close").error.clone();
+                    let connection_error = self.inner.shared.read("poll_drive").error.clone();
                     let connection_error = match connection_error {
                         Some(e) => e,
                         None => {
-                            self.inner.shared.write("poll_close error").error = Some(e.clone());
+                            self.inner.shared.write("poll_drive error").error = Some(e.clone());
                             e
                         }
                     };
@@ -444,3 +766,16 @@ where
         Poll::Pending
     }
 }
+
+impl<C, B> Drop for Connection<C, B>
+where
+    C: quic::Connection<B>,
+    B: Buf,
+{
+    fn drop(&mut self) {
+        if self.inner.shared.read("driver drop").error.is_none() {
+            self.inner
+                .close(Code::H3_NO_ERROR, "connection driver dropped");
+        }
+    }
+}
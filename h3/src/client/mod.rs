@@ -1,10 +1,14 @@
 //! HTTP/3 client
 
+#[cfg(feature = "http-body")]
+mod body;
 mod connection;
 mod stream;
 
 mod builder;
 
+#[cfg(feature = "http-body")]
+pub use body::ResponseBody;
 pub use builder::builder;
 pub use builder::new;
 pub use builder::Builder;
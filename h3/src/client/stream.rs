@@ -1,15 +1,19 @@
-use bytes::Buf;
-use futures_util::future;
+use bytes::{Buf, Bytes};
+use futures_util::{future, ready};
 use http::{HeaderMap, Response};
 
 use crate::{
     connection::{self, ConnectionState, SharedStateRef},
     error::{Code, Error, ErrorLevel},
-    proto::{frame::Frame, headers::Header},
+    frame::EncodedRequest,
+    proto::{frame::Frame, headers::Header, varint::VarInt},
     qpack,
     quic::{self},
 };
-use std::convert::TryFrom;
+use std::{
+    convert::TryFrom,
+    task::{Context, Poll},
+};
 
 /// Manage request bodies transfer, response and trailers.
 ///
@@ -65,6 +69,52 @@ use std::convert::TryFrom;
 /// [`stop_sending()`]: #method.stop_sending
 pub struct RequestStream<S, B> {
     pub(super) inner: connection::RequestStream<S, B>,
+    // Request sent in 0-RTT, until the handshake completes or `SendRequest::confirm_early()`
+    pub(super) early: Option<Early>,
+}
+
+/// A request sent in 0-RTT, which is lost if the server rejects early data
+pub(super) struct Early {
+    // Request frames as first encoded, to replay the request
+    pub(super) request: EncodedRequest,
+    // Polls the connection handshake, resolving to whether early data was accepted
+    pub(super) handshake: PollHandshake,
+}
+
+pub(super) type PollHandshake = Box<dyn FnMut(&mut Context<'_>) -> Poll<bool> + Send>;
+
+impl<S, B> RequestStream<S, B> {
+    /// Wraps a request stream no connection counts as in flight
+    #[cfg(test)]
+    pub(crate) fn detached(inner: connection::RequestStream<S, B>) -> Self {
+        Self { inner, early: None }
+    }
+}
+
+impl<S, B> RequestStream<S, B> {
+    /// Waits for the handshake if the request was sent in 0-RTT, failing if early data was
+    /// rejected
+    ///
+    /// The response cannot be received before the handshake completes anyway. A rejected
+    /// request is kept, so that [`SendRequest::confirm_early()`] can replay it.
+    ///
+    /// [`SendRequest::confirm_early()`]: super::SendRequest::confirm_early
+    fn poll_early(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        let early = match self.early {
+            Some(ref mut early) => early,
+            None => return Poll::Ready(Ok(())),
+        };
+        if ready!((early.handshake)(cx)) {
+            self.early = None;
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Ready(Err(Error::early_data_rejected()))
+        }
+    }
+
+    async fn check_early(&mut self) -> Result<(), Error> {
+        future::poll_fn(|cx| self.poll_early(cx)).await
+    }
 }
 
 impl<S, B> ConnectionState for RequestStream<S, B> {
@@ -79,13 +129,28 @@ where
 {
     /// Receive the HTTP/3 response
     ///
-    /// This should be called before trying to receive any data with [`recv_data()`].
+    /// This should be called before trying to receive any data with [`recv_data()`]. Interim
+    /// responses, with a 1xx status, are returned as well: this is then called again for the
+    /// final response. Once it is received, calling this fails.
+    ///
+    /// This is cancel safe: the response headers are only taken from the stream once entirely
+    /// received, so dropping the future before it completes loses nothing.
+    ///
+    /// The headers of a request sent with [`SendRequest::try_send_request()`] are not flushed
+    /// by this, nor by the other `recv_*` methods. Unless something was sent after them, or the
+    /// stream finished, this may wait for a response to a request the server never received.
+    ///
+    /// A request sent with [`SendRequest::send_request_early()`] is lost if the server rejects
+    /// early data: this, and the other `recv_*` methods, then fail with an error for which
+    /// [`Error::is_early_data_rejected()`] is true.
     ///
     /// [`recv_data()`]: #method.recv_data
+    /// [`SendRequest::send_request_early()`]: super::SendRequest::send_request_early
+    /// [`SendRequest::try_send_request()`]: super::SendRequest::try_send_request
     pub async fn recv_response(&mut self) -> Result<Response<()>, Error> {
-        let mut frame = future::poll_fn(|cx| self.inner.stream.poll_next(cx))
-            .await
-            .map_err(|e| self.maybe_conn_err(e))?
+        self.check_early().await?;
+        let frame = future::poll_fn(|cx| self.inner.poll_next_frame(cx))
+            .await?
             .ok_or_else(|| {
                 Code::H3_GENERAL_PROTOCOL_ERROR.with_reason(
                     "Did not receive response headers",
@@ -93,12 +158,6 @@ where
                 )
             })?;
 
-        //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.5
-        //= type=TODO
-        //# A client MUST treat
-        //# receipt of a PUSH_PROMISE frame that contains a larger push ID than
-        //# the client has advertised as a connection error of H3_ID_ERROR.
-
         //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.5
         //= type=TODO
         //# If a client
@@ -106,31 +165,39 @@ where
         //# mismatch, it MUST respond with a connection error of type
         //# H3_GENERAL_PROTOCOL_ERROR.
 
-        let decoded = if let Frame::Headers(ref mut encoded) = frame {
-            match qpack::decode_stateless(encoded, self.inner.max_field_section_size) {
-                //= https://www.rfc-editor.org/rfc/rfc9114#section-4.2.2
-                //# An HTTP/3 implementation MAY impose a limit on the maximum size of
-                //# the message header it will accept on an individual HTTP message.
-                Err(qpack::DecoderError::HeaderTooLong(cancel_size)) => {
-                    self.inner.stop_sending(Code::H3_REQUEST_CANCELLED);
-                    return Err(Error::header_too_big(
-                        cancel_size,
-                        self.inner.max_field_section_size,
-                    ));
-                }
-                Ok(decoded) => decoded,
-                Err(e) => return Err(e.into()),
+        let mut encoded = match frame {
+            Frame::Headers(encoded) => encoded,
+            // The response was already received, the body follows
+            _ => {
+                return Err(Code::H3_FRAME_UNEXPECTED.with_reason(
+                    "First response frame is not headers",
+                    ErrorLevel::ConnectionError,
+                ))
             }
-        } else {
-            return Err(Code::H3_FRAME_UNEXPECTED.with_reason(
-                "First response frame is not headers",
-                ErrorLevel::ConnectionError,
-            ));
+        };
+        let decoded = match qpack::decode_stateless(&mut encoded, self.inner.max_field_section_size)
+        {
+            //= https://www.rfc-editor.org/rfc/rfc9114#section-4.2.2
+            //# An HTTP/3 implementation MAY impose a limit on the maximum size of
+            //# the message header it will accept on an individual HTTP message.
+            Err(qpack::DecoderError::HeaderTooLong(cancel_size)) => {
+                self.inner.stop_sending(Code::H3_REQUEST_CANCELLED);
+                return Err(Error::header_too_big(
+                    cancel_size,
+                    self.inner.max_field_section_size,
+                ));
+            }
+            Ok(decoded) => decoded,
+            Err(e) => return Err(e.into()),
         };
 
         let qpack::Decoded { fields, .. } = decoded;
 
         let (status, headers) = Header::try_from(fields)?.into_response_parts()?;
+        if status.is_informational() {
+            // The final response, or another interim one, follows
+            self.inner.interim_received();
+        }
         let mut resp = Response::new(());
         *resp.status_mut() = status;
         *resp.headers_mut() = headers;
@@ -140,17 +207,36 @@ where
     }
 
     /// Receive some of the request body.
+    ///
+    /// This is cancel safe, and does not flush the request headers, like [`recv_response()`](#method.recv_response).
     // TODO what if called before recv_response ?
     pub async fn recv_data(&mut self) -> Result<Option<impl Buf>, Error> {
+        self.check_early().await?;
         self.inner.recv_data().await
     }
 
+    /// Receive the next length-delimited message of the response body, as gRPC frames them
+    ///
+    /// Messages are read as with [`server::RequestStream::recv_message()`]. This is cancel safe,
+    /// and does not flush the request headers, like [`recv_response()`](#method.recv_response).
+    ///
+    /// [`server::RequestStream::recv_message()`]: crate::server::RequestStream::recv_message
+    pub async fn recv_message(&mut self) -> Result<Option<Bytes>, Error> {
+        self.check_early().await?;
+        self.inner.recv_message().await
+    }
+
     /// Receive an optional set of trailers for the response.
+    ///
+    /// This is cancel safe, and does not flush the request headers, like [`recv_response()`](#method.recv_response).
     pub async fn recv_trailers(&mut self) -> Result<Option<HeaderMap>, Error> {
+        self.check_early().await?;
         let res = self.inner.recv_trailers().await;
         if let Err(ref e) = res {
             if e.is_header_too_big() {
-                self.inner.stream.stop_sending(Code::H3_REQUEST_CANCELLED);
+                self.inner
+                    .stream_mut()
+                    .stop_sending(Code::H3_REQUEST_CANCELLED);
             }
         }
         res
@@ -160,7 +246,12 @@ where
     pub fn stop_sending(&mut self, error_code: crate::error::Code) {
         // TODO take by value to prevent any further call as this request is cancelled
         // rename `cancel()` ?
-        self.inner.stream.stop_sending(error_code)
+        self.inner.stream_mut().stop_sending(error_code)
+    }
+
+    /// Returns the underlying stream id
+    pub fn id(&self) -> quic::StreamId {
+        self.inner.stream().id()
     }
 }
 
@@ -174,6 +265,17 @@ where
         self.inner.send_data(buf).await
     }
 
+    /// Send a frame of extension type `frame_type`, followed by `payload`
+    ///
+    /// See [`ExtensionFrame::new()`](crate::codec::ExtensionFrame::new) for the types allowed.
+    pub async fn send_extension_frame(
+        &mut self,
+        frame_type: VarInt,
+        payload: Bytes,
+    ) -> Result<(), Error> {
+        self.inner.send_extension_frame(frame_type, payload).await
+    }
+
     /// Send a set of trailers to end the request.
     ///
     /// Either [`RequestStream::finish`] or
@@ -188,10 +290,24 @@ where
     /// Either [`RequestStream::finish`] or
     /// [`RequestStream::send_trailers`] must be called to finalize a
     /// request.
+    ///
+    /// Only the sending side is closed, the response can still be received afterwards. Once
+    /// finished, sending anything else fails. This is cancel-safe, calling it again after
+    /// the future was dropped resumes finishing the stream.
     pub async fn finish(&mut self) -> Result<(), Error> {
         self.inner.finish().await
     }
 
+    /// Sets the code the stream is reset with when dropped without being finished
+    ///
+    /// Defaults to [`Code::H3_REQUEST_CANCELLED`] for the send half of a [split] stream, and to
+    /// `None` otherwise, which leaves the stream as is.
+    ///
+    /// [split]: RequestStream::split
+    pub fn reset_on_drop(&mut self, code: Option<Code>) {
+        self.inner.reset_on_drop(code);
+    }
+
     //= https://www.rfc-editor.org/rfc/rfc9114#section-4.1.1
     //= type=TODO
     //# Implementations SHOULD cancel requests by abruptly terminating any
@@ -207,6 +323,14 @@ where
     B: Buf,
 {
     /// Split this stream into two halves that can be driven independently.
+    ///
+    /// For a request sent with [`SendRequest::send_request_early()`], the receiving half still
+    /// waits for the handshake, and fails if early data is rejected. The request can no longer
+    /// be replayed though, [`SendRequest::confirm_early()`] needs the whole stream: confirm it
+    /// before splitting to have it replayed.
+    ///
+    /// [`SendRequest::send_request_early()`]: super::SendRequest::send_request_early
+    /// [`SendRequest::confirm_early()`]: super::SendRequest::confirm_early
     pub fn split(
         self,
     ) -> (
@@ -214,6 +338,15 @@ where
         RequestStream<S::RecvStream, B>,
     ) {
         let (send, recv) = self.inner.split();
-        (RequestStream { inner: send }, RequestStream { inner: recv })
+        (
+            RequestStream {
+                inner: send,
+                early: None,
+            },
+            RequestStream {
+                inner: recv,
+                early: self.early,
+            },
+        )
     }
 }
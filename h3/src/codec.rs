@@ -0,0 +1,254 @@
+//! Encoding and decoding of HTTP/3 frames and QUIC variable-length integers
+//!
+//! This is the wire format of [RFC 9114], for protocol analyzers, test harnesses or prototypes of
+//! extensions, which need frames without running a connection. It is a low-level API: connections
+//! build and check the frames they send by themselves, so nothing done with this module can
+//! break their state. What it decodes is still subject to the checks a connection applies, such
+//! as which frames are allowed on which stream.
+//!
+//! Frames are encoded with [`Encode`], and decoded with [`Frame::decode()`]:
+//!
+//! ```
+//! use bytes::{Bytes, BytesMut};
+//! use h3::codec::{Encode, Frame, PayloadLen, SettingId, Settings, VarInt};
+//!
+//! let mut settings = Settings::default();
+//! settings.insert(SettingId::MAX_HEADER_LIST_SIZE, 16384).unwrap();
+//!
+//! let mut buf = BytesMut::new();
+//! Frame::<Bytes>::Settings(settings).encode(&mut buf);
+//! Frame::<Bytes>::Goaway(VarInt::from_u32(4)).encode(&mut buf);
+//!
+//! let mut buf = buf.freeze();
+//! match Frame::decode(&mut buf).unwrap() {
+//!     Frame::Settings(settings) => {
+//!         assert_eq!(settings.get(SettingId::MAX_HEADER_LIST_SIZE), Some(16384))
+//!     }
+//!     frame => panic!("unexpected {:?}", frame),
+//! }
+//! assert!(matches!(Frame::decode(&mut buf), Ok(Frame::<PayloadLen>::Goaway(id)) if id == VarInt::from_u32(4)));
+//! ```
+//!
+//! The payload of DATA, HEADERS and extension frames is not part of their encoding, it follows it
+//! on the stream. When decoding, the payload of a DATA frame is left in the buffer, its length
+//! given by [`PayloadLen`].
+//!
+//! [RFC 9114]: https://www.rfc-editor.org/rfc/rfc9114
+
+use bytes::{Buf, BufMut};
+
+pub use crate::proto::{
+    coding::{BufferTooShort, Encode, UnexpectedEnd},
+    frame::{
+        ExtensionFrame, Frame, FrameError, FrameKind, FrameType, OriginEntry, PayloadLen,
+        PushPromise, SettingId, Settings, SettingsError,
+    },
+    push::{InvalidPushId, PushId},
+    varint::{VarInt, VarIntBoundsExceeded, VarIntError},
+};
+pub use crate::webtransport::SessionId;
+
+/// Encodes `x` as a variable-length integer, returning the number of bytes written
+///
+/// Fails without writing anything if `x` does not fit, that is from 2^62 on.
+///
+/// ```
+/// let mut buf = Vec::new();
+/// assert_eq!(h3::codec::encode_varint(494_878_333, &mut buf), Ok(4));
+/// assert_eq!(buf, [0x9d, 0x7f, 0x3e, 0x7d]);
+/// assert!(h3::codec::encode_varint(1 << 62, &mut buf).is_err());
+/// ```
+pub fn encode_varint<B: BufMut>(x: u64, buf: &mut B) -> Result<usize, VarIntBoundsExceeded> {
+    let x = VarInt::from_u64(x)?;
+    x.encode(buf);
+    Ok(x.size())
+}
+
+/// Decodes a variable-length integer from the start of `buf`
+///
+/// Fails with [`VarIntError::UnexpectedEnd`] if `buf` ends before the integer does, leaving `buf`
+/// untouched, so that it can be retried once more bytes are received.
+///
+/// ```
+/// use h3::codec::{decode_varint, VarIntError};
+///
+/// let mut buf = &[0x9d, 0x7f, 0x3e, 0x7d, 0x25][..];
+/// assert_eq!(decode_varint(&mut buf).unwrap().into_inner(), 494_878_333);
+/// assert_eq!(buf, [0x25]);
+///
+/// let mut buf = &[0x9d, 0x7f][..];
+/// assert!(matches!(decode_varint(&mut buf), Err(VarIntError::UnexpectedEnd(_))));
+/// assert_eq!(buf.len(), 2);
+/// ```
+pub fn decode_varint<B: Buf>(buf: &mut B) -> Result<VarInt, VarIntError> {
+    if !buf.has_remaining() {
+        return Err(UnexpectedEnd(1).into());
+    }
+    let size = VarInt::encoded_size(buf.chunk()[0]);
+    if buf.remaining() < size {
+        return Err(UnexpectedEnd(size - buf.remaining()).into());
+    }
+    VarInt::decode_with(buf, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use arbitrary::{Arbitrary, Unstructured};
+    use bytes::{Bytes, BytesMut};
+    use proptest::{prop_assert_eq, proptest, sample::select};
+
+    use super::*;
+
+    #[test]
+    fn varint_round_trip() {
+        let mut rng = fastrand::Rng::with_seed(0x6833);
+        let mut buf = BytesMut::new();
+        for _ in 0..4096 {
+            // Evenly spread over the encoded lengths
+            let x = rng.u64(..) >> rng.u32(2..64);
+            buf.clear();
+            let size = encode_varint(x, &mut buf).unwrap();
+            assert_eq!(size, buf.len());
+            assert_eq!(size, VarInt::from_u64(x).unwrap().size());
+
+            let mut encoded = buf.clone().freeze();
+            let mut partial = encoded.slice(..rng.usize(..size));
+            assert!(decode_varint(&mut partial).is_err());
+            assert_eq!(decode_varint(&mut encoded).unwrap().into_inner(), x);
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn varint_out_of_bounds() {
+        let mut buf = Vec::new();
+        assert_eq!(
+            encode_varint(u64::MAX, &mut buf),
+            Err(VarIntBoundsExceeded(u64::MAX))
+        );
+        assert_eq!(
+            encode_varint(VarInt::MAX.into_inner(), &mut buf),
+            Ok(VarInt::MAX_SIZE)
+        );
+        assert_eq!(buf, [0xff; 8]);
+    }
+
+    #[test]
+    fn varint_length_boundaries() {
+        let mut rng = fastrand::Rng::with_seed(0x6833);
+        let mut buf = BytesMut::new();
+        // Each encoded length ends right before the next power
+        for (bound, size) in [(1 << 6, 1), (1 << 14, 2), (1 << 30, 4), (1 << 62, 8)] {
+            let around = [
+                bound - 1,
+                bound - 1 - rng.u64(..bound / 2),
+                bound,
+                bound + 1,
+            ];
+            for x in around {
+                buf.clear();
+                match VarInt::try_from(x) {
+                    Ok(v) => {
+                        let expected = if x < bound { size } else { size * 2 };
+                        assert_eq!(v.size(), expected, "{}", x);
+                        v.encode(&mut buf);
+                        assert_eq!(buf.len(), expected, "{}", x);
+                        assert_eq!(decode_varint(&mut buf.clone().freeze()), Ok(v));
+                    }
+                    Err(e) => {
+                        assert!(x > VarInt::MAX.into_inner(), "{}", x);
+                        assert_eq!(e, VarIntBoundsExceeded(x));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn varint_arithmetic() {
+        let one = VarInt::from_u32(1);
+        let below_max = VarInt::from_u64(VarInt::MAX.into_inner() - 1).unwrap();
+        assert_eq!(below_max.checked_add(one), Some(VarInt::MAX));
+        assert_eq!(below_max.saturating_add(one), VarInt::MAX);
+        assert_eq!(VarInt::MAX.checked_add(one), None);
+        assert_eq!(VarInt::MAX.checked_add(VarInt::MAX), None);
+        assert_eq!(VarInt::MAX.saturating_add(VarInt::MAX), VarInt::MAX);
+        assert_eq!(
+            VarInt::from_u32(63).checked_add(one).map(VarInt::size),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn frame_round_trip() {
+        let mut rng = fastrand::Rng::with_seed(0x6833);
+        let mut data = Vec::new();
+        let mut buf = BytesMut::new();
+        for _ in 0..4096 {
+            data.clear();
+            data.extend((0..rng.usize(..256)).map(|_| rng.u8(..)));
+            let frame = match Frame::<Bytes>::arbitrary_take_rest(Unstructured::new(&data)) {
+                // Never decoded, frames of unknown types are skipped
+                Ok(Frame::Grease) | Ok(Frame::Extension(_)) | Err(_) => continue,
+                Ok(frame) => frame,
+            };
+
+            buf.clear();
+            frame.encode(&mut buf);
+            if let Frame::Headers(payload) = &frame {
+                buf.put_slice(payload);
+            }
+            assert_eq!(buf.len(), frame.encoded_len() - frame_data_len(&frame));
+
+            let decoded = Frame::decode(&mut buf).unwrap();
+            assert!(decoded == frame, "{:?} decoded as {:?}", frame, decoded);
+            assert!(buf.is_empty());
+        }
+    }
+
+    fn frame_data_len(frame: &Frame<Bytes>) -> usize {
+        match frame {
+            Frame::Data(payload) => payload.len(),
+            _ => 0,
+        }
+    }
+
+    #[test]
+    fn frame_type_is_reserved() {
+        assert!(FrameType::grease().is_reserved());
+        assert!(FrameType::from(VarInt::from_u32(0x21)).is_reserved());
+        assert!(!FrameType::DATA.is_reserved());
+        assert!(!FrameType::H2_PING.is_reserved());
+        assert!(!FrameType::from(VarInt::try_from(0x20u64).unwrap()).is_reserved());
+    }
+
+    proptest! {
+        #[test]
+        fn goaway_and_max_push_id_round_trip_at_length_boundaries(
+            bound in select(vec![1u64 << 6, 1 << 14, 1 << 30, 1 << 62]),
+            distance in 0u64..64,
+            above in proptest::bool::ANY,
+        ) {
+            // The largest value of a length, or the smallest of the next one
+            let x = if above { bound + distance } else { bound - 1 - distance };
+            let id = match VarInt::from_u64(x) {
+                Ok(id) => id,
+                Err(_) => {
+                    prop_assert_eq!(bound, 1 << 62);
+                    return Ok(());
+                }
+            };
+
+            let mut buf = BytesMut::new();
+            for frame in [Frame::<Bytes>::Goaway(id), Frame::MaxPushId(id.into())] {
+                buf.clear();
+                frame.encode(&mut buf);
+                // Type, length, then the id
+                prop_assert_eq!(buf.len(), 2 + id.size());
+                prop_assert_eq!(Frame::decode(&mut buf).unwrap(), frame);
+            }
+        }
+    }
+}
@@ -1,9 +1,27 @@
-use std::convert::TryFrom;
+use std::{collections::HashMap, convert::TryFrom, fmt, sync::Arc};
 
-use crate::proto::{frame, varint::VarInt};
+use crate::{
+    error::{Code, Error},
+    ext::UniStream,
+    frame::ExtensionHandler,
+    proto::{
+        frame,
+        stream::{StreamId, StreamType},
+        varint::VarInt,
+    },
+};
+
+/// Receives the incoming unidirectional streams of a registered type
+pub(crate) type UniStreamHandler = Arc<dyn Fn(StreamId, UniStream) + Send + Sync>;
+
+/// Handlers of unidirectional streams, by stream type
+pub(crate) type UniStreamHandlers = HashMap<u64, UniStreamHandler>;
+
+/// Decides what becomes of the incoming unidirectional streams of unknown type
+pub(crate) type UnknownStreamHandler = Arc<dyn Fn(u64) -> UnknownStreamAction + Send + Sync>;
 
 /// Configures the HTTP/3 connection
-#[derive(Debug, Clone, Copy)]
+#[derive(Clone)]
 #[non_exhaustive]
 pub struct Config {
     /// Just like in HTTP/2, HTTP/3 also uses the concept of "grease"
@@ -15,6 +33,15 @@ pub struct Config {
     #[cfg(test)]
     pub(crate) send_settings: bool,
 
+    /// Decides what becomes of incoming unidirectional streams of unknown type
+    pub(crate) unknown_uni_stream: UnknownStreamHandler,
+    /// Number of unknown unidirectional streams discarded at once before closing the connection
+    pub(crate) max_unknown_uni_streams: usize,
+    /// Receives the frames of unknown type arriving on request streams, skipped when unset
+    pub(crate) on_extension_frame: Option<ExtensionHandler>,
+    /// Unidirectional stream types handled by the application
+    pub(crate) uni_stream_handlers: UniStreamHandlers,
+
     /// HTTP/3 Settings
     pub settings: Settings,
 }
@@ -69,6 +96,25 @@ impl From<&frame::Settings> for Settings {
     }
 }
 
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("Config");
+        debug.field("send_grease", &self.send_grease);
+        #[cfg(test)]
+        debug.field("send_settings", &self.send_settings);
+        debug
+            .field("unknown_uni_stream", &"...")
+            .field("max_unknown_uni_streams", &self.max_unknown_uni_streams)
+            .field(
+                "on_extension_frame",
+                &self.on_extension_frame.as_ref().map(|_| "..."),
+            )
+            .field("uni_stream_handlers", &self.uni_stream_handlers.keys())
+            .field("settings", &self.settings)
+            .finish()
+    }
+}
+
 impl TryFrom<Config> for frame::Settings {
     type Error = frame::SettingsError;
     fn try_from(value: Config) -> Result<Self, Self::Error> {
@@ -78,6 +124,10 @@ impl TryFrom<Config> for frame::Settings {
             send_grease,
             #[cfg(test)]
                 send_settings: _,
+            unknown_uni_stream: _,
+            max_unknown_uni_streams: _,
+            on_extension_frame: _,
+            uni_stream_handlers: _,
             settings:
                 Settings {
                     max_field_section_size,
@@ -113,10 +163,7 @@ impl TryFrom<Config> for frame::Settings {
             frame::SettingId::MAX_HEADER_LIST_SIZE,
             max_field_section_size,
         )?;
-        settings.insert(
-            frame::SettingId::ENABLE_CONNECT_PROTOCOL,
-            enable_extended_connect as u64,
-        )?;
+        settings.enable_connect_protocol(enable_extended_connect)?;
         settings.insert(
             frame::SettingId::ENABLE_WEBTRANSPORT,
             enable_webtransport as u64,
@@ -170,7 +217,55 @@ impl Default for Config {
             send_grease: true,
             #[cfg(test)]
             send_settings: true,
+            unknown_uni_stream: Arc::new(stop_unknown_stream),
+            max_unknown_uni_streams: 64,
+            on_extension_frame: None,
+            uni_stream_handlers: HashMap::new(),
             settings: Default::default(),
         }
     }
 }
+
+impl Config {
+    /// Hands the incoming unidirectional streams of type `ty` to `handler`
+    ///
+    /// Fails for the stream types HTTP/3 and QPACK depend on.
+    pub(crate) fn register_uni_stream_handler(
+        &mut self,
+        ty: VarInt,
+        handler: impl Fn(StreamId, UniStream) + Send + Sync + 'static,
+    ) -> Result<(), Error> {
+        let ty = ty.into_inner();
+        let critical = [
+            StreamType::CONTROL,
+            StreamType::PUSH,
+            StreamType::ENCODER,
+            StreamType::DECODER,
+        ];
+        if critical.iter().any(|t| t.value() == ty) {
+            return Err(Error::forbidden_stream_type(ty));
+        }
+        self.uni_stream_handlers.insert(ty, Arc::new(handler));
+        Ok(())
+    }
+}
+
+/// What to do with an incoming unidirectional stream of unknown type
+///
+/// Streams of reserved types are always ignored, and are not subject to this.
+///
+/// See: <https://www.rfc-editor.org/rfc/rfc9114#section-6.2>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum UnknownStreamAction {
+    /// Abort reading with the given code, then drop the stream
+    StopSending(Code),
+    /// Drop the stream, leaving it to the QUIC implementation to stop it
+    Drop,
+    /// Read and discard the data until the peer ends the stream
+    Discard,
+}
+
+fn stop_unknown_stream(_: u64) -> UnknownStreamAction {
+    UnknownStreamAction::StopSending(Code::H3_STREAM_CREATION_ERROR)
+}
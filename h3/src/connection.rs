@@ -1,6 +1,9 @@
 use std::{
     convert::TryFrom,
-    sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
     task::{Context, Poll},
 };
 
@@ -11,18 +14,24 @@ use stream::WriteBuf;
 use tracing::{trace, warn};
 
 use crate::{
-    config::{Config, Settings},
-    error::{Code, Error},
-    frame::FrameStream,
+    config::{Config, Settings, UnknownStreamAction},
+    error::{Code, Error, ErrorLevel, Kind},
+    frame::{
+        AppliedSettings, FrameStream, FrameStreamError, LengthDelimitedReader, RequestGrammar,
+    },
     proto::{
-        frame::{self, Frame, PayloadLen},
+        frame::{self, ExtensionFrame, Frame, PayloadLen},
         headers::Header,
-        stream::StreamType,
+        push::{PushId, PushIdLimit},
+        stream::{StreamId, StreamType},
         varint::VarInt,
     },
     qpack,
-    quic::{self, SendStream as _},
-    stream::{self, AcceptRecvStream, AcceptedRecvStream, BufRecvStream, UniStreamHeader},
+    quic::{self, RecvStream as _, SendStream as _},
+    stream::{
+        self, AcceptedRecvStream, BufRecvStream, CriticalStreams, UniStreamDispatcher,
+        UniStreamForwarder, UniStreamHeader,
+    },
     webtransport::SessionId,
 };
 
@@ -130,9 +139,17 @@ where
     /// implementation to choose what stream or datagram to discard.
     accepted_streams: AcceptedStreams<C, B>,
 
-    pending_recv_streams: Vec<AcceptRecvStream<C::RecvStream, B>>,
+    pending_recv_streams: Vec<UniStreamDispatcher<C::RecvStream, B>>,
+    // Control and QPACK streams received so far
+    critical_recv_streams: CriticalStreams,
+    // Streams of unknown type whose data is discarded until they end
+    discarded_uni_streams: Vec<BufRecvStream<C::RecvStream, B>>,
+    // Streams of registered types, being passed to the application
+    registered_uni_streams: Vec<UniStreamForwarder<C::RecvStream, B>>,
 
     got_peer_settings: bool,
+    // Raised by the MAX_PUSH_ID frames a client sends, which this one never does
+    pub(super) push_id_limit: PushIdLimit,
     pub send_grease_frame: bool,
     pub config: Config,
 }
@@ -148,7 +165,7 @@ where
             return Ok(());
         }
 
-        let settings = frame::Settings::try_from(self.config)
+        let settings = frame::Settings::try_from(self.config.clone())
             .map_err(|e| Code::H3_INTERNAL_ERROR.with_cause(e))?;
 
         tracing::debug!("Sending server settings: {:#x?}", settings);
@@ -212,7 +229,11 @@ where
             decoder_recv: None,
             encoder_recv: None,
             pending_recv_streams: Vec::with_capacity(3),
+            critical_recv_streams: CriticalStreams::default(),
+            discarded_uni_streams: Vec::new(),
+            registered_uni_streams: Vec::new(),
             got_peer_settings: false,
+            push_id_limit: PushIdLimit::default(),
             send_grease_frame: config.send_grease,
             config,
             accepted_streams: Default::default(),
@@ -221,7 +242,7 @@ where
         conn_inner.send_settings().await?;
 
         // start a grease stream
-        if config.send_grease {
+        if conn_inner.config.send_grease {
             //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.8
             //= type=implication
             //# Frame types of the format 0x1f * N + 0x21 for non-negative integer
@@ -245,22 +266,53 @@ where
         T: From<VarInt> + PartialOrd<T> + Copy,
         VarInt: From<T>,
     {
-        if let Some(sent_id) = sent_closing {
-            if *sent_id <= max_id {
-                return Ok(());
-            }
-        }
+        future::poll_fn(|cx| self.poll_shutdown(cx, sent_closing, max_id)).await
+    }
 
-        *sent_closing = Some(max_id);
-        self.shared.write("shutdown").closing = true;
+    /// Polls for a GOAWAY frame with `max_id` to be sent, like [`ConnectionInner::shutdown()`]
+    ///
+    /// The frame is handed to the control stream at most once, calling it again after that only
+    /// waits for it to be sent.
+    pub fn poll_shutdown<T>(
+        &mut self,
+        cx: &mut Context<'_>,
+        sent_closing: &mut Option<T>,
+        max_id: T,
+    ) -> Poll<Result<(), Error>>
+    where
+        T: From<VarInt> + PartialOrd<T> + Copy,
+        VarInt: From<T>,
+    {
+        let queued = matches!(sent_closing, Some(sent_id) if *sent_id <= max_id);
+        if !queued {
+            // Data handed over without waiting may still be pending
+            ready!(stream::poll_flush(&mut self.control_send, cx))?;
+
+            *sent_closing = Some(max_id);
+            self.shared.write("shutdown").closing = true;
+
+            //= https://www.rfc-editor.org/rfc/rfc9114#section-3.3
+            //# When either endpoint chooses to close the HTTP/3
+            //# connection, the terminating endpoint SHOULD first send a GOAWAY frame
+            //# (Section 5.2) so that both endpoints can reliably determine whether
+            //# previously sent frames have been processed and gracefully complete or
+            //# terminate any necessary remaining tasks.
+            self.control_send.send_data(Frame::Goaway(max_id.into()))?;
+        }
+        stream::poll_flush_or_reset(&mut self.control_send, cx)
+    }
 
-        //= https://www.rfc-editor.org/rfc/rfc9114#section-3.3
-        //# When either endpoint chooses to close the HTTP/3
-        //# connection, the terminating endpoint SHOULD first send a GOAWAY frame
-        //# (Section 5.2) so that both endpoints can reliably determine whether
-        //# previously sent frames have been processed and gracefully complete or
-        //# terminate any necessary remaining tasks.
-        stream::write(&mut self.control_send, Frame::Goaway(max_id.into())).await
+    /// Sends a frame of extension type `frame_type` on the control stream
+    ///
+    /// Types are checked like for `RequestStream::send_extension_frame()`.
+    pub async fn send_extension_frame(
+        &mut self,
+        frame_type: VarInt,
+        payload: Bytes,
+    ) -> Result<(), Error> {
+        let frame = ExtensionFrame::new(frame_type, payload)
+            .ok_or_else(|| Error::forbidden_frame_type(frame_type.into_inner()))?;
+        stream::write(&mut self.control_send, Frame::Extension(frame)).await
     }
 
     #[allow(missing_docs)]
@@ -294,7 +346,7 @@ where
             match self.conn.poll_accept_recv(cx)? {
                 Poll::Ready(Some(stream)) => self
                     .pending_recv_streams
-                    .push(AcceptRecvStream::new(stream)),
+                    .push(UniStreamDispatcher::new(stream)),
                 Poll::Ready(None) => {
                     return Err(Code::H3_GENERAL_PROTOCOL_ERROR.with_reason(
                         "Connection closed unexpected",
@@ -308,7 +360,7 @@ where
         let mut resolved = vec![];
 
         for (index, pending) in self.pending_recv_streams.iter_mut().enumerate() {
-            match pending.poll_type(cx)? {
+            match pending.poll_type(cx, &self.config.uni_stream_handlers)? {
                 Poll::Ready(()) => resolved.push(index),
                 Poll::Pending => (),
             }
@@ -320,37 +372,44 @@ where
             //# As certain stream types can affect connection state, a recipient
             //# SHOULD NOT discard data from incoming unidirectional streams prior to
             //# reading the stream type.
-            let stream = self
-                .pending_recv_streams
-                .remove(index - removed)
-                .into_stream()?;
+            let stream = match self.pending_recv_streams.remove(index - removed).dispatch(
+                &mut self.critical_recv_streams,
+                &self.config.uni_stream_handlers,
+            ) {
+                Ok(stream) => stream,
+                Err(err) => {
+                    return Err(match err.kind() {
+                        Kind::Application {
+                            code,
+                            reason: Some(reason),
+                            level: ErrorLevel::ConnectionError,
+                        } => self.close(code, reason),
+                        _ => err,
+                    })
+                }
+            };
 
             match stream {
-                //= https://www.rfc-editor.org/rfc/rfc9114#section-6.2.1
-                //# Only one control stream per peer is permitted;
-                //# receipt of a second stream claiming to be a control stream MUST be
-                //# treated as a connection error of type H3_STREAM_CREATION_ERROR.
-                AcceptedRecvStream::Control(s) => {
-                    if self.control_recv.is_some() {
-                        return Err(
-                            self.close(Code::H3_STREAM_CREATION_ERROR, "got two control streams")
-                        );
-                    }
-                    self.control_recv = Some(s);
+                AcceptedRecvStream::Control(s) => self.control_recv = Some(s),
+                enc @ AcceptedRecvStream::Encoder(_) => self.encoder_recv = Some(enc),
+                dec @ AcceptedRecvStream::Decoder(_) => self.decoder_recv = Some(dec),
+                AcceptedRecvStream::Registered(handler, ty, s) => {
+                    let id = s.recv_id();
+                    let (forwarder, stream) = UniStreamForwarder::new(ty, s);
+                    self.registered_uni_streams.push(forwarder);
+                    handler(id, stream);
                 }
-                enc @ AcceptedRecvStream::Encoder(_) => {
-                    if let Some(_prev) = self.encoder_recv.replace(enc) {
-                        return Err(
-                            self.close(Code::H3_STREAM_CREATION_ERROR, "got two encoder streams")
-                        );
-                    }
-                }
-                dec @ AcceptedRecvStream::Decoder(_) => {
-                    if let Some(_prev) = self.decoder_recv.replace(dec) {
-                        return Err(
-                            self.close(Code::H3_STREAM_CREATION_ERROR, "got two decoder streams")
-                        );
+                AcceptedRecvStream::Push(id, s) => {
+                    //= https://www.rfc-editor.org/rfc/rfc9114#section-4.6
+                    //# A client MUST treat receipt of a push stream as a connection error
+                    //# of type H3_ID_ERROR when no MAX_PUSH_ID frame has been sent or when
+                    //# the stream references a push ID that is greater than the maximum
+                    //# push ID.
+                    if s.id().is_push() && !self.push_id_limit.allows(id) {
+                        return Err(self.close(Code::H3_ID_ERROR, format!("push stream of {}", id)));
                     }
+                    // Server push is not implemented
+                    trace!("ignore stream of {}", id)
                 }
                 AcceptedRecvStream::WebTransportUni(id, s)
                     if self.config.settings.enable_webtransport =>
@@ -360,6 +419,41 @@ where
                     self.accepted_streams.wt_uni_streams.push((id, s))
                 }
 
+                //= https://www.rfc-editor.org/rfc/rfc9114#section-6.2
+                //# Recipients of unknown stream types MUST
+                //# either abort reading of the stream or discard incoming data without
+                //# further processing.
+
+                //= https://www.rfc-editor.org/rfc/rfc9114#section-6.2
+                //# If reading is aborted, the recipient SHOULD use
+                //# the H3_STREAM_CREATION_ERROR error code or a reserved error code
+                //# (Section 8.1).
+
+                //= https://www.rfc-editor.org/rfc/rfc9114#section-6.2
+                //= type=implication
+                //# The recipient MUST NOT consider unknown stream types
+                //# to be a connection error of any kind.
+                AcceptedRecvStream::Unknown(ty, mut s) => {
+                    trace!("ignore unknown stream type {:#x}", ty.value());
+                    match (self.config.unknown_uni_stream)(ty.value()) {
+                        UnknownStreamAction::StopSending(code) => s.stop_sending(code.value()),
+                        UnknownStreamAction::Drop => (),
+                        // Only the streams still open count, stopped ones cost nothing
+                        UnknownStreamAction::Discard if poll_discard(&mut s, cx).is_pending() => {
+                            if self.discarded_uni_streams.len()
+                                >= self.config.max_unknown_uni_streams
+                            {
+                                return Err(self.close(
+                                    Code::H3_EXCESSIVE_LOAD,
+                                    "too many streams of unknown type",
+                                ));
+                            }
+                            self.discarded_uni_streams.push(s);
+                        }
+                        UnknownStreamAction::Discard => (),
+                    }
+                }
+
                 //= https://www.rfc-editor.org/rfc/rfc9114#section-6.2.3
                 //= type=implication
                 //# Endpoints MUST NOT consider these streams to have any meaning upon
@@ -368,6 +462,11 @@ where
             }
         }
 
+        self.registered_uni_streams
+            .retain_mut(|s| s.poll_forward(cx).is_pending());
+        self.discarded_uni_streams
+            .retain_mut(|s| poll_discard(s, cx).is_pending());
+
         Ok(())
     }
 
@@ -388,7 +487,18 @@ where
             }
         };
 
-        let recvd = ready!(recv.poll_next(cx))?;
+        let recvd = match ready!(recv.poll_next(cx)) {
+            //= https://www.rfc-editor.org/rfc/rfc9114#section-6.2.1
+            //# If either control
+            //# stream is closed at any point, this MUST be treated as a connection
+            //# error of type H3_CLOSED_CRITICAL_STREAM.
+            Err(FrameStreamError::Quic(e)) if e.is_stream_reset() => {
+                return Poll::Ready(Err(
+                    self.close(Code::H3_CLOSED_CRITICAL_STREAM, "control stream reset")
+                ));
+            }
+            recvd => recvd?,
+        };
 
         let res = match recvd {
             //= https://www.rfc-editor.org/rfc/rfc9114#section-6.2.1
@@ -429,15 +539,10 @@ where
                         Ok(Frame::Settings(settings))
                     }
                     f @ Frame::Goaway(_) => Ok(f),
-                    f @ Frame::CancelPush(_) | f @ Frame::MaxPushId(_) => {
+                    f @ Frame::CancelPush(_)
+                    | f @ Frame::MaxPushId(_)
+                    | f @ Frame::Origin { .. } => {
                         if self.got_peer_settings {
-                            //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.3
-                            //= type=TODO
-                            //# If a CANCEL_PUSH frame is received that
-                            //# references a push ID greater than currently allowed on the
-                            //# connection, this MUST be treated as a connection error of type
-                            //# H3_ID_ERROR.
-
                             Ok(f)
                         } else {
                             //= https://www.rfc-editor.org/rfc/rfc9114#section-6.2.1
@@ -485,10 +590,10 @@ where
     pub(crate) fn process_goaway<T>(
         &mut self,
         recv_closing: &mut Option<T>,
-        id: VarInt,
+        id: T,
     ) -> Result<(), Error>
     where
-        T: From<VarInt> + Copy,
+        T: Copy,
         VarInt: From<T>,
     {
         {
@@ -502,6 +607,7 @@ where
             //# Like the server,
             //# the client MAY send subsequent GOAWAY frames so long as the specified
             //# push ID is no greater than any previously sent value.
+            let (new_id, id) = (id, VarInt::from(id));
             if let Some(prev_id) = recv_closing.map(VarInt::from) {
                 if prev_id < id {
                     //= https://www.rfc-editor.org/rfc/rfc9114#section-5.2
@@ -516,7 +622,7 @@ where
                     ));
                 }
             }
-            *recv_closing = Some(id.into());
+            *recv_closing = Some(new_id);
             if !self.shared.read("connection goaway read").closing {
                 self.shared.write("connection goaway overwrite").closing = true;
             }
@@ -585,29 +691,185 @@ where
     }
 }
 
+/// Reads and drops the data of `stream`, resolving once it ends or fails
+fn poll_discard<S, B>(stream: &mut BufRecvStream<S, B>, cx: &mut Context<'_>) -> Poll<()>
+where
+    S: quic::RecvStream,
+{
+    loop {
+        match ready!(stream.poll_data(cx)) {
+            Ok(Some(_)) => (),
+            Ok(None) | Err(_) => return Poll::Ready(()),
+        }
+    }
+}
+
 #[allow(missing_docs)]
 pub struct RequestStream<S, B> {
-    pub(super) stream: FrameStream<S, B>,
+    // Only taken out by `split()`, which consumes the stream
+    stream: Option<FrameStream<S, B>>,
     pub(super) trailers: Option<Bytes>,
+    // Body received but not yet returned by `poll_recv_message()`
+    messages: LengthDelimitedReader,
     pub(super) conn_state: SharedStateRef,
     pub(super) max_field_section_size: u64,
     send_grease_frame: bool,
+    // The sending side has been finished or reset
+    finished: bool,
+    reset_on_drop: Option<Code>,
+    // Set when `S` can be reset, which `Drop` cannot require
+    reset: Option<ResetFn<S, B>>,
+    // DATA frames received, and how many are allowed
+    data_frames: usize,
+    max_data_frames: Option<usize>,
+    // Order of the frames received so far
+    grammar: RequestGrammar,
+    // Order of the frames sent so far
+    sent: RequestGrammar,
+    // Told when a direction is done, shared with the other half once split
+    end: Option<Arc<RequestEnd>>,
 }
 
+type ResetFn<S, B> = fn(&mut FrameStream<S, B>, u64);
+
 impl<S, B> RequestStream<S, B> {
     #[allow(missing_docs)]
     pub fn new(
-        stream: FrameStream<S, B>,
+        mut stream: FrameStream<S, B>,
         max_field_section_size: u64,
         conn_state: SharedStateRef,
         grease: bool,
     ) -> Self {
+        stream.apply_settings(&AppliedSettings {
+            max_field_section_size,
+        });
         Self {
-            stream,
+            stream: Some(stream),
             conn_state,
             max_field_section_size,
             trailers: None,
+            messages: LengthDelimitedReader::default(),
             send_grease_frame: grease,
+            finished: false,
+            reset_on_drop: None,
+            reset: None,
+            data_frames: 0,
+            max_data_frames: None,
+            grammar: RequestGrammar::default(),
+            sent: RequestGrammar::default(),
+            end: None,
+        }
+    }
+
+    /// Accounts for the HEADERS frame sent before the stream was wrapped
+    pub(crate) fn headers_sent(&mut self) {
+        self.sent = RequestGrammar::after_headers();
+    }
+
+    /// Accounts for the HEADERS frame received before the stream was wrapped
+    pub(crate) fn headers_received(&mut self) {
+        self.grammar = RequestGrammar::after_headers();
+    }
+
+    /// Accounts for the HEADERS frame received last being an interim response
+    pub(crate) fn interim_received(&mut self) {
+        self.grammar.on_interim_headers();
+    }
+
+    /// Accounts for the HEADERS frame sent last being an interim response
+    pub(crate) fn interim_sent(&mut self) {
+        self.sent.on_interim_headers();
+    }
+
+    /// Reports the end of the request to `end` once both directions are done
+    pub(crate) fn track_end(&mut self, end: Arc<RequestEnd>) {
+        self.end = Some(end);
+    }
+
+    fn send_done(&self) {
+        if let Some(end) = &self.end {
+            end.done(RequestEnd::SEND);
+        }
+    }
+
+    fn recv_done(&self) {
+        if let Some(end) = &self.end {
+            end.done(RequestEnd::RECV);
+        }
+    }
+
+    pub(crate) fn stream(&self) -> &FrameStream<S, B> {
+        self.stream.as_ref().expect("stream taken by split")
+    }
+
+    pub(crate) fn stream_mut(&mut self) -> &mut FrameStream<S, B> {
+        self.stream.as_mut().expect("stream taken by split")
+    }
+
+    /// Sets the code used to reset the sending side if dropped before being finished
+    ///
+    /// `None` leaves the stream open on drop, to the discretion of the QUIC implementation.
+    pub fn reset_on_drop(&mut self, code: Option<Code>) {
+        self.reset_on_drop = code;
+    }
+
+    /// Limits how many DATA frames the body may be made of, whatever their size
+    ///
+    /// `None`, the default, allows any number.
+    pub fn limit_data_frames(&mut self, max: Option<usize>) {
+        self.max_data_frames = max;
+    }
+
+    pub(crate) fn check_not_finished(&self) -> Result<(), Error> {
+        if self.finished {
+            return Err(Error::already_finished());
+        }
+        Ok(())
+    }
+
+    /// Checks `frame` can be sent next, accounting for it if so
+    ///
+    /// The message must be sent as HEADERS, then DATA, then optionally trailing HEADERS, as the
+    /// peer would otherwise close the connection. Frames out of this order fail with
+    /// `H3_INTERNAL_ERROR` at the stream level, and are not to be sent.
+    pub(crate) fn check_send_order<T>(&mut self, frame: &Frame<T>) -> Result<(), Error> {
+        self.check_not_finished()?;
+        self.sent.on_frame(frame).map_err(|e| {
+            Code::H3_INTERNAL_ERROR.with_reason(format!("sent {}", e), ErrorLevel::StreamError)
+        })
+    }
+
+    /// Checks the HEADERS `frame` can be sent next, as trailers or as a response
+    ///
+    /// The grammar takes any HEADERS frame following the response for trailers, so this also
+    /// keeps trailers from being sent first, and a second final response from being sent at all.
+    pub(crate) fn check_send_headers<T>(
+        &mut self,
+        frame: &Frame<T>,
+        trailers: bool,
+    ) -> Result<(), Error> {
+        self.check_not_finished()?;
+        if self.sent.has_headers() != trailers {
+            let reason = if trailers {
+                "sent trailers before HEADERS"
+            } else {
+                "sent a second final response"
+            };
+            return Err(Code::H3_INTERNAL_ERROR.with_reason(reason, ErrorLevel::StreamError));
+        }
+        self.check_send_order(frame)
+    }
+}
+
+impl<S, B> Drop for RequestStream<S, B> {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        if let (Some(reset), Some(code), Some(stream)) =
+            (self.reset, self.reset_on_drop, self.stream.as_mut())
+        {
+            reset(stream, code.value());
         }
     }
 }
@@ -627,104 +889,142 @@ where
         &mut self,
         cx: &mut Context<'_>,
     ) -> Poll<Result<Option<impl Buf>, Error>> {
-        if !self.stream.has_data() {
-            let frame = self
-                .stream
-                .poll_next(cx)
-                .map_err(|e| self.maybe_conn_err(e))?;
+        if self.trailers.is_some() {
+            // The body already ended with the trailers
+            return Poll::Ready(Ok(None));
+        }
 
-            match ready!(frame) {
-                Some(Frame::Data { .. }) => (),
+        // Empty DATA frames are skipped, they must not end the body
+        while !self.stream().has_data() {
+            match ready!(self.poll_next_frame(cx))? {
+                Some(Frame::Data { .. }) => self.count_data_frame()?,
                 Some(Frame::Headers(encoded)) => {
                     self.trailers = Some(encoded);
                     return Poll::Ready(Ok(None));
                 }
+                Some(_) => unreachable!("only DATA and HEADERS frames are returned"),
+                None => {
+                    self.recv_done();
+                    return Poll::Ready(Ok(None));
+                }
+            }
+        }
 
-                //= https://www.rfc-editor.org/rfc/rfc9114#section-4.1
-                //# Receipt of an invalid sequence of frames MUST be treated as a
-                //# connection error of type H3_FRAME_UNEXPECTED.
-
-                //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.3
-                //# Receiving a
-                //# CANCEL_PUSH frame on a stream other than the control stream MUST be
-                //# treated as a connection error of type H3_FRAME_UNEXPECTED.
+        self.stream_mut()
+            .poll_data(cx)
+            .map_err(|e| self.maybe_conn_err(e))
+    }
 
-                //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.4
-                //# If an endpoint receives a SETTINGS frame on a different
-                //# stream, the endpoint MUST respond with a connection error of type
-                //# H3_FRAME_UNEXPECTED.
+    /// Receive the next length-delimited message of the body
+    pub fn poll_recv_message(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<Bytes>, Error>> {
+        loop {
+            if let Some(message) = self.messages.next_message() {
+                return Poll::Ready(Ok(Some(message)));
+            }
+            match ready!(self.poll_recv_data(cx))? {
+                Some(mut data) => self.messages.push(&mut data),
+                None if self.messages.is_empty() => return Poll::Ready(Ok(None)),
+                None => {
+                    return Poll::Ready(Err(Code::H3_MESSAGE_ERROR.with_reason(
+                        "body ended in the middle of a message",
+                        ErrorLevel::StreamError,
+                    )))
+                }
+            }
+        }
+    }
 
-                //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.6
-                //# A client MUST treat a GOAWAY frame on a stream other than
-                //# the control stream as a connection error of type H3_FRAME_UNEXPECTED.
+    /// Receives the next DATA or HEADERS frame, checking the frames follow [`RequestGrammar`]
+    ///
+    /// Other frames the grammar allows, such as PUSH_PROMISE, are not handled on request streams
+    /// and fail with `H3_FRAME_UNEXPECTED`.
+    pub(crate) fn poll_next_frame(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<Frame<PayloadLen>>, Error>> {
+        let frame = match ready!(self.stream_mut().poll_next(cx)) {
+            Ok(Some(frame)) => frame,
+            Ok(None) => return Poll::Ready(Ok(None)),
+            Err(e) => return Poll::Ready(Err(self.maybe_conn_err(e))),
+        };
+        if let Err(e) = self.grammar.on_frame(&frame) {
+            return Poll::Ready(Err(self.maybe_conn_err(FrameStreamError::from(e))));
+        }
+        match frame {
+            Frame::Data(_) | Frame::Headers(_) => Poll::Ready(Ok(Some(frame))),
+            _ => Poll::Ready(Err(Code::H3_FRAME_UNEXPECTED.into())),
+        }
+    }
 
-                //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.7
-                //# The MAX_PUSH_ID frame is always sent on the control stream.  Receipt
-                //# of a MAX_PUSH_ID frame on any other stream MUST be treated as a
-                //# connection error of type H3_FRAME_UNEXPECTED.
-                Some(_) => return Poll::Ready(Err(Code::H3_FRAME_UNEXPECTED.into())),
-                None => return Poll::Ready(Ok(None)),
+    // A body split in many tiny DATA frames costs far more to process than its size suggests
+    fn count_data_frame(&mut self) -> Result<(), Error> {
+        self.data_frames += 1;
+        match self.max_data_frames {
+            Some(max) if self.data_frames > max => {
+                self.stream_mut().stop_sending(Code::H3_EXCESSIVE_LOAD);
+                Err(Code::H3_EXCESSIVE_LOAD.with_reason(
+                    format!("more than {} DATA frames", max),
+                    ErrorLevel::StreamError,
+                ))
             }
+            _ => Ok(()),
         }
-
-        self.stream
-            .poll_data(cx)
-            .map_err(|e| self.maybe_conn_err(e))
     }
+
     /// Receive some of the request body.
+    ///
+    /// This is cancel safe: what is received is kept in the stream, so dropping the future before
+    /// it completes loses nothing, and the next call carries on.
     pub async fn recv_data(&mut self) -> Result<Option<impl Buf>, Error> {
         future::poll_fn(|cx| self.poll_recv_data(cx)).await
     }
 
+    /// Receive the next length-delimited message of the body
+    pub async fn recv_message(&mut self) -> Result<Option<Bytes>, Error> {
+        future::poll_fn(|cx| self.poll_recv_message(cx)).await
+    }
+
     /// Receive trailers
+    ///
+    /// This is cancel safe, like [`RequestStream::recv_data()`].
     pub async fn recv_trailers(&mut self) -> Result<Option<HeaderMap>, Error> {
+        future::poll_fn(|cx| self.poll_recv_trailers(cx)).await
+    }
+
+    /// Poll for the trailers
+    pub fn poll_recv_trailers(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Error>> {
         let mut trailers = if let Some(encoded) = self.trailers.take() {
             encoded
         } else {
-            let frame = future::poll_fn(|cx| self.stream.poll_next(cx))
-                .await
-                .map_err(|e| self.maybe_conn_err(e))?;
-            match frame {
+            match ready!(self.poll_next_frame(cx))? {
                 Some(Frame::Headers(encoded)) => encoded,
-
-                //= https://www.rfc-editor.org/rfc/rfc9114#section-4.1
-                //# Receipt of an invalid sequence of frames MUST be treated as a
-                //# connection error of type H3_FRAME_UNEXPECTED.
-
-                //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.3
-                //# Receiving a
-                //# CANCEL_PUSH frame on a stream other than the control stream MUST be
-                //# treated as a connection error of type H3_FRAME_UNEXPECTED.
-
-                //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.4
-                //# If an endpoint receives a SETTINGS frame on a different
-                //# stream, the endpoint MUST respond with a connection error of type
-                //# H3_FRAME_UNEXPECTED.
-
-                //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.6
-                //# A client MUST treat a GOAWAY frame on a stream other than
-                //# the control stream as a connection error of type H3_FRAME_UNEXPECTED.
-
-                //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.7
-                //# The MAX_PUSH_ID frame is always sent on the control stream.  Receipt
-                //# of a MAX_PUSH_ID frame on any other stream MUST be treated as a
-                //# connection error of type H3_FRAME_UNEXPECTED.
-                Some(_) => return Err(Code::H3_FRAME_UNEXPECTED.into()),
-                None => return Ok(None),
+                // The body must be received before the trailers
+                Some(_) => return Poll::Ready(Err(Code::H3_FRAME_UNEXPECTED.into())),
+                None => {
+                    self.recv_done();
+                    return Poll::Ready(Ok(None));
+                }
             }
         };
 
-        if !self.stream.is_eos() {
-            // Get the trailing frame
-            let trailing_frame = future::poll_fn(|cx| self.stream.poll_next(cx))
-                .await
-                .map_err(|e| self.maybe_conn_err(e))?;
-
-            if trailing_frame.is_some() {
-                // if it's not unknown or reserved, fail.
-                return Err(Code::H3_FRAME_UNEXPECTED.into());
-            }
+        if !self.stream().is_eos() {
+            // Wait for the end of the stream, keeping the trailers until it arrives. Any frame
+            // after the trailers is refused by the grammar.
+            match self.poll_next_frame(cx) {
+                Poll::Ready(frame) => frame?,
+                Poll::Pending => {
+                    self.trailers = Some(trailers);
+                    return Poll::Pending;
+                }
+            };
         }
+        self.recv_done();
 
         let qpack::Decoded { fields, .. } =
             match qpack::decode_stateless(&mut trailers, self.max_field_section_size) {
@@ -732,21 +1032,22 @@ where
                 //# An HTTP/3 implementation MAY impose a limit on the maximum size of
                 //# the message header it will accept on an individual HTTP message.
                 Err(qpack::DecoderError::HeaderTooLong(cancel_size)) => {
-                    return Err(Error::header_too_big(
+                    return Poll::Ready(Err(Error::header_too_big(
                         cancel_size,
                         self.max_field_section_size,
-                    ))
+                    )))
                 }
                 Ok(decoded) => decoded,
-                Err(e) => return Err(e.into()),
+                Err(e) => return Poll::Ready(Err(e.into())),
             };
 
-        Ok(Some(Header::try_from(fields)?.into_fields()))
+        Poll::Ready(Ok(Some(Header::try_from(fields)?.into_fields())))
     }
 
     #[allow(missing_docs)]
     pub fn stop_sending(&mut self, err_code: Code) {
-        self.stream.stop_sending(err_code);
+        self.stream_mut().stop_sending(err_code);
+        self.recv_done();
     }
 }
 
@@ -758,8 +1059,28 @@ where
     /// Send some data on the response body.
     pub async fn send_data(&mut self, buf: B) -> Result<(), Error> {
         let frame = Frame::Data(buf);
+        self.check_send_order(&frame)?;
+
+        stream::write(self.stream_mut(), frame)
+            .await
+            .map_err(|e| self.maybe_conn_err(e))?;
+        Ok(())
+    }
 
-        stream::write(&mut self.stream, frame)
+    /// Send a frame of extension type `frame_type`, followed by `payload`
+    ///
+    /// Peers which do not know the type ignore the frame. Types defined or reserved by HTTP/3
+    /// are rejected with an error, and nothing is sent: the stream is left as it was.
+    pub async fn send_extension_frame(
+        &mut self,
+        frame_type: VarInt,
+        payload: Bytes,
+    ) -> Result<(), Error> {
+        self.check_not_finished()?;
+        let frame = ExtensionFrame::new(frame_type, payload)
+            .ok_or_else(|| Error::forbidden_frame_type(frame_type.into_inner()))?;
+
+        stream::write(self.stream_mut(), Frame::Extension(frame))
             .await
             .map_err(|e| self.maybe_conn_err(e))?;
         Ok(())
@@ -767,6 +1088,18 @@ where
 
     /// Send a set of trailers to end the request.
     pub async fn send_trailers(&mut self, trailers: HeaderMap) -> Result<(), Error> {
+        self.check_not_finished()?;
+        let frame = self.trailers_frame(trailers)?;
+        self.check_send_headers(&frame, true)?;
+        stream::write(self.stream_mut(), frame)
+            .await
+            .map_err(|e| self.maybe_conn_err(e))?;
+
+        Ok(())
+    }
+
+    /// Encodes `trailers` in a HEADERS frame, checking the peer accepts its size
+    pub(crate) fn trailers_frame(&self, trailers: HeaderMap) -> Result<Frame<B>, Error> {
         //= https://www.rfc-editor.org/rfc/rfc9114#section-4.2
         //= type=TODO
         //# Characters in field names MUST be
@@ -788,31 +1121,56 @@ where
         if mem_size > max_mem_size {
             return Err(Error::header_too_big(mem_size, max_mem_size));
         }
-        stream::write(&mut self.stream, Frame::Headers(block.freeze()))
-            .await
-            .map_err(|e| self.maybe_conn_err(e))?;
-
-        Ok(())
+        Ok(Frame::Headers(block.freeze()))
     }
 
     /// Stops an stream with an error code
     pub fn stop_stream(&mut self, code: Code) {
-        self.stream.reset(code.into());
+        self.stream_mut().reset(code.into());
+        self.finished = true;
+        self.send_done();
     }
 
-    #[allow(missing_docs)]
+    /// Finishes the sending side of the stream
+    ///
+    /// Only the sending direction is closed: frames can still be received, and receiving can
+    /// still be stopped.
+    ///
+    /// Resolves once the FIN has been handed over to the QUIC implementation. This is
+    /// cancel-safe: if the future is dropped, calling `finish()` again resumes where it stopped,
+    /// and the FIN is queued only once. Calling it on a finished stream does nothing.
     pub async fn finish(&mut self) -> Result<(), Error> {
+        future::poll_fn(|cx| self.poll_finish(cx)).await
+    }
+
+    /// Polls for the sending side to be finished, see [`RequestStream::finish()`]
+    pub(crate) fn poll_finish(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        if self.finished {
+            return Poll::Ready(Ok(()));
+        }
+        if !self.sent.has_headers() {
+            return Poll::Ready(Err(Code::H3_INTERNAL_ERROR.with_reason(
+                "finished the stream before sending HEADERS",
+                ErrorLevel::StreamError,
+            )));
+        }
+
+        ready!(stream::poll_flush(self.stream_mut(), cx)).map_err(|e| self.maybe_conn_err(e))?;
         if self.send_grease_frame {
-            // send a grease frame once per Connection
-            stream::write(&mut self.stream, Frame::Grease)
-                .await
-                .map_err(|e| self.maybe_conn_err(e))?;
+            // send a grease frame once per Connection, the flag is cleared first so a
+            // cancelled finish() does not send it again
             self.send_grease_frame = false;
+            self.stream_mut()
+                .send_data(Frame::Grease)
+                .map_err(|e| self.maybe_conn_err(e))?;
+            ready!(stream::poll_flush_or_reset(self.stream_mut(), cx))
+                .map_err(|e| self.maybe_conn_err(e))?;
         }
 
-        future::poll_fn(|cx| self.stream.poll_finish(cx))
-            .await
-            .map_err(|e| self.maybe_conn_err(e))
+        ready!(self.stream_mut().poll_finish(cx)).map_err(|e| self.maybe_conn_err(e))?;
+        self.finished = true;
+        self.send_done();
+        Poll::Ready(Ok(()))
     }
 }
 
@@ -822,28 +1180,120 @@ where
     B: Buf,
 {
     pub(crate) fn split(
-        self,
+        mut self,
     ) -> (
         RequestStream<S::SendStream, B>,
         RequestStream<S::RecvStream, B>,
     ) {
-        let (send, recv) = self.stream.split();
+        let (send, recv) = self.stream.take().expect("stream taken by split").split();
 
         (
             RequestStream {
-                stream: send,
+                stream: Some(send),
                 trailers: None,
+                messages: LengthDelimitedReader::default(),
                 conn_state: self.conn_state.clone(),
                 max_field_section_size: 0,
                 send_grease_frame: self.send_grease_frame,
+                finished: self.finished,
+                reset_on_drop: Some(Code::H3_REQUEST_CANCELLED),
+                reset: Some(|stream, code| stream.reset(code)),
+                data_frames: 0,
+                max_data_frames: None,
+                grammar: RequestGrammar::default(),
+                sent: self.sent,
+                end: self.end.clone(),
             },
             RequestStream {
-                stream: recv,
-                trailers: self.trailers,
-                conn_state: self.conn_state,
+                stream: Some(recv),
+                trailers: self.trailers.take(),
+                messages: std::mem::take(&mut self.messages),
+                conn_state: self.conn_state.clone(),
                 max_field_section_size: self.max_field_section_size,
                 send_grease_frame: self.send_grease_frame,
+                finished: self.finished,
+                reset_on_drop: None,
+                reset: None,
+                data_frames: self.data_frames,
+                max_data_frames: self.max_data_frames,
+                grammar: self.grammar,
+                sent: RequestGrammar::default(),
+                end: self.end.take(),
             },
         )
     }
 }
+
+/// Reports the end of a request, once both directions of its stream are done
+///
+/// Shared by the halves of a split stream. Should a direction never be done, the end is
+/// reported when the last handle on the request is dropped instead.
+pub(crate) struct RequestEnd {
+    done: AtomicU8,
+    on_end: Box<dyn Fn() + Send + Sync>,
+}
+
+impl RequestEnd {
+    const SEND: u8 = 0b01;
+    const RECV: u8 = 0b10;
+
+    pub(crate) fn new(on_end: impl Fn() + Send + Sync + 'static) -> Self {
+        Self {
+            done: AtomicU8::new(0),
+            on_end: Box::new(on_end),
+        }
+    }
+
+    fn done(&self, direction: u8) {
+        let both = Self::SEND | Self::RECV;
+        // Only the call completing the pair reports the end
+        let before = self.done.fetch_or(direction, Ordering::AcqRel);
+        if before != both && before | direction == both {
+            (self.on_end)();
+        }
+    }
+}
+
+impl Drop for RequestEnd {
+    fn drop(&mut self) {
+        if *self.done.get_mut() != Self::SEND | Self::RECV {
+            (self.on_end)();
+        }
+    }
+}
+
+/// The id carried by a GOAWAY frame, whose type depends on the side sending it
+///
+/// A server sends the id of a client-initiated bidirectional stream, while a client sends a
+/// push id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Goaway(VarInt);
+
+impl Goaway {
+    /// Wraps the id of a received GOAWAY frame
+    pub fn new(id: VarInt) -> Self {
+        Self(id)
+    }
+
+    /// The last request stream id the server might process, for a GOAWAY sent by a server
+    ///
+    /// Fails with a connection error of type `H3_ID_ERROR` if the id is not the one of a
+    /// request stream.
+    pub fn as_stream_id(&self) -> Result<StreamId, Error> {
+        let id = StreamId::from(self.0);
+        if !id.is_request() {
+            return Err(Code::H3_ID_ERROR.with_reason(
+                format!("non-request StreamId in a GoAway frame: {}", self.0),
+                ErrorLevel::ConnectionError,
+            ));
+        }
+        Ok(id)
+    }
+
+    /// The last push id the client might accept, for a GOAWAY sent by a client
+    ///
+    /// Any value is a valid push id.
+    pub fn as_push_id(&self) -> PushId {
+        PushId::from(self.0)
+    }
+}
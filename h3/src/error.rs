@@ -30,6 +30,25 @@ impl Code {
     pub fn value(&self) -> u64 {
         self.code
     }
+
+    /// Numerical error code, as [`Code::value()`]
+    pub fn to_u64(self) -> u64 {
+        self.value()
+    }
+
+    /// The code received from the peer as `code`
+    ///
+    /// The value is kept as is, as new codes can be used without negotiation. Codes this crate
+    /// does not know, reserved ones included, are told by [`Code::is_known()`].
+    pub fn from_u64(code: u64) -> Code {
+        //= https://www.rfc-editor.org/rfc/rfc9114#section-8
+        //= type=TODO
+        //# Because new error codes can be defined without negotiation (see
+        //# Section 9), use of an error code in an unexpected context or receipt
+        //# of an unknown error code MUST be treated as equivalent to
+        //# H3_NO_ERROR.
+        Code { code }
+    }
 }
 
 impl PartialEq<u64> for Code {
@@ -80,6 +99,16 @@ pub enum Kind {
     // Currently in a graceful shutdown procedure
     Closing,
     Timeout,
+    // Sending on a stream which has already been finished
+    AlreadyFinished,
+    // Sending an extension frame of a type defined or reserved by HTTP/3
+    ForbiddenFrameType(u64),
+    // Registering a handler for a stream type HTTP/3 handles itself
+    ForbiddenStreamType(u64),
+    // A request could not be started without waiting
+    NotReady,
+    // The server rejected the 0-RTT data a request was sent in
+    EarlyDataRejected,
 }
 
 // ===== impl Code =====
@@ -98,6 +127,21 @@ macro_rules! codes {
         )+
         }
 
+        impl Code {
+            /// Whether this code is one of the registered codes above
+            ///
+            /// A code received from the peer may be unknown, reserved codes being sent on purpose.
+            /// Such a code is to be treated as [`Code::H3_NO_ERROR`].
+            pub fn is_known(&self) -> bool {
+                match self.code {
+                $(
+                    $num => true,
+                )+
+                    _ => false,
+                }
+            }
+        }
+
         impl fmt::Debug for Code {
             fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
                 match self.code {
@@ -232,6 +276,28 @@ impl Error {
         }
     }
 
+    /// Returns the code to stop receiving, or to reset, a stream with after this error
+    ///
+    /// This is the error code if there is one. Otherwise, a closed connection gives
+    /// [`Code::H3_NO_ERROR`], errors caused by the request give [`Code::H3_REQUEST_CANCELLED`]
+    /// and the others [`Code::H3_INTERNAL_ERROR`].
+    pub fn stop_sending_code(&self) -> u64 {
+        let code = match self.inner.kind {
+            Kind::Application { code, .. } => code,
+            Kind::Closed => Code::H3_NO_ERROR,
+            Kind::Closing
+            | Kind::HeaderTooBig { .. }
+            | Kind::NotReady
+            | Kind::EarlyDataRejected => Code::H3_REQUEST_CANCELLED,
+            Kind::Transport(_)
+            | Kind::Timeout
+            | Kind::AlreadyFinished
+            | Kind::ForbiddenFrameType(_)
+            | Kind::ForbiddenStreamType(_) => Code::H3_INTERNAL_ERROR,
+        };
+        code.value()
+    }
+
     /// returns the [`ErrorLevel`] of an [`Error`]
     /// This indicates weather a accept loop should continue.
     pub fn get_error_level(&self) -> ErrorLevel {
@@ -241,6 +307,10 @@ impl Error {
                 reason: _,
                 level,
             } => level,
+            Kind::AlreadyFinished
+            | Kind::ForbiddenFrameType(_)
+            | Kind::NotReady
+            | Kind::EarlyDataRejected => ErrorLevel::StreamError,
             // return Connection error on other kinds
             _ => ErrorLevel::ConnectionError,
         }
@@ -266,6 +336,42 @@ impl Error {
         Self::new(Kind::Closed)
     }
 
+    pub(crate) fn already_finished() -> Self {
+        Self::new(Kind::AlreadyFinished)
+    }
+
+    pub(crate) fn forbidden_frame_type(ty: u64) -> Self {
+        Self::new(Kind::ForbiddenFrameType(ty))
+    }
+
+    pub(crate) fn forbidden_stream_type(ty: u64) -> Self {
+        Self::new(Kind::ForbiddenStreamType(ty))
+    }
+
+    pub(crate) fn not_ready() -> Self {
+        Self::new(Kind::NotReady)
+    }
+
+    /// Whether a request failed to start because it would have had to wait
+    ///
+    /// The request was not sent, it can be retried once
+    /// [`SendRequest::poll_ready()`](crate::client::SendRequest::poll_ready) resolves.
+    pub fn is_not_ready(&self) -> bool {
+        matches!(self.inner.kind, Kind::NotReady)
+    }
+
+    pub(crate) fn early_data_rejected() -> Self {
+        Self::new(Kind::EarlyDataRejected)
+    }
+
+    /// Whether a request sent in 0-RTT was lost because the server rejected early data
+    ///
+    /// The request can be sent again with
+    /// [`SendRequest::confirm_early()`](crate::client::SendRequest::confirm_early).
+    pub fn is_early_data_rejected(&self) -> bool {
+        matches!(self.inner.kind, Kind::EarlyDataRejected)
+    }
+
     pub(crate) fn is_closed(&self) -> bool {
         if let Kind::Closed = self.inner.kind {
             return true;
@@ -297,6 +403,21 @@ impl fmt::Debug for Error {
             Kind::Timeout => {
                 builder.field("timeout", &true);
             }
+            Kind::AlreadyFinished => {
+                builder.field("already finished", &true);
+            }
+            Kind::ForbiddenFrameType(ty) => {
+                builder.field("forbidden frame type", &ty);
+            }
+            Kind::ForbiddenStreamType(ty) => {
+                builder.field("forbidden stream type", &ty);
+            }
+            Kind::NotReady => {
+                builder.field("not ready", &true);
+            }
+            Kind::EarlyDataRejected => {
+                builder.field("early data rejected", &true);
+            }
             Kind::Application {
                 code, ref reason, ..
             } => {
@@ -333,6 +454,15 @@ impl fmt::Display for Error {
             Kind::Closing => write!(f, "connection is gracefully closing")?,
             Kind::Transport(ref e) => write!(f, "quic transport error: {}", e)?,
             Kind::Timeout => write!(f, "timeout",)?,
+            Kind::AlreadyFinished => write!(f, "stream is already finished")?,
+            Kind::ForbiddenFrameType(ty) => {
+                write!(f, "frame type {:#x} is not available to extensions", ty)?
+            }
+            Kind::ForbiddenStreamType(ty) => {
+                write!(f, "stream type {:#x} is not available to extensions", ty)?
+            }
+            Kind::NotReady => write!(f, "no request can be started without waiting")?,
+            Kind::EarlyDataRejected => write!(f, "request lost with rejected early data")?,
             Kind::Application {
                 code, ref reason, ..
             } => {
@@ -383,7 +513,8 @@ impl From<qpack::EncoderError> for Error {
 impl From<qpack::DecoderError> for Error {
     fn from(e: qpack::DecoderError) -> Self {
         match e {
-            qpack::DecoderError::InvalidStaticIndex(_) => {
+            qpack::DecoderError::InvalidStaticIndex(_)
+            | qpack::DecoderError::InvalidInsertCount(_) => {
                 Self::from(Code::QPACK_DECOMPRESSION_FAILED).with_cause(e)
             }
             _ => Self::from(Code::QPACK_DECODER_STREAM_ERROR).with_cause(e),
@@ -414,6 +545,50 @@ impl From<frame::FrameStreamError> for Error {
             frame::FrameStreamError::UnexpectedEnd => Code::H3_FRAME_ERROR
                 .with_reason("received incomplete frame", ErrorLevel::ConnectionError),
 
+            frame::FrameStreamError::HeaderTooBig { size, max_size } => {
+                Error::header_too_big(size, max_size)
+            }
+
+            frame::FrameStreamError::DataNotDecoded => Code::H3_INTERNAL_ERROR.with_reason(
+                "polled data before decoding the DATA frame",
+                ErrorLevel::StreamError,
+            ),
+
+            frame::FrameStreamError::DataNotRead => Code::H3_INTERNAL_ERROR.with_reason(
+                "polled the next frame before reading the DATA payload",
+                ErrorLevel::StreamError,
+            ),
+
+            frame::FrameStreamError::ExtensionRejected(code) => {
+                code.with_reason("extension frame rejected", ErrorLevel::StreamError)
+            }
+
+            //= https://www.rfc-editor.org/rfc/rfc9114#section-4.1
+            //# Receipt of an invalid sequence of frames MUST be treated as a
+            //# connection error of type H3_FRAME_UNEXPECTED.
+            frame::FrameStreamError::Grammar(e) => {
+                Code::H3_FRAME_UNEXPECTED.with_reason(e.to_string(), ErrorLevel::ConnectionError)
+            }
+
+            frame::FrameStreamError::BufferBudgetExceeded => Code::H3_EXCESSIVE_LOAD.with_reason(
+                "received data exceeds the shared buffer budget",
+                ErrorLevel::StreamError,
+            ),
+
+            //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.5
+            //# A client MUST treat
+            //# receipt of a PUSH_PROMISE frame that contains a larger push ID than
+            //# the client has advertised as a connection error of H3_ID_ERROR.
+            frame::FrameStreamError::PushIdNotAllowed(id) => Code::H3_ID_ERROR.with_reason(
+                format!("received a PUSH_PROMISE for {} above the limit", id),
+                ErrorLevel::ConnectionError,
+            ),
+
+            frame::FrameStreamError::Proto(proto::frame::FrameError::HeaderBlockTooLong {
+                len,
+                max,
+            }) => Error::header_too_big(len as u64, max as u64),
+
             frame::FrameStreamError::Proto(e) => match e {
                 proto::frame::FrameError::InvalidStreamId(_)
                 | proto::frame::FrameError::InvalidPushId(_) => Code::H3_ID_ERROR,
@@ -432,7 +607,11 @@ impl From<frame::FrameStreamError> for Error {
                 //# encodings MUST be verified to be self-consistent; see Section 10.8.
                 proto::frame::FrameError::Incomplete(_)
                 | proto::frame::FrameError::InvalidFrameValue
+                | proto::frame::FrameError::NonMinimalVarInt(_)
                 | proto::frame::FrameError::Malformed => Code::H3_FRAME_ERROR,
+                proto::frame::FrameError::HeaderBlockTooLong { .. } => {
+                    unreachable!("mapped to a header too big error above")
+                }
             }
             .with_cause(e),
         }
@@ -473,13 +652,116 @@ impl From<proto::stream::InvalidStreamId> for Error {
     }
 }
 
+impl From<proto::push::InvalidPushId> for Error {
+    fn from(e: proto::push::InvalidPushId) -> Self {
+        Self::from(Code::H3_ID_ERROR).with_cause(format!("{}", e))
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::Error;
-    use std::mem;
+    use super::{Code, Error};
+    use crate::{
+        frame::FrameStreamError,
+        proto::{
+            frame::{FrameError, SettingsError},
+            push::PushId,
+            stream::InvalidStreamId,
+        },
+    };
+    use std::{convert::TryFrom, mem};
 
     #[test]
     fn test_size_of() {
         assert_eq!(mem::size_of::<Error>(), mem::size_of::<usize>());
     }
+
+    #[test]
+    fn code_round_trip() {
+        let registered = [
+            Code::H3_DATAGRAM_ERROR,
+            Code::H3_NO_ERROR,
+            Code::H3_GENERAL_PROTOCOL_ERROR,
+            Code::H3_INTERNAL_ERROR,
+            Code::H3_STREAM_CREATION_ERROR,
+            Code::H3_CLOSED_CRITICAL_STREAM,
+            Code::H3_FRAME_UNEXPECTED,
+            Code::H3_FRAME_ERROR,
+            Code::H3_EXCESSIVE_LOAD,
+            Code::H3_ID_ERROR,
+            Code::H3_SETTINGS_ERROR,
+            Code::H3_MISSING_SETTINGS,
+            Code::H3_REQUEST_REJECTED,
+            Code::H3_REQUEST_CANCELLED,
+            Code::H3_REQUEST_INCOMPLETE,
+            Code::H3_MESSAGE_ERROR,
+            Code::H3_CONNECT_ERROR,
+            Code::H3_VERSION_FALLBACK,
+            Code::QPACK_DECOMPRESSION_FAILED,
+            Code::QPACK_ENCODER_STREAM_ERROR,
+            Code::QPACK_DECODER_STREAM_ERROR,
+        ];
+        for code in registered {
+            assert!(code.is_known(), "{:?}", code);
+            assert_eq!(Code::from_u64(code.to_u64()), code);
+        }
+        assert_eq!(Code::H3_VERSION_FALLBACK.value(), 0x110);
+        assert_eq!(Code::QPACK_DECODER_STREAM_ERROR.value(), 0x202);
+
+        // Reserved codes, and others not registered yet
+        for unknown in [0x21, 0x1f * 9 + 0x21, 0x111, 0x203, (1 << 62) - 1] {
+            let code = Code::from_u64(unknown);
+            assert!(!code.is_known(), "{:#x}", unknown);
+            assert_eq!(code.to_u64(), unknown);
+        }
+    }
+
+    #[test]
+    fn stop_sending_code() {
+        let coded: Vec<Error> = vec![
+            Code::H3_MESSAGE_ERROR.into(),
+            FrameStreamError::UnexpectedEnd.into(),
+            FrameStreamError::Proto(FrameError::InvalidStreamId(InvalidStreamId(u64::MAX))).into(),
+            FrameStreamError::Proto(FrameError::InvalidPushId(
+                PushId::try_from(u64::MAX).unwrap_err(),
+            ))
+            .into(),
+            FrameStreamError::Proto(FrameError::Settings(SettingsError::Exceeded)).into(),
+            FrameStreamError::Proto(FrameError::UnsupportedFrame(0x2)).into(),
+            FrameStreamError::Proto(FrameError::UnknownFrame(0x21)).into(),
+            FrameStreamError::Proto(FrameError::Incomplete(3)).into(),
+            FrameStreamError::Proto(FrameError::InvalidFrameValue).into(),
+            FrameStreamError::Proto(FrameError::NonMinimalVarInt(1)).into(),
+            FrameStreamError::Proto(FrameError::Malformed).into(),
+            FrameStreamError::BufferBudgetExceeded.into(),
+        ];
+        for err in coded {
+            assert_eq!(err.stop_sending_code(), err.try_get_code().unwrap().value());
+        }
+
+        assert_eq!(
+            Error::closed().stop_sending_code(),
+            Code::H3_NO_ERROR.value()
+        );
+        assert_eq!(
+            Error::closing().stop_sending_code(),
+            Code::H3_REQUEST_CANCELLED.value()
+        );
+        assert_eq!(
+            Error::header_too_big(2, 1).stop_sending_code(),
+            Code::H3_REQUEST_CANCELLED.value()
+        );
+        assert_eq!(
+            Error::from(FrameStreamError::HeaderTooBig {
+                size: 2,
+                max_size: 1
+            })
+            .stop_sending_code(),
+            Code::H3_REQUEST_CANCELLED.value()
+        );
+        assert_eq!(
+            Error::already_finished().stop_sending_code(),
+            Code::H3_INTERNAL_ERROR.value()
+        );
+    }
 }
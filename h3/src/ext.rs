@@ -1,14 +1,27 @@
 //! Extensions for the HTTP/3 protocol.
 
 use std::convert::TryFrom;
+use std::fmt;
 use std::str::FromStr;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use std::task::{Context, Poll};
 
 use bytes::{Buf, Bytes};
+use futures_channel::mpsc;
+use futures_util::{ready, StreamExt};
 
 use crate::{
     error::Code,
-    proto::{stream::StreamId, varint::VarInt},
-    Error,
+    proto::{
+        frame::{ExtensionFrame, Frame},
+        push::PushId,
+        stream::StreamId,
+        varint::VarInt,
+    },
+    quic, stream, Error,
 };
 
 /// Describes the `:protocol` pseudo-header for extended connect
@@ -54,6 +67,86 @@ impl FromStr for Protocol {
     }
 }
 
+/// Request extension marking a request received in 0-RTT
+///
+/// Early data can be replayed by an attacker, the application should only act on requests
+/// carrying this marker if doing so twice is harmless.
+///
+/// See: <https://www.rfc-editor.org/rfc/rfc9114#section-10.9>
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct EarlyData;
+
+/// Request extension allowing a non-idempotent request to be sent in 0-RTT
+///
+/// By inserting it, the caller asserts the request can safely be replayed.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct AllowEarlyData;
+
+/// Priority parameters of a request, see <https://www.rfc-editor.org/rfc/rfc9218#section-4>
+///
+/// It displays as a Priority Field Value, the Structured Fields Dictionary carried by the
+/// `priority` header and by PRIORITY_UPDATE frames. Parameters with their default value are
+/// left out, so the default priority displays as an empty string.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Priority {
+    /// Urgency, from 0 to 7, requests of lower urgency are served first
+    pub urgency: u8,
+    /// Whether the response can be used as it is received, rather than once complete
+    pub incremental: bool,
+}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Self {
+            urgency: 3,
+            incremental: false,
+        }
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let default = Self::default();
+        if self.urgency != default.urgency {
+            write!(f, "u={}", self.urgency)?;
+        }
+        if self.incremental != default.incremental {
+            // A true Boolean is serialized as the bare key
+            let sep = if self.urgency != default.urgency {
+                ", "
+            } else {
+                ""
+            };
+            write!(f, "{}i", sep)?;
+        }
+        Ok(())
+    }
+}
+
+/// Sends a PRIORITY_UPDATE frame reprioritizing the request stream `element_id`
+///
+/// `control` is the control stream, see <https://www.rfc-editor.org/rfc/rfc9218#section-7.1>.
+pub async fn send_priority_update<S: quic::SendStream<Bytes>>(
+    control: &mut S,
+    element_id: u64,
+    priority: &Priority,
+) -> Result<(), Error> {
+    let element_id = StreamId::try_from(element_id)?;
+    let frame = ExtensionFrame::priority_update(element_id.into(), priority.to_string());
+    stream::write(control, Frame::Extension(frame)).await
+}
+
+/// Sends a CANCEL_PUSH frame on the control stream, cancelling the push `push_id`
+///
+/// Waits for the frame to be sent, so a slow peer applies backpressure.
+pub async fn cancel_push<S: quic::SendStream<Bytes>>(
+    control: &mut S,
+    push_id: u64,
+) -> Result<(), Error> {
+    let push_id = PushId::try_from(push_id)?;
+    stream::write(control, Frame::<Bytes>::CancelPush(push_id)).await
+}
+
 /// HTTP datagram frames
 /// See: <https://www.rfc-editor.org/rfc/rfc9297#section-2.1>
 pub struct Datagram<B = Bytes> {
@@ -120,3 +213,85 @@ where
         self.payload
     }
 }
+
+/// Data received on a [`UniStream`], or the error which ended it
+pub(crate) type UniStreamData = Result<Bytes, Box<dyn quic::Error>>;
+
+/// An incoming unidirectional stream of a type registered with `register_uni_stream_handler()`
+///
+/// The connection keeps receiving the data of the stream while it is driven, and passes it
+/// along, up to a few chunks ahead of the reads. It stops the stream with the code given to
+/// [`quic::RecvStream::stop_sending()`], or with `H3_NO_ERROR` if this is dropped first.
+pub struct UniStream {
+    id: StreamId,
+    ty: u64,
+    data: mpsc::Receiver<UniStreamData>,
+    // Code to stop the stream with, once `data` is closed
+    stop_code: Arc<AtomicU64>,
+}
+
+impl UniStream {
+    pub(crate) fn new(
+        id: StreamId,
+        ty: u64,
+        data: mpsc::Receiver<UniStreamData>,
+        stop_code: Arc<AtomicU64>,
+    ) -> Self {
+        Self {
+            id,
+            ty,
+            data,
+            stop_code,
+        }
+    }
+
+    /// The type of this stream, as received
+    pub fn stream_type(&self) -> u64 {
+        self.ty
+    }
+}
+
+impl quic::RecvStream for UniStream {
+    type Buf = Bytes;
+    type Error = Box<dyn quic::Error>;
+
+    fn poll_data(&mut self, cx: &mut Context<'_>) -> Poll<Result<Option<Bytes>, Self::Error>> {
+        Poll::Ready(ready!(self.data.poll_next_unpin(cx)).transpose())
+    }
+
+    fn stop_sending(&mut self, error_code: u64) {
+        self.stop_code.store(error_code, Ordering::Release);
+        self.data.close();
+    }
+
+    fn recv_id(&self) -> StreamId {
+        self.id
+    }
+}
+
+impl std::fmt::Debug for UniStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UniStream")
+            .field("id", &self.id)
+            .field("type", &self.ty)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn priority_field_value() {
+        let priority = |urgency, incremental| Priority {
+            urgency,
+            incremental,
+        };
+        assert_eq!(Priority::default().to_string(), "");
+        assert_eq!(priority(3, false).to_string(), "");
+        assert_eq!(priority(5, false).to_string(), "u=5");
+        assert_eq!(priority(3, true).to_string(), "i");
+        assert_eq!(priority(0, true).to_string(), "u=0, i");
+    }
+}
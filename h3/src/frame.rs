@@ -1,26 +1,82 @@
-use std::task::{Context, Poll};
+use std::{
+    fmt,
+    hash::Hasher,
+    ops::ControlFlow,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+};
 
-use bytes::Buf;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures_util::{future, ready};
 
 use tracing::trace;
 
-use crate::stream::{BufRecvStream, WriteBuf};
+pub use crate::budget::BufferBudget;
+use crate::stream::{self, BufRecvStream, WriteBuf};
 use crate::{
+    budget::BudgetReservation,
     buf::BufList,
-    error::TransportError,
+    error::{Code, Error, ErrorLevel, TransportError},
     proto::{
-        frame::{self, Frame, PayloadLen},
+        coding::Encode,
+        frame::{self, ExtensionFrame, Frame, FrameType, PayloadLen},
+        push::{PushId, PushIdLimit},
         stream::StreamId,
+        varint::VarInt,
     },
     quic::{BidiStream, RecvStream, SendStream},
 };
 
+/// Connection settings enforced on the frames received on a stream
+#[derive(Debug, Clone, Copy)]
+pub struct AppliedSettings {
+    /// The `SETTINGS_MAX_FIELD_SECTION_SIZE` advertised to the peer
+    ///
+    /// The limit applies to the decoded size of a field section, which is only known once it is
+    /// decoded. HEADERS frames too long to possibly fit are cut off early though, see
+    /// [`FrameStream::apply_settings()`].
+    pub max_field_section_size: u64,
+}
+
+/// Receives the frames of unknown type decoded on a stream, see [`FrameStream::set_extension_handler()`]
+pub(crate) type ExtensionHandler =
+    Arc<dyn Fn(StreamId, u64, Bytes) -> ControlFlow<Code> + Send + Sync>;
+
 /// Decodes Frames from the underlying QUIC stream
 pub struct FrameStream<S, B> {
     pub stream: BufRecvStream<S, B>,
     // Already read data from the stream
     decoder: FrameDecoder,
     remaining_data: usize,
+    // A DATA frame was decoded, and `poll_data()` did not return `None` for it yet
+    in_data: bool,
+    extension_handler: Option<ExtensionHandler>,
+    // Frames of unknown type are returned as `Frame::Extension`, see `set_passthrough_unknown()`
+    passthrough_unknown: bool,
+    // ORIGIN frames are only meaningful on the control stream, they are skipped elsewhere
+    accept_origin: bool,
+    // PUSH_PROMISE frames above this limit are rejected with `PushIdNotAllowed`
+    push_id_limit: Option<PushIdLimit>,
+    // `poll_data()` carries on with the DATA frames following the one read
+    coalesce_data: bool,
+    // The end of the stream is unexpected, see `expect_more()`
+    expect_more: bool,
+    // Payload bytes `poll_data()` waits for before returning, see `set_min_data_chunk()`
+    min_data_chunk: usize,
+    // Body size `recv_request()` accepts, see `set_max_request_body()`
+    max_request_body: usize,
+    // Hashes the bytes received, see `with_digest()`
+    digest: Option<Box<dyn Hasher + Send + Sync>>,
+    // Bytes buffered on behalf of a shared budget, see `with_buffer_budget()`
+    budget: Option<BudgetReservation>,
+    // Failure to receive while looking for the end of the stream, see `poll_data_last()`
+    deferred_error: Option<FrameStreamError>,
+    // Called with the type and length of each frame decoded, see `set_frame_observer()`
+    frame_observer: Option<Box<dyn FnMut(FrameType, u64) + Send + Sync>>,
+    // High and low water marks of the buffer, see `set_read_watermarks()`
+    watermarks: Option<(usize, usize)>,
+    // Receiving paused above the high water mark, with the waker of the last poll
+    paused: Option<Waker>,
 }
 
 impl<S, B> FrameStream<S, B> {
@@ -29,6 +85,21 @@ impl<S, B> FrameStream<S, B> {
             stream,
             decoder: FrameDecoder::default(),
             remaining_data: 0,
+            in_data: false,
+            extension_handler: None,
+            passthrough_unknown: false,
+            accept_origin: false,
+            push_id_limit: None,
+            coalesce_data: false,
+            expect_more: false,
+            min_data_chunk: 0,
+            max_request_body: usize::MAX,
+            digest: None,
+            budget: None,
+            deferred_error: None,
+            frame_observer: None,
+            watermarks: None,
+            paused: None,
         }
     }
 
@@ -37,42 +108,219 @@ impl<S, B> FrameStream<S, B> {
     pub fn into_inner(self) -> BufRecvStream<S, B> {
         self.stream
     }
+
+    /// Enforces the limits of the connection settings on the frames received from now on
+    ///
+    /// A HEADERS frame announcing a payload too long to decode within the field section size
+    /// limit is then rejected with [`FrameStreamError::HeaderTooBig`] before its payload is
+    /// buffered. The others are left for the decoder to check against the decoded size.
+    pub fn apply_settings(&mut self, settings: &AppliedSettings) {
+        self.decoder.max_field_section_size = Some(settings.max_field_section_size);
+    }
+
+    /// Hands the frames of unknown type received from now on to `handler`, instead of skipping them
+    ///
+    /// The handler is given the stream id, the frame type and its payload, once entirely
+    /// received. Frames of reserved types are included, [`FrameType::is_reserved()`] tells them
+    /// apart. Returning `Break` stops the stream with the given code, and decoding fails with
+    /// [`FrameStreamError::ExtensionRejected`].
+    pub(crate) fn set_extension_handler(&mut self, handler: Option<ExtensionHandler>) {
+        self.decoder.decode_extensions = handler.is_some() || self.passthrough_unknown;
+        self.extension_handler = handler;
+    }
+
+    /// Returns the frames of unknown type as [`Frame::Extension`], rather than skipping them
+    ///
+    /// This is for proxies forwarding the frames they do not understand: encoding the frame
+    /// returned, followed by its payload, gives back the bytes received, unless the peer encoded
+    /// the type or length on more bytes than needed. Grease frames are included. The extension
+    /// handler, if any, is not called in this mode.
+    pub fn set_passthrough_unknown(&mut self, passthrough: bool) {
+        self.passthrough_unknown = passthrough;
+        self.decoder.decode_extensions = passthrough || self.extension_handler.is_some();
+    }
+
+    /// Returns the ORIGIN frames received from now on, rather than skipping them
+    ///
+    /// This is for the control stream, where they are sent. As in HTTP/2, see
+    /// <https://www.rfc-editor.org/rfc/rfc8336#section-2>, they are ignored on any other stream.
+    pub(crate) fn accept_origin(&mut self) {
+        self.accept_origin = true;
+    }
+
+    /// Checks the push id of PUSH_PROMISE frames against the limit set by the MAX_PUSH_ID frames
+    /// the client sent
+    ///
+    /// Decoding a PUSH_PROMISE for a push id `limit` does not allow then fails with
+    /// [`FrameStreamError::PushIdNotAllowed`]. By default, any push id is accepted.
+    pub fn set_push_id_limit(&mut self, limit: PushIdLimit) {
+        self.push_id_limit = Some(limit);
+    }
+
+    fn allows_push(&self, id: PushId) -> bool {
+        self.push_id_limit.map_or(true, |limit| limit.allows(id))
+    }
+
+    /// Reads the payload of consecutive DATA frames as a single one, which is off by default
+    ///
+    /// Once the payload of a DATA frame is read, `poll_data()` then decodes the next frame if it
+    /// is a DATA frame too, and goes on with its payload, rather than returning `None`. Any other
+    /// frame, such as trailers, still ends the data, and is decoded with `poll_next()`.
+    pub fn set_coalesce_data(&mut self, coalesce: bool) {
+        self.coalesce_data = coalesce;
+    }
+
+    /// Whether more frames must follow, so `poll_next()` fails when the stream ends
+    ///
+    /// A clean end of the stream then yields [`FrameStreamError::UnexpectedEnd`] instead of
+    /// `None`, as for a request whose body was announced but not finished. Unset by default.
+    pub fn expect_more(&mut self, expect: bool) {
+        self.expect_more = expect;
+    }
+
+    /// Minimum number of payload bytes `poll_data()` returns at once, 0 by default
+    ///
+    /// Smaller pieces are returned as soon as they are received, which suits latency-sensitive
+    /// streaming. Above 0, `poll_data()` waits for this many bytes to be buffered, gathering the
+    /// chunks received, unless the frame or the stream ends first.
+    pub fn set_min_data_chunk(&mut self, min: usize) {
+        self.min_data_chunk = min;
+    }
+
+    /// Largest body `recv_request()` gathers, in bytes, unlimited by default
+    pub fn set_max_request_body(&mut self, max: usize) {
+        self.max_request_body = max;
+    }
+
+    /// Feeds `hasher` with all the bytes received from now on, frames and payload alike
+    ///
+    /// This is for checking that a stream went through a proxy unmodified, by comparing the
+    /// digest of both ends. Bytes already buffered are not included.
+    pub fn with_digest<H>(mut self, hasher: H) -> Self
+    where
+        H: Hasher + Send + Sync + 'static,
+    {
+        self.digest = Some(Box::new(hasher));
+        self
+    }
+
+    /// Calls `observer` with the type and payload length of each frame, as soon as it is decoded
+    ///
+    /// This is before the frame is returned, or handled by the stream: frames which are not
+    /// returned, such as those given to the extension handler, are observed too. Frames of
+    /// unknown type the decoder skips are not, nor are grease frames. The observer only sees the
+    /// frame header, deciding on the stream is left to the caller once the poll returns.
+    pub fn set_frame_observer<F>(&mut self, observer: F)
+    where
+        F: FnMut(FrameType, u64) + Send + Sync + 'static,
+    {
+        self.frame_observer = Some(Box::new(observer));
+    }
+
+    fn observe(&mut self, frame: Option<&Frame<PayloadLen>>) {
+        let ty = frame.and_then(Frame::frame_type);
+        if let (Some(observer), Some(ty)) = (self.frame_observer.as_mut(), ty) {
+            observer(ty, self.decoder.frame_len);
+        }
+    }
+
+    /// The digest of the bytes received since [`FrameStream::with_digest()`], if it was called
+    pub fn finish_digest(&self) -> Option<u64> {
+        self.digest.as_ref().map(|hasher| hasher.finish())
+    }
+
+    /// Stops receiving while more than `high` bytes are buffered, until no more than `low` are
+    ///
+    /// Polls return `Pending` in between, once what is buffered is consumed. The stream still
+    /// receives above the high water mark when the buffered bytes do not hold a whole frame, as
+    /// decoding could not progress otherwise.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `low` is above `high`.
+    pub fn set_read_watermarks(&mut self, high: usize, low: usize) {
+        assert!(low <= high, "low water mark {} above {}", low, high);
+        self.watermarks = Some((high, low));
+    }
+
+    /// Counts the bytes received from now on against `budget`, shared with other streams
+    ///
+    /// Receiving a chunk which does not fit in what is left of the budget stops the stream with
+    /// `H3_EXCESSIVE_LOAD`, and fails with [`FrameStreamError::BufferBudgetExceeded`]. Bytes are
+    /// given back to the budget as they are consumed, and all at once when the stream is dropped.
+    /// Bytes already buffered are not counted.
+    pub fn with_buffer_budget(mut self, budget: Arc<BufferBudget>) -> Self {
+        self.budget = Some(BudgetReservation::new(budget));
+        self
+    }
 }
 
 impl<S, B> FrameStream<S, B>
 where
     S: RecvStream,
 {
+    /// Decodes the next frame, receiving more data as needed
+    ///
+    /// All the state is kept in the stream, so this can be called again after dropping a
+    /// `Pending` poll. The payload of a DATA frame must be read with `poll_data()` before the next
+    /// frame: until then, this fails with [`FrameStreamError::DataNotRead`], leaving the stream
+    /// untouched.
     pub fn poll_next(
         &mut self,
         cx: &mut Context<'_>,
     ) -> Poll<Result<Option<Frame<PayloadLen>>, FrameStreamError>> {
-        assert!(
-            self.remaining_data == 0,
-            "There is still data to read, please call poll_data() until it returns None."
-        );
+        if self.remaining_data != 0 {
+            return Poll::Ready(Err(FrameStreamError::DataNotRead));
+        }
 
         loop {
-            let end = self.try_recv(cx)?;
+            let end = match self.try_recv(cx) {
+                // Frames received before the stream was reset are handed out first
+                Poll::Ready(Err(e @ FrameStreamError::Quic(_))) if self.is_decodable() => {
+                    self.deferred_error = Some(e);
+                    Poll::Ready(false)
+                }
+                end => end?,
+            };
+            let decoded = self.decode_buffered();
+            self.release_consumed();
+            let decoded = decoded?;
+            self.observe(decoded.as_ref());
 
-            return match self.decoder.decode(self.stream.buf_mut())? {
+            return match decoded {
                 Some(Frame::Data(PayloadLen(len))) => {
                     self.remaining_data = len;
+                    self.in_data = true;
                     Poll::Ready(Ok(Some(Frame::Data(PayloadLen(len)))))
                 }
                 frame @ Some(Frame::WebTransportStream(_)) => {
                     self.remaining_data = usize::MAX;
+                    self.in_data = true;
+                    Poll::Ready(Ok(frame))
+                }
+                frame @ Some(Frame::Extension(_)) if self.passthrough_unknown => {
                     Poll::Ready(Ok(frame))
                 }
+                Some(Frame::Extension(frame)) => {
+                    self.handle_extension(frame)?;
+                    continue;
+                }
+                Some(Frame::Origin { .. }) if !self.accept_origin => {
+                    trace!("ignore ORIGIN frame outside of the control stream");
+                    continue;
+                }
+                Some(Frame::PushPromise(frame)) if !self.allows_push(frame.id()) => {
+                    Poll::Ready(Err(FrameStreamError::PushIdNotAllowed(frame.id())))
+                }
                 Some(frame) => Poll::Ready(Ok(Some(frame))),
                 None => match end {
                     // Received a chunk but frame is incomplete, poll until we get `Pending`.
                     Poll::Ready(false) => continue,
                     Poll::Pending => Poll::Pending,
                     Poll::Ready(true) => {
-                        if self.stream.buf_mut().has_remaining() {
+                        if self.stream.buf_mut().has_remaining() || self.expect_more {
                             // Reached the end of receive stream, but there is still some data:
-                            // The frame is incomplete.
+                            // The frame is incomplete. Or the caller expected more frames.
                             Poll::Ready(Err(FrameStreamError::UnexpectedEnd))
                         } else {
                             Poll::Ready(Ok(None))
@@ -83,24 +331,116 @@ where
         }
     }
 
+    /// Receives the type and length of the next frame, leaving it buffered for `poll_next()`
+    ///
+    /// Only the bytes needed for the frame header are waited for, so a stream announcing a frame
+    /// too long to accept can be stopped before its payload is received. Frames of unknown type
+    /// are included, though `poll_next()` skips them. As with `poll_next()`, the payload of a DATA
+    /// frame must be read first.
+    pub fn poll_peek_header(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<(FrameType, u64)>, FrameStreamError>> {
+        if self.remaining_data != 0 {
+            return Poll::Ready(Err(FrameStreamError::DataNotRead));
+        }
+
+        loop {
+            // Non-minimal varints are rejected by the decoder, this only peeks
+            if let Some(header) = FrameType::decode_header(&mut self.stream.buf().cursor(), false) {
+                return Poll::Ready(Ok(Some(header)));
+            }
+
+            if ready!(self.try_recv(cx))? {
+                return Poll::Ready(if self.stream.buf().has_remaining() {
+                    Err(FrameStreamError::UnexpectedEnd)
+                } else {
+                    Ok(None)
+                });
+            }
+        }
+    }
+
+    /// Same as [`FrameStream::poll_next()`], also returning the bytes the frame was decoded from
+    ///
+    /// These are the frame type, length and payload as received, sharing memory with the receive
+    /// buffer when they are contiguous in it. For DATA frames, only the type and length are
+    /// included, the payload is then read with `poll_data()` as usual.
+    ///
+    /// Panics unless [`FrameStream::set_retain_raw()`] enabled keeping these bytes.
+    #[allow(clippy::type_complexity)]
+    pub fn poll_next_raw(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<(Frame<PayloadLen>, Bytes)>, FrameStreamError>> {
+        assert!(
+            self.decoder.retain_raw,
+            "Raw frames are not retained, please call set_retain_raw(true) first."
+        );
+
+        Poll::Ready(Ok(ready!(self.poll_next(cx))?.map(|frame| {
+            let raw = self
+                .decoder
+                .raw
+                .take()
+                .expect("decoded frame without raw bytes");
+            (frame, raw)
+        })))
+    }
+
     /// Retrieves the next piece of data in an incoming data packet or webtransport stream
     ///
     ///
     /// WebTransport bidirectional payload has no finite length and is processed until the end of the stream.
+    ///
+    /// The frame must have been decoded by [`FrameStream::poll_next()`] first. Once its payload
+    /// is read, this returns `None` a single time, then fails with
+    /// [`FrameStreamError::DataNotDecoded`] if another DATA frame is buffered, rather than
+    /// hiding its payload.
     pub fn poll_data(
         &mut self,
         cx: &mut Context<'_>,
     ) -> Poll<Result<Option<impl Buf>, FrameStreamError>> {
+        if self.remaining_data == 0 && self.in_data && self.coalesce_data {
+            ready!(self.poll_next_data_frame(cx))?;
+        }
+
         if self.remaining_data == 0 {
+            if !std::mem::take(&mut self.in_data) && self.is_data_buffered() {
+                return Poll::Ready(Err(FrameStreamError::DataNotDecoded));
+            }
             return Poll::Ready(Ok(None));
         };
 
-        let end = match self.try_recv(cx) {
-            Poll::Ready(Ok(end)) => end,
-            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
-            Poll::Pending => false,
+        // Less is returned only at the end of the payload, or of what can be received
+        let wanted = usize::min(self.min_data_chunk, self.remaining_data);
+        let mut end = false;
+        loop {
+            match self.try_recv(cx) {
+                Poll::Ready(Ok(e)) => end = e,
+                Poll::Ready(Err(e @ FrameStreamError::Quic(_)))
+                    if self.stream.buf().has_remaining() =>
+                {
+                    self.deferred_error = Some(e);
+                    break;
+                }
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending if self.stream.buf().remaining() < wanted => return Poll::Pending,
+                Poll::Pending => break,
+            }
+            if end || self.stream.buf().remaining() >= wanted {
+                break;
+            }
+        }
+        let buf = self.stream.buf_mut();
+        let data = if buf.chunk().len() < wanted && buf.remaining() > buf.chunk().len() {
+            // Gather the chunks received, rather than return the first one below the minimum
+            let len = usize::min(self.remaining_data, buf.remaining());
+            Some(buf.copy_to_bytes(len))
+        } else {
+            buf.take_chunk(self.remaining_data)
         };
-        let data = self.stream.buf_mut().take_chunk(self.remaining_data);
+        self.release_consumed();
 
         match (data, end) {
             (None, true) => Poll::Ready(Ok(None)),
@@ -112,39 +452,499 @@ where
                 Poll::Ready(Err(FrameStreamError::UnexpectedEnd))
             }
             (Some(d), _) => {
+                debug_assert!(
+                    d.remaining() <= self.remaining_data,
+                    "data must not extend past the end of the frame"
+                );
                 self.remaining_data -= d.remaining();
                 Poll::Ready(Ok(Some(d)))
             }
         }
     }
 
+    /// Like `poll_data()`, also telling whether the chunk returned is the last of the stream
+    ///
+    /// The flag is set when the chunk ends the payload, no frame follows, and the stream is
+    /// finished, so the caller learns of the end along with the body rather than with another
+    /// poll. When the payload ends in the buffer, this receives once more without waiting, in
+    /// case the end of the stream came right after it. An error receiving then is returned by the
+    /// next poll, after the chunk.
+    pub fn poll_data_last(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<(impl Buf, bool)>, FrameStreamError>> {
+        let data = match ready!(self.poll_data(cx))? {
+            Some(data) => data,
+            None => return Poll::Ready(Ok(None)),
+        };
+        if self.has_data() || self.stream.buf().has_remaining() {
+            return Poll::Ready(Ok(Some((data, false))));
+        }
+        let last = match self.try_recv(cx) {
+            Poll::Ready(Ok(end)) => end,
+            Poll::Ready(Err(e)) => {
+                self.deferred_error = Some(e);
+                false
+            }
+            Poll::Pending => false,
+        };
+        Poll::Ready(Ok(Some((data, last))))
+    }
+
+    /// Ends the read of the payload of a DATA frame, checking it was read entirely
+    ///
+    /// This stands for the `None` `poll_data()` returns once the payload is read, for callers
+    /// reading exactly the length of the frame. With [`FrameStream::set_coalesce_data()`], the
+    /// DATA frames which follow are decoded first, and must be empty. Fails with
+    /// [`FrameStreamError::DataNotRead`] if payload is left to read.
+    pub fn poll_data_end(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), FrameStreamError>> {
+        if self.remaining_data == 0 && self.in_data && self.coalesce_data {
+            ready!(self.poll_next_data_frame(cx))?;
+        }
+
+        if !self.data_complete() {
+            return Poll::Ready(Err(FrameStreamError::DataNotRead));
+        }
+        self.in_data = false;
+        Poll::Ready(Ok(()))
+    }
+
+    /// Decodes the DATA frames following the one read, until one has a payload left to read
+    ///
+    /// Resolves with no payload left once the next frame is of another type, or the stream ends.
+    fn poll_next_data_frame(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), FrameStreamError>> {
+        while self.remaining_data == 0 {
+            let buf = self.stream.buf();
+            if buf.has_remaining() {
+                // Non-minimal varints are rejected by the decoder, this only peeks at the type
+                match FrameType::decode_header(&mut buf.cursor(), false) {
+                    Some((FrameType::DATA, _)) => {
+                        match self.decode_buffered()? {
+                            Some(Frame::Data(PayloadLen(len))) => self.remaining_data = len,
+                            _ => unreachable!("DATA frame header buffered"),
+                        }
+                        continue;
+                    }
+                    Some(_) => return Poll::Ready(Ok(())),
+                    None => (),
+                }
+            }
+
+            if ready!(self.try_recv(cx))? {
+                return Poll::Ready(Ok(()));
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// Decodes the next frame from the buffer
+    ///
+    /// The buffer may hold more than the frame being read, so a frame header must never be
+    /// decoded while the payload of a DATA frame is still there, or it would be taken for one.
+    fn decode_buffered(&mut self) -> Result<Option<Frame<PayloadLen>>, FrameStreamError> {
+        debug_assert_eq!(
+            self.remaining_data, 0,
+            "decoding a frame before the DATA payload is read"
+        );
+        self.decoder.decode(self.stream.buf_mut())
+    }
+
+    /// Receives more data, then tells whether decoding the next frame is worth attempting
+    ///
+    /// Resolves to `false` while the buffered bytes are short of the minimum the decoder found
+    /// missing on its last attempt, so the caller can keep requesting data from the transport
+    /// instead. Returns `Pending` if no data is available.
+    pub fn poll_decodable(&mut self, cx: &mut Context<'_>) -> Poll<Result<bool, FrameStreamError>> {
+        ready!(self.try_recv(cx))?;
+        Poll::Ready(Ok(self.is_decodable()))
+    }
+
+    /// Whether the buffered bytes satisfy the minimum expected by the decoder
+    pub fn is_decodable(&self) -> bool {
+        self.decoder.is_satisfied_by(self.stream.buf().remaining())
+    }
+
+    /// Decodes the frames already buffered, without receiving more data, up to `max_batch`
+    ///
+    /// This returns less than `max_batch` frames only once the buffered bytes are exhausted, so a
+    /// stream with a large backlog can be drained over several calls, giving others a turn in
+    /// between. A DATA frame ends the batch, as its payload must then be read with `poll_data()`.
+    pub fn drain_buffered(
+        &mut self,
+        max_batch: usize,
+    ) -> Result<Vec<Frame<PayloadLen>>, FrameStreamError> {
+        if self.remaining_data != 0 {
+            return Err(FrameStreamError::DataNotRead);
+        }
+
+        let mut frames = Vec::new();
+        while frames.len() < max_batch {
+            let decoded = self.decode_buffered()?;
+            self.observe(decoded.as_ref());
+            match decoded {
+                Some(Frame::Data(PayloadLen(len))) => {
+                    self.remaining_data = len;
+                    self.in_data = true;
+                    frames.push(Frame::Data(PayloadLen(len)));
+                    break;
+                }
+                Some(frame @ Frame::WebTransportStream(_)) => {
+                    self.remaining_data = usize::MAX;
+                    self.in_data = true;
+                    frames.push(frame);
+                    break;
+                }
+                Some(frame @ Frame::Extension(_)) if self.passthrough_unknown => frames.push(frame),
+                Some(Frame::Extension(frame)) => self.handle_extension(frame)?,
+                Some(Frame::Origin { .. }) if !self.accept_origin => {
+                    trace!("ignore ORIGIN frame outside of the control stream");
+                }
+                Some(Frame::PushPromise(frame)) if !self.allows_push(frame.id()) => {
+                    return Err(FrameStreamError::PushIdNotAllowed(frame.id()))
+                }
+                Some(frame) => frames.push(frame),
+                None => break,
+            }
+        }
+        Ok(frames)
+    }
+
+    /// Number of frames decoded so far
+    ///
+    /// This includes the frames of unknown type which are skipped, such as empty grease frames
+    /// some peers send as keepalives, so an increase denotes activity on the stream.
+    pub fn frame_count(&self) -> u64 {
+        self.decoder.decoded
+    }
+
+    /// Number of frames of unknown type decoded so far, such as grease frames
+    ///
+    /// Each stream counts its own, so the control stream tells those of the connection apart from
+    /// the ones received on requests.
+    pub fn unknown_frame_count(&self) -> u64 {
+        self.decoder.unknown
+    }
+
+    /// Number of bytes received and held in the buffer, not consumed yet
+    ///
+    /// These are incomplete frames, or DATA payload not read with `poll_data()` yet.
+    pub fn buffered_len(&self) -> usize {
+        self.stream.buf().remaining()
+    }
+
+    /// Length of the next frame, header and payload included, once its header is buffered
+    ///
+    /// Unlike [`FrameDecoder::expected()`], which is only the minimum to make progress, this is
+    /// enough to decode the whole frame, so it can size a single read. It is `None` until the type
+    /// and length of the frame are both buffered, and while the payload of a DATA frame is left
+    /// to read.
+    pub fn next_frame_len_hint(&self) -> Option<u64> {
+        if self.remaining_data > 0 {
+            return None;
+        }
+        let mut cur = self.stream.buf().cursor();
+        let (_, len) = FrameType::decode_header(&mut cur, self.decoder.strict_varint)?;
+        len.checked_add(cur.position() as u64)
+    }
+
+    /// Rejects frames carrying a varint not encoded on as few bytes as possible
+    ///
+    /// This is off by default, as receivers are allowed to accept such encodings. When set,
+    /// decoding one fails with [`frame::FrameError::NonMinimalVarInt`].
+    pub fn set_strict_varint(&mut self, strict: bool) {
+        self.decoder.strict_varint = strict;
+    }
+
+    /// Keeps the bytes each frame is decoded from, for [`FrameStream::poll_next_raw()`]
+    pub fn set_retain_raw(&mut self, retain: bool) {
+        self.decoder.retain_raw = retain;
+        self.decoder.raw = None;
+    }
+
     /// Stops the underlying stream with the provided error code
     pub(crate) fn stop_sending(&mut self, error_code: crate::error::Code) {
         self.stream.stop_sending(error_code.into());
     }
 
+    /// Stops the stream with `code` and discards what is left of it, after a decoding error
+    ///
+    /// The data buffered is dropped, as is the payload of the DATA frame being read, and the
+    /// stream then reads as if it ended: `poll_next()` and `poll_data()` return `None`.
+    /// Stopping a QUIC stream does not wait for the peer, so this always completes at once.
+    pub fn poll_abort(&mut self, _cx: &mut Context<'_>, code: Code) -> Poll<()> {
+        self.stream.abort(code.value());
+        self.remaining_data = 0;
+        self.in_data = false;
+        self.decoder.expected = None;
+        self.release_consumed();
+        Poll::Ready(())
+    }
+
     pub(crate) fn has_data(&self) -> bool {
         self.remaining_data != 0
     }
 
+    /// Whether the stream is at a frame boundary, with no DATA payload left to read
+    pub fn data_complete(&self) -> bool {
+        !self.has_data()
+    }
+
+    /// Whether the next buffered frame is a DATA frame
+    fn is_data_buffered(&self) -> bool {
+        matches!(
+            FrameType::decode_header(&mut self.stream.buf().cursor(), self.decoder.strict_varint),
+            Some((FrameType::DATA, _))
+        )
+    }
+
+    fn handle_extension(&mut self, frame: ExtensionFrame) -> Result<(), FrameStreamError> {
+        let id = self.id();
+        let handler = self
+            .extension_handler
+            .as_ref()
+            .expect("extension frame decoded without a handler");
+        let ty = frame.frame_type().into_inner();
+        match handler(id, ty, frame.payload().clone()) {
+            ControlFlow::Continue(()) => Ok(()),
+            ControlFlow::Break(code) => {
+                self.stop_sending(code);
+                Err(FrameStreamError::ExtensionRejected(code))
+            }
+        }
+    }
+
     pub(crate) fn is_eos(&self) -> bool {
         self.stream.is_eos() && !self.stream.buf().has_remaining()
     }
 
     fn try_recv(&mut self, cx: &mut Context<'_>) -> Poll<Result<bool, FrameStreamError>> {
+        if let Some(e) = self.deferred_error.take() {
+            return Poll::Ready(Err(e));
+        }
         if self.stream.is_eos() {
             return Poll::Ready(Ok(true));
         }
+        self.release_consumed();
+        if self.poll_paused(cx) {
+            return Poll::Pending;
+        }
         match self.stream.poll_read(cx) {
             Poll::Ready(Err(e)) => Poll::Ready(Err(FrameStreamError::Quic(e.into()))),
             Poll::Pending => Poll::Pending,
-            Poll::Ready(Ok(eos)) => Poll::Ready(Ok(eos)),
+            Poll::Ready(Ok(true)) => Poll::Ready(Ok(true)),
+            Poll::Ready(Ok(false)) => {
+                let chunk = self.stream.buf().last_chunk();
+                let chunk = chunk.expect("received chunk is buffered");
+                if let Some(hasher) = self.digest.as_mut() {
+                    hasher.write(chunk);
+                }
+                if let Some(budget) = self.budget.as_mut() {
+                    if !budget.reserve(chunk.len()) {
+                        self.stop_sending(Code::H3_EXCESSIVE_LOAD);
+                        return Poll::Ready(Err(FrameStreamError::BufferBudgetExceeded));
+                    }
+                }
+                Poll::Ready(Ok(false))
+            }
+        }
+    }
+
+    /// Whether receiving is paused by the high water mark, registering `cx` to be woken if so
+    fn poll_paused(&mut self, cx: &mut Context<'_>) -> bool {
+        let high = match self.watermarks {
+            Some((high, _)) => high,
+            None => return false,
+        };
+        if !self.has_data() && !self.is_decodable() {
+            self.paused = None;
+            return false;
+        }
+        if self.paused.is_none() && self.stream.buf().remaining() <= high {
+            return false;
+        }
+        self.paused = Some(cx.waker().clone());
+        true
+    }
+
+    /// Accounts for the bytes consumed since the last call
+    ///
+    /// They are given back to the buffer budget, if any. The buffer is consumed from the front, so
+    /// the bytes reserved are the last ones received. Receiving resumes once the buffer is down to
+    /// the low water mark.
+    fn release_consumed(&mut self) {
+        let buffered = self.stream.buf().remaining();
+        if let Some(budget) = self.budget.as_mut() {
+            budget.release_above(buffered);
+        }
+        match self.watermarks {
+            Some((_, low)) if buffered <= low => {
+                if let Some(waker) = self.paused.take() {
+                    waker.wake();
+                }
+            }
+            _ => (),
         }
     }
 
     pub fn id(&self) -> StreamId {
         self.stream.recv_id()
     }
+
+    /// Reads the payload of the DATA frames to come as a stream of its own, see [`DataRecvStream`]
+    pub fn into_data_stream(self) -> DataRecvStream<S, B> {
+        DataRecvStream {
+            stream: self,
+            trailing: None,
+        }
+    }
+}
+
+/// Message produced by [`FrameStream::forward_to()`]
+#[cfg(any(test, feature = "tokio"))]
+#[derive(Debug)]
+pub enum ForwardedFrame {
+    /// A decoded frame, [`Frame::Data`] ones are followed by their payload
+    Frame(Frame<PayloadLen>),
+    /// A piece of the payload of the last DATA frame
+    Data(Bytes),
+}
+
+impl<S, B> FrameStream<S, B>
+where
+    S: RecvStream,
+{
+    /// Decodes frames from the stream and sends them through a channel until the stream ends
+    ///
+    /// A slot is reserved in the channel before each read, so nothing is read from the stream
+    /// while the channel is full. Forwarding stops without error once the receiver is dropped.
+    #[cfg(any(test, feature = "tokio"))]
+    pub async fn forward_to(
+        mut self,
+        sender: tokio::sync::mpsc::Sender<ForwardedFrame>,
+    ) -> Result<(), Error> {
+        loop {
+            let permit = match sender.reserve().await {
+                Ok(permit) => permit,
+                Err(_) => return Ok(()),
+            };
+
+            let forwarded = if self.has_data() {
+                match future::poll_fn(|cx| self.poll_data(cx)).await? {
+                    Some(mut data) => ForwardedFrame::Data(data.copy_to_bytes(data.remaining())),
+                    None => return Ok(()),
+                }
+            } else {
+                match future::poll_fn(|cx| self.poll_next(cx)).await? {
+                    Some(frame) => ForwardedFrame::Frame(frame),
+                    None => return Ok(()),
+                }
+            };
+            permit.send(forwarded);
+        }
+    }
+
+    /// Receives frames until one of type `ty`, and returns it
+    ///
+    /// DATA frames found on the way are skipped along with their payload, as are frames of
+    /// unknown type. Any other frame fails with `H3_FRAME_UNEXPECTED`. Resolves to `None` if the
+    /// stream ends first.
+    pub async fn recv_frame_of_type(
+        &mut self,
+        ty: FrameType,
+    ) -> Result<Option<Frame<PayloadLen>>, Error> {
+        loop {
+            while self.has_data() {
+                if future::poll_fn(|cx| self.poll_data(cx)).await?.is_none() {
+                    break;
+                }
+            }
+
+            let frame = match future::poll_fn(|cx| self.poll_next(cx)).await? {
+                Some(frame) => frame,
+                None => return Ok(None),
+            };
+            match frame.frame_type() {
+                Some(t) if t == ty => return Ok(Some(frame)),
+                Some(FrameType::DATA) => continue,
+                _ => {
+                    return Err(Code::H3_FRAME_UNEXPECTED.with_reason(
+                        format!("expected frame {:?}, got {:?}", ty, frame),
+                        ErrorLevel::ConnectionError,
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Receives a whole request: the fields of its HEADERS frame, its body, and its trailers
+    ///
+    /// The payload of the DATA frames is gathered in a single buffer. Beyond the size set with
+    /// `set_max_request_body()`, the stream is stopped and this fails with `H3_EXCESSIVE_LOAD`.
+    /// Frames out of the order [`RequestGrammar`] checks fail with `H3_FRAME_UNEXPECTED`, and a
+    /// stream ending before the HEADERS frame with `H3_REQUEST_INCOMPLETE`. PUSH_PROMISE and
+    /// extension frames are skipped.
+    pub async fn recv_request(&mut self) -> Result<(Bytes, Bytes, Option<Bytes>), Error> {
+        let mut grammar = RequestGrammar::default();
+        let mut headers = None;
+        let mut body = BytesMut::new();
+        let mut trailers = None;
+        loop {
+            if self.has_data() {
+                if let Some(data) = future::poll_fn(|cx| self.poll_data(cx)).await? {
+                    if body.len() + data.remaining() > self.max_request_body {
+                        self.stop_sending(Code::H3_EXCESSIVE_LOAD);
+                        return Err(Code::H3_EXCESSIVE_LOAD.with_reason(
+                            format!("request body exceeds {} bytes", self.max_request_body),
+                            ErrorLevel::StreamError,
+                        ));
+                    }
+                    body.put(data);
+                    continue;
+                }
+            }
+
+            let frame = match future::poll_fn(|cx| self.poll_next(cx)).await? {
+                Some(frame) => frame,
+                None => break,
+            };
+            grammar.on_frame(&frame).map_err(FrameStreamError::from)?;
+            match frame {
+                Frame::Headers(fields) if headers.is_none() => headers = Some(fields),
+                Frame::Headers(fields) => trailers = Some(fields),
+                _ => (),
+            }
+        }
+
+        match headers {
+            Some(headers) => Ok((headers, body.freeze(), trailers)),
+            None => Err(Code::H3_REQUEST_INCOMPLETE.with_reason(
+                "request stream closed before headers",
+                ErrorLevel::StreamError,
+            )),
+        }
+    }
+
+    /// Discards the rest of the stream until it ends, returning the code it was reset with
+    ///
+    /// After a decoding error, nothing more is worth decoding, but the error code of the peer
+    /// can tell what went wrong on its side, for logging. Resolves to `None` if the stream is
+    /// finished instead, or fails with a transport error carrying no code.
+    pub async fn drain_to_end(&mut self) -> Option<u64> {
+        self.remaining_data = 0;
+        self.in_data = false;
+        loop {
+            let buf = self.stream.buf_mut();
+            buf.advance(buf.remaining());
+            if self.stream.is_eos() {
+                return None;
+            }
+
+            if let Err(e) = future::poll_fn(|cx| self.stream.poll_read(cx)).await {
+                let e: TransportError = e.into();
+                return e.err_code();
+            }
+        }
+    }
 }
 
 impl<T, B> SendStream<B> for FrameStream<T, B>
@@ -177,33 +977,469 @@ where
 
 impl<S, B> FrameStream<S, B>
 where
-    S: BidiStream<B>,
+    S: SendStream<B>,
     B: Buf,
 {
-    pub(crate) fn split(self) -> (FrameStream<S::SendStream, B>, FrameStream<S::RecvStream, B>) {
-        let (send, recv) = self.stream.split();
-        (
-            FrameStream {
-                stream: send,
-                decoder: FrameDecoder::default(),
-                remaining_data: 0,
-            },
+    /// Sends `frame` once the stream is ready for it, then polls until it is flushed
+    ///
+    /// The frame is taken out of `frame` when handed to the stream, which encodes it then. Until
+    /// this resolves, it must be called again with the same `frame`, taken or not, so a poll-based
+    /// state machine keeps the frame across wakeups without knowing how far it went. On failure,
+    /// the stream is reset, as with the frames sent by the connection, unless the stream failed
+    /// before the frame was handed to it.
+    pub fn poll_send_frame(
+        &mut self,
+        cx: &mut Context<'_>,
+        frame: &mut Option<Frame<B>>,
+    ) -> Poll<Result<(), Error>> {
+        if frame.is_none() {
+            // The last frame handed over may have been partially written
+            return stream::poll_flush_or_reset(&mut self.stream, cx);
+        }
+        ready!(stream::poll_flush(&mut self.stream, cx))?;
+        if let Some(frame) = frame.take() {
+            if let Err(e) = self.stream.send_data(frame) {
+                self.stream.reset(Code::H3_INTERNAL_ERROR.value());
+                return Poll::Ready(Err(Error::from(e.into())));
+            }
+        }
+        stream::poll_flush_or_reset(&mut self.stream, cx)
+    }
+}
+
+impl<S, B> FrameStream<S, B>
+where
+    S: RecvStream + SendStream<B>,
+    B: Buf,
+{
+    /// Rejects a request without processing it, aborting both directions with `H3_REQUEST_REJECTED`
+    ///
+    /// The client can then retry the request, as if it had never been sent. This must not be used
+    /// once any data of the request was acted upon.
+    pub fn reject(&mut self) {
+        //= https://www.rfc-editor.org/rfc/rfc9114#section-4.1.1
+        //# When the server cancels a request without performing any application
+        //# processing, the request is considered "rejected".  The server SHOULD
+        //# abort its response stream with the error code H3_REQUEST_REJECTED.
+        self.stop_sending(Code::H3_REQUEST_REJECTED);
+        self.stream.reset(Code::H3_REQUEST_REJECTED.value());
+    }
+}
+
+impl<S, B> FrameStream<S, B>
+where
+    S: BidiStream<B>,
+    B: Buf,
+{
+    /// Splits a bidirectional stream into its sending and receiving halves
+    ///
+    /// The receiving half keeps the decoding state and options, such as frames partially
+    /// received, and the sending half starts afresh. Each half can then be used from a task of its
+    /// own, to send frames while others are being received.
+    pub fn split(self) -> (FrameStream<S::SendStream, B>, FrameStream<S::RecvStream, B>) {
+        let (send, recv) = self.stream.split();
+        (
+            FrameStream::new(send),
             FrameStream {
                 stream: recv,
                 decoder: self.decoder,
                 remaining_data: self.remaining_data,
+                in_data: self.in_data,
+                extension_handler: self.extension_handler,
+                passthrough_unknown: self.passthrough_unknown,
+                accept_origin: self.accept_origin,
+                push_id_limit: self.push_id_limit,
+                coalesce_data: self.coalesce_data,
+                expect_more: self.expect_more,
+                min_data_chunk: self.min_data_chunk,
+                max_request_body: self.max_request_body,
+                digest: self.digest,
+                budget: self.budget,
+                deferred_error: self.deferred_error,
+                frame_observer: self.frame_observer,
+                watermarks: self.watermarks,
+                paused: self.paused,
             },
         )
     }
 }
 
+/// Size of the prefix of a length-delimited message: a compression flag and a 4-byte length
+const MESSAGE_PREFIX_LEN: usize = 5;
+
+/// Reassembles length-delimited messages out of the payload of DATA frames
+///
+/// This is the framing gRPC uses in request and response bodies: each message starts with a
+/// 1-byte compression flag and a 4-byte big-endian length, followed by the payload. Messages
+/// are not aligned on DATA frames, so one may span several of them and a frame may hold several
+/// messages.
+#[derive(Default)]
+pub(crate) struct LengthDelimitedReader {
+    buf: BufList<Bytes>,
+}
+
+impl LengthDelimitedReader {
+    /// Buffers payload received, until it completes a message
+    pub(crate) fn push<T: Buf>(&mut self, data: &mut T) {
+        if data.has_remaining() {
+            self.buf.push_bytes(data);
+        }
+    }
+
+    /// Takes the next complete message, prefix included so the compression flag stays available
+    pub(crate) fn next_message(&mut self) -> Option<Bytes> {
+        if self.buf.remaining() < MESSAGE_PREFIX_LEN {
+            return None;
+        }
+
+        let len = {
+            let mut cur = self.buf.cursor();
+            cur.advance(1);
+            cur.get_u32() as usize
+        };
+        if self.buf.remaining() - MESSAGE_PREFIX_LEN < len {
+            return None;
+        }
+
+        Some(self.buf.copy_to_bytes(MESSAGE_PREFIX_LEN + len))
+    }
+
+    /// Whether part of a message is buffered
+    pub(crate) fn is_empty(&self) -> bool {
+        !self.buf.has_remaining()
+    }
+}
+
+/// The body of a request stream, as a [`RecvStream`] other protocols can be layered on
+///
+/// The payload of successive DATA frames is received as if it was contiguous. The body ends with
+/// the stream, or with the first frame other than DATA, such as trailers, which is then kept for
+/// [`DataRecvStream::into_inner()`]. Errors decoding the frames are transport errors for the
+/// layer above, with the HTTP/3 error code they map to.
+pub struct DataRecvStream<S, B> {
+    stream: FrameStream<S, B>,
+    // First non-DATA frame received, which ends the body
+    trailing: Option<Frame<PayloadLen>>,
+}
+
+impl<S, B> DataRecvStream<S, B> {
+    /// Returns the frame stream along with the frame which ended the body, if any
+    pub fn into_inner(self) -> (FrameStream<S, B>, Option<Frame<PayloadLen>>) {
+        (self.stream, self.trailing)
+    }
+}
+
+impl<S, B> RecvStream for DataRecvStream<S, B>
+where
+    S: RecvStream,
+{
+    type Buf = Bytes;
+    type Error = Box<dyn crate::quic::Error>;
+
+    fn poll_data(&mut self, cx: &mut Context<'_>) -> Poll<Result<Option<Bytes>, Self::Error>> {
+        loop {
+            if self.trailing.is_some() {
+                return Poll::Ready(Ok(None));
+            }
+
+            if self.stream.has_data() {
+                match ready!(self.stream.poll_data(cx)).map_err(body_error)? {
+                    Some(mut data) if data.has_remaining() => {
+                        return Poll::Ready(Ok(Some(data.copy_to_bytes(data.remaining()))))
+                    }
+                    Some(_) => continue,
+                    // The stream ended before the announced frame length
+                    None => return Poll::Ready(Err(body_error(FrameStreamError::UnexpectedEnd))),
+                }
+            }
+
+            match ready!(self.stream.poll_next(cx)).map_err(body_error)? {
+                Some(Frame::Data(_)) => continue,
+                Some(frame) => self.trailing = Some(frame),
+                None => return Poll::Ready(Ok(None)),
+            }
+        }
+    }
+
+    fn stop_sending(&mut self, error_code: u64) {
+        self.stream.stream.stop_sending(error_code)
+    }
+
+    fn recv_id(&self) -> StreamId {
+        self.stream.id()
+    }
+}
+
+/// Errors of the QUIC stream are passed as is, the others are wrapped in a [`BodyError`]
+fn body_error(e: FrameStreamError) -> TransportError {
+    match e {
+        FrameStreamError::Quic(e) => e,
+        e => Box::new(BodyError(e.into())),
+    }
+}
+
+/// An error decoding the frames under a [`DataRecvStream`]
+#[derive(Debug)]
+struct BodyError(Error);
+
+impl fmt::Display for BodyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for BodyError {}
+
+impl crate::quic::Error for BodyError {
+    fn is_timeout(&self) -> bool {
+        false
+    }
+
+    fn err_code(&self) -> Option<u64> {
+        self.0.try_get_code().map(|code| code.value())
+    }
+}
+
+/// Checks the frames received on a request stream follow the grammar of HTTP messages
+///
+/// A message is a HEADERS frame, then DATA frames, then optionally a trailing HEADERS frame,
+/// after which the stream must end. Frames of other types allowed on request streams, such as
+/// PUSH_PROMISE or extension frames, may be interleaved anywhere. Which of them an endpoint
+/// accepts is left to the caller.
+///
+/// Responses may start with interim responses, HEADERS frames with a 1xx status preceding the
+/// final response. They cannot be told apart from the frame alone: once the fields are decoded,
+/// [`RequestGrammar::on_interim_headers()`] lets another response follow.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RequestGrammar {
+    state: GrammarState,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum GrammarState {
+    #[default]
+    Start,
+    Headers,
+    Body,
+    Trailers {
+        after_data: bool,
+    },
+}
+
+/// A frame out of the sequence a request stream must follow, see [`RequestGrammar`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GrammarError {
+    /// A DATA frame was received before the HEADERS frame
+    DataBeforeHeaders,
+    /// A third frame followed two HEADERS frames with no DATA between them
+    RepeatedHeaders(FrameType),
+    /// A frame of this type was received after the trailing HEADERS frame
+    FrameAfterTrailers(FrameType),
+    /// A frame of this type is never allowed on a request stream
+    Forbidden(FrameType),
+}
+
+impl fmt::Display for GrammarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GrammarError::DataBeforeHeaders => write!(f, "DATA frame before HEADERS"),
+            GrammarError::RepeatedHeaders(ty) => {
+                write!(f, "frame {:#x} after repeated HEADERS", ty.into_inner())
+            }
+            GrammarError::FrameAfterTrailers(ty) => {
+                write!(f, "frame {:#x} after trailers", ty.into_inner())
+            }
+            GrammarError::Forbidden(ty) => {
+                write!(
+                    f,
+                    "frame {:#x} not allowed on a request stream",
+                    ty.into_inner()
+                )
+            }
+        }
+    }
+}
+
+impl RequestGrammar {
+    /// The grammar of a stream whose HEADERS frame was received before it is checked
+    pub fn after_headers() -> Self {
+        Self {
+            state: GrammarState::Headers,
+        }
+    }
+
+    /// Whether the HEADERS frame of the message was received
+    pub fn has_headers(&self) -> bool {
+        self.state != GrammarState::Start
+    }
+
+    /// Accounts for the HEADERS frame just received or sent being an interim response
+    ///
+    /// Any number of interim responses may precede the final one, the next HEADERS frame is
+    /// then a response again rather than trailers.
+    pub fn on_interim_headers(&mut self) {
+        if self.state == GrammarState::Headers {
+            self.state = GrammarState::Start;
+        }
+    }
+
+    /// Accounts for `frame`, as returned by [`FrameStream::poll_next()`] or about to be sent
+    ///
+    /// Once an error is returned, the stream is in error and the state is not updated anymore.
+    pub fn on_frame<B>(&mut self, frame: &Frame<B>) -> Result<(), GrammarError> {
+        let ty = match frame {
+            Frame::Headers(_) | Frame::Data(_) => frame.frame_type().expect("known type"),
+            Frame::PushPromise(_) | Frame::Extension(_) | Frame::Origin { .. } | Frame::Grease => {
+                return Ok(())
+            }
+            //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.3
+            //# Receiving a
+            //# CANCEL_PUSH frame on a stream other than the control stream MUST be
+            //# treated as a connection error of type H3_FRAME_UNEXPECTED.
+
+            //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.4
+            //# If an endpoint receives a SETTINGS frame on a different
+            //# stream, the endpoint MUST respond with a connection error of type
+            //# H3_FRAME_UNEXPECTED.
+
+            //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.6
+            //# A client MUST treat a GOAWAY frame on a stream other than
+            //# the control stream as a connection error of type H3_FRAME_UNEXPECTED.
+
+            //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.7
+            //# The MAX_PUSH_ID frame is always sent on the control stream.  Receipt
+            //# of a MAX_PUSH_ID frame on any other stream MUST be treated as a
+            //# connection error of type H3_FRAME_UNEXPECTED.
+            Frame::Settings(_)
+            | Frame::CancelPush(_)
+            | Frame::Goaway(_)
+            | Frame::MaxPushId(_)
+            | Frame::WebTransportStream(_) => {
+                return Err(GrammarError::Forbidden(
+                    frame.frame_type().expect("known type"),
+                ))
+            }
+        };
+
+        self.state = match (self.state, ty) {
+            (GrammarState::Start, FrameType::HEADERS) => GrammarState::Headers,
+            (GrammarState::Start, _) => return Err(GrammarError::DataBeforeHeaders),
+            (GrammarState::Headers, FrameType::DATA) | (GrammarState::Body, FrameType::DATA) => {
+                GrammarState::Body
+            }
+            (GrammarState::Headers, _) => GrammarState::Trailers { after_data: false },
+            (GrammarState::Body, _) => GrammarState::Trailers { after_data: true },
+            (GrammarState::Trailers { after_data: false }, _) => {
+                return Err(GrammarError::RepeatedHeaders(ty))
+            }
+            (GrammarState::Trailers { after_data: true }, _) => {
+                return Err(GrammarError::FrameAfterTrailers(ty))
+            }
+        };
+        Ok(())
+    }
+}
+
+/// The frames of a request encoded once, to be sent again as they are
+///
+/// A request sent in 0-RTT is replayed on a new stream if the server rejects early data. The
+/// replay must carry the same field sections, without running them through the QPACK encoder
+/// again, whose dynamic table may have changed in between.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodedRequest {
+    encoded: Bytes,
+}
+
+impl EncodedRequest {
+    /// Encodes `frames` and their payload, such as a HEADERS frame followed by DATA frames
+    pub fn from_frames(frames: &[Frame<Bytes>]) -> Self {
+        let mut buf = BytesMut::new();
+        Frame::reserve_frames(frames, &mut buf);
+        for frame in frames {
+            frame.encode(&mut buf);
+            if let Some(payload) = frame.payload() {
+                buf.put_slice(payload.chunk());
+            }
+        }
+        Self {
+            encoded: buf.freeze(),
+        }
+    }
+
+    /// Writes the encoded frames to `stream`, waiting for them to be sent
+    ///
+    /// The stream is left open, for the caller to send more or finish it.
+    pub async fn send_to<S: SendStream<Bytes>>(&self, stream: &mut S) -> Result<(), Error> {
+        stream::write(stream, WriteBuf::encoded(self.encoded.clone())).await
+    }
+}
+
+/// Decodes frames out of the bytes received on a stream
 #[derive(Default)]
 pub struct FrameDecoder {
     expected: Option<usize>,
+    // Every decoded frame, including skipped ones
+    decoded: u64,
+    // Frames of unknown type decoded, whether skipped or handed to an extension
+    unknown: u64,
+    // Payload length of the last frame returned
+    frame_len: u64,
+    // Reject varints which are not minimally encoded
+    strict_varint: bool,
+    // Keep the bytes of the last decoded frame in `raw`
+    retain_raw: bool,
+    raw: Option<Bytes>,
+    // Longest HEADERS payload accepted
+    max_field_section_size: Option<u64>,
+    // Return frames of unknown type as `Frame::Extension`, rather than skipping them
+    decode_extensions: bool,
 }
 
 impl FrameDecoder {
-    fn decode<B: Buf>(
+    /// A decoder resuming in the middle of a frame, which waits for `expected` buffered bytes
+    ///
+    /// `expected` counts from the start of the frame, which must still be buffered, and comes
+    /// from [`FrameDecoder::expected()`] when decoding was suspended. The options of the
+    /// suspended decoder, such as limits, are not carried over.
+    #[cfg(any(
+        test,
+        feature = "i-implement-a-third-party-backend-and-opt-into-breaking-changes"
+    ))]
+    pub fn resume(expected: Option<usize>) -> Self {
+        Self {
+            expected,
+            ..Self::default()
+        }
+    }
+
+    /// Minimum number of buffered bytes needed to decode the next frame, once known
+    ///
+    /// It is known when the last attempt found the frame incomplete.
+    #[cfg(any(
+        test,
+        feature = "i-implement-a-third-party-backend-and-opt-into-breaking-changes"
+    ))]
+    pub fn expected(&self) -> Option<usize> {
+        self.expected
+    }
+
+    fn is_satisfied_by(&self, buffered: usize) -> bool {
+        match self.expected {
+            Some(min) => buffered >= min,
+            None => buffered > 0,
+        }
+    }
+
+    /// Decode the next frame of `src`, skipping unknown ones
+    ///
+    /// Returns `None` until a whole frame is buffered. The payload of a DATA frame is not part of
+    /// it, and is left in `src` for the caller to consume.
+    ///
+    /// The field section of a HEADERS frame received in a single chunk is a slice of that chunk,
+    /// rather than a copy. The trade-off is that the whole chunk stays allocated for as long as
+    /// the field section is held, other frames and DATA payload it carries included. Connections
+    /// drop it once QPACK-decoded, right away.
+    pub fn decode<B: Buf>(
         &mut self,
         src: &mut BufList<B>,
     ) -> Result<Option<Frame<PayloadLen>>, FrameStreamError> {
@@ -214,23 +1450,60 @@ impl FrameDecoder {
                 return Ok(None);
             }
 
+            if let Some(max_size) = self.max_field_section_size {
+                let header = FrameType::decode_header(&mut src.cursor(), self.strict_varint);
+                match header {
+                    Some((FrameType::HEADERS, size)) if size > header_cutoff(max_size) => {
+                        return Err(FrameStreamError::HeaderTooBig { size, max_size })
+                    }
+                    _ => (),
+                }
+            }
+
             if let Some(min) = self.expected {
                 if src.remaining() < min {
                     return Ok(None);
                 }
             }
 
+            if let Some(frame) = self.decode_headers(src) {
+                return Ok(Some(frame));
+            }
+
             let (pos, decoded) = {
                 let mut cur = src.cursor();
-                let decoded = Frame::decode(&mut cur);
+                let decoded = Frame::decode_with(&mut cur, self.strict_varint);
                 (cur.position(), decoded)
             };
 
             match decoded {
+                Err(frame::FrameError::UnknownFrame(ty)) if self.decode_extensions => {
+                    let header_len =
+                        FrameType::decode_header(&mut src.cursor(), self.strict_varint)
+                            .map_or(pos, |(_, len)| pos - len as usize);
+                    let raw = src.copy_to_bytes(pos);
+                    self.expected = None;
+                    self.decoded += 1;
+                    self.unknown += 1;
+                    self.frame_len = (pos - header_len) as u64;
+                    // PRIORITY_UPDATE is unimplemented rather than unknown, it is skipped anyway
+                    let extension = VarInt::from_u64(ty)
+                        .ok()
+                        .and_then(|ty| ExtensionFrame::new(ty, raw.slice(header_len..)));
+                    if self.retain_raw {
+                        self.raw = Some(raw);
+                    }
+                    match extension {
+                        Some(frame) => return Ok(Some(Frame::Extension(frame))),
+                        None => continue,
+                    }
+                }
                 Err(frame::FrameError::UnknownFrame(ty)) => {
                     trace!("ignore unknown frame type {:#x}", ty);
                     src.advance(pos);
                     self.expected = None;
+                    self.decoded += 1;
+                    self.unknown += 1;
                     continue;
                 }
                 Err(frame::FrameError::Incomplete(min)) => {
@@ -239,13 +1512,59 @@ impl FrameDecoder {
                 }
                 Err(e) => return Err(e.into()),
                 Ok(frame) => {
-                    src.advance(pos);
+                    self.frame_len = match frame {
+                        // No length, the rest of the stream is the payload
+                        Frame::WebTransportStream(_) => 0,
+                        _ => FrameType::decode_header(&mut src.cursor(), self.strict_varint)
+                            .map_or(0, |(_, len)| len),
+                    };
+                    if self.retain_raw {
+                        self.raw = Some(src.copy_to_bytes(pos));
+                    } else {
+                        src.advance(pos);
+                    }
                     self.expected = None;
+                    self.decoded += 1;
                     return Ok(Some(frame));
                 }
             }
         }
     }
+
+    /// Decodes the HEADERS frame at the start of `src`, if it is entirely buffered
+    ///
+    /// Unlike [`Frame::decode_with()`] on a cursor, this takes the frame out of `src`, so its
+    /// field section shares the memory of the chunk holding it, rather than being copied.
+    fn decode_headers<B: Buf>(&mut self, src: &mut BufList<B>) -> Option<Frame<PayloadLen>> {
+        let mut cur = src.cursor();
+        let (ty, len) = FrameType::decode_header(&mut cur, self.strict_varint)?;
+        let header_len = cur.position();
+        let frame_len = usize::try_from(len).ok()?.checked_add(header_len)?;
+        if ty != FrameType::HEADERS || src.remaining() < frame_len {
+            return None;
+        }
+
+        let raw = src.copy_to_bytes(frame_len);
+        self.expected = None;
+        self.decoded += 1;
+        self.frame_len = len;
+        let encoded = raw.slice(header_len..);
+        if self.retain_raw {
+            self.raw = Some(raw);
+        }
+        trace!("got frame HEADERS, len: {}", len);
+        Some(Frame::Headers(encoded))
+    }
+}
+
+/// Longest HEADERS payload which may decode within `max_field_section_size`
+///
+/// Each field line adds 32 bytes to the decoded size on top of its name and value, which is
+/// more than the integers encoding it take. Huffman coding may still make names and values
+/// longer, by up to 30 bits per byte, so only payloads over 4 times the limit are sure not to
+/// fit, unless integers are padded, which no encoder does.
+fn header_cutoff(max_field_section_size: u64) -> u64 {
+    max_field_section_size.saturating_mul(4)
 }
 
 #[derive(Debug)]
@@ -253,6 +1572,25 @@ pub enum FrameStreamError {
     Proto(frame::FrameError),
     Quic(TransportError),
     UnexpectedEnd,
+    /// A HEADERS frame is too long to fit the applied field section size limit once decoded,
+    /// `size` is its payload length
+    HeaderTooBig {
+        size: u64,
+        max_size: u64,
+    },
+    /// `poll_data()` was called while a DATA frame `poll_next()` did not decode is buffered
+    DataNotDecoded,
+    /// The next frame was polled while the payload of a DATA frame is still to be read
+    DataNotRead,
+    /// The extension handler refused a frame, and stopped the stream with this code
+    ExtensionRejected(Code),
+    /// A PUSH_PROMISE frame was received for a push id above the limit, see
+    /// [`FrameStream::set_push_id_limit()`]
+    PushIdNotAllowed(PushId),
+    /// The frames received do not follow the grammar of a request stream
+    Grammar(GrammarError),
+    /// A chunk received does not fit in the [`BufferBudget`] shared with other streams
+    BufferBudgetExceeded,
 }
 
 impl From<frame::FrameError> for FrameStreamError {
@@ -261,6 +1599,74 @@ impl From<frame::FrameError> for FrameStreamError {
     }
 }
 
+impl From<GrammarError> for FrameStreamError {
+    fn from(err: GrammarError) -> Self {
+        FrameStreamError::Grammar(err)
+    }
+}
+
+impl fmt::Display for FrameStreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameStreamError::Proto(e) => write!(f, "invalid frame: {}", e),
+            FrameStreamError::Quic(e) => write!(f, "transport error: {}", e),
+            FrameStreamError::UnexpectedEnd => write!(f, "received incomplete frame"),
+            FrameStreamError::HeaderTooBig { size, max_size } => write!(
+                f,
+                "HEADERS frame of {} bytes exceeds the limit of {}",
+                size, max_size
+            ),
+            FrameStreamError::DataNotDecoded => {
+                write!(f, "polled data before decoding the DATA frame")
+            }
+            FrameStreamError::DataNotRead => {
+                write!(f, "polled the next frame before reading the DATA payload")
+            }
+            FrameStreamError::ExtensionRejected(code) => {
+                write!(f, "extension frame rejected with {:?}", code)
+            }
+            FrameStreamError::PushIdNotAllowed(id) => {
+                write!(f, "received a PUSH_PROMISE for {} above the limit", id)
+            }
+            FrameStreamError::Grammar(e) => e.fmt(f),
+            FrameStreamError::BufferBudgetExceeded => {
+                write!(f, "received data exceeds the shared buffer budget")
+            }
+        }
+    }
+}
+
+impl std::error::Error for FrameStreamError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FrameStreamError::Proto(e) => Some(e),
+            FrameStreamError::Quic(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// For the adapters of a stream to `AsyncRead`, the original error being the source of the result
+impl From<FrameStreamError> for std::io::Error {
+    fn from(err: FrameStreamError) -> Self {
+        use std::io::ErrorKind;
+
+        let kind = match err {
+            FrameStreamError::UnexpectedEnd => ErrorKind::UnexpectedEof,
+            FrameStreamError::Quic(_)
+            | FrameStreamError::DataNotDecoded
+            | FrameStreamError::DataNotRead
+            | FrameStreamError::BufferBudgetExceeded => ErrorKind::Other,
+            FrameStreamError::Proto(_)
+            | FrameStreamError::HeaderTooBig { .. }
+            | FrameStreamError::ExtensionRejected(_)
+            | FrameStreamError::PushIdNotAllowed(_)
+            | FrameStreamError::Grammar(_) => ErrorKind::InvalidData,
+        };
+        std::io::Error::new(kind, err)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -268,10 +1674,21 @@ mod tests {
     use assert_matches::assert_matches;
     use bytes::{BufMut, Bytes, BytesMut};
     use futures_util::future::poll_fn;
-    use std::{collections::VecDeque, fmt, sync::Arc};
+    use std::{
+        collections::VecDeque,
+        fmt,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
 
     use crate::{
-        proto::{coding::Encode, frame::FrameType, varint::VarInt},
+        proto::{
+            coding::Encode,
+            frame::{FrameType, PushPromise},
+            varint::VarInt,
+        },
         quic,
     };
 
@@ -313,6 +1730,59 @@ mod tests {
         assert_matches!(decoder.decode(&mut buf_list), Ok(Some(Frame::Headers(_))));
     }
 
+    /// Whether `inner` points into the memory of `outer`
+    fn shares_memory(outer: &Bytes, inner: &Bytes) -> bool {
+        outer.as_ptr_range().contains(&inner.as_ptr())
+    }
+
+    #[test]
+    fn headers_sliced_from_chunk() {
+        let mut buf = BytesMut::with_capacity(32);
+        Frame::headers(&b"salut"[..]).encode_with_payload(&mut buf);
+        Frame::Data(Bytes::from("data")).encode_with_payload(&mut buf);
+        let chunk = buf.freeze();
+        let mut buf_list = BufList::new();
+        buf_list.push(chunk.clone());
+
+        let mut decoder = FrameDecoder::default();
+        let encoded = match decoder.decode(&mut buf_list) {
+            Ok(Some(Frame::Headers(encoded))) => encoded,
+            frame => panic!("unexpected {:?}", frame),
+        };
+        assert_eq!(&encoded[..], b"salut");
+        assert!(shares_memory(&chunk, &encoded));
+
+        // The chunk is held by the field section, then by the DATA frame left in the buffer
+        assert_matches!(decoder.decode(&mut buf_list), Ok(Some(Frame::Data(_))));
+        drop(encoded);
+        assert!(!chunk.is_unique());
+        buf_list.advance(4);
+        assert!(chunk.is_unique());
+    }
+
+    #[test]
+    fn headers_copied_across_chunks() {
+        let mut buf = BytesMut::with_capacity(16);
+        Frame::headers(&b"salut"[..]).encode_with_payload(&mut buf);
+        // Separate allocations, as received from the transport
+        let first = Bytes::copy_from_slice(&buf[..4]);
+        let buf = Bytes::copy_from_slice(&buf[4..]);
+        let mut buf_list = BufList::new();
+        buf_list.push(first.clone());
+        buf_list.push(buf.clone());
+
+        let mut decoder = FrameDecoder::default();
+        let encoded = match decoder.decode(&mut buf_list) {
+            Ok(Some(Frame::Headers(encoded))) => encoded,
+            frame => panic!("unexpected {:?}", frame),
+        };
+        assert_eq!(&encoded[..], b"salut");
+        assert!(!shares_memory(&first, &encoded) && !shares_memory(&buf, &encoded));
+        // The chunks are released while the field section is still held
+        assert!(!buf_list.has_remaining());
+        assert!(first.is_unique() && buf.is_unique());
+    }
+
     #[test]
     fn varint_spread_multiple_buf() {
         let mut buf = BytesMut::with_capacity(16);
@@ -346,6 +1816,137 @@ mod tests {
         assert_matches!(decoder.decode(&mut buf), Ok(None));
     }
 
+    #[test]
+    fn resume_split_frame() {
+        let mut buf = BytesMut::with_capacity(16);
+        Frame::headers(&b"header"[..]).encode_with_payload(&mut buf);
+        let mut buf_list = BufList::new();
+        buf_list.push(&buf[..3]);
+
+        let mut decoder = FrameDecoder::default();
+        assert_matches!(decoder.decode(&mut buf_list), Ok(None));
+        assert_eq!(decoder.expected(), Some(buf.len()));
+
+        // Resumed from the persisted state, with the rest received in two parts
+        let mut decoder = FrameDecoder::resume(Some(buf.len()));
+        buf_list.push(&buf[3..buf.len() - 1]);
+        assert_matches!(decoder.decode(&mut buf_list), Ok(None));
+        buf_list.push(&buf[buf.len() - 1..]);
+        assert_matches!(
+            decoder.decode(&mut buf_list),
+            Ok(Some(Frame::Headers(b))) if &b[..] == b"header"
+        );
+        assert_eq!(decoder.expected(), None);
+    }
+
+    // RequestGrammar
+
+    fn grammar_check(frames: &[Frame<Bytes>]) -> Result<(), GrammarError> {
+        let mut grammar = RequestGrammar::default();
+        frames.iter().try_for_each(|frame| grammar.on_frame(frame))
+    }
+
+    #[test]
+    fn request_grammar_valid() {
+        let headers = || Frame::headers(&b"header"[..]);
+        let data = || Frame::Data(Bytes::from("data"));
+        let ext = || Frame::Extension(ExtensionFrame::new(VarInt(0x4d), Bytes::new()).unwrap());
+        let push = || Frame::PushPromise(PushPromise::new(PushId(0), Bytes::new()));
+
+        assert_eq!(grammar_check(&[headers()]), Ok(()));
+        assert_eq!(grammar_check(&[headers(), data(), data()]), Ok(()));
+        assert_eq!(grammar_check(&[headers(), headers()]), Ok(()));
+        assert_eq!(
+            grammar_check(&[ext(), headers(), push(), data(), ext(), headers(), ext()]),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn request_grammar_data_first() {
+        let frames = [
+            Frame::Data(Bytes::from("data")),
+            Frame::headers(&b"header"[..]),
+        ];
+        assert_eq!(grammar_check(&frames), Err(GrammarError::DataBeforeHeaders));
+    }
+
+    #[test]
+    fn request_grammar_repeated_headers() {
+        let headers = || Frame::headers(&b"header"[..]);
+        assert_eq!(
+            grammar_check(&[headers(), headers(), headers()]),
+            Err(GrammarError::RepeatedHeaders(FrameType::HEADERS))
+        );
+        assert_eq!(
+            grammar_check(&[headers(), headers(), Frame::Data(Bytes::from("data"))]),
+            Err(GrammarError::RepeatedHeaders(FrameType::DATA))
+        );
+    }
+
+    #[test]
+    fn response_grammar_interim_headers() {
+        let headers = || Frame::headers(&b"header"[..]);
+        let data = || Frame::Data(Bytes::from("data"));
+
+        // 103, then 100, then the final response
+        let mut grammar = RequestGrammar::default();
+        for _ in 0..2 {
+            grammar.on_frame(&headers()).unwrap();
+            grammar.on_interim_headers();
+            assert!(!grammar.has_headers());
+        }
+        assert_eq!(
+            grammar.on_frame(&data()),
+            Err(GrammarError::DataBeforeHeaders)
+        );
+
+        let mut grammar = RequestGrammar::default();
+        grammar.on_frame(&headers()).unwrap();
+        grammar.on_interim_headers();
+        for frame in [headers(), data(), data(), headers()] {
+            grammar.on_frame(&frame).unwrap();
+        }
+
+        // A repeated final response is only ever taken for trailers
+        let mut grammar = RequestGrammar::default();
+        grammar.on_frame(&headers()).unwrap();
+        grammar.on_interim_headers();
+        grammar.on_frame(&headers()).unwrap();
+        grammar.on_frame(&headers()).unwrap();
+        assert_eq!(
+            grammar.on_frame(&data()),
+            Err(GrammarError::RepeatedHeaders(FrameType::DATA))
+        );
+    }
+
+    #[test]
+    fn request_grammar_frame_after_trailers() {
+        let headers = || Frame::headers(&b"header"[..]);
+        let data = || Frame::Data(Bytes::from("data"));
+        assert_eq!(
+            grammar_check(&[headers(), data(), headers(), data()]),
+            Err(GrammarError::FrameAfterTrailers(FrameType::DATA))
+        );
+        assert_eq!(
+            grammar_check(&[headers(), data(), headers(), headers()]),
+            Err(GrammarError::FrameAfterTrailers(FrameType::HEADERS))
+        );
+    }
+
+    #[test]
+    fn request_grammar_forbidden_frames() {
+        let mut grammar = RequestGrammar::default();
+        grammar.on_frame(&Frame::headers(&b"header"[..])).unwrap();
+        let err = grammar
+            .on_frame(&Frame::<Bytes>::Goaway(VarInt(4)))
+            .unwrap_err();
+        assert_eq!(err, GrammarError::Forbidden(FrameType::GOAWAY));
+
+        let err = Error::from(FrameStreamError::from(err));
+        assert_eq!(err.try_get_code(), Some(Code::H3_FRAME_UNEXPECTED));
+    }
+
     // FrameStream
 
     macro_rules! assert_poll_matches {
@@ -388,41 +1989,137 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn poll_next_incomplete_frame() {
-        let mut recv = FakeRecv::default();
-        let mut buf = BytesMut::with_capacity(64);
-
-        Frame::headers(&b"header"[..]).encode_with_payload(&mut buf);
+    async fn peek_header_before_reading_body() {
+        let mut buf = BytesMut::with_capacity(16);
+        FrameType::DATA.encode(&mut buf);
+        VarInt::from_u32(1 << 20).encode(&mut buf);
         let mut buf = buf.freeze();
-        recv.chunk(buf.split_to(buf.len() - 1));
+        let mut recv = FakeRecv::default();
+        recv.chunk(buf.split_to(1))
+            .chunk(buf)
+            .chunk(Bytes::from("start of the body"));
+        let stopped = recv.stopped.clone();
         let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
 
-        assert_poll_matches!(
+        // Only the header is received, and it is left to decode
+        for _ in 0..2 {
+            assert_poll_matches!(
+                |cx| stream.poll_peek_header(cx),
+                Ok(Some((FrameType::DATA, len))) if len == 1 << 20
+            );
+            assert_eq!(stream.buffered_len(), 5);
+        }
+        assert_poll_matches!(
+            |cx| stream.poll_next(cx),
+            Ok(Some(Frame::Data(PayloadLen(len)))) if len == 1 << 20
+        );
+
+        // The body is too long to accept, it is refused before being read
+        stream.stop_sending(Code::H3_EXCESSIVE_LOAD);
+        assert_eq!(
+            *stopped.lock().unwrap(),
+            Some(Code::H3_EXCESSIVE_LOAD.value())
+        );
+    }
+
+    #[tokio::test]
+    async fn poll_next_incomplete_frame() {
+        let mut recv = FakeRecv::default();
+        let mut buf = BytesMut::with_capacity(64);
+
+        Frame::headers(&b"header"[..]).encode_with_payload(&mut buf);
+        let mut buf = buf.freeze();
+        recv.chunk(buf.split_to(buf.len() - 1));
+        let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+
+        assert_poll_matches!(
+            |cx| stream.poll_next(cx),
+            Err(FrameStreamError::UnexpectedEnd)
+        );
+    }
+
+    #[tokio::test]
+    async fn poll_next_expect_more() {
+        let mut recv = FakeRecv::default();
+        let mut buf = BytesMut::with_capacity(64);
+
+        Frame::headers(&b"header"[..]).encode_with_payload(&mut buf);
+        recv.chunk(buf.freeze());
+        let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+        stream.expect_more(true);
+
+        assert_poll_matches!(|cx| stream.poll_next(cx), Ok(Some(Frame::Headers(_))));
+        assert_poll_matches!(
             |cx| stream.poll_next(cx),
             Err(FrameStreamError::UnexpectedEnd)
         );
+
+        stream.expect_more(false);
+        assert_poll_matches!(|cx| stream.poll_next(cx), Ok(None));
     }
 
     #[tokio::test]
-    #[should_panic(
-        expected = "There is still data to read, please call poll_data() until it returns None"
-    )]
     async fn poll_next_reamining_data() {
         let mut recv = FakeRecv::default();
         let mut buf = BytesMut::with_capacity(64);
 
         FrameType::DATA.encode(&mut buf);
         VarInt::from(4u32).encode(&mut buf);
-        recv.chunk(buf.freeze());
+        recv.chunk(buf.freeze()).chunk(Bytes::from("body"));
+        let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+
+        assert_poll_matches!(
+            |cx| stream.poll_next(cx),
+            Ok(Some(Frame::Data(PayloadLen(4))))
+        );
+
+        // There is still data to consume, which is kept for poll_data
+        assert_poll_matches!(
+            |cx| stream.poll_next(cx),
+            Err(FrameStreamError::DataNotRead)
+        );
+        assert_matches!(stream.drain_buffered(1), Err(FrameStreamError::DataNotRead));
+        assert_poll_matches!(
+            |cx| to_bytes(stream.poll_data(cx)),
+            Ok(Some(b)) if &b[..] == b"body"
+        );
+        assert_poll_matches!(|cx| stream.poll_next(cx), Ok(None));
+    }
+
+    #[tokio::test]
+    async fn buffered_frame_after_undrained_data() {
+        let mut recv = FakeRecv::default();
+        let mut buf = BytesMut::with_capacity(64);
+
+        // The HEADERS frame is buffered with the end of the DATA payload
+        Frame::Data(Bytes::from("body")).encode_with_payload(&mut buf);
+        Frame::headers("header").encode_with_payload(&mut buf);
+        let mut buf = buf.freeze();
+        recv.chunk(buf.split_to(4)).chunk(buf);
         let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
 
         assert_poll_matches!(
             |cx| stream.poll_next(cx),
             Ok(Some(Frame::Data(PayloadLen(4))))
         );
+        assert_poll_matches!(
+            |cx| to_bytes(stream.poll_data(cx)),
+            Ok(Some(b)) if &b[..] == b"bo"
+        );
+        assert_poll_matches!(
+            |cx| stream.poll_next(cx),
+            Err(FrameStreamError::DataNotRead)
+        );
+        assert_matches!(stream.drain_buffered(4), Err(FrameStreamError::DataNotRead));
 
-        // There is still data to consume, poll_next should panic
-        let _ = poll_fn(|cx| stream.poll_next(cx)).await;
+        // Only the rest of the payload is handed out, the HEADERS frame stays buffered
+        assert_poll_matches!(
+            |cx| to_bytes(stream.poll_data(cx)),
+            Ok(Some(b)) if &b[..] == b"dy"
+        );
+        assert_eq!(stream.buffered_len(), 8);
+        assert_poll_matches!(|cx| stream.poll_next(cx), Ok(Some(Frame::Headers(_))));
+        assert_poll_matches!(|cx| stream.poll_next(cx), Ok(None));
     }
 
     #[tokio::test]
@@ -455,6 +2152,299 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn poll_data_stops_at_frame_boundary() {
+        let mut recv = FakeRecv::default();
+        let mut buf = BytesMut::with_capacity(64);
+
+        // The body and the following frames arrive in one contiguous chunk
+        Frame::Data(Bytes::from("body")).encode_with_payload(&mut buf);
+        Frame::Data(Bytes::from("next")).encode_with_payload(&mut buf);
+        Frame::headers(&b"trailer"[..]).encode_with_payload(&mut buf);
+        recv.chunk(buf.freeze());
+        let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+
+        assert_poll_matches!(
+            |cx| stream.poll_next(cx),
+            Ok(Some(Frame::Data(PayloadLen(4))))
+        );
+        assert_poll_matches!(
+            |cx| to_bytes(stream.poll_data(cx)),
+            Ok(Some(b)) if &b[..] == b"body"
+        );
+        assert_poll_matches!(|cx| to_bytes(stream.poll_data(cx)), Ok(None));
+
+        assert_poll_matches!(
+            |cx| stream.poll_next(cx),
+            Ok(Some(Frame::Data(PayloadLen(4))))
+        );
+        assert_poll_matches!(
+            |cx| to_bytes(stream.poll_data(cx)),
+            Ok(Some(b)) if &b[..] == b"next"
+        );
+        assert_poll_matches!(
+            |cx| stream.poll_next(cx),
+            Ok(Some(Frame::Headers(b))) if &b[..] == b"trailer"
+        );
+    }
+
+    #[tokio::test]
+    async fn poll_data_coalesces_data_frames() {
+        let mut recv = FakeRecv::default();
+        let mut buf = BytesMut::with_capacity(64);
+
+        // The third frame arrives separately, its header split from its payload
+        Frame::Data(Bytes::from("hyper")).encode_with_payload(&mut buf);
+        Frame::Data(Bytes::from("")).encode_with_payload(&mut buf);
+        Frame::Data(Bytes::from("text")).encode(&mut buf);
+        recv.chunk(buf.split().freeze());
+        recv.chunk(Bytes::from("text"));
+        Frame::Data(Bytes::from(" transfer")).encode_with_payload(&mut buf);
+        Frame::headers(&b"trailer"[..]).encode_with_payload(&mut buf);
+        recv.chunk(buf.freeze());
+        let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+        stream.set_coalesce_data(true);
+
+        assert_poll_matches!(
+            |cx| stream.poll_next(cx),
+            Ok(Some(Frame::Data(PayloadLen(5))))
+        );
+        let mut body = BytesMut::new();
+        while let Some(data) = poll_fn(|cx| to_bytes(stream.poll_data(cx))).await.unwrap() {
+            body.put_slice(&data);
+        }
+        assert_eq!(&body[..], b"hypertext transfer");
+
+        assert_poll_matches!(
+            |cx| stream.poll_next(cx),
+            Ok(Some(Frame::Headers(b))) if &b[..] == b"trailer"
+        );
+        assert_poll_matches!(|cx| stream.poll_next(cx), Ok(None));
+    }
+
+    #[tokio::test]
+    async fn data_complete_once_payload_read() {
+        let mut recv = FakeRecv::default();
+        let mut buf = BytesMut::with_capacity(64);
+        Frame::Data(Bytes::from("hypertext")).encode(&mut buf);
+        buf.put_slice(b"hyper");
+        recv.chunk(buf.split().freeze()).chunk(Bytes::from("text"));
+        Frame::headers(&b"trailer"[..]).encode_with_payload(&mut buf);
+        recv.chunk(buf.freeze());
+        let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+
+        assert!(stream.data_complete());
+        assert_poll_matches!(
+            |cx| stream.poll_next(cx),
+            Ok(Some(Frame::Data(PayloadLen(9))))
+        );
+        assert!(!stream.data_complete());
+
+        let mut body = BytesMut::new();
+        while body.len() < 9 {
+            assert_poll_matches!(
+                |cx| stream.poll_data_end(cx),
+                Err(FrameStreamError::DataNotRead)
+            );
+            let data = poll_fn(|cx| to_bytes(stream.poll_data(cx))).await.unwrap();
+            body.put_slice(&data.unwrap());
+        }
+        assert_eq!(&body[..], b"hypertext");
+        assert!(stream.data_complete());
+        assert_poll_matches!(|cx| stream.poll_data_end(cx), Ok(()));
+
+        assert_poll_matches!(
+            |cx| stream.poll_next(cx),
+            Ok(Some(Frame::Headers(b))) if &b[..] == b"trailer"
+        );
+    }
+
+    #[tokio::test]
+    async fn drain_to_end_returns_reset_code() {
+        let mut recv = FakeRecv::default();
+        let mut buf = BytesMut::with_capacity(64);
+
+        // A HEADERS frame over the limit, then a DATA frame cut short by the reset
+        Frame::headers(&b"header"[..]).encode_with_payload(&mut buf);
+        Frame::Data(Bytes::from("body")).encode(&mut buf);
+        recv.chunk(buf.freeze())
+            .chunk(Bytes::from("bo"))
+            .reset(Code::H3_REQUEST_CANCELLED.value());
+        let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+        stream.apply_settings(&AppliedSettings {
+            max_field_section_size: 1,
+        });
+
+        assert_poll_matches!(
+            |cx| stream.poll_next(cx),
+            Err(FrameStreamError::HeaderTooBig { .. })
+        );
+        assert_eq!(
+            stream.drain_to_end().await,
+            Some(Code::H3_REQUEST_CANCELLED.value())
+        );
+
+        // A finished stream has no code
+        let mut recv = FakeRecv::default();
+        recv.chunk(Bytes::from("garbage"));
+        let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+        assert_eq!(stream.drain_to_end().await, None);
+        assert_eq!(stream.buffered_len(), 0);
+    }
+
+    #[tokio::test]
+    async fn frames_before_reset() {
+        let mut recv = FakeRecv::default();
+        let mut buf = BytesMut::with_capacity(64);
+        Frame::headers(&b"header"[..]).encode_with_payload(&mut buf);
+        recv.chunk(buf.split().freeze())
+            .reset(Code::H3_REQUEST_CANCELLED.value());
+        let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+
+        assert_poll_matches!(|cx| stream.poll_next(cx), Ok(Some(Frame::Headers(_))));
+        assert_poll_matches!(
+            |cx| stream.poll_next(cx),
+            Err(FrameStreamError::Quic(e)) if e.err_code() == Some(Code::H3_REQUEST_CANCELLED.value())
+        );
+
+        // Several frames buffered when the reset is received
+        let mut recv = FakeRecv::default();
+        Frame::headers(&b"header"[..]).encode_with_payload(&mut buf);
+        Frame::Data(Bytes::from("body")).encode_with_payload(&mut buf);
+        Frame::headers(&b"trailer"[..]).encode_with_payload(&mut buf);
+        Frame::Data(Bytes::from("cut")).encode(&mut buf);
+        recv.chunk(buf.freeze())
+            .reset(Code::H3_REQUEST_CANCELLED.value());
+        let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+
+        assert_poll_matches!(|cx| stream.poll_next(cx), Ok(Some(Frame::Headers(_))));
+        assert_poll_matches!(
+            |cx| stream.poll_next(cx),
+            Ok(Some(Frame::Data(PayloadLen(4))))
+        );
+        assert_poll_matches!(|cx| to_bytes(stream.poll_data(cx)), Ok(Some(b)) if b == "body");
+        assert_poll_matches!(|cx| stream.poll_data(cx).map_ok(|d| d.is_none()), Ok(true));
+        assert_poll_matches!(
+            |cx| stream.poll_next(cx),
+            Ok(Some(Frame::Headers(h))) if h.remaining() == 7
+        );
+        // The DATA frame header is buffered, but not its payload
+        assert_poll_matches!(
+            |cx| stream.poll_next(cx),
+            Ok(Some(Frame::Data(PayloadLen(3))))
+        );
+        assert_poll_matches!(
+            |cx| to_bytes(stream.poll_data(cx)),
+            Err(FrameStreamError::Quic(e)) if e.err_code() == Some(Code::H3_REQUEST_CANCELLED.value())
+        );
+    }
+
+    #[tokio::test]
+    async fn poll_data_before_poll_next() {
+        let mut recv = FakeRecv::default();
+        let mut buf = BytesMut::with_capacity(64);
+        Frame::Data(Bytes::from("body")).encode_with_payload(&mut buf);
+        Frame::Data(Bytes::from("next")).encode_with_payload(&mut buf);
+        recv.chunk(buf.freeze());
+        let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+
+        // Buffers the frame without decoding it
+        assert_poll_matches!(|cx| stream.poll_decodable(cx), Ok(true));
+        assert_poll_matches!(
+            |cx| to_bytes(stream.poll_data(cx)),
+            Err(FrameStreamError::DataNotDecoded)
+        );
+
+        assert_poll_matches!(
+            |cx| stream.poll_next(cx),
+            Ok(Some(Frame::Data(PayloadLen(4))))
+        );
+        assert_poll_matches!(
+            |cx| to_bytes(stream.poll_data(cx)),
+            Ok(Some(b)) if &b[..] == b"body"
+        );
+        assert_poll_matches!(|cx| to_bytes(stream.poll_data(cx)), Ok(None));
+        assert_poll_matches!(
+            |cx| to_bytes(stream.poll_data(cx)),
+            Err(FrameStreamError::DataNotDecoded)
+        );
+    }
+
+    #[tokio::test]
+    async fn applied_field_section_size() {
+        let mut recv = FakeRecv::default();
+        let mut buf = BytesMut::with_capacity(64);
+        Frame::headers(&b"head"[..]).encode_with_payload(&mut buf);
+        Frame::headers(&b"trailer"[..]).encode_with_payload(&mut buf);
+        let mut buf = buf.freeze();
+        recv.chunk(buf.split_to(6));
+        // Only the type and length of the oversized frame are received
+        recv.chunk(buf.split_to(2));
+        let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+        stream.apply_settings(&AppliedSettings {
+            max_field_section_size: 1,
+        });
+
+        // Longer than the limit, but it could still decode within it
+        assert_poll_matches!(|cx| stream.poll_next(cx), Ok(Some(Frame::Headers(_))));
+        assert_poll_matches!(
+            |cx| stream.poll_next(cx),
+            Err(FrameStreamError::HeaderTooBig {
+                size: 7,
+                max_size: 1
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn push_promise_above_push_id_limit() {
+        let mut buf = BytesMut::with_capacity(64);
+        let promise = || PushPromise::new(PushId(3), Bytes::from("promised"));
+        Frame::headers(&b"header"[..]).encode_with_payload(&mut buf);
+        Frame::<Bytes>::PushPromise(promise()).encode(&mut buf);
+        let buf = buf.freeze();
+
+        let mut recv = FakeRecv::default();
+        recv.chunk(buf.clone());
+        let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+        assert_poll_matches!(|cx| stream.poll_next(cx), Ok(Some(Frame::Headers(_))));
+        assert_poll_matches!(
+            |cx| stream.poll_next(cx),
+            Ok(Some(Frame::PushPromise(p))) if p == promise()
+        );
+
+        let mut recv = FakeRecv::default();
+        recv.chunk(buf.clone());
+        let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+        let mut limit = PushIdLimit::default();
+        limit.update(PushId(3)).unwrap();
+        stream.set_push_id_limit(limit);
+        assert_poll_matches!(|cx| stream.poll_next(cx), Ok(Some(Frame::Headers(_))));
+        assert_poll_matches!(
+            |cx| stream.poll_next(cx),
+            Ok(Some(Frame::PushPromise(p))) if p == promise()
+        );
+
+        // No push is allowed until a MAX_PUSH_ID frame
+        let mut recv = FakeRecv::default();
+        recv.chunk(buf.clone());
+        let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+        stream.set_push_id_limit(PushIdLimit::default());
+        assert_poll_matches!(|cx| stream.poll_next(cx), Ok(Some(Frame::Headers(_))));
+        let err = poll_fn(|cx| stream.poll_next(cx)).await.unwrap_err();
+        assert_matches!(err, FrameStreamError::PushIdNotAllowed(PushId(3)));
+        assert_eq!(Error::from(err).try_get_code(), Some(Code::H3_ID_ERROR));
+
+        let mut stream: FrameStream<_, ()> =
+            FrameStream::new(BufRecvStream::new(FakeRecv::default()));
+        stream.set_push_id_limit(PushIdLimit::default());
+        stream.stream.buf_mut().push_bytes(&mut buf.clone());
+        assert_matches!(
+            stream.drain_buffered(2),
+            Err(FrameStreamError::PushIdNotAllowed(PushId(3)))
+        );
+    }
+
     #[tokio::test]
     async fn poll_data_unexpected_end() {
         let mut recv = FakeRecv::default();
@@ -541,18 +2531,1147 @@ mod tests {
         );
     }
 
-    // Helpers
+    #[test]
+    fn length_delimited_message_across_data_frames() {
+        // A 10 bytes message, with its prefix split between the two frames, then a short one
+        let mut messages = BytesMut::new();
+        messages.put_u8(0);
+        messages.put_u32(10);
+        messages.put_slice(b"0123456789");
+        messages.put_u8(1);
+        messages.put_u32(2);
+        messages.put_slice(b"ok");
+        let mut messages = messages.freeze();
+
+        let mut reader = LengthDelimitedReader::default();
+        reader.push(&mut messages.split_to(3));
+        assert_eq!(reader.next_message(), None);
+        reader.push(&mut messages);
 
-    #[derive(Default)]
-    struct FakeRecv {
-        chunks: VecDeque<Bytes>,
+        assert_matches!(
+            reader.next_message(),
+            Some(m) if &m[..] == b"\0\0\0\0\x0a0123456789"
+        );
+        assert_matches!(reader.next_message(), Some(m) if &m[..] == b"\x01\0\0\0\x02ok");
+        assert_eq!(reader.next_message(), None);
+        assert!(reader.is_empty());
     }
 
-    impl FakeRecv {
-        fn chunk(&mut self, buf: Bytes) -> &mut Self {
-            self.chunks.push_back(buf);
-            self
-        }
+    #[test]
+    fn length_delimited_incomplete_message() {
+        let mut message = BytesMut::new();
+        message.put_u8(0);
+        message.put_u32(10);
+        message.put_slice(b"01234");
+
+        let mut reader = LengthDelimitedReader::default();
+        reader.push(&mut message.freeze());
+        assert_eq!(reader.next_message(), None);
+        assert!(!reader.is_empty());
+    }
+
+    #[tokio::test]
+    async fn frames_nested_in_data_stream() {
+        let mut recv = FakeRecv::default();
+        let mut buf = BytesMut::with_capacity(64);
+
+        // Frames carried by the body, split in the middle of the HEADERS one
+        let mut inner = BytesMut::new();
+        Frame::headers(&b"capsule"[..]).encode_with_payload(&mut inner);
+        Frame::<Bytes>::Goaway(VarInt(4)).encode(&mut inner);
+        let mut inner = inner.freeze();
+
+        Frame::Data(inner.split_to(5)).encode_with_payload(&mut buf);
+        Frame::Data(inner).encode_with_payload(&mut buf);
+        Frame::headers(&b"trailer"[..]).encode_with_payload(&mut buf);
+        recv.chunk(buf.freeze());
+        let stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+        let mut body = stream.into_data_stream();
+
+        let mut decoder = FrameDecoder::default();
+        let mut received = BufList::new();
+        let mut frames = Vec::new();
+        while let Some(data) = poll_fn(|cx| body.poll_data(cx)).await.unwrap() {
+            received.push(data);
+            while let Some(frame) = decoder.decode(&mut received).unwrap() {
+                frames.push(frame);
+            }
+        }
+        assert!(!received.has_remaining());
+        assert_matches!(
+            &frames[..],
+            [Frame::Headers(h), Frame::Goaway(id)] if &h[..] == b"capsule" && *id == VarInt(4)
+        );
+
+        let (_, trailing) = body.into_inner();
+        assert_matches!(trailing, Some(Frame::Headers(h)) if &h[..] == b"trailer");
+    }
+
+    #[tokio::test]
+    async fn data_stream_error_code() {
+        let mut recv = FakeRecv::default();
+        let mut buf = BytesMut::with_capacity(64);
+
+        Frame::Data(&b"body"[..]).encode_with_payload(&mut buf);
+        Frame::<Bytes>::PushPromise(PushPromise::new(PushId(3), Bytes::from("promised")))
+            .encode(&mut buf);
+        recv.chunk(buf.freeze());
+        let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+        stream.set_push_id_limit(PushIdLimit::default());
+        let mut body = stream.into_data_stream();
+
+        assert_poll_matches!(|cx| body.poll_data(cx), Ok(Some(b)) if &b[..] == b"body");
+        let err = poll_fn(|cx| body.poll_data(cx)).await.unwrap_err();
+        assert_eq!(err.err_code(), Some(Code::H3_ID_ERROR.value()));
+    }
+
+    #[tokio::test]
+    async fn decodable_once_expected_bytes_buffered() {
+        let mut buf = BytesMut::with_capacity(16);
+        Frame::headers(&b"salut"[..]).encode_with_payload(&mut buf);
+        let mut buf = buf.freeze();
+
+        let mut stream: FrameStream<_, ()> =
+            FrameStream::new(BufRecvStream::new(FakeRecv::default()));
+        assert!(!stream.is_decodable());
+
+        // Nothing is known about the frame before a first decoding attempt
+        stream.stream.buf_mut().push_bytes(&mut buf.split_to(4));
+        assert!(stream.is_decodable());
+        assert_matches!(stream.decoder.decode(stream.stream.buf_mut()), Ok(None));
+        assert!(!stream.is_decodable());
+
+        stream.stream.buf_mut().push_bytes(&mut buf.split_to(2));
+        assert!(!stream.is_decodable());
+
+        stream.stream.buf_mut().push_bytes(&mut buf);
+        assert!(stream.is_decodable());
+        assert_poll_matches!(|cx| stream.poll_next(cx), Ok(Some(Frame::Headers(_))));
+    }
+
+    #[test]
+    fn drain_buffered_in_batches() {
+        let mut buf = BytesMut::with_capacity(64);
+        for _ in 0..5 {
+            Frame::headers(&b"salut"[..]).encode_with_payload(&mut buf);
+        }
+        let mut stream: FrameStream<_, ()> =
+            FrameStream::new(BufRecvStream::new(FakeRecv::default()));
+        stream.stream.buf_mut().push_bytes(&mut buf.freeze());
+
+        assert_matches!(stream.drain_buffered(2), Ok(f) if f.len() == 2);
+        assert_matches!(stream.drain_buffered(2), Ok(f) if f.len() == 2);
+        assert_matches!(stream.drain_buffered(2), Ok(f) if f.len() == 1);
+        assert_matches!(stream.drain_buffered(2), Ok(f) if f.is_empty());
+    }
+
+    #[tokio::test]
+    async fn frame_count_includes_unknown_frames() {
+        use crate::proto::varint::BufMutExt as _;
+
+        let mut recv = FakeRecv::default();
+        for _ in 0..2 {
+            let mut buf = BytesMut::with_capacity(16);
+            crate::proto::frame::FrameType::grease().encode(&mut buf);
+            buf.write_var(0);
+            recv.chunk(buf.freeze());
+        }
+        let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+        assert_eq!(stream.frame_count(), 0);
+
+        assert_poll_matches!(|cx| stream.poll_next(cx), Ok(None));
+        assert_eq!(stream.frame_count(), 2);
+    }
+
+    #[tokio::test]
+    async fn split_halves_in_separate_tasks() {
+        let (mut client, mut server) = crate::test::pair();
+        let bidi = poll_fn(|cx| quic::Connection::poll_open_bidi(&mut client, cx))
+            .await
+            .unwrap();
+        let stream: FrameStream<_, Bytes> = FrameStream::new(BufRecvStream::new(bidi));
+        let (mut send, mut recv) = stream.split();
+
+        let write = tokio::spawn(async move {
+            for fields in ["first", "second"] {
+                let mut frame = Some(Frame::headers(fields.as_bytes()));
+                poll_fn(|cx| send.poll_send_frame(cx, &mut frame))
+                    .await
+                    .unwrap();
+            }
+            poll_fn(|cx| send.poll_finish(cx)).await.unwrap();
+        });
+        let read = tokio::spawn(async move {
+            let mut received = vec![];
+            while let Some(frame) = poll_fn(|cx| recv.poll_next(cx)).await.unwrap() {
+                received.push(frame);
+            }
+            received
+        });
+
+        // The peer answers while the request is still being sent
+        let bidi = poll_fn(|cx| quic::Connection::poll_accept_bidi(&mut server, cx))
+            .await
+            .unwrap()
+            .unwrap();
+        let mut peer: FrameStream<_, Bytes> = FrameStream::new(BufRecvStream::new(bidi));
+        let mut frame = Some(Frame::headers(&b"answer"[..]));
+        poll_fn(|cx| peer.poll_send_frame(cx, &mut frame))
+            .await
+            .unwrap();
+        poll_fn(|cx| peer.poll_finish(cx)).await.unwrap();
+
+        for fields in ["first", "second"] {
+            assert_matches!(
+                poll_fn(|cx| peer.poll_next(cx)).await,
+                Ok(Some(Frame::Headers(h))) if h == fields
+            );
+        }
+        assert_matches!(poll_fn(|cx| peer.poll_next(cx)).await, Ok(None));
+
+        write.await.unwrap();
+        assert_matches!(&read.await.unwrap()[..], [Frame::Headers(h)] if h == "answer");
+    }
+
+    /// Sends `frames` on a new request stream, and returns the frame stream of the server
+    async fn request_with(
+        frames: Vec<Frame<Bytes>>,
+    ) -> FrameStream<crate::test::BidiStream, Bytes> {
+        let (mut client, mut server) = crate::test::pair();
+        let bidi = poll_fn(|cx| quic::Connection::poll_open_bidi(&mut client, cx))
+            .await
+            .unwrap();
+        let mut request: FrameStream<_, Bytes> = FrameStream::new(BufRecvStream::new(bidi));
+        for frame in frames {
+            let mut frame = Some(frame);
+            poll_fn(|cx| request.poll_send_frame(cx, &mut frame))
+                .await
+                .unwrap();
+        }
+        poll_fn(|cx| request.poll_finish(cx)).await.unwrap();
+
+        let bidi = poll_fn(|cx| quic::Connection::poll_accept_bidi(&mut server, cx))
+            .await
+            .unwrap()
+            .unwrap();
+        FrameStream::new(BufRecvStream::new(bidi))
+    }
+
+    #[tokio::test]
+    async fn recv_request_round_trip() {
+        let mut stream = request_with(vec![
+            Frame::headers(&b"header"[..]),
+            Frame::Data(Bytes::from("bo")),
+            Frame::Data(Bytes::from("dy")),
+            Frame::headers(&b"trailer"[..]),
+        ])
+        .await;
+        stream.set_max_request_body(4);
+
+        let (headers, body, trailers) = stream.recv_request().await.unwrap();
+        assert_eq!(headers, "header");
+        assert_eq!(body, "body");
+        assert_eq!(trailers.as_deref(), Some(&b"trailer"[..]));
+
+        let mut stream = request_with(vec![Frame::headers(&b"header"[..])]).await;
+        let (_, body, trailers) = stream.recv_request().await.unwrap();
+        assert!(body.is_empty());
+        assert_eq!(trailers, None);
+    }
+
+    #[tokio::test]
+    async fn recv_request_body_above_limit() {
+        let mut stream = request_with(vec![
+            Frame::headers(&b"header"[..]),
+            Frame::Data(Bytes::from("bo")),
+            Frame::Data(Bytes::from("dy")),
+        ])
+        .await;
+        stream.set_max_request_body(3);
+
+        let err = stream.recv_request().await.unwrap_err();
+        assert_eq!(err.try_get_code(), Some(Code::H3_EXCESSIVE_LOAD));
+
+        let mut stream = request_with(vec![Frame::Data(Bytes::from("body"))]).await;
+        let err = stream.recv_request().await.unwrap_err();
+        assert_eq!(err.try_get_code(), Some(Code::H3_FRAME_UNEXPECTED));
+    }
+
+    #[tokio::test]
+    async fn poll_send_frame_waits_for_stream() {
+        let send = NotReadyOnce::default();
+        let sent = send.sent.clone();
+        let mut stream: FrameStream<_, Bytes> = FrameStream::new(BufRecvStream::new(send));
+        let mut frames = [
+            Some(Frame::headers(&b"header"[..])),
+            Some(Frame::Data(Bytes::from("body"))),
+        ];
+
+        for frame in frames.iter_mut() {
+            let mut polls = 0;
+            poll_fn(|cx| {
+                polls += 1;
+                stream.poll_send_frame(cx, frame)
+            })
+            .await
+            .unwrap();
+            // Not ready before sending, then while flushing
+            assert_eq!(polls, 3);
+            assert!(frame.is_none());
+        }
+
+        let mut wire = BytesMut::new();
+        Frame::headers(&b"header"[..]).encode_with_payload(&mut wire);
+        Frame::Data(Bytes::from("body")).encode_with_payload(&mut wire);
+        assert_eq!(*sent.lock().unwrap(), wire);
+    }
+
+    #[tokio::test]
+    async fn poll_send_frame_resets_on_send_error() {
+        let send = BrokenSend::default();
+        let reset = send.reset.clone();
+        let mut stream: FrameStream<_, Bytes> = FrameStream::new(BufRecvStream::new(send));
+        let mut frame = Some(Frame::Data(Bytes::from("body")));
+
+        let err = poll_fn(|cx| stream.poll_send_frame(cx, &mut frame))
+            .await
+            .unwrap_err();
+        assert_eq!(err.try_get_code(), Some(Code::H3_REQUEST_CANCELLED));
+        assert_eq!(
+            *reset.lock().unwrap(),
+            Some(Code::H3_INTERNAL_ERROR.value())
+        );
+    }
+
+    #[tokio::test]
+    async fn encoded_request_replay() {
+        let request = EncodedRequest::from_frames(&[
+            Frame::headers(&b"header"[..]),
+            Frame::Data(Bytes::from("body")),
+        ]);
+
+        let mut wire = BytesMut::new();
+        Frame::headers(&b"header"[..]).encode_with_payload(&mut wire);
+        Frame::Data(Bytes::from("body")).encode_with_payload(&mut wire);
+
+        // Sent once, then replayed on another stream
+        for _ in 0..2 {
+            let mut send = NotReadyOnce::default();
+            let sent = send.sent.clone();
+            request.send_to(&mut send).await.unwrap();
+            assert_eq!(*sent.lock().unwrap(), wire);
+        }
+    }
+
+    #[tokio::test]
+    async fn poll_abort_after_decode_error() {
+        let mut recv = FakeRecv::default();
+        let mut buf = BytesMut::with_capacity(64);
+        Frame::Data(Bytes::from("body")).encode_with_payload(&mut buf);
+        // An HTTP/2 PRIORITY frame, then more frames that are never read
+        buf.put_slice(&[0x02, 0x00]);
+        Frame::headers(&b"trailer"[..]).encode_with_payload(&mut buf);
+        recv.chunk(buf.freeze()).chunk(Bytes::from("unread"));
+        let stopped = recv.stopped.clone();
+        let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+
+        assert_poll_matches!(
+            |cx| stream.poll_next(cx),
+            Ok(Some(Frame::Data(PayloadLen(4))))
+        );
+        assert_poll_matches!(|cx| to_bytes(stream.poll_data(cx)), Ok(Some(_)));
+        assert_poll_matches!(|cx| to_bytes(stream.poll_data(cx)), Ok(None));
+        assert_poll_matches!(
+            |cx| stream.poll_next(cx),
+            Err(FrameStreamError::Proto(
+                frame::FrameError::UnsupportedFrame(2)
+            ))
+        );
+
+        poll_fn(|cx| stream.poll_abort(cx, Code::H3_FRAME_UNEXPECTED)).await;
+        assert_eq!(
+            *stopped.lock().unwrap(),
+            Some(Code::H3_FRAME_UNEXPECTED.value())
+        );
+        assert_eq!(stream.buffered_len(), 0);
+        assert_poll_matches!(|cx| stream.poll_next(cx), Ok(None));
+        assert_poll_matches!(|cx| to_bytes(stream.poll_data(cx)), Ok(None));
+        assert_poll_matches!(|cx| stream.poll_next(cx), Ok(None));
+    }
+
+    #[tokio::test]
+    async fn next_frame_len_hint() {
+        let mut buf = BytesMut::new();
+        Frame::Data(Bytes::from(vec![0x68; 300])).encode(&mut buf);
+        let header = buf.split().freeze();
+        assert_eq!(header.len(), 3);
+
+        let mut recv = FakeRecv::default();
+        recv.chunk(header.slice(..2)).chunk(header.slice(2..));
+        let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+        assert_eq!(stream.next_frame_len_hint(), None);
+
+        // Type and the first byte of the length
+        assert_poll_matches!(|cx| stream.try_recv(cx), Ok(false));
+        assert_eq!(stream.buffered_len(), 2);
+        assert_eq!(stream.next_frame_len_hint(), None);
+
+        assert_poll_matches!(|cx| stream.try_recv(cx), Ok(false));
+        assert_eq!(stream.buffered_len(), 3);
+        assert_eq!(stream.next_frame_len_hint(), Some(303));
+    }
+
+    #[test]
+    fn paused_stream_wakes_last_waker() {
+        let recv = PausedRecv::default();
+        let paused = recv.0.clone();
+        let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+
+        let first = Arc::new(WakeCounter::default());
+        let second = Arc::new(WakeCounter::default());
+        let mut buf = BytesMut::new();
+        Frame::headers(&b"header"[..]).encode_with_payload(&mut buf);
+        let buf = buf.freeze();
+
+        let poll = stream.poll_next(&mut Context::from_waker(&first.waker()));
+        assert_matches!(poll, Poll::Pending);
+        // Half a frame, still pending, with the waker of the last poll
+        paused.lock().unwrap().resume(buf.slice(..3));
+        assert_eq!(first.woken(), 1);
+        let poll = stream.poll_next(&mut Context::from_waker(&second.waker()));
+        assert_matches!(poll, Poll::Pending);
+        assert_eq!(second.woken(), 0);
+
+        paused.lock().unwrap().resume(buf.slice(3..));
+        assert_eq!((first.woken(), second.woken()), (1, 1));
+        let poll = stream.poll_next(&mut Context::from_waker(&second.waker()));
+        assert_matches!(poll, Poll::Ready(Ok(Some(Frame::Headers(f)))) if f == b"header"[..]);
+    }
+
+    #[test]
+    fn min_data_chunk() {
+        let recv = PausedRecv::default();
+        let paused = recv.0.clone();
+        let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+        stream.set_min_data_chunk(4);
+        let waker = Arc::new(WakeCounter::default());
+        let waker = waker.waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let mut buf = BytesMut::new();
+        Frame::Data(Bytes::from("abcdefghij")).encode(&mut buf);
+        buf.put_slice(b"ab");
+        paused.lock().unwrap().resume(buf.freeze());
+        assert_matches!(
+            stream.poll_next(&mut cx),
+            Poll::Ready(Ok(Some(Frame::Data(PayloadLen(10)))))
+        );
+
+        // Below the minimum, the chunks received are gathered
+        assert_matches!(to_bytes(stream.poll_data(&mut cx)), Poll::Pending);
+        paused.lock().unwrap().resume(Bytes::from("c"));
+        assert_matches!(to_bytes(stream.poll_data(&mut cx)), Poll::Pending);
+        paused.lock().unwrap().resume(Bytes::from("d"));
+        assert_matches!(
+            to_bytes(stream.poll_data(&mut cx)),
+            Poll::Ready(Ok(Some(b))) if b == "abcd"
+        );
+
+        // A chunk at the minimum is returned as is
+        paused.lock().unwrap().resume(Bytes::from("efgh"));
+        assert_matches!(
+            to_bytes(stream.poll_data(&mut cx)),
+            Poll::Ready(Ok(Some(b))) if b == "efgh"
+        );
+
+        // The end of the frame is below the minimum
+        paused.lock().unwrap().resume(Bytes::from("ij"));
+        assert_matches!(
+            to_bytes(stream.poll_data(&mut cx)),
+            Poll::Ready(Ok(Some(b))) if b == "ij"
+        );
+        assert_matches!(to_bytes(stream.poll_data(&mut cx)), Poll::Ready(Ok(None)));
+    }
+
+    #[tokio::test]
+    async fn poll_data_last_with_fin() {
+        let mut buf = BytesMut::new();
+        Frame::headers(&b"header"[..]).encode_with_payload(&mut buf);
+        let headers = buf.split().freeze();
+        Frame::Data(Bytes::from("body")).encode_with_payload(&mut buf);
+        let data = buf.split().freeze();
+        Frame::headers(&b"trailer"[..]).encode_with_payload(&mut buf);
+        let trailers = buf.split().freeze();
+
+        // The end of the stream is received after the last chunk
+        let mut recv = FakeRecv::default();
+        recv.chunk(headers.clone())
+            .chunk(data.slice(..3))
+            .chunk(data.slice(3..));
+        let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+
+        assert_poll_matches!(|cx| stream.poll_next(cx), Ok(Some(Frame::Headers(_))));
+        assert_poll_matches!(
+            |cx| stream.poll_next(cx),
+            Ok(Some(Frame::Data(PayloadLen(4))))
+        );
+        assert_poll_matches!(
+            |cx| to_last(stream.poll_data_last(cx)),
+            Ok(Some((b, false))) if b == "b"
+        );
+        assert_poll_matches!(
+            |cx| to_last(stream.poll_data_last(cx)),
+            Ok(Some((b, true))) if b == "ody"
+        );
+        assert_poll_matches!(|cx| stream.poll_data(cx).map_ok(|d| d.is_none()), Ok(true));
+        assert_poll_matches!(|cx| stream.poll_next(cx), Ok(None));
+
+        // Trailers follow the body
+        let mut recv = FakeRecv::default();
+        recv.chunk(headers).chunk(data).chunk(trailers);
+        let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+
+        assert_poll_matches!(|cx| stream.poll_next(cx), Ok(Some(Frame::Headers(_))));
+        assert_poll_matches!(
+            |cx| stream.poll_next(cx),
+            Ok(Some(Frame::Data(PayloadLen(4))))
+        );
+        assert_poll_matches!(
+            |cx| to_last(stream.poll_data_last(cx)),
+            Ok(Some((b, false))) if b == "body"
+        );
+        assert_poll_matches!(|cx| stream.poll_data(cx).map_ok(|d| d.is_none()), Ok(true));
+        assert_poll_matches!(|cx| stream.poll_next(cx), Ok(Some(Frame::Headers(_))));
+        assert_poll_matches!(|cx| stream.poll_next(cx), Ok(None));
+    }
+
+    #[tokio::test]
+    async fn frame_observer() {
+        let mut buf = BytesMut::new();
+        Frame::headers(&b"header"[..]).encode_with_payload(&mut buf);
+        Frame::Data(Bytes::from("body")).encode_with_payload(&mut buf);
+        Frame::Data(Bytes::new()).encode_with_payload(&mut buf);
+        Frame::<Bytes>::Grease.encode(&mut buf);
+        Frame::headers(&b"trailer"[..]).encode_with_payload(&mut buf);
+
+        let mut recv = FakeRecv::default();
+        recv.chunk(buf.freeze());
+        let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+        let observed = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let record = observed.clone();
+        stream.set_frame_observer(move |ty, len| record.lock().unwrap().push((ty, len)));
+
+        assert_poll_matches!(|cx| stream.poll_next(cx), Ok(Some(Frame::Headers(_))));
+        // Observed before the frame is returned
+        assert_eq!(*observed.lock().unwrap(), [(FrameType::HEADERS, 6)]);
+        assert_poll_matches!(|cx| stream.poll_next(cx), Ok(Some(Frame::Data(_))));
+        assert_poll_matches!(|cx| to_bytes(stream.poll_data(cx)), Ok(Some(b)) if b == "body");
+        assert_poll_matches!(|cx| stream.poll_data(cx).map_ok(|d| d.is_none()), Ok(true));
+        assert_poll_matches!(|cx| stream.poll_next(cx), Ok(Some(Frame::Data(_))));
+        assert_poll_matches!(|cx| stream.poll_data(cx).map_ok(|d| d.is_none()), Ok(true));
+        assert_poll_matches!(|cx| stream.poll_next(cx), Ok(Some(Frame::Headers(_))));
+        assert_poll_matches!(|cx| stream.poll_next(cx), Ok(None));
+
+        assert_eq!(
+            *observed.lock().unwrap(),
+            [
+                (FrameType::HEADERS, 6),
+                (FrameType::DATA, 4),
+                (FrameType::DATA, 0),
+                (FrameType::HEADERS, 7),
+            ]
+        );
+    }
+
+    #[test]
+    fn poll_data_pending_before_body() {
+        let recv = PausedRecv::default();
+        let paused = recv.0.clone();
+        let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+        let woken = Arc::new(WakeCounter::default());
+        let waker = woken.waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let mut buf = BytesMut::new();
+        Frame::headers(&b"header"[..]).encode_with_payload(&mut buf);
+        Frame::Data(Bytes::from("body")).encode(&mut buf);
+        paused.lock().unwrap().resume(buf.freeze());
+
+        assert_matches!(
+            stream.poll_next(&mut cx),
+            Poll::Ready(Ok(Some(Frame::Headers(_))))
+        );
+        assert_matches!(
+            stream.poll_next(&mut cx),
+            Poll::Ready(Ok(Some(Frame::Data(_))))
+        );
+        // Nothing is buffered or set aside for the body while waiting for it
+        for _ in 0..3 {
+            assert_matches!(to_bytes(stream.poll_data(&mut cx)), Poll::Pending);
+            assert_eq!(stream.buffered_len(), 0);
+        }
+        assert_eq!(woken.woken(), 0);
+
+        paused.lock().unwrap().resume(Bytes::from("bo"));
+        assert_eq!(woken.woken(), 1);
+        assert_matches!(to_bytes(stream.poll_data(&mut cx)), Poll::Ready(Ok(Some(b))) if b == "bo");
+        assert_matches!(to_bytes(stream.poll_data(&mut cx)), Poll::Pending);
+        paused.lock().unwrap().resume(Bytes::from("dy"));
+        assert_matches!(to_bytes(stream.poll_data(&mut cx)), Poll::Ready(Ok(Some(b))) if b == "dy");
+        assert_matches!(to_bytes(stream.poll_data(&mut cx)), Poll::Ready(Ok(None)));
+    }
+
+    #[test]
+    fn read_watermarks() {
+        let mut buf = BytesMut::new();
+        Frame::Data(Bytes::from(vec![0x68; 30])).encode_with_payload(&mut buf);
+        let mut recv = FakeRecv::default();
+        for chunk in crate::fuzzing::chunks(&buf, &[4; 8]) {
+            recv.chunk(Bytes::copy_from_slice(chunk));
+        }
+        let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+        stream.set_read_watermarks(8, 4);
+        let woken = Arc::new(WakeCounter::default());
+        let waker = woken.waker();
+        let mut cx = Context::from_waker(&waker);
+
+        assert_matches!(
+            stream.poll_next(&mut cx),
+            Poll::Ready(Ok(Some(Frame::Data(_))))
+        );
+        assert_eq!(stream.buffered_len(), 2);
+        // 10 bytes buffered, above the high water mark
+        assert_matches!(stream.try_recv(&mut cx), Poll::Ready(Ok(false)));
+        assert_matches!(stream.try_recv(&mut cx), Poll::Ready(Ok(false)));
+        assert_eq!(stream.buffered_len(), 10);
+        assert_matches!(stream.try_recv(&mut cx), Poll::Pending);
+        assert_eq!(stream.buffered_len(), 10);
+
+        // Down to 8, still paused
+        assert_matches!(to_bytes(stream.poll_data(&mut cx)), Poll::Ready(Ok(Some(b))) if b.len() == 2);
+        assert_eq!(stream.buffered_len(), 8);
+        assert_eq!(woken.woken(), 0);
+        // Down to 4, resumed
+        assert_matches!(to_bytes(stream.poll_data(&mut cx)), Poll::Ready(Ok(Some(b))) if b.len() == 4);
+        assert_eq!(stream.buffered_len(), 4);
+        assert_eq!(woken.woken(), 1);
+        assert_matches!(stream.try_recv(&mut cx), Poll::Ready(Ok(false)));
+        assert_eq!(stream.buffered_len(), 8);
+
+        let mut read = 6;
+        while let Poll::Ready(Ok(Some(b))) = to_bytes(stream.poll_data(&mut cx)) {
+            read += b.len();
+        }
+        assert_eq!(read, 30);
+    }
+
+    #[tokio::test]
+    async fn passthrough_unknown_frames() {
+        let mut buf = BytesMut::with_capacity(64);
+        // A grease frame and an extension frame around a HEADERS frame
+        let grease = [0x21, 0x03, 0xfa, 0xce, 0x00];
+        buf.put_slice(&grease);
+        Frame::headers(&b"header"[..]).encode_with_payload(&mut buf);
+        let extension = [0x4d, 0x02, 0x02, 0x68, 0x33];
+        buf.put_slice(&extension);
+        let wire = buf.freeze();
+
+        let mut recv = FakeRecv::default();
+        for chunk in crate::fuzzing::chunks(&wire, &[1, 2, 4]) {
+            recv.chunk(Bytes::copy_from_slice(chunk));
+        }
+        let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+        stream.set_passthrough_unknown(true);
+
+        let mut forwarded = BytesMut::new();
+        while let Some(frame) = poll_fn(|cx| stream.poll_next(cx)).await.unwrap() {
+            let frame = match frame {
+                Frame::Extension(f) => Frame::<Bytes>::Extension(f),
+                Frame::Headers(f) => Frame::Headers(f),
+                frame => panic!("unexpected {:?}", frame),
+            };
+            for chunk in frame.encode_chunks() {
+                forwarded.put_slice(&chunk);
+            }
+        }
+        assert_eq!(forwarded, wire);
+        assert_eq!(stream.unknown_frame_count(), 2);
+    }
+
+    #[test]
+    fn io_error_kind() {
+        use std::io;
+
+        let io_error = |e: FrameStreamError| {
+            let e = io::Error::from(e);
+            let kind = e.kind();
+            let inner = e.into_inner().expect("original error kept");
+            assert!(inner.is::<FrameStreamError>());
+            (kind, inner.source().is_some())
+        };
+
+        assert_eq!(
+            io_error(FrameStreamError::UnexpectedEnd),
+            (io::ErrorKind::UnexpectedEof, false)
+        );
+        assert_eq!(
+            io_error(FrameStreamError::Quic(FakeError(0x10c).into())),
+            (io::ErrorKind::Other, true)
+        );
+        assert_eq!(
+            io_error(FrameStreamError::Proto(frame::FrameError::Malformed)),
+            (io::ErrorKind::InvalidData, true)
+        );
+        assert_eq!(
+            io_error(FrameStreamError::HeaderTooBig {
+                size: 2,
+                max_size: 1
+            }),
+            (io::ErrorKind::InvalidData, false)
+        );
+        assert_eq!(
+            io_error(FrameStreamError::Grammar(GrammarError::DataBeforeHeaders)),
+            (io::ErrorKind::InvalidData, false)
+        );
+        assert_eq!(
+            io_error(FrameStreamError::DataNotRead),
+            (io::ErrorKind::Other, false)
+        );
+    }
+
+    #[tokio::test]
+    async fn digest_of_received_bytes() {
+        use std::collections::hash_map::DefaultHasher;
+
+        let mut buf = BytesMut::with_capacity(64);
+        Frame::headers(&b"header"[..]).encode_with_payload(&mut buf);
+        Frame::<Bytes>::Grease.encode(&mut buf);
+        Frame::Data(Bytes::from("body")).encode_with_payload(&mut buf);
+        Frame::headers(&b"trailer"[..]).encode_with_payload(&mut buf);
+        let wire = buf.freeze();
+
+        let mut recv = FakeRecv::default();
+        for chunk in crate::fuzzing::chunks(&wire, &[3, 9, 1, 5]) {
+            recv.chunk(Bytes::copy_from_slice(chunk));
+        }
+        let mut stream: FrameStream<_, ()> =
+            FrameStream::new(BufRecvStream::new(recv)).with_digest(DefaultHasher::new());
+
+        assert_poll_matches!(|cx| stream.poll_next(cx), Ok(Some(Frame::Headers(_))));
+        assert_poll_matches!(
+            |cx| stream.poll_next(cx),
+            Ok(Some(Frame::Data(PayloadLen(4))))
+        );
+        while poll_fn(|cx| to_bytes(stream.poll_data(cx)))
+            .await
+            .unwrap()
+            .is_some()
+        {}
+        assert_poll_matches!(|cx| stream.poll_next(cx), Ok(Some(Frame::Headers(_))));
+        assert_poll_matches!(|cx| stream.poll_next(cx), Ok(None));
+
+        let mut expected = DefaultHasher::new();
+        expected.write(&wire);
+        assert_eq!(stream.finish_digest(), Some(expected.finish()));
+    }
+
+    #[tokio::test]
+    async fn buffer_budget_shared_by_streams() {
+        let mut buf = BytesMut::with_capacity(16);
+        Frame::Data(Bytes::from("0123456789")).encode_with_payload(&mut buf);
+        let wire = buf.freeze();
+        let budget = Arc::new(BufferBudget::new(16));
+        let recv = || {
+            let mut recv = FakeRecv::default();
+            recv.chunk(wire.clone());
+            recv
+        };
+
+        let mut first: FrameStream<_, ()> =
+            FrameStream::new(BufRecvStream::new(recv())).with_buffer_budget(budget.clone());
+        assert_poll_matches!(
+            |cx| first.poll_next(cx),
+            Ok(Some(Frame::Data(PayloadLen(10))))
+        );
+        // The frame header is consumed, its payload is not
+        assert_eq!(budget.used(), 10);
+
+        let second_recv = recv();
+        let stopped = second_recv.stopped.clone();
+        let mut second: FrameStream<_, ()> =
+            FrameStream::new(BufRecvStream::new(second_recv)).with_buffer_budget(budget.clone());
+        assert_poll_matches!(
+            |cx| second.poll_next(cx),
+            Err(FrameStreamError::BufferBudgetExceeded)
+        );
+        assert_eq!(
+            *stopped.lock().unwrap(),
+            Some(Code::H3_EXCESSIVE_LOAD.value())
+        );
+        assert_eq!(budget.used(), 10);
+
+        let data = poll_fn(|cx| to_bytes(first.poll_data(cx))).await;
+        assert_eq!(data.unwrap(), Some(Bytes::from("0123456789")));
+        assert_eq!(budget.available(), 16);
+
+        let mut third: FrameStream<_, ()> =
+            FrameStream::new(BufRecvStream::new(recv())).with_buffer_budget(budget.clone());
+        assert_poll_matches!(
+            |cx| third.poll_next(cx),
+            Ok(Some(Frame::Data(PayloadLen(10))))
+        );
+        drop(third);
+        assert_eq!(budget.used(), 0);
+    }
+
+    #[tokio::test]
+    async fn unknown_frames_counted_per_stream() {
+        use crate::proto::{frame::Settings, varint::BufMutExt as _};
+
+        // Grease frames around the SETTINGS of a control stream
+        let mut buf = BytesMut::with_capacity(64);
+        FrameType::grease().encode(&mut buf);
+        buf.write_var(0);
+        Frame::<Bytes>::Settings(Settings::default()).encode(&mut buf);
+        Frame::<Bytes>::Grease.encode(&mut buf);
+        let mut recv = FakeRecv::default();
+        recv.chunk(buf.freeze());
+        let mut control: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+        control.accept_origin();
+
+        let mut buf = BytesMut::with_capacity(64);
+        Frame::headers(&b"header"[..]).encode_with_payload(&mut buf);
+        let mut recv = FakeRecv::default();
+        recv.chunk(buf.freeze());
+        let mut request: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+
+        assert_poll_matches!(|cx| control.poll_next(cx), Ok(Some(Frame::Settings(_))));
+        assert_eq!(control.unknown_frame_count(), 1);
+        assert_poll_matches!(|cx| control.poll_next(cx), Ok(None));
+        assert_eq!(control.unknown_frame_count(), 2);
+        assert_eq!(control.frame_count(), 3);
+
+        assert_poll_matches!(|cx| request.poll_next(cx), Ok(Some(Frame::Headers(_))));
+        assert_poll_matches!(|cx| request.poll_next(cx), Ok(None));
+        assert_eq!(request.unknown_frame_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn buffered_len_of_partial_frame() {
+        let mut buf = BytesMut::with_capacity(16);
+        Frame::headers(&b"header"[..]).encode_with_payload(&mut buf);
+        Frame::headers(&b"trailer"[..]).encode_with_payload(&mut buf);
+        let mut recv = FakeRecv::default();
+        recv.chunk(buf.freeze().slice(..12));
+        let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+        assert_eq!(stream.buffered_len(), 0);
+
+        assert_poll_matches!(|cx| stream.poll_next(cx), Ok(Some(Frame::Headers(_))));
+        // The first 4 bytes of the trailer frame
+        assert_eq!(stream.buffered_len(), 4);
+        assert_poll_matches!(
+            |cx| stream.poll_next(cx),
+            Err(FrameStreamError::UnexpectedEnd)
+        );
+        assert_eq!(stream.buffered_len(), 4);
+    }
+
+    #[tokio::test]
+    async fn strict_varint_decoder() {
+        // HEADERS frame whose length is encoded on 2 bytes
+        let frame = Bytes::from_static(&[1, 0x40, 5, b's', b'a', b'l', b'u', b't']);
+
+        for strict in [false, true] {
+            let mut recv = FakeRecv::default();
+            recv.chunk(frame.clone());
+            let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+            stream.set_strict_varint(strict);
+
+            if strict {
+                assert_poll_matches!(
+                    |cx| stream.poll_next(cx),
+                    Err(FrameStreamError::Proto(
+                        frame::FrameError::NonMinimalVarInt(5)
+                    ))
+                );
+            } else {
+                assert_poll_matches!(
+                    |cx| stream.poll_next(cx),
+                    Ok(Some(Frame::Headers(h))) if &h[..] == b"salut"
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn raw_frames_reencode() {
+        let mut buf = BytesMut::with_capacity(64);
+        Frame::headers(&b"header"[..]).encode_with_payload(&mut buf);
+        Frame::Data(Bytes::from("body")).encode_with_payload(&mut buf);
+        Frame::headers(&b"trailer"[..]).encode_with_payload(&mut buf);
+        let wire = buf.freeze();
+
+        // The trailers are split across two chunks
+        let mut recv = FakeRecv::default();
+        recv.chunk(wire.slice(..wire.len() - 3))
+            .chunk(wire.slice(wire.len() - 3..));
+        let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+        stream.set_retain_raw(true);
+
+        let mut received = BytesMut::new();
+        while let Some((frame, raw)) = poll_fn(|cx| stream.poll_next_raw(cx)).await.unwrap() {
+            if received.is_empty() {
+                // Taken from the received chunk without copying
+                assert_eq!(raw.as_ptr(), wire.as_ptr());
+            }
+            received.extend_from_slice(&raw);
+            match frame {
+                Frame::Headers(h) => {
+                    let mut encoded = BytesMut::new();
+                    Frame::headers(h).encode_with_payload(&mut encoded);
+                    assert_eq!(encoded, raw);
+                }
+                Frame::Data(_) => {
+                    while let Some(mut data) = poll_fn(|cx| stream.poll_data(cx)).await.unwrap() {
+                        received.extend_from_slice(&data.copy_to_bytes(data.remaining()));
+                    }
+                }
+                f => panic!("unexpected frame: {:?}", f),
+            }
+        }
+        assert_eq!(received.freeze(), wire);
+    }
+
+    #[tokio::test]
+    async fn forward_to_bounded_channel() {
+        let mut recv = FakeRecv::default();
+        let mut buf = BytesMut::with_capacity(64);
+        Frame::headers(&b"header"[..]).encode_with_payload(&mut buf);
+        Frame::Data(Bytes::from("body")).encode_with_payload(&mut buf);
+        Frame::headers(&b"trailer"[..]).encode_with_payload(&mut buf);
+        recv.chunk(buf.freeze());
+        let stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(1);
+        let forward = tokio::spawn(stream.forward_to(sender));
+
+        assert_matches!(
+            receiver.recv().await,
+            Some(ForwardedFrame::Frame(Frame::Headers(h))) if &h[..] == b"header"
+        );
+        assert_matches!(
+            receiver.recv().await,
+            Some(ForwardedFrame::Frame(Frame::Data(PayloadLen(4))))
+        );
+        assert_matches!(
+            receiver.recv().await,
+            Some(ForwardedFrame::Data(d)) if &d[..] == b"body"
+        );
+        assert_matches!(
+            receiver.recv().await,
+            Some(ForwardedFrame::Frame(Frame::Headers(h))) if &h[..] == b"trailer"
+        );
+        assert_matches!(receiver.recv().await, None);
+        assert_matches!(forward.await, Ok(Ok(())));
+    }
+
+    #[tokio::test]
+    async fn recv_frame_of_type_skips_unknown_frames() {
+        use crate::proto::varint::BufMutExt as _;
+
+        let mut recv = FakeRecv::default();
+        let mut buf = BytesMut::with_capacity(64);
+        FrameType::grease().encode(&mut buf);
+        buf.write_var(0);
+        FrameType::RESERVED.encode(&mut buf);
+        buf.write_var(6);
+        buf.put_slice(b"grease");
+        Frame::headers(&b"header"[..]).encode_with_payload(&mut buf);
+        recv.chunk(buf.freeze());
+        let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+
+        assert_matches!(
+            stream.recv_frame_of_type(FrameType::HEADERS).await,
+            Ok(Some(Frame::Headers(h))) if &h[..] == b"header"
+        );
+        assert_matches!(
+            stream.recv_frame_of_type(FrameType::HEADERS).await,
+            Ok(None)
+        );
+    }
+
+    #[tokio::test]
+    async fn recv_frame_of_type_drains_data() {
+        let mut recv = FakeRecv::default();
+        let mut buf = BytesMut::with_capacity(64);
+        Frame::Data(Bytes::from("body")).encode_with_payload(&mut buf);
+        recv.chunk(buf.split().freeze());
+        Frame::headers(&b"trailer"[..]).encode_with_payload(&mut buf);
+        Frame::headers(&b"trailer"[..]).encode_with_payload(&mut buf);
+        recv.chunk(buf.freeze());
+        let mut stream: FrameStream<_, ()> = FrameStream::new(BufRecvStream::new(recv));
+
+        assert_matches!(
+            stream.recv_frame_of_type(FrameType::HEADERS).await,
+            Ok(Some(Frame::Headers(h))) if &h[..] == b"trailer"
+        );
+        assert_matches!(
+            stream.recv_frame_of_type(FrameType::DATA).await,
+            Err(e) if e.try_get_code() == Some(Code::H3_FRAME_UNEXPECTED)
+        );
+    }
+
+    #[test]
+    fn reject_aborts_both_directions() {
+        let aborts = AbortRecorder::default();
+        let mut stream: FrameStream<_, Bytes> =
+            FrameStream::new(BufRecvStream::new(aborts.clone()));
+        stream.reject();
+
+        assert_eq!(
+            *aborts.0.lock().unwrap(),
+            [("stop_sending", 0x10b), ("reset", 0x10b)]
+        );
+    }
+
+    // Helpers
+
+    /// Stream recording the codes it is stopped and reset with
+    #[derive(Default, Clone)]
+    struct AbortRecorder(Arc<std::sync::Mutex<Vec<(&'static str, u64)>>>);
+
+    impl RecvStream for AbortRecorder {
+        type Buf = Bytes;
+        type Error = FakeError;
+
+        fn poll_data(&mut self, _: &mut Context<'_>) -> Poll<Result<Option<Bytes>, FakeError>> {
+            Poll::Ready(Ok(None))
+        }
+
+        fn stop_sending(&mut self, error_code: u64) {
+            self.0.lock().unwrap().push(("stop_sending", error_code));
+        }
+
+        fn recv_id(&self) -> StreamId {
+            unimplemented!()
+        }
+    }
+
+    impl SendStream<Bytes> for AbortRecorder {
+        type Error = FakeError;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), FakeError>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn send_data<T: Into<WriteBuf<Bytes>>>(&mut self, _: T) -> Result<(), FakeError> {
+            Ok(())
+        }
+
+        fn poll_finish(&mut self, _: &mut Context<'_>) -> Poll<Result<(), FakeError>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn reset(&mut self, reset_code: u64) {
+            self.0.lock().unwrap().push(("reset", reset_code));
+        }
+
+        fn send_id(&self) -> StreamId {
+            unimplemented!()
+        }
+    }
+
+    /// Records the data sent, the stream is only ready every other poll
+    #[derive(Default)]
+    struct NotReadyOnce {
+        waited: bool,
+        sent: Arc<std::sync::Mutex<BytesMut>>,
+    }
+
+    impl SendStream<Bytes> for NotReadyOnce {
+        type Error = FakeError;
+
+        fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), FakeError>> {
+            if std::mem::take(&mut self.waited) {
+                return Poll::Ready(Ok(()));
+            }
+            self.waited = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+
+        fn send_data<T: Into<WriteBuf<Bytes>>>(&mut self, data: T) -> Result<(), FakeError> {
+            let mut data = data.into();
+            while data.has_remaining() {
+                let len = data.chunk().len();
+                self.sent.lock().unwrap().put_slice(data.chunk());
+                data.advance(len);
+            }
+            Ok(())
+        }
+
+        fn poll_finish(&mut self, _: &mut Context<'_>) -> Poll<Result<(), FakeError>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn reset(&mut self, _: u64) {
+            unimplemented!()
+        }
+
+        fn send_id(&self) -> StreamId {
+            unimplemented!()
+        }
+    }
+
+    /// Fails to send anything, as if stopped by the peer, and records the code it is reset with
+    #[derive(Default)]
+    struct BrokenSend {
+        reset: Arc<std::sync::Mutex<Option<u64>>>,
+    }
+
+    impl SendStream<Bytes> for BrokenSend {
+        type Error = FakeError;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), FakeError>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn send_data<T: Into<WriteBuf<Bytes>>>(&mut self, _: T) -> Result<(), FakeError> {
+            Err(FakeError(Code::H3_REQUEST_CANCELLED.value()))
+        }
+
+        fn poll_finish(&mut self, _: &mut Context<'_>) -> Poll<Result<(), FakeError>> {
+            unimplemented!()
+        }
+
+        fn reset(&mut self, code: u64) {
+            *self.reset.lock().unwrap() = Some(code);
+        }
+
+        fn send_id(&self) -> StreamId {
+            unimplemented!()
+        }
+    }
+
+    #[derive(Default)]
+    struct FakeRecv {
+        chunks: VecDeque<Bytes>,
+        // Reset code received once the chunks are read, rather than the end of the stream
+        reset: Option<u64>,
+        // Code of the last `stop_sending()` call
+        stopped: Arc<std::sync::Mutex<Option<u64>>>,
+    }
+
+    impl FakeRecv {
+        fn chunk(&mut self, buf: Bytes) -> &mut Self {
+            self.chunks.push_back(buf);
+            self
+        }
+
+        fn reset(&mut self, code: u64) -> &mut Self {
+            self.reset = Some(code);
+            self
+        }
     }
 
     impl RecvStream for FakeRecv {
@@ -563,11 +3682,14 @@ mod tests {
             &mut self,
             _: &mut Context<'_>,
         ) -> Poll<Result<Option<Self::Buf>, Self::Error>> {
-            Poll::Ready(Ok(self.chunks.pop_front()))
+            match (self.chunks.pop_front(), self.reset) {
+                (None, Some(code)) => Poll::Ready(Err(FakeError(code))),
+                (chunk, _) => Poll::Ready(Ok(chunk)),
+            }
         }
 
-        fn stop_sending(&mut self, _: u64) {
-            unimplemented!()
+        fn stop_sending(&mut self, error_code: u64) {
+            *self.stopped.lock().unwrap() = Some(error_code);
         }
 
         fn recv_id(&self) -> StreamId {
@@ -575,16 +3697,17 @@ mod tests {
         }
     }
 
+    /// A stream reset with this code
     #[derive(Debug)]
-    struct FakeError;
+    struct FakeError(u64);
 
     impl quic::Error for FakeError {
         fn is_timeout(&self) -> bool {
-            unimplemented!()
+            false
         }
 
         fn err_code(&self) -> Option<u64> {
-            unimplemented!()
+            Some(self.0)
         }
     }
 
@@ -601,6 +3724,86 @@ mod tests {
         }
     }
 
+    /// A waker counting how many times it is woken
+    ///
+    /// Polling with its `context()` tells whether a pending poll registered the waker, and
+    /// whether the stream later woke it.
+    #[derive(Default)]
+    struct WakeCounter(AtomicUsize);
+
+    impl WakeCounter {
+        fn waker(self: &Arc<Self>) -> std::task::Waker {
+            std::task::Waker::from(self.clone())
+        }
+
+        fn woken(&self) -> usize {
+            self.0.load(Ordering::SeqCst)
+        }
+    }
+
+    impl std::task::Wake for WakeCounter {
+        fn wake(self: Arc<Self>) {
+            self.wake_by_ref();
+        }
+
+        fn wake_by_ref(self: &Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// A stream pending until chunks are pushed through the `Arc` shared with the test
+    #[derive(Default)]
+    struct PausedRecv(Arc<std::sync::Mutex<Paused>>);
+
+    #[derive(Default)]
+    struct Paused {
+        chunks: VecDeque<Bytes>,
+        waker: Option<std::task::Waker>,
+    }
+
+    impl Paused {
+        /// Receives `chunk`, waking the last poll left pending
+        fn resume(&mut self, chunk: Bytes) {
+            self.chunks.push_back(chunk);
+            if let Some(waker) = self.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    impl RecvStream for PausedRecv {
+        type Buf = Bytes;
+        type Error = FakeError;
+
+        fn poll_data(
+            &mut self,
+            cx: &mut Context<'_>,
+        ) -> Poll<Result<Option<Self::Buf>, Self::Error>> {
+            let mut paused = self.0.lock().unwrap();
+            match paused.chunks.pop_front() {
+                Some(chunk) => Poll::Ready(Ok(Some(chunk))),
+                None => {
+                    paused.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        }
+
+        fn stop_sending(&mut self, _: u64) {
+            unimplemented!()
+        }
+
+        fn recv_id(&self) -> StreamId {
+            unimplemented!()
+        }
+    }
+
+    fn to_last(
+        x: Poll<Result<Option<(impl Buf, bool)>, FrameStreamError>>,
+    ) -> Poll<Result<Option<(Bytes, bool)>, FrameStreamError>> {
+        x.map_ok(|d| d.map(|(mut b, last)| (b.copy_to_bytes(b.remaining()), last)))
+    }
+
     fn to_bytes(
         x: Poll<Result<Option<impl Buf>, FrameStreamError>>,
     ) -> Poll<Result<Option<Bytes>, FrameStreamError>> {
@@ -0,0 +1,198 @@
+//! Decoder entry points for fuzzing
+//!
+//! Each function takes bytes straight from a fuzzer and must not panic, whatever they are,
+//! except [`round_trip()`] which checks a property of well-formed frames. The targets in the
+//! `fuzz` directory of the repository are built on them.
+
+use std::convert::TryFrom;
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+pub use crate::buf::BufList;
+use crate::{
+    frame::{FrameDecoder, FrameStreamError},
+    proto::{
+        coding::Encode,
+        frame::{Frame, PayloadLen, Settings, SettingsError},
+        headers::Header,
+    },
+    qpack, Error,
+};
+
+/// Splits `data` in chunks of the successive lengths of `lens`, as received from a stream
+///
+/// Lengths are clamped so that no chunk is empty or out of bounds, and the last chunk holds
+/// whatever `lens` does not cover.
+pub fn chunks<'a>(mut data: &'a [u8], lens: &'a [usize]) -> impl Iterator<Item = &'a [u8]> {
+    let mut lens = lens.iter();
+    std::iter::from_fn(move || {
+        if data.is_empty() {
+            return None;
+        }
+        let len = lens
+            .next()
+            .map_or(data.len(), |len| (*len).clamp(1, data.len()));
+        let (chunk, rest) = data.split_at(len);
+        data = rest;
+        Some(chunk)
+    })
+}
+
+/// Decodes the frames of a stream receiving `data` in [`chunks()`] of `lens`
+///
+/// The payload of DATA frames is skipped, as is the rest of the stream after a WebTransport
+/// stream header. It fails if the stream ends in the middle of a frame.
+pub fn decode_frames(
+    data: &[u8],
+    lens: &[usize],
+) -> Result<Vec<Frame<PayloadLen>>, FrameStreamError> {
+    let mut decoder = FrameDecoder::default();
+    let mut buf = BufList::new();
+    let mut frames = Vec::new();
+    // DATA payload not received yet
+    let mut skip = 0;
+
+    for chunk in chunks(data, lens) {
+        buf.push(chunk);
+        loop {
+            let skipped = skip.min(buf.remaining());
+            buf.advance(skipped);
+            skip -= skipped;
+            if skip > 0 {
+                break;
+            }
+
+            match decoder.decode(&mut buf)? {
+                Some(Frame::Data(PayloadLen(len))) => {
+                    skip = len;
+                    frames.push(Frame::Data(PayloadLen(len)));
+                }
+                Some(frame @ Frame::WebTransportStream(_)) => {
+                    frames.push(frame);
+                    return Ok(frames);
+                }
+                Some(frame) => frames.push(frame),
+                None => break,
+            }
+        }
+    }
+
+    if skip > 0 || buf.has_remaining() {
+        return Err(FrameStreamError::UnexpectedEnd);
+    }
+    Ok(frames)
+}
+
+/// Decodes the payload of a SETTINGS frame
+pub fn decode_settings(mut data: &[u8]) -> Result<Settings, SettingsError> {
+    Settings::decode(&mut data, false)
+}
+
+/// Decodes a field section as received in a HEADERS frame, up to `max_size`
+///
+/// Field sections referring to the dynamic table are rejected, as they are by the connections.
+pub fn decode_field_section(mut data: &[u8], max_size: u64) -> Result<Header, Error> {
+    let qpack::Decoded { fields, .. } = match qpack::decode_stateless(&mut data, max_size) {
+        Err(qpack::DecoderError::HeaderTooLong(size)) => {
+            return Err(Error::header_too_big(size, max_size))
+        }
+        Ok(decoded) => decoded,
+        Err(e) => return Err(e.into()),
+    };
+    Ok(Header::try_from(fields)?)
+}
+
+/// Checks that `frame` decodes back to itself, received in [`chunks()`] of `lens`
+///
+/// # Panics
+///
+/// Panics if the frame cannot be decoded, or differs once decoded.
+pub fn round_trip(frame: Frame<Bytes>, lens: &[usize]) {
+    let mut buf = BytesMut::new();
+    frame.encode(&mut buf);
+    match &frame {
+        Frame::Data(payload) | Frame::Headers(payload) => buf.put_slice(payload),
+        Frame::Extension(f) => buf.put_slice(f.payload()),
+        _ => (),
+    }
+
+    let decoded = decode_frames(&buf, lens)
+        .unwrap_or_else(|e| panic!("{:?} could not be decoded: {:?}", frame, e));
+    let matches = match &frame {
+        // The type of grease and extension frames is unknown, they are skipped
+        Frame::Grease | Frame::Extension(_) => decoded.is_empty(),
+        Frame::Data(payload) => {
+            matches!(decoded[..], [Frame::Data(PayloadLen(len))] if len == payload.len())
+        }
+        _ => matches!(&decoded[..], [d] if *d == frame),
+    };
+    assert!(matches, "{:?} decoded as {:?}", frame, decoded);
+}
+
+#[cfg(test)]
+mod tests {
+    use arbitrary::{Arbitrary, Unstructured};
+
+    use super::*;
+
+    /// Runs `target` on `data` like `cargo fuzz` does for a typed input
+    fn fuzz<'a, T: Arbitrary<'a>>(data: &'a [u8], target: impl FnOnce(T)) {
+        if let Ok(input) = T::arbitrary_take_rest(Unstructured::new(data)) {
+            target(input);
+        }
+    }
+
+    #[test]
+    fn chunks_are_clamped() {
+        let data = b"hypertext";
+        let split = |lens| chunks(data, lens).collect::<Vec<_>>();
+
+        assert_eq!(split(&[]), [&b"hypertext"[..]]);
+        assert_eq!(split(&[5, 0, 2]), [&b"hyper"[..], b"t", b"ex", b"t"]);
+        assert_eq!(split(&[usize::MAX, 1]), [&b"hypertext"[..]]);
+        assert!(chunks(b"", &[1, 2]).next().is_none());
+    }
+
+    #[test]
+    fn decode_frames_across_chunks() {
+        let mut buf = BytesMut::new();
+        Frame::Data(Bytes::from("hyper")).encode(&mut buf);
+        buf.put_slice(b"hyper");
+        Frame::headers("text").encode(&mut buf);
+        buf.put_slice(b"text");
+
+        let frames = decode_frames(&buf, &[1, 3, 1, 2]).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0], Frame::Data(Bytes::from("hyper")));
+        assert_eq!(frames[1], Frame::headers("text"));
+
+        assert!(matches!(
+            decode_frames(&buf[..buf.len() - 1], &[3]),
+            Err(FrameStreamError::UnexpectedEnd)
+        ));
+    }
+
+    /// Every fuzz target on pseudo-random inputs, as when running without a corpus
+    #[test]
+    fn smoke() {
+        let mut rng = fastrand::Rng::with_seed(0x6833);
+        let mut data = Vec::new();
+        for _ in 0..4096 {
+            data.clear();
+            data.extend((0..rng.usize(..512)).map(|_| rng.u8(..)));
+
+            fuzz(&data, |(data, lens): (&[u8], Vec<usize>)| {
+                let _ = decode_frames(data, &lens);
+            });
+            fuzz(&data, |data: &[u8]| {
+                let _ = decode_settings(data);
+            });
+            fuzz(&data, |(max_size, data): (u64, &[u8])| {
+                let _ = decode_field_section(data, max_size);
+            });
+            fuzz(&data, |(frame, lens): (Frame<Bytes>, Vec<usize>)| {
+                round_trip(frame, &lens)
+            });
+        }
+    }
+}
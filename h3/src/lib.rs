@@ -3,18 +3,31 @@
 #![allow(clippy::derive_partial_eq_without_eq)]
 
 pub mod client;
+pub mod codec;
 
 mod config;
 pub mod error;
 pub mod ext;
 pub mod quic;
+pub mod rt;
 
 pub mod server;
+#[cfg(any(test, feature = "test-helpers"))]
+pub mod test;
 
+pub use config::UnknownStreamAction;
 pub use error::Error;
 
+#[cfg(feature = "http-body")]
+mod body;
+mod budget;
 mod buf;
 
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+#[cfg(all(test, not(feature = "fuzzing")))]
+mod fuzzing;
+
 #[cfg(feature = "i-implement-a-third-party-backend-and-opt-into-breaking-changes")]
 #[allow(missing_docs)]
 pub mod connection;
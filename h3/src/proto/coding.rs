@@ -2,17 +2,28 @@ use bytes::{Buf, BufMut};
 
 use super::varint::VarInt;
 
+/// Error returned when a buffer ends before the value being decoded
+///
+/// It holds a hint of the missing length, which depends on the decoder.
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct UnexpectedEnd(pub usize);
 
 pub type Result<T> = ::std::result::Result<T, UnexpectedEnd>;
 
+/// Error returned when a buffer has not enough room left for the value being encoded
+///
+/// It holds the length the value needs. Nothing was written.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct BufferTooShort(pub usize);
+
 // Trait for encoding / decoding helpers on basic types, such as `u16`, for
 // example: `buf.decode::<u16>()?`.
 // This enables to return `UnexpectedEnd` instead of panicking as the `Buf`
 // impls do when there is not enough bytes.
 
+/// Encoding of a value to the wire format
 pub trait Encode {
+    /// Writes the encoded value to `buf`
     fn encode<B: BufMut>(&self, buf: &mut B);
 }
 
@@ -1,6 +1,7 @@
-use bytes::{Buf, BufMut, Bytes};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use smallvec::{smallvec, SmallVec};
 use std::{
-    convert::TryInto,
+    convert::{TryFrom, TryInto},
     fmt::{self, Debug},
 };
 use tracing::trace;
@@ -8,22 +9,40 @@ use tracing::trace;
 use crate::webtransport::SessionId;
 
 use super::{
-    coding::{Decode, Encode},
+    coding::{BufferTooShort, Encode},
     push::{InvalidPushId, PushId},
     stream::InvalidStreamId,
-    varint::{BufExt, BufMutExt, UnexpectedEnd, VarInt},
+    varint::{BufExt, BufMutExt, UnexpectedEnd, VarInt, VarIntBoundsExceeded, VarIntError},
 };
 
+/// Error decoding a frame
 #[derive(Debug, PartialEq)]
 pub enum FrameError {
+    /// The frame is not consistent with its length
     Malformed,
-    UnsupportedFrame(u64), // Known frames that should generate an error
-    UnknownFrame(u64),     // Unknown frames that should be ignored
+    /// A frame type of HTTP/2 reserved by HTTP/3, which must not be received
+    UnsupportedFrame(u64),
+    /// A frame of unknown type, to be ignored, its payload is skipped
+    UnknownFrame(u64),
+    /// A frame field has a value out of its range
     InvalidFrameValue,
+    /// The frame is not entirely buffered, at least this length is needed to decode it
     Incomplete(usize),
+    /// The payload of a SETTINGS frame is invalid
     Settings(SettingsError),
+    /// An invalid stream id was received
     InvalidStreamId(InvalidStreamId),
+    /// An invalid push id was received
     InvalidPushId(InvalidPushId),
+    /// A varint was not encoded on as few bytes as possible, while this was enforced
+    NonMinimalVarInt(u64),
+    /// The encoded field section of a HEADERS frame is longer than allowed
+    HeaderBlockTooLong {
+        /// Length of the encoded field section
+        len: usize,
+        /// Longest encoded field section allowed
+        max: usize,
+    },
 }
 
 impl std::error::Error for FrameError {}
@@ -39,17 +58,55 @@ impl fmt::Display for FrameError {
             FrameError::Settings(x) => write!(f, "invalid settings: {}", x),
             FrameError::InvalidStreamId(x) => write!(f, "{}", x),
             FrameError::InvalidPushId(x) => write!(f, "{}", x),
+            FrameError::NonMinimalVarInt(x) => write!(f, "varint {} is not minimally encoded", x),
+            FrameError::HeaderBlockTooLong { len, max } => {
+                write!(f, "header block of {} bytes exceeds {}", len, max)
+            }
         }
     }
 }
 
+/// An HTTP/3 frame, as defined in <https://www.rfc-editor.org/rfc/rfc9114#section-7.2>
+///
+/// `B` is the type of the DATA payload, [`PayloadLen`] for decoded frames.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(serialize = "B: SerdePayload", deserialize = "B: SerdePayload"))
+)]
+#[cfg_attr(any(test, feature = "arbitrary"), derive(arbitrary::Arbitrary))]
+#[cfg_attr(
+    any(test, feature = "arbitrary"),
+    arbitrary(bound = "B: From<Vec<u8>>")
+)]
 pub enum Frame<B> {
-    Data(B),
-    Headers(Bytes),
+    /// DATA frame, with its payload
+    ///
+    /// Only the type and length are encoded, the payload follows on the stream.
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            serialize_with = "SerdePayload::serialize",
+            deserialize_with = "SerdePayload::deserialize"
+        )
+    )]
+    Data(#[cfg_attr(any(test, feature = "arbitrary"), arbitrary(with = arbitrary_payload))] B),
+    /// HEADERS frame, with its QPACK-encoded field section
+    ///
+    /// As for DATA frames, the payload is not encoded with the frame.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    Headers(
+        #[cfg_attr(any(test, feature = "arbitrary"), arbitrary(with = arbitrary_payload))] Bytes,
+    ),
+    /// CANCEL_PUSH frame, with the id of the push to cancel
     CancelPush(PushId),
+    /// SETTINGS frame
     Settings(Settings),
+    /// PUSH_PROMISE frame
     PushPromise(PushPromise),
+    /// GOAWAY frame, with the last stream or push id which may be processed
     Goaway(VarInt),
+    /// MAX_PUSH_ID frame, with the largest push id the server may use
     MaxPushId(PushId),
     /// Describes the header for a webtransport stream.
     ///
@@ -59,6 +116,26 @@ pub enum Frame<B> {
     ///
     /// Conversely, when sending, send this frame and unwrap the stream
     WebTransportStream(SessionId),
+    /// ORIGIN frame, with the ASCII serializations of the origins the server is authoritative for
+    ///
+    /// See <https://www.rfc-editor.org/rfc/rfc9412>.
+    Origin {
+        /// The origins, in the order of the frame
+        entries: Vec<OriginEntry>,
+    },
+    /// A frame of an extension type
+    ///
+    /// As with DATA frames, its payload follows the encoded frame. [`Frame::decode()`] fails
+    /// with [`FrameError::UnknownFrame`] on frames of unknown type, and request and control
+    /// streams skip them. They are only returned, with their payload, by a `FrameStream` told so
+    /// with `set_passthrough_unknown()`, or handed to the handler set with `on_extension_frame()`
+    /// on the client or server builder.
+    Extension(ExtensionFrame),
+    /// A frame of reserved type, to exercise the requirement that unknown frames are ignored
+    ///
+    /// Its type is randomly chosen when encoded, see [`FrameType::is_reserved()`]. Frames of
+    /// such types are never decoded as this variant, they are unknown frames as any other, see
+    /// [`Frame::Extension`].
     Grease,
 }
 
@@ -66,6 +143,7 @@ pub enum Frame<B> {
 ///
 /// Decoding received frames does not handle `Data` frames payload. Instead, receiving it
 /// and passing it to the user is left under the responsibility of `RequestStream`s.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PayloadLen(pub usize);
 
 impl From<usize> for PayloadLen {
@@ -75,49 +153,72 @@ impl From<usize> for PayloadLen {
 }
 
 impl Frame<PayloadLen> {
+    /// Maximum encoded length of a frame without payload
     pub const MAX_ENCODED_SIZE: usize = VarInt::MAX_SIZE * 7;
 
     /// Decodes a Frame from the stream according to <https://www.rfc-editor.org/rfc/rfc9114#section-7.1>
     pub fn decode<T: Buf>(buf: &mut T) -> Result<Self, FrameError> {
+        Self::decode_with(buf, false)
+    }
+
+    /// Decodes a Frame, rejecting non-minimally encoded varints if `minimal` is set
+    pub fn decode_with<T: Buf>(buf: &mut T, minimal: bool) -> Result<Self, FrameError> {
         let remaining = buf.remaining();
-        let ty = FrameType::decode(buf).map_err(|_| FrameError::Incomplete(remaining + 1))?;
+        let header_err = |e| match e {
+            VarIntError::UnexpectedEnd(_) => FrameError::Incomplete(remaining + 1),
+            VarIntError::NonMinimal(x) => FrameError::NonMinimalVarInt(x.into_inner()),
+        };
+        let ty = FrameType::decode(buf, minimal).map_err(header_err)?;
+        let kind = ty.kind();
 
         // Webtransport streams need special handling as they have no length.
         //
         // See: https://datatracker.ietf.org/doc/html/draft-ietf-webtrans-http3/#section-4.2
-        if ty == FrameType::WEBTRANSPORT_BI_STREAM {
+        if kind == FrameKind::WebTransportStream {
             tracing::trace!("webtransport frame");
-            return Ok(Frame::WebTransportStream(SessionId::decode(buf)?));
+            return Ok(Frame::WebTransportStream(SessionId::from_varint(
+                VarInt::decode_with(buf, minimal)?,
+            )));
         }
 
-        let len = buf
-            .get_var()
-            .map_err(|_| FrameError::Incomplete(remaining + 1))?;
+        let len = buf.get_var_with(minimal).map_err(header_err)?;
+        // Lengths past the address space are only possible on 32-bit targets, no such frame
+        // could ever be buffered
+        let len = usize::try_from(len).map_err(|_| FrameError::Malformed)?;
 
-        if ty == FrameType::DATA {
-            return Ok(Frame::Data((len as usize).into()));
+        if kind == FrameKind::Data {
+            return Ok(Frame::Data(len.into()));
         }
 
-        if buf.remaining() < len as usize {
-            return Err(FrameError::Incomplete(2 + len as usize));
+        if buf.remaining() < len {
+            return Err(FrameError::Incomplete(len.saturating_add(2)));
         }
 
-        let mut payload = buf.take(len as usize);
-        trace!("frame ty: {:?}", ty);
-        let frame = match ty {
-            FrameType::HEADERS => Ok(Frame::Headers(payload.copy_to_bytes(len as usize))),
-            FrameType::SETTINGS => Ok(Frame::Settings(Settings::decode(&mut payload)?)),
-            FrameType::CANCEL_PUSH => Ok(Frame::CancelPush(payload.get_var()?.try_into()?)),
-            FrameType::PUSH_PROMISE => Ok(Frame::PushPromise(PushPromise::decode(&mut payload)?)),
-            FrameType::GOAWAY => Ok(Frame::Goaway(VarInt::decode(&mut payload)?)),
-            FrameType::MAX_PUSH_ID => Ok(Frame::MaxPushId(payload.get_var()?.try_into()?)),
-            FrameType::H2_PRIORITY
-            | FrameType::H2_PING
-            | FrameType::H2_WINDOW_UPDATE
-            | FrameType::H2_CONTINUATION => Err(FrameError::UnsupportedFrame(ty.0)),
-            FrameType::WEBTRANSPORT_BI_STREAM | FrameType::DATA => unreachable!(),
-            _ => {
-                buf.advance(len as usize);
+        let payload = FramePayload::new(&mut *buf, len);
+        trace!("frame ty: {:?}", kind);
+        let frame = match kind {
+            FrameKind::Headers => payload.decode_all(|p| Ok(Frame::Headers(p.rest()))),
+            FrameKind::Settings => {
+                payload.decode_all(|p| Ok(Frame::Settings(Settings::decode(&mut p.0, minimal)?)))
+            }
+            FrameKind::CancelPush => {
+                payload.decode_all(|p| Ok(Frame::CancelPush(p.var(minimal)?.try_into()?)))
+            }
+            FrameKind::PushPromise => {
+                payload.decode_all(|p| Ok(Frame::PushPromise(PushPromise::decode(p, minimal)?)))
+            }
+            FrameKind::Goaway => payload.decode_all(|p| Ok(Frame::Goaway(p.varint(minimal)?))),
+            FrameKind::MaxPushId => {
+                payload.decode_all(|p| Ok(Frame::MaxPushId(p.var(minimal)?.try_into()?)))
+            }
+            FrameKind::Origin => payload
+                .decode_all(|p| decode_origin_entries(p).map(|entries| Frame::Origin { entries })),
+            FrameKind::Reserved(ty) => Err(FrameError::UnsupportedFrame(ty)),
+            FrameKind::WebTransportStream | FrameKind::Data => unreachable!(),
+            FrameKind::PriorityUpdateRequest
+            | FrameKind::PriorityUpdatePush
+            | FrameKind::Unknown(_) => {
+                buf.advance(len);
                 Err(FrameError::UnknownFrame(ty.0))
             }
         };
@@ -163,32 +264,317 @@ where
                 id.encode(buf);
                 // rest of the data is sent streaming
             }
+            Frame::Origin { entries } => {
+                FrameType::ORIGIN.encode(buf);
+                buf.write_var(origin_entries_len(entries) as u64);
+                for entry in entries {
+                    // Checked when the entry is built
+                    buf.put_u16(entry.0.len() as u16);
+                    buf.put_slice(&entry.0);
+                }
+            }
+            Frame::Extension(f) => {
+                f.ty.encode(buf);
+                buf.write_var(f.payload.len() as u64);
+            }
+        }
+    }
+}
+
+/// The payload of a frame being decoded, bounded by the length of the frame
+///
+/// The whole payload is buffered by then, so a field running past the end of the frame is
+/// malformed, rather than waiting for more bytes. So are bytes left once all fields are decoded.
+struct FramePayload<B>(bytes::buf::Take<B>);
+
+impl<B: Buf> FramePayload<B> {
+    fn new(buf: B, len: usize) -> Self {
+        Self(buf.take(len))
+    }
+
+    /// Decodes the fields with `f`, which must consume the whole payload
+    fn decode_all<T>(
+        mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, FrameError>,
+    ) -> Result<T, FrameError> {
+        let decoded = f(&mut self)?;
+        if self.0.has_remaining() {
+            return Err(FrameError::Malformed);
+        }
+        Ok(decoded)
+    }
+
+    fn var(&mut self, minimal: bool) -> Result<u64, FrameError> {
+        self.0.get_var_with(minimal).map_err(Self::overrun)
+    }
+
+    fn varint(&mut self, minimal: bool) -> Result<VarInt, FrameError> {
+        VarInt::decode_with(&mut self.0, minimal).map_err(Self::overrun)
+    }
+
+    fn u16(&mut self) -> Result<u16, FrameError> {
+        if self.0.remaining() < 2 {
+            return Err(FrameError::Malformed);
+        }
+        Ok(self.0.get_u16())
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<Bytes, FrameError> {
+        if self.0.remaining() < len {
+            return Err(FrameError::Malformed);
+        }
+        Ok(self.0.copy_to_bytes(len))
+    }
+
+    fn rest(&mut self) -> Bytes {
+        self.0.copy_to_bytes(self.0.remaining())
+    }
+
+    fn overrun(e: VarIntError) -> FrameError {
+        match e {
+            VarIntError::UnexpectedEnd(_) => FrameError::Malformed,
+            e => e.into(),
+        }
+    }
+}
+
+/// Decodes the entries of an ORIGIN frame, each an origin prefixed by its 16-bit length
+fn decode_origin_entries<B: Buf>(
+    payload: &mut FramePayload<B>,
+) -> Result<Vec<OriginEntry>, FrameError> {
+    let mut entries = Vec::new();
+    while payload.0.has_remaining() {
+        let len = payload.u16()?;
+        entries.push(OriginEntry(payload.bytes(len as usize)?));
+    }
+    Ok(entries)
+}
+
+/// Length of the payload of an ORIGIN frame holding `entries`
+fn origin_entries_len(entries: &[OriginEntry]) -> usize {
+    entries.iter().map(|entry| 2 + entry.0.len()).sum()
+}
+
+impl<B> Frame<B> {
+    /// The type this frame is encoded with, `None` for grease frames which get a random one
+    pub fn frame_type(&self) -> Option<FrameType> {
+        match self {
+            Frame::Data(_) => Some(FrameType::DATA),
+            Frame::Headers(_) => Some(FrameType::HEADERS),
+            Frame::CancelPush(_) => Some(FrameType::CANCEL_PUSH),
+            Frame::Settings(_) => Some(FrameType::SETTINGS),
+            Frame::PushPromise(_) => Some(FrameType::PUSH_PROMISE),
+            Frame::Goaway(_) => Some(FrameType::GOAWAY),
+            Frame::MaxPushId(_) => Some(FrameType::MAX_PUSH_ID),
+            Frame::WebTransportStream(_) => Some(FrameType::WEBTRANSPORT_BI_STREAM),
+            Frame::Origin { .. } => Some(FrameType::ORIGIN),
+            Frame::Extension(f) => Some(f.ty),
+            Frame::Grease => None,
+        }
+    }
+
+    /// The encoded field section of a HEADERS frame
+    pub fn headers_bytes(&self) -> Option<&Bytes> {
+        match self {
+            Frame::Headers(block) => Some(block),
+            _ => None,
+        }
+    }
+
+    /// Checks the encoded field section of a HEADERS frame is not empty, and at most `max` long
+    ///
+    /// This bounds the QPACK-encoded length, without decoding it. The decoded size, limited by
+    /// `SETTINGS_MAX_FIELD_SECTION_SIZE`, is at least as large. Frames of other types pass.
+    pub fn validate_header_block_len(&self, max: usize) -> Result<(), FrameError> {
+        match self.headers_bytes() {
+            Some(block) if block.is_empty() => Err(FrameError::Malformed),
+            Some(block) if block.len() > max => Err(FrameError::HeaderBlockTooLong {
+                len: block.len(),
+                max,
+            }),
+            _ => Ok(()),
         }
     }
 }
 
+/// Number of payload bytes shown by [`Frame::wire_dump()`]
+pub const WIRE_DUMP_PREVIEW: usize = 16;
+
 impl<B> Frame<B>
 where
     B: Buf,
 {
+    /// The payload of DATA, HEADERS and PUSH_PROMISE frames, not encoded with them
     pub fn payload(&self) -> Option<&dyn Buf> {
         match self {
             Frame::Data(f) => Some(f),
             Frame::Headers(f) => Some(f),
             Frame::PushPromise(f) => Some(&f.encoded),
+            Frame::Extension(f) => Some(&f.payload),
             _ => None,
         }
     }
 
+    /// Mutable access to the payload returned by [`Frame::payload()`]
     pub fn payload_mut(&mut self) -> Option<&mut dyn Buf> {
         match self {
             Frame::Data(f) => Some(f),
             Frame::Headers(f) => Some(f),
             Frame::PushPromise(f) => Some(&mut f.encoded),
+            Frame::Extension(f) => Some(&mut f.payload),
             _ => None,
         }
     }
 
+    /// Number of bytes this frame takes on the wire, header and payload included
+    ///
+    /// For `Grease` frames the type is randomly chosen at encode time, so this
+    /// returns an upper bound.
+    pub fn encoded_len(&self) -> usize {
+        fn var_size(x: u64) -> usize {
+            VarInt::from_u64(x).expect("frame varint overflow").size()
+        }
+
+        match self {
+            Frame::Data(b) => 1 + var_size(b.remaining() as u64) + b.remaining(),
+            Frame::Headers(f) => 1 + var_size(f.len() as u64) + f.len(),
+            Frame::Settings(f) => 1 + var_size(f.len() as u64) + f.len(),
+            Frame::PushPromise(f) => 1 + var_size(f.len() as u64) + f.len(),
+            Frame::CancelPush(id) | Frame::MaxPushId(id) => {
+                let size = var_size(id.0);
+                1 + var_size(size as u64) + size
+            }
+            Frame::Goaway(id) => 1 + var_size(id.size() as u64) + id.size(),
+            Frame::Grease => VarInt::MAX_SIZE + 1 + 6,
+            Frame::WebTransportStream(id) => 2 + var_size(id.into_inner()),
+            Frame::Origin { entries } => {
+                let len = origin_entries_len(entries);
+                1 + var_size(len as u64) + len
+            }
+            Frame::Extension(f) => {
+                var_size(f.ty.0) + var_size(f.payload.len() as u64) + f.payload.len()
+            }
+        }
+    }
+
+    /// Number of bytes [`Encode::encode()`] writes for this frame, exactly
+    ///
+    /// Unlike [`Frame::encoded_len()`], this leaves out the payload of DATA, HEADERS and extension
+    /// frames, which is not encoded with them. The type of `Grease` frames is drawn at encode
+    /// time, this is an upper bound for them.
+    pub fn encoded_size(&self) -> usize {
+        fn var_size(x: u64) -> usize {
+            VarInt::from_u64(x).expect("frame varint overflow").size()
+        }
+
+        match self {
+            Frame::Data(b) => 1 + var_size(b.remaining() as u64),
+            Frame::Headers(f) => 1 + var_size(f.len() as u64),
+            Frame::Extension(f) => var_size(f.ty.0) + var_size(f.payload.len() as u64),
+            _ => self.encoded_len(),
+        }
+    }
+
+    /// Encodes this frame into `buf`, failing instead of panicking when there is no room for it
+    ///
+    /// On success, [`Frame::encoded_size()`] bytes are written, and their number is returned. On
+    /// failure, nothing is written, so `buf` can be flushed before encoding again. Growable
+    /// buffers, such as `BytesMut`, always have room.
+    pub fn try_encode<T: BufMut>(&self, buf: &mut T) -> Result<usize, BufferTooShort> {
+        let size = self.encoded_size();
+        if buf.remaining_mut() < size {
+            return Err(BufferTooShort(size));
+        }
+        let remaining = buf.remaining_mut();
+        self.encode(buf);
+        Ok(remaining - buf.remaining_mut())
+    }
+
+    /// Reserve enough space in `buf` to encode this frame and its payload
+    pub fn reserve_into(&self, buf: &mut BytesMut) {
+        buf.reserve(self.encoded_len());
+    }
+
+    /// Reserve enough space in `buf` to encode all `frames`, so they can be
+    /// written one after the other without reallocating
+    pub fn reserve_frames(frames: &[Frame<B>], buf: &mut BytesMut) {
+        buf.reserve(frames.iter().map(Frame::encoded_len).sum());
+    }
+
+    /// Encodes this frame as separate chunks, for a vectored write
+    ///
+    /// The payload of HEADERS and extension frames follows as a chunk of its own, sharing their
+    /// memory. That of a DATA frame is left out, for the caller to write after its header,
+    /// without copying it.
+    pub fn encode_chunks(&self) -> SmallVec<[Bytes; 2]> {
+        let mut header = BytesMut::with_capacity(self.encoded_size());
+        self.encode(&mut header);
+        let mut chunks = smallvec![header.freeze()];
+        match self {
+            Frame::Headers(f) => chunks.push(f.clone()),
+            Frame::Extension(f) => chunks.push(f.payload.clone()),
+            _ => (),
+        }
+        chunks
+    }
+
+    /// A hexadecimal dump of this frame as it goes on the wire, for logs and debugging
+    ///
+    /// The type and length varints are annotated with their value, then at most
+    /// [`WIRE_DUMP_PREVIEW`] bytes of the payload follow, with an ellipsis when it is longer.
+    /// WebTransport stream headers have no length, their session id is shown instead.
+    pub fn wire_dump(&self) -> String {
+        fn hex(bytes: &[u8]) -> String {
+            bytes
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>()
+                .join(" ")
+        }
+
+        let mut header = BytesMut::with_capacity(self.encoded_size());
+        self.encode(&mut header);
+        let mut rest = &header[..];
+        let ty = rest.get_var_with(false).expect("encoded frame type");
+        let ty_size = header.len() - rest.len();
+        let second = rest.get_var_with(false).expect("encoded frame length");
+        let second_end = header.len() - rest.len();
+        let label = match self {
+            Frame::WebTransportStream(_) => "session",
+            _ => "length",
+        };
+        let mut dump = format!(
+            "type {:#x} [{}] {} {} [{}]",
+            ty,
+            hex(&header[..ty_size]),
+            label,
+            second,
+            hex(&header[ty_size..second_end])
+        );
+        if let Frame::WebTransportStream(_) = self {
+            return dump;
+        }
+
+        let mut preview = rest.to_vec();
+        let payload_len = match self.payload() {
+            Some(payload) => {
+                let chunk = payload.chunk();
+                let wanted = WIRE_DUMP_PREVIEW.saturating_sub(preview.len());
+                preview.extend_from_slice(&chunk[..chunk.len().min(wanted)]);
+                rest.len() + payload.remaining()
+            }
+            None => rest.len(),
+        };
+        preview.truncate(WIRE_DUMP_PREVIEW);
+        dump.push_str(" payload [");
+        dump.push_str(&hex(&preview));
+        if payload_len > preview.len() {
+            dump.push_str(" ...");
+        }
+        dump.push(']');
+        dump
+    }
+
     #[cfg(test)]
     pub fn encode_with_payload<T: BufMut>(&mut self, buf: &mut T) {
         self.encode(buf);
@@ -204,6 +590,7 @@ where
                 }
             }
             Frame::Headers(b) => buf.put_slice(b),
+            Frame::Extension(f) => buf.put_slice(&f.payload),
             _ => (),
         }
     }
@@ -221,6 +608,8 @@ impl fmt::Debug for Frame<PayloadLen> {
             Frame::MaxPushId(id) => write!(f, "MaxPushId({})", id),
             Frame::Grease => write!(f, "Grease()"),
             Frame::WebTransportStream(session) => write!(f, "WebTransportStream({:?})", session),
+            Frame::Origin { entries } => write!(f, "Origin({} entries)", entries.len()),
+            Frame::Extension(frame) => write!(f, "Extension({:#x})", frame.ty.0),
         }
     }
 }
@@ -240,14 +629,17 @@ where
             Frame::MaxPushId(id) => write!(f, "MaxPushId({})", id),
             Frame::Grease => write!(f, "Grease()"),
             Frame::WebTransportStream(_) => write!(f, "WebTransportStream()"),
+            Frame::Origin { entries } => write!(f, "Origin({} entries)", entries.len()),
+            Frame::Extension(frame) => write!(f, "Extension({:#x})", frame.ty.0),
         }
     }
 }
 
 /// Compare two frames ignoring data
 ///
-/// Only useful for `encode() -> Frame<Buf>` then `decode() -> Frame<PayloadLen>` unit tests.
-#[cfg(test)]
+/// Only useful for `encode() -> Frame<Buf>` then `decode() -> Frame<PayloadLen>` unit tests
+/// and round-trip fuzzing.
+#[cfg(any(test, feature = "fuzzing"))]
 impl<T, U> PartialEq<Frame<T>> for Frame<U> {
     fn eq(&self, other: &Frame<T>) -> bool {
         match self {
@@ -262,10 +654,19 @@ impl<T, U> PartialEq<Frame<T>> for Frame<U> {
             Frame::WebTransportStream(x) => {
                 matches!(other, Frame::WebTransportStream(y) if x == y)
             }
+            Frame::Origin { entries: x } => matches!(other, Frame::Origin { entries: y } if x == y),
+            Frame::Extension(x) => matches!(other, Frame::Extension(y) if x == y),
         }
     }
 }
 
+#[cfg(any(test, feature = "arbitrary"))]
+fn arbitrary_payload<B: From<Vec<u8>>>(
+    u: &mut arbitrary::Unstructured<'_>,
+) -> arbitrary::Result<B> {
+    Ok(u.arbitrary::<Vec<u8>>()?.into())
+}
+
 #[cfg(test)]
 impl Frame<Bytes> {
     pub fn headers<T: Into<Bytes>>(block: T) -> Self {
@@ -274,26 +675,44 @@ impl Frame<Bytes> {
 }
 
 macro_rules! frame_types {
-    {$($name:ident = $val:expr,)*} => {
+    {$($(#[$doc:meta])* $name:ident = $val:expr,)*} => {
         impl FrameType {
-            $(pub const $name: FrameType = FrameType($val);)*
+            $($(#[$doc])* pub const $name: FrameType = FrameType($val);)*
         }
     }
 }
 
 frame_types! {
+    /// DATA
     DATA = 0x0,
+    /// HEADERS
     HEADERS = 0x1,
+    /// PRIORITY of HTTP/2, reserved
     H2_PRIORITY = 0x2,
+    /// CANCEL_PUSH
     CANCEL_PUSH = 0x3,
+    /// SETTINGS
     SETTINGS = 0x4,
+    /// PUSH_PROMISE
     PUSH_PROMISE = 0x5,
+    /// PING of HTTP/2, reserved
     H2_PING = 0x6,
+    /// GOAWAY
     GOAWAY = 0x7,
+    /// WINDOW_UPDATE of HTTP/2, reserved
     H2_WINDOW_UPDATE = 0x8,
+    /// CONTINUATION of HTTP/2, reserved
     H2_CONTINUATION = 0x9,
+    /// ORIGIN, see <https://www.rfc-editor.org/rfc/rfc9412#section-2>
+    ORIGIN = 0xC,
+    /// MAX_PUSH_ID
     MAX_PUSH_ID = 0xD,
-    // Reserved frame types
+    /// PRIORITY_UPDATE for a request stream, see <https://www.rfc-editor.org/rfc/rfc9218#section-7.1>
+    PRIORITY_UPDATE_REQUEST = 0xF0700,
+    /// PRIORITY_UPDATE for a push stream
+    PRIORITY_UPDATE_PUSH = 0xF0701,
+    /// Header of a WebTransport bidirectional stream, see
+    /// <https://datatracker.ietf.org/doc/html/draft-ietf-webtrans-http3/#section-4.2>
     WEBTRANSPORT_BI_STREAM = 0x41,
 }
 
@@ -305,21 +724,121 @@ impl FrameType {
     }
 }
 
+/// The type of a frame, as encoded before its length
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FrameType(u64);
 
+impl From<VarInt> for FrameType {
+    fn from(ty: VarInt) -> Self {
+        FrameType(ty.0)
+    }
+}
+
 impl FrameType {
-    fn decode<B: Buf>(buf: &mut B) -> Result<Self, UnexpectedEnd> {
-        Ok(FrameType(buf.get_var()?))
+    fn decode<B: Buf>(buf: &mut B, minimal: bool) -> Result<Self, VarIntError> {
+        Ok(FrameType(buf.get_var_with(minimal)?))
     }
+
+    /// Decodes the type and payload length of the frame at the start of `buf`
+    ///
+    /// Returns `None` unless both are entirely buffered and valid.
+    pub(crate) fn decode_header<B: Buf>(buf: &mut B, minimal: bool) -> Option<(Self, u64)> {
+        let ty = Self::decode(buf, minimal).ok()?;
+        let len = buf.get_var_with(minimal).ok()?;
+        Some((ty, len))
+    }
+
+    /// Encodes this type as a varint
     pub fn encode<B: BufMut>(&self, buf: &mut B) {
         buf.write_var(self.0);
     }
 
+    /// The value of this type
+    pub fn into_inner(self) -> u64 {
+        self.0
+    }
+
+    /// Whether this type is reserved to exercise the requirement that unknown types be ignored
+    ///
+    /// Types of HTTP/2 frames which have no HTTP/3 counterpart are also reserved, but cannot be
+    /// received. Those are classified as [`FrameKind::Reserved`] instead.
+    pub fn is_reserved(self) -> bool {
+        //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.8
+        //# Frame types of the format 0x1f * N + 0x21 for non-negative integer
+        //# values of N are reserved to exercise the requirement that unknown
+        //# types be ignored (Section 9).
+        self.0 >= 0x21 && (self.0 - 0x21) % 0x1f == 0
+    }
+
+    /// Classify this frame type
+    pub fn kind(self) -> FrameKind {
+        match self {
+            FrameType::DATA => FrameKind::Data,
+            FrameType::HEADERS => FrameKind::Headers,
+            FrameType::CANCEL_PUSH => FrameKind::CancelPush,
+            FrameType::SETTINGS => FrameKind::Settings,
+            FrameType::PUSH_PROMISE => FrameKind::PushPromise,
+            FrameType::GOAWAY => FrameKind::Goaway,
+            FrameType::MAX_PUSH_ID => FrameKind::MaxPushId,
+            FrameType::ORIGIN => FrameKind::Origin,
+            FrameType::PRIORITY_UPDATE_REQUEST => FrameKind::PriorityUpdateRequest,
+            FrameType::PRIORITY_UPDATE_PUSH => FrameKind::PriorityUpdatePush,
+            FrameType::WEBTRANSPORT_BI_STREAM => FrameKind::WebTransportStream,
+            //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.8
+            //# Frame types that were used in HTTP/2 where there is no corresponding
+            //# HTTP/3 frame have also been reserved (Section 11.2.1).
+            FrameType::H2_PRIORITY
+            | FrameType::H2_PING
+            | FrameType::H2_WINDOW_UPDATE
+            | FrameType::H2_CONTINUATION => FrameKind::Reserved(self.0),
+            FrameType(ty) => FrameKind::Unknown(ty),
+        }
+    }
+
     #[cfg(test)]
     pub(crate) const RESERVED: FrameType = FrameType(0x1f * 1337 + 0x21);
 }
 
+/// Known frame types, as a strongly-typed discriminant of [`FrameType`]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum FrameKind {
+    /// [`FrameType::DATA`]
+    Data,
+    /// [`FrameType::HEADERS`]
+    Headers,
+    /// [`FrameType::CANCEL_PUSH`]
+    CancelPush,
+    /// [`FrameType::SETTINGS`]
+    Settings,
+    /// [`FrameType::PUSH_PROMISE`]
+    PushPromise,
+    /// [`FrameType::GOAWAY`]
+    Goaway,
+    /// [`FrameType::MAX_PUSH_ID`]
+    MaxPushId,
+    /// [`FrameType::ORIGIN`]
+    Origin,
+    /// [`FrameType::PRIORITY_UPDATE_REQUEST`]
+    PriorityUpdateRequest,
+    /// [`FrameType::PRIORITY_UPDATE_PUSH`]
+    PriorityUpdatePush,
+    /// [`FrameType::WEBTRANSPORT_BI_STREAM`]
+    WebTransportStream,
+    /// HTTP/2 frame types reserved by HTTP/3, receiving one is a connection error
+    Reserved(u64),
+    /// Extension or grease frame types, to be ignored
+    Unknown(u64),
+}
+
+impl TryFrom<u64> for FrameKind {
+    type Error = VarIntBoundsExceeded;
+
+    fn try_from(ty: u64) -> Result<Self, Self::Error> {
+        Ok(FrameType(VarInt::try_from(ty)?.0).kind())
+    }
+}
+
 pub(crate) trait FrameHeader {
     fn len(&self) -> usize;
     const TYPE: FrameType;
@@ -329,9 +848,12 @@ pub(crate) trait FrameHeader {
     }
 }
 
+/// Payload of a PUSH_PROMISE frame: the id of the push and the field section of its request
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PushPromise {
     id: u64,
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
     encoded: Bytes,
 }
 
@@ -352,11 +874,36 @@ impl FrameHeader for PushPromise {
     }
 }
 
+#[cfg(any(test, feature = "arbitrary"))]
+impl<'a> arbitrary::Arbitrary<'a> for PushPromise {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(PushPromise {
+            id: u.arbitrary::<VarInt>()?.0,
+            encoded: arbitrary_payload(u)?,
+        })
+    }
+}
+
 impl PushPromise {
-    fn decode<B: Buf>(buf: &mut B) -> Result<Self, UnexpectedEnd> {
+    /// A promise of push `id`, for the request of the QPACK-encoded field section `encoded`
+    pub fn new(id: PushId, encoded: Bytes) -> Self {
+        Self { id: id.0, encoded }
+    }
+
+    /// The id of the promised push
+    pub fn id(&self) -> PushId {
+        PushId(self.id)
+    }
+
+    /// The encoded field section of the promised request
+    pub fn encoded(&self) -> &Bytes {
+        &self.encoded
+    }
+
+    fn decode<B: Buf>(payload: &mut FramePayload<B>, minimal: bool) -> Result<Self, FrameError> {
         Ok(PushPromise {
-            id: buf.get_var()?,
-            encoded: buf.copy_to_bytes(buf.remaining()),
+            id: payload.var(minimal)?,
+            encoded: payload.rest(),
         })
     }
     fn encode<B: BufMut>(&self, buf: &mut B) {
@@ -365,13 +912,121 @@ impl PushPromise {
     }
 }
 
+/// A frame of a type this crate does not know, for extensions
+///
+/// Receivers ignore frames of unknown types, so extension frames can be sent to peers which do
+/// not implement the extension.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExtensionFrame {
+    ty: FrameType,
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    payload: Bytes,
+}
+
+impl ExtensionFrame {
+    /// A frame of type `ty`, with `payload`
+    ///
+    /// Returns `None` if `ty` is not available to extensions: defined by HTTP/3 or an extension
+    /// implemented here, or reserved for HTTP/2 frame types.
+    pub fn new(ty: VarInt, payload: Bytes) -> Option<Self> {
+        let ty = FrameType::from(ty);
+        match ty.kind() {
+            FrameKind::Unknown(_) => Some(Self { ty, payload }),
+            _ => None,
+        }
+    }
+
+    /// A PRIORITY_UPDATE frame for the request stream `element_id`, carrying `field_value`
+    ///
+    /// PRIORITY_UPDATE is not an extension type, as received frames of this type are skipped.
+    /// This only builds one to be sent.
+    pub(crate) fn priority_update(element_id: VarInt, field_value: String) -> Self {
+        let mut payload = BytesMut::with_capacity(element_id.size() + field_value.len());
+        element_id.encode(&mut payload);
+        payload.put_slice(field_value.as_bytes());
+        Self {
+            ty: FrameType::PRIORITY_UPDATE_REQUEST,
+            payload: payload.freeze(),
+        }
+    }
+
+    /// The type of this frame
+    pub fn frame_type(&self) -> FrameType {
+        self.ty
+    }
+
+    /// The payload of this frame
+    pub fn payload(&self) -> &Bytes {
+        &self.payload
+    }
+}
+
+#[cfg(any(test, feature = "arbitrary"))]
+impl<'a> arbitrary::Arbitrary<'a> for ExtensionFrame {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let ty = u.arbitrary::<VarInt>()?;
+        ExtensionFrame::new(ty, arbitrary_payload(u)?).ok_or(arbitrary::Error::IncorrectFormat)
+    }
+}
+
+/// An entry of an ORIGIN frame, the ASCII serialization of an origin
+///
+/// Entries are encoded after their length on 16 bits, so they are at most 65535 bytes long.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OriginEntry(Bytes);
+
+impl OriginEntry {
+    /// The entry for `origin`
+    ///
+    /// Returns `None` if `origin` is too long to be encoded, that is over 65535 bytes.
+    pub fn new(origin: Bytes) -> Option<Self> {
+        match u16::try_from(origin.len()) {
+            Ok(_) => Some(Self(origin)),
+            Err(_) => None,
+        }
+    }
+
+    /// The serialization of the origin
+    pub fn as_bytes(&self) -> &Bytes {
+        &self.0
+    }
+}
+
+#[cfg(any(test, feature = "arbitrary"))]
+impl<'a> arbitrary::Arbitrary<'a> for OriginEntry {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let mut entry = u.arbitrary::<Vec<u8>>()?;
+        entry.truncate(u16::MAX as usize);
+        Ok(Self(entry.into()))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for OriginEntry {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        hex_bytes::serialize(&self.0, serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for OriginEntry {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let origin = hex_bytes::deserialize(deserializer)?;
+        OriginEntry::new(origin)
+            .ok_or_else(|| serde::de::Error::custom("origin entry over 65535 bytes"))
+    }
+}
+
 fn simple_frame_encode<B: BufMut>(ty: FrameType, id: VarInt, buf: &mut B) {
     ty.encode(buf);
     buf.write_var(id.size() as u64);
     id.encode(buf);
 }
 
+/// The identifier of a setting
 #[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SettingId(pub u64);
 
 impl SettingId {
@@ -383,17 +1038,19 @@ impl SettingId {
         SettingId(fastrand::u64(0..0x210842108421083) * 0x1f + 0x21)
     }
 
+    const SUPPORTED: [SettingId; 8] = [
+        SettingId::MAX_HEADER_LIST_SIZE,
+        SettingId::QPACK_MAX_TABLE_CAPACITY,
+        SettingId::QPACK_MAX_BLOCKED_STREAMS,
+        SettingId::ENABLE_CONNECT_PROTOCOL,
+        SettingId::ENABLE_WEBTRANSPORT,
+        SettingId::WEBTRANSPORT_MAX_SESSIONS,
+        SettingId::WT_MAX_SESSIONS,
+        SettingId::H3_DATAGRAM,
+    ];
+
     fn is_supported(self) -> bool {
-        matches!(
-            self,
-            SettingId::MAX_HEADER_LIST_SIZE
-                | SettingId::QPACK_MAX_TABLE_CAPACITY
-                | SettingId::QPACK_MAX_BLOCKED_STREAMS
-                | SettingId::ENABLE_CONNECT_PROTOCOL
-                | SettingId::ENABLE_WEBTRANSPORT
-                | SettingId::WEBTRANSPORT_MAX_SESSIONS
-                | SettingId::H3_DATAGRAM,
-        )
+        Self::SUPPORTED.contains(&self)
     }
 
     /// Returns if a Settings Identifier is forbidden
@@ -410,8 +1067,8 @@ impl SettingId {
         )
     }
 
-    fn decode<B: Buf>(buf: &mut B) -> Result<Self, UnexpectedEnd> {
-        Ok(SettingId(buf.get_var()?))
+    fn decode<B: Buf>(buf: &mut B, minimal: bool) -> Result<Self, VarIntError> {
+        Ok(SettingId(buf.get_var_with(minimal)?))
     }
 
     fn encode<B: BufMut>(&self, buf: &mut B) {
@@ -420,35 +1077,57 @@ impl SettingId {
 }
 
 macro_rules! setting_identifiers {
-    {$($name:ident = $val:expr,)*} => {
+    {$($(#[$doc:meta])* $name:ident = $val:expr,)*} => {
         impl SettingId {
-            $(pub const $name: SettingId = SettingId($val);)*
+            $($(#[$doc])* pub const $name: SettingId = SettingId($val);)*
         }
     }
 }
 
 setting_identifiers! {
+    /// SETTINGS_QPACK_MAX_TABLE_CAPACITY
     QPACK_MAX_TABLE_CAPACITY = 0x1,
+    /// SETTINGS_QPACK_BLOCKED_STREAMS
     QPACK_MAX_BLOCKED_STREAMS = 0x7,
+    /// SETTINGS_MAX_FIELD_SECTION_SIZE
     MAX_HEADER_LIST_SIZE = 0x6,
-    // https://datatracker.ietf.org/doc/html/rfc9220#section-5
+    /// SETTINGS_ENABLE_CONNECT_PROTOCOL, see <https://datatracker.ietf.org/doc/html/rfc9220#section-5>
     ENABLE_CONNECT_PROTOCOL = 0x8,
-    // https://datatracker.ietf.org/doc/html/rfc9297#name-http-3-setting
+    /// SETTINGS_H3_DATAGRAM, see <https://datatracker.ietf.org/doc/html/rfc9297#name-http-3-setting>
     H3_DATAGRAM = 0x33,
-    // https://datatracker.ietf.org/doc/html/draft-ietf-webtrans-http3/#section-8.2
+    /// SETTINGS_ENABLE_WEBTRANSPORT, see
+    /// <https://datatracker.ietf.org/doc/html/draft-ietf-webtrans-http3/#section-8.2>
     ENABLE_WEBTRANSPORT = 0x2B603742,
-    // https://datatracker.ietf.org/doc/html/draft-ietf-webtrans-http3/#section-8.2
+    /// The datagram setting of an older draft, still sent by Chrome
     H3_SETTING_ENABLE_DATAGRAM_CHROME_SPECIFIC= 0xFFD277,
-
+    /// SETTINGS_WEBTRANSPORT_MAX_SESSIONS
     WEBTRANSPORT_MAX_SESSIONS = 0x2b603743,
+    /// SETTINGS_WT_MAX_SESSIONS, which replaces SETTINGS_WEBTRANSPORT_MAX_SESSIONS in later drafts,
+    /// see <https://datatracker.ietf.org/doc/html/draft-ietf-webtrans-http3/#section-9.2>
+    WT_MAX_SESSIONS = 0xc671706a,
 }
 
 const SETTINGS_LEN: usize = 8;
 
+/// Payload of a SETTINGS frame, holding up to 8 settings
+///
+/// When decoding, settings this crate does not support are kept apart, in the order received,
+/// see [`Settings::unknown_iter()`]. They are encoded again after the others. Up to
+/// [`Settings::MAX_UNKNOWN`] of them are kept, a frame with more fails to decode.
 #[derive(Debug, PartialEq)]
 pub struct Settings {
     entries: [(SettingId, u64); SETTINGS_LEN],
     len: usize,
+    unknown: Vec<(SettingId, u64)>,
+}
+
+/// The QPACK settings of a peer, which its encoder and decoder streams are set up with
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct QpackParams {
+    /// SETTINGS_QPACK_MAX_TABLE_CAPACITY, the size of the dynamic table the peer decoder allows
+    pub max_table_capacity: u64,
+    /// SETTINGS_QPACK_BLOCKED_STREAMS, the number of streams which may wait for table updates
+    pub blocked_streams: u64,
 }
 
 impl Default for Settings {
@@ -456,26 +1135,62 @@ impl Default for Settings {
         Self {
             entries: [(SettingId::NONE, 0); SETTINGS_LEN],
             len: 0,
+            unknown: Vec::new(),
+        }
+    }
+}
+
+// Only supported settings are generated, others are not decoded
+#[cfg(any(test, feature = "arbitrary"))]
+impl<'a> arbitrary::Arbitrary<'a> for Settings {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let mut settings = Settings::default();
+        for _ in 0..u.int_in_range(0..=SETTINGS_LEN)? {
+            let id = *u.choose(&SettingId::SUPPORTED)?;
+            // A repeated id is left out, the frame would not decode
+            let _ = settings.insert(id, u.arbitrary::<VarInt>()?.0);
         }
+        Ok(settings)
     }
 }
 
 impl FrameHeader for Settings {
     const TYPE: FrameType = FrameType::SETTINGS;
     fn len(&self) -> usize {
-        self.entries[..self.len].iter().fold(0, |len, (id, val)| {
-            len + VarInt::from_u64(id.0).unwrap().size() + VarInt::from_u64(*val).unwrap().size()
-        })
+        self.entries[..self.len]
+            .iter()
+            .chain(self.unknown.iter())
+            .fold(0, |len, (id, val)| {
+                len + VarInt::from_u64(id.0).unwrap().size()
+                    + VarInt::from_u64(*val).unwrap().size()
+            })
     }
 }
 
 impl Settings {
+    /// Maximum encoded length of the payload, unknown settings aside
     pub const MAX_ENCODED_SIZE: usize = SETTINGS_LEN * 2 * VarInt::MAX_SIZE;
 
+    /// Maximum number of settings of unknown identifier kept when decoding
+    pub const MAX_UNKNOWN: usize = 64;
+
+    /// Adds a setting, which must not be set already
+    ///
+    /// Fails if there is no room left, or if `id` or `value` cannot be encoded as a varint.
     pub fn insert(&mut self, id: SettingId, value: u64) -> Result<(), SettingsError> {
         if self.len >= self.entries.len() {
             return Err(SettingsError::Exceeded);
         }
+        if VarInt::from_u64(id.0).is_err() {
+            return Err(SettingsError::InvalidSettingId(id.0));
+        }
+        if VarInt::from_u64(value).is_err() {
+            return Err(SettingsError::InvalidSettingValue(id, value));
+        }
+        // RFC 9220 section 3: the value MUST be 0 or 1, and other values are a H3_SETTINGS_ERROR
+        if id == SettingId::ENABLE_CONNECT_PROTOCOL && value > 1 {
+            return Err(SettingsError::InvalidSettingValue(id, value));
+        }
 
         //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.4
         //# The same setting identifier MUST NOT occur more than once in the
@@ -489,6 +1204,22 @@ impl Settings {
         Ok(())
     }
 
+    // Each one takes more memory than it does on the wire, so a peer only gets to send a few
+    fn insert_unknown(&mut self, id: SettingId, value: u64) -> Result<(), SettingsError> {
+        if self.unknown.len() >= Self::MAX_UNKNOWN {
+            return Err(SettingsError::Exceeded);
+        }
+        if VarInt::from_u64(id.0).is_err() {
+            return Err(SettingsError::InvalidSettingId(id.0));
+        }
+        if VarInt::from_u64(value).is_err() {
+            return Err(SettingsError::InvalidSettingValue(id, value));
+        }
+        self.unknown.push((id, value));
+        Ok(())
+    }
+
+    /// The value of setting `id`, if set
     pub fn get(&self, id: SettingId) -> Option<u64> {
         for (entry_id, value) in self.entries.iter() {
             if id == *entry_id {
@@ -498,15 +1229,67 @@ impl Settings {
         None
     }
 
-    pub(crate) fn encode<T: BufMut>(&self, buf: &mut T) {
+    /// Sets SETTINGS_ENABLE_CONNECT_PROTOCOL, for the extended CONNECT method of RFC 9220
+    ///
+    /// Fails like [`Settings::insert()`] if the setting is already present or there is no room left.
+    pub fn enable_connect_protocol(&mut self, enabled: bool) -> Result<(), SettingsError> {
+        self.insert(SettingId::ENABLE_CONNECT_PROTOCOL, enabled as u64)
+    }
+
+    /// Sets SETTINGS_WT_MAX_SESSIONS, the number of WebTransport sessions accepted at once
+    ///
+    /// Fails like [`Settings::insert()`] if the setting is already present or there is no room left.
+    pub fn set_webtransport_max_sessions(&mut self, max: u64) -> Result<(), SettingsError> {
+        self.insert(SettingId::WT_MAX_SESSIONS, max)
+    }
+
+    /// The maximum number of WebTransport sessions, if advertised
+    ///
+    /// SETTINGS_WT_MAX_SESSIONS is preferred over SETTINGS_WEBTRANSPORT_MAX_SESSIONS, which peers
+    /// following older drafts send instead.
+    pub fn webtransport_max_sessions(&self) -> Option<u64> {
+        self.get(SettingId::WT_MAX_SESSIONS)
+            .or_else(|| self.get(SettingId::WEBTRANSPORT_MAX_SESSIONS))
+    }
+
+    /// The QPACK settings, for setting up the encoder and decoder streams
+    ///
+    /// Both are 0 unless advertised, which disables the dynamic table.
+    pub fn qpack_params(&self) -> QpackParams {
+        QpackParams {
+            max_table_capacity: self.get(SettingId::QPACK_MAX_TABLE_CAPACITY).unwrap_or(0),
+            blocked_streams: self.get(SettingId::QPACK_MAX_BLOCKED_STREAMS).unwrap_or(0),
+        }
+    }
+
+    /// The settings of unknown identifier decoded, as `(identifier, value)` in the order received
+    ///
+    /// These include reserved settings sent to exercise the requirement that unknown identifiers
+    /// be ignored, which a proxy forwards like any other.
+    pub fn unknown_iter(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.unknown.iter().map(|(id, value)| (id.0, *value))
+    }
+
+    /// Whether SETTINGS_ENABLE_CONNECT_PROTOCOL is set to 1
+    ///
+    /// An absent setting is the same as 0, extended CONNECT is then disabled.
+    pub fn connect_protocol_enabled(&self) -> bool {
+        self.get(SettingId::ENABLE_CONNECT_PROTOCOL) == Some(1)
+    }
+
+    /// Encodes the SETTINGS frame, its type and length included
+    pub fn encode<T: BufMut>(&self, buf: &mut T) {
         self.encode_header(buf);
-        for (id, val) in self.entries[..self.len].iter() {
+        for (id, val) in self.entries[..self.len].iter().chain(self.unknown.iter()) {
             id.encode(buf);
             buf.write_var(*val);
         }
     }
 
-    pub(super) fn decode<T: Buf>(buf: &mut T) -> Result<Settings, SettingsError> {
+    /// Decodes the payload of a SETTINGS frame, which must be all of `buf`
+    ///
+    /// Non-minimally encoded varints are rejected if `minimal` is set.
+    pub fn decode<T: Buf>(buf: &mut T, minimal: bool) -> Result<Settings, SettingsError> {
         let mut settings = Settings::default();
         while buf.has_remaining() {
             if buf.remaining() < 2 {
@@ -514,8 +1297,11 @@ impl Settings {
                 return Err(SettingsError::Malformed);
             }
 
-            let identifier = SettingId::decode(buf).map_err(|_| SettingsError::Malformed)?;
-            let value = buf.get_var().map_err(|_| SettingsError::Malformed)?;
+            let identifier =
+                SettingId::decode(buf, minimal).map_err(|_| SettingsError::Malformed)?;
+            let value = buf
+                .get_var_with(minimal)
+                .map_err(|_| SettingsError::Malformed)?;
 
             if identifier.is_forbidden() {
                 //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.4.1
@@ -536,19 +1322,27 @@ impl Settings {
                 //# H3_SETTINGS_ERROR.
                 settings.insert(identifier, value)?;
             } else {
-                tracing::warn!("Unsupported setting: {:#x?}", identifier);
+                // Peers routinely send reserved settings, this is no cause for concern
+                tracing::trace!("Unsupported setting: {:#x?}", identifier);
+                settings.insert_unknown(identifier, value)?;
             }
         }
         Ok(settings)
     }
 }
 
+/// Error decoding or building a SETTINGS frame
 #[derive(Debug, PartialEq)]
 pub enum SettingsError {
+    /// More settings than a frame holds
     Exceeded,
+    /// The payload does not end with a complete setting
     Malformed,
+    /// A setting is present twice
     Repeated(SettingId),
+    /// A setting identifier is reserved or out of varint bounds
     InvalidSettingId(u64),
+    /// A setting value is out of range
     InvalidSettingValue(SettingId, u64),
 }
 
@@ -583,6 +1377,15 @@ impl From<UnexpectedEnd> for FrameError {
     }
 }
 
+impl From<VarIntError> for FrameError {
+    fn from(e: VarIntError) -> Self {
+        match e {
+            VarIntError::UnexpectedEnd(e) => e.into(),
+            VarIntError::NonMinimal(x) => FrameError::NonMinimalVarInt(x.into_inner()),
+        }
+    }
+}
+
 impl From<InvalidStreamId> for FrameError {
     fn from(e: InvalidStreamId) -> Self {
         FrameError::InvalidStreamId(e)
@@ -595,6 +1398,91 @@ impl From<InvalidPushId> for FrameError {
     }
 }
 
+/// Payload of a DATA frame, which the `serde` feature handles depending on its type
+///
+/// Payloads of received frames are only known by their length, while the bytes of frames to
+/// send are serialized as a hex string.
+#[cfg(feature = "serde")]
+pub trait SerdePayload: Sized {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>;
+    fn deserialize<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>;
+}
+
+#[cfg(feature = "serde")]
+impl SerdePayload for PayloadLen {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serde::Serialize::serialize(self, serializer)
+    }
+
+    fn deserialize<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        serde::Deserialize::deserialize(deserializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl SerdePayload for Bytes {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        hex_bytes::serialize(self, serializer)
+    }
+
+    fn deserialize<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        hex_bytes::deserialize(deserializer)
+    }
+}
+
+/// Opaque bytes, such as encoded header blocks, as hex strings
+#[cfg(feature = "serde")]
+mod hex_bytes {
+    use bytes::Bytes;
+    use serde::{de::Error as _, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &Bytes, serializer: S) -> Result<S::Ok, S::Error> {
+        let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        serializer.serialize_str(&hex)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Bytes, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        if !hex.is_ascii() || hex.len() % 2 != 0 {
+            return Err(D::Error::custom("invalid hex string"));
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Bytes::from)
+            .map_err(D::Error::custom)
+    }
+}
+
+/// Settings are (de)serialized as a list of identifier and value pairs, in the encoding order
+#[cfg(feature = "serde")]
+impl serde::Serialize for Settings {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.entries[..self.len].iter().chain(self.unknown.iter()))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Settings {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error as _;
+
+        let mut settings = Settings::default();
+        for (id, value) in Vec::<(SettingId, u64)>::deserialize(deserializer)? {
+            let inserted = if id.is_forbidden() {
+                Err(SettingsError::InvalidSettingId(id.0))
+            } else if id.is_supported() {
+                settings.insert(id, value)
+            } else {
+                settings.insert_unknown(id, value)
+            };
+            inserted.map_err(D::Error::custom)?;
+        }
+        Ok(settings)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -608,6 +1496,46 @@ mod tests {
         assert_matches!(Frame::decode(&mut buf), Ok(Frame::CancelPush(PushId(2))));
     }
 
+    #[test]
+    fn non_minimal_varint() {
+        // GOAWAY carrying the id 5, encoded on 1 then 8 bytes
+        let minimal = [7, 1, 5];
+        let non_minimal = [7, 8, 0xc0, 0, 0, 0, 0, 0, 0, 5];
+
+        for strict in [false, true] {
+            let decoded = Frame::decode_with(&mut Cursor::new(&minimal), strict);
+            assert_matches!(decoded, Ok(Frame::Goaway(id)) if id.into_inner() == 5);
+        }
+        let decoded = Frame::decode_with(&mut Cursor::new(&non_minimal), false);
+        assert_matches!(decoded, Ok(Frame::Goaway(id)) if id.into_inner() == 5);
+        let decoded = Frame::decode_with(&mut Cursor::new(&non_minimal), true);
+        assert_matches!(decoded, Err(FrameError::NonMinimalVarInt(5)));
+
+        // Frame type encoded on 2 bytes
+        let decoded = Frame::decode_with(&mut Cursor::new(&[0x40, 7, 1, 5]), true);
+        assert_matches!(decoded, Err(FrameError::NonMinimalVarInt(7)));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_headers_roundtrip() {
+        let frame = Frame::<Bytes>::headers(&b"\x00\x00\xd1"[..]);
+        let json = serde_json::to_string(&frame).unwrap();
+        assert_eq!(json, r#"{"Headers":"0000d1"}"#);
+        let decoded: Frame<Bytes> = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, frame);
+
+        let mut settings = Settings::default();
+        settings
+            .insert(SettingId::MAX_HEADER_LIST_SIZE, 12)
+            .unwrap();
+        let frame = Frame::<PayloadLen>::Settings(settings);
+        let json = serde_json::to_string(&frame).unwrap();
+        assert_eq!(json, r#"{"Settings":[[6,12]]}"#);
+        let decoded: Frame<PayloadLen> = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, frame);
+    }
+
     #[test]
     fn len_unexpected_end() {
         let mut buf = Cursor::new(&[0, 255]);
@@ -654,6 +1582,7 @@ mod tests {
                     (SettingId::NONE, 0),
                 ],
                 len: 4,
+                unknown: Vec::new(),
             }),
             &[
                 4, 18, 6, 128, 0, 250, 209, 1, 128, 0, 250, 210, 7, 128, 0, 250, 211, 64, 95, 0,
@@ -663,7 +1592,6 @@ mod tests {
                     (SettingId::MAX_HEADER_LIST_SIZE, 0xfad1),
                     (SettingId::QPACK_MAX_TABLE_CAPACITY, 0xfad2),
                     (SettingId::QPACK_MAX_BLOCKED_STREAMS, 0xfad3),
-                    // check without the Grease setting because this is ignored
                     (SettingId(0), 0),
                     (SettingId::NONE, 0),
                     (SettingId::NONE, 0),
@@ -671,10 +1599,164 @@ mod tests {
                     (SettingId::NONE, 0),
                 ],
                 len: 3,
+                // The Grease setting is kept apart
+                unknown: vec![(SettingId(95), 0)],
             }),
         );
     }
 
+    #[test]
+    fn settings_unknown_round_trip() {
+        let mut payload = BytesMut::new();
+        for (id, value) in [(0x21, 7), (0x6, 0xfad1), (0x40, 0), (0x1, 4096), (0x3f, 1)] {
+            payload.write_var(id);
+            payload.write_var(value);
+        }
+
+        let settings = Settings::decode(&mut payload.clone().freeze(), true).unwrap();
+        assert_eq!(settings.get(SettingId::MAX_HEADER_LIST_SIZE), Some(0xfad1));
+        assert_eq!(
+            settings.get(SettingId::QPACK_MAX_TABLE_CAPACITY),
+            Some(4096)
+        );
+        assert_eq!(
+            settings.unknown_iter().collect::<Vec<_>>(),
+            [(0x21, 7), (0x40, 0), (0x3f, 1)]
+        );
+
+        // Known settings first, then the unknown ones in their original order
+        let mut encoded = BytesMut::new();
+        settings.encode(&mut encoded);
+        let mut expected = BytesMut::new();
+        expected.write_var(FrameType::SETTINGS.0);
+        expected.write_var(payload.len() as u64);
+        for (id, value) in [(0x6, 0xfad1), (0x1, 4096), (0x21, 7), (0x40, 0), (0x3f, 1)] {
+            expected.write_var(id);
+            expected.write_var(value);
+        }
+        assert_eq!(encoded, expected);
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&settings).unwrap();
+            assert_eq!(json, "[[6,64209],[1,4096],[33,7],[64,0],[63,1]]");
+            assert_eq!(serde_json::from_str::<Settings>(&json).unwrap(), settings);
+        }
+
+        let mut encoded = encoded.freeze();
+        assert_eq!(
+            Frame::decode(&mut encoded).unwrap(),
+            Frame::<Bytes>::Settings(settings)
+        );
+    }
+
+    #[test]
+    fn settings_too_many_unknown() {
+        let mut payload = BytesMut::new();
+        for id in 0..Settings::MAX_UNKNOWN as u64 {
+            payload.write_var(0x21 + id * 0x1f);
+            payload.write_var(0);
+        }
+        let settings = Settings::decode(&mut payload.clone().freeze(), true).unwrap();
+        assert_eq!(settings.unknown_iter().count(), Settings::MAX_UNKNOWN);
+
+        payload.write_var(0x40);
+        payload.write_var(0);
+        assert_eq!(
+            Settings::decode(&mut payload.freeze(), true),
+            Err(SettingsError::Exceeded)
+        );
+    }
+
+    #[test]
+    fn settings_connect_protocol() {
+        assert!(!SettingId::ENABLE_CONNECT_PROTOCOL.is_forbidden());
+        assert!(!Settings::default().connect_protocol_enabled());
+
+        for enabled in [false, true] {
+            let mut settings = Settings::default();
+            settings.enable_connect_protocol(enabled).unwrap();
+            let mut buf = BytesMut::new();
+            settings.encode(&mut buf);
+            assert_eq!(&buf[..], &[4, 2, 8, enabled as u8]);
+
+            let mut payload = buf.split_off(2).freeze();
+            let decoded = Settings::decode(&mut payload, true).unwrap();
+            assert_eq!(decoded.connect_protocol_enabled(), enabled);
+            assert_eq!(
+                decoded.get(SettingId::ENABLE_CONNECT_PROTOCOL),
+                Some(enabled as u64)
+            );
+        }
+    }
+
+    #[test]
+    fn settings_qpack_params() {
+        let mut payload = Bytes::from_static(&[0x01, 0x40, 0xff, 0x07, 0x10, 0x06, 0x20]);
+        let settings = Settings::decode(&mut payload, true).unwrap();
+        assert_eq!(
+            settings.qpack_params(),
+            QpackParams {
+                max_table_capacity: 0xff,
+                blocked_streams: 0x10,
+            }
+        );
+
+        // RFC 9204 section 5: both default to 0
+        let mut payload = Bytes::from_static(&[0x06, 0x20]);
+        let settings = Settings::decode(&mut payload, true).unwrap();
+        assert_eq!(settings.qpack_params(), QpackParams::default());
+    }
+
+    #[test]
+    fn settings_webtransport_max_sessions() {
+        assert_eq!(Settings::default().webtransport_max_sessions(), None);
+
+        let mut settings = Settings::default();
+        settings.set_webtransport_max_sessions(16).unwrap();
+        let mut buf = BytesMut::new();
+        settings.encode(&mut buf);
+        // The identifier takes an 8-byte varint
+        assert_eq!(&buf[..], &[4, 9, 0xc0, 0, 0, 0, 0xc6, 0x71, 0x70, 0x6a, 16]);
+
+        let mut payload = buf.split_off(2).freeze();
+        let decoded = Settings::decode(&mut payload, true).unwrap();
+        assert_eq!(decoded.webtransport_max_sessions(), Some(16));
+        assert_eq!(decoded.get(SettingId::WT_MAX_SESSIONS), Some(16));
+        assert_eq!(decoded.unknown_iter().count(), 0);
+
+        // The identifier of older drafts is used when the other is absent
+        let mut settings = Settings::default();
+        settings
+            .insert(SettingId::WEBTRANSPORT_MAX_SESSIONS, 4)
+            .unwrap();
+        assert_eq!(settings.webtransport_max_sessions(), Some(4));
+        settings.set_webtransport_max_sessions(8).unwrap();
+        assert_eq!(settings.webtransport_max_sessions(), Some(8));
+        assert_eq!(
+            settings.set_webtransport_max_sessions(8),
+            Err(SettingsError::Repeated(SettingId::WT_MAX_SESSIONS))
+        );
+    }
+
+    #[test]
+    fn settings_connect_protocol_out_of_range() {
+        assert_eq!(
+            Settings::decode(&mut &[8, 2][..], true),
+            Err(SettingsError::InvalidSettingValue(
+                SettingId::ENABLE_CONNECT_PROTOCOL,
+                2
+            ))
+        );
+        assert_eq!(
+            Settings::default().insert(SettingId::ENABLE_CONNECT_PROTOCOL, 2),
+            Err(SettingsError::InvalidSettingValue(
+                SettingId::ENABLE_CONNECT_PROTOCOL,
+                2
+            ))
+        );
+    }
+
     #[test]
     fn settings_frame_emtpy() {
         codec_frame_check(
@@ -732,6 +1814,145 @@ mod tests {
         );
     }
 
+    #[test]
+    fn header_block_within_limit() {
+        let frame = Frame::<Bytes>::headers("TODO QPACK");
+        assert_eq!(frame.headers_bytes().unwrap(), "TODO QPACK");
+        assert_eq!(frame.validate_header_block_len(10), Ok(()));
+        assert_eq!(frame.validate_header_block_len(usize::MAX), Ok(()));
+
+        // Only HEADERS frames carry a header block
+        let frame = Frame::<Bytes>::Data(Bytes::from("TODO QPACK"));
+        assert!(frame.headers_bytes().is_none());
+        assert_eq!(frame.validate_header_block_len(0), Ok(()));
+    }
+
+    #[test]
+    fn header_block_over_limit() {
+        let frame = Frame::<Bytes>::headers("TODO QPACK");
+        assert_eq!(
+            frame.validate_header_block_len(9),
+            Err(FrameError::HeaderBlockTooLong { len: 10, max: 9 })
+        );
+        assert_eq!(
+            Frame::<Bytes>::headers("").validate_header_block_len(10),
+            Err(FrameError::Malformed)
+        );
+    }
+
+    #[test]
+    fn origin_frames() {
+        let entry = |origin: &'static str| OriginEntry::new(Bytes::from(origin)).unwrap();
+        let origins = || Frame::Origin {
+            entries: vec![entry("https://a.example"), entry("https://b.example:8443")],
+        };
+        let mut wire = vec![0xc, 43, 0, 17];
+        wire.extend(b"https://a.example");
+        wire.extend(&[0, 22]);
+        wire.extend(b"https://b.example:8443");
+        codec_frame_check(origins(), &wire, origins());
+        assert_eq!(origins().encoded_len(), wire.len());
+
+        let empty = || Frame::Origin { entries: vec![] };
+        codec_frame_check(empty(), &[0xc, 0], empty());
+        let empty_entry = || Frame::Origin {
+            entries: vec![entry("")],
+        };
+        codec_frame_check(empty_entry(), &[0xc, 2, 0, 0], empty_entry());
+    }
+
+    #[test]
+    fn origin_entry_too_long() {
+        let longest = OriginEntry::new(vec![b'a'; 0xffff].into()).unwrap();
+        assert_eq!(longest.as_bytes().len(), 0xffff);
+        let origins = || Frame::Origin {
+            entries: vec![longest.clone()],
+        };
+        let mut wire = vec![0xc, 0x80, 0x01, 0x00, 0x01, 0xff, 0xff];
+        wire.extend(longest.as_bytes());
+        codec_frame_check(origins(), &wire, origins());
+
+        assert_eq!(OriginEntry::new(vec![b'a'; 0x10000].into()), None);
+    }
+
+    #[test]
+    fn origin_entry_overruns_frame() {
+        // The entry claims 18 bytes, only 17 are left in the frame, the next one is another frame
+        let mut raw = vec![0xc, 19, 0, 18];
+        raw.extend(b"https://a.example");
+        raw.extend(&[7, 1, 0]);
+        let decoded = Frame::decode(&mut Cursor::new(&raw));
+        assert_matches!(decoded, Err(FrameError::Malformed));
+
+        // A length cut in half
+        let decoded = Frame::decode(&mut Cursor::new(&[0xc, 3, 0, 0, 1]));
+        assert_matches!(decoded, Err(FrameError::Malformed));
+    }
+
+    #[test]
+    fn push_promise_id_overruns_frame() {
+        // The push id is 2 bytes long, the frame only 1, a DATA frame follows
+        let decoded = Frame::decode(&mut Cursor::new(&[0x5, 1, 0x40, 0x0, 0x0]));
+        assert_matches!(decoded, Err(FrameError::Malformed));
+
+        // Not a frame waiting for more bytes, even when the payload ends the buffer
+        let decoded = Frame::decode(&mut Cursor::new(&[0x5, 1, 0x40]));
+        assert_matches!(decoded, Err(FrameError::Malformed));
+        let decoded = Frame::decode(&mut Cursor::new(&[0x5, 0]));
+        assert_matches!(decoded, Err(FrameError::Malformed));
+    }
+
+    #[test]
+    fn single_field_frame_overruns() {
+        for ty in [
+            FrameType::CANCEL_PUSH,
+            FrameType::GOAWAY,
+            FrameType::MAX_PUSH_ID,
+        ] {
+            let mut raw = vec![];
+            ty.encode(&mut raw);
+            // An 8-byte id in a frame of 4
+            raw.extend(&[4, 0xc0, 0, 0, 0, 0, 0, 0, 1]);
+            let decoded = Frame::decode(&mut Cursor::new(&raw));
+            assert_matches!(decoded, Err(FrameError::Malformed), "{:?}", ty);
+
+            // Bytes left after the id
+            let mut raw = vec![];
+            ty.encode(&mut raw);
+            raw.extend(&[2, 4, 0]);
+            let decoded = Frame::decode(&mut Cursor::new(&raw));
+            assert_matches!(decoded, Err(FrameError::Malformed), "{:?}", ty);
+        }
+    }
+
+    #[test]
+    fn zero_length_payloads() {
+        // Frames carrying an id need at least the 1 byte of its varint
+        for ty in [
+            FrameType::CANCEL_PUSH,
+            FrameType::PUSH_PROMISE,
+            FrameType::GOAWAY,
+            FrameType::MAX_PUSH_ID,
+        ] {
+            let mut raw = vec![];
+            ty.encode(&mut raw);
+            raw.push(0);
+            let decoded = Frame::decode(&mut Cursor::new(&raw));
+            assert_matches!(decoded, Err(FrameError::Malformed), "{:?}", ty);
+        }
+
+        // An empty SETTINGS frame is valid, and the frame which follows is left intact
+        let mut buf = Cursor::new(&[0x4, 0, 0x7, 1, 4][..]);
+        assert_eq!(
+            Frame::decode(&mut buf).unwrap(),
+            Frame::<Bytes>::Settings(Settings::default())
+        );
+        assert_eq!(
+            Frame::decode(&mut buf).unwrap(),
+            Frame::<Bytes>::Goaway(VarInt(4))
+        );
+    }
+
     #[test]
     fn reserved_frame() {
         let mut raw = vec![];
@@ -741,4 +1962,224 @@ mod tests {
         let decoded = Frame::decode(&mut buf);
         assert_matches!(decoded, Err(FrameError::UnknownFrame(95)));
     }
+
+    #[test]
+    fn frame_kind_known() {
+        assert_eq!(FrameKind::try_from(0x0), Ok(FrameKind::Data));
+        assert_eq!(FrameKind::try_from(0x1), Ok(FrameKind::Headers));
+        assert_eq!(FrameKind::try_from(0x3), Ok(FrameKind::CancelPush));
+        assert_eq!(FrameKind::try_from(0x4), Ok(FrameKind::Settings));
+        assert_eq!(FrameKind::try_from(0x5), Ok(FrameKind::PushPromise));
+        assert_eq!(FrameKind::try_from(0x7), Ok(FrameKind::Goaway));
+        assert_eq!(FrameKind::try_from(0xC), Ok(FrameKind::Origin));
+        assert_eq!(FrameKind::try_from(0xD), Ok(FrameKind::MaxPushId));
+        assert_eq!(
+            FrameKind::try_from(0xF0700),
+            Ok(FrameKind::PriorityUpdateRequest)
+        );
+        assert_eq!(
+            FrameKind::try_from(0xF0701),
+            Ok(FrameKind::PriorityUpdatePush)
+        );
+        assert_eq!(FrameKind::try_from(0x41), Ok(FrameKind::WebTransportStream));
+    }
+
+    #[test]
+    fn frame_kind_reserved() {
+        for ty in [0x2, 0x6, 0x8, 0x9] {
+            assert_eq!(FrameKind::try_from(ty), Ok(FrameKind::Reserved(ty)));
+        }
+    }
+
+    #[test]
+    fn frame_kind_unknown() {
+        assert_eq!(FrameKind::try_from(0x21), Ok(FrameKind::Unknown(0x21)));
+        assert_eq!(
+            FrameKind::try_from(FrameType::RESERVED.0),
+            Ok(FrameKind::Unknown(FrameType::RESERVED.0))
+        );
+        let grease = FrameType::grease();
+        assert_eq!(grease.kind(), FrameKind::Unknown(grease.0));
+        assert_eq!(FrameKind::try_from(0xff), Ok(FrameKind::Unknown(0xff)));
+        assert!(FrameKind::try_from(1 << 62).is_err());
+    }
+
+    #[test]
+    fn reserve_frames_fits_encoding() {
+        let mut frames = vec![
+            Frame::headers("TODO QPACK"),
+            Frame::Data(Bytes::from(vec![0xaa; 100])),
+            Frame::Data(Bytes::from(vec![0xbb; 20_000])),
+            Frame::Goaway(VarInt(42)),
+            Frame::Grease,
+        ];
+        let expected: usize = frames.iter().map(Frame::encoded_len).sum();
+
+        let mut buf = BytesMut::new();
+        Frame::reserve_frames(&frames, &mut buf);
+        let capacity = buf.capacity();
+        assert!(capacity >= expected);
+
+        for frame in frames.iter_mut() {
+            frame.encode_with_payload(&mut buf);
+        }
+        assert_eq!(buf.capacity(), capacity);
+        assert!(buf.len() <= expected);
+    }
+
+    #[test]
+    fn encoded_len_matches_wire() {
+        let mut frame = Frame::<Bytes>::PushPromise(PushPromise {
+            id: 134,
+            encoded: Bytes::from("TODO QPACK"),
+        });
+        let mut buf = BytesMut::new();
+        frame.reserve_into(&mut buf);
+        frame.encode_with_payload(&mut buf);
+        assert_eq!(frame.encoded_len(), buf.len());
+    }
+
+    #[test]
+    fn encode_chunks_concatenate_to_wire() {
+        let frames = [
+            Frame::Data(Bytes::from("body")),
+            Frame::headers("header"),
+            Frame::PushPromise(PushPromise {
+                id: 134,
+                encoded: Bytes::from("TODO QPACK"),
+            }),
+            Frame::Goaway(VarInt(4)),
+            Frame::Extension(ExtensionFrame::new(VarInt(0x4d), Bytes::from("ext")).unwrap()),
+        ];
+        for mut frame in frames {
+            let chunks = frame.encode_chunks();
+            let mut concatenated = BytesMut::new();
+            for chunk in &chunks {
+                concatenated.put_slice(chunk);
+            }
+            if let Frame::Data(body) = &frame {
+                assert_eq!(chunks.len(), 1);
+                concatenated.put_slice(body);
+            }
+
+            let mut wire = BytesMut::new();
+            frame.encode_with_payload(&mut wire);
+            assert_eq!(concatenated, wire, "{:?}", frame);
+        }
+
+        let payload = Bytes::from("header");
+        let chunks = Frame::<Bytes>::Headers(payload.clone()).encode_chunks();
+        assert_eq!(chunks[1].as_ptr(), payload.as_ptr());
+    }
+
+    #[test]
+    fn wire_dump_headers() {
+        let dump = Frame::<Bytes>::headers("header").wire_dump();
+        assert_eq!(
+            dump,
+            "type 0x1 [01] length 6 [06] payload [68 65 61 64 65 72]"
+        );
+
+        let dump = Frame::Data(Bytes::from(vec![0xab; 20])).wire_dump();
+        assert!(dump.starts_with("type 0x0 [00] length 20 [14] payload [ab ab"));
+        assert!(dump.ends_with("ab ...]"));
+        assert_eq!(dump.matches("ab").count(), WIRE_DUMP_PREVIEW);
+
+        let dump = Frame::<Bytes>::Goaway(VarInt(4)).wire_dump();
+        assert_eq!(dump, "type 0x7 [07] length 1 [01] payload [04]");
+    }
+
+    /// A body of `0` bytes, of any length without allocating it
+    struct Zeros(usize);
+
+    impl Buf for Zeros {
+        fn remaining(&self) -> usize {
+            self.0
+        }
+
+        fn chunk(&self) -> &[u8] {
+            &[0; 64][..self.0.min(64)]
+        }
+
+        fn advance(&mut self, cnt: usize) {
+            self.0 -= cnt;
+        }
+    }
+
+    fn assert_encoded_size<B: Buf>(frame: &Frame<B>) {
+        let mut buf = BytesMut::new();
+        frame.encode(&mut buf);
+        match frame {
+            Frame::Grease => assert!(buf.len() <= frame.encoded_size()),
+            _ => assert_eq!(buf.len(), frame.encoded_size()),
+        }
+    }
+
+    #[test]
+    fn encoded_size_matches_encode() {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        let mut rng = fastrand::Rng::with_seed(0x6833);
+        let mut data = Vec::new();
+        for _ in 0..4096 {
+            data.clear();
+            data.extend((0..rng.usize(..256)).map(|_| rng.u8(..)));
+            if let Ok(frame) = Frame::<Bytes>::arbitrary_take_rest(Unstructured::new(&data)) {
+                assert_encoded_size(&frame);
+            }
+        }
+    }
+
+    #[test]
+    fn encoded_size_at_varint_boundaries() {
+        // On each side of the largest length encoded on 1, 2, 4 and 8 bytes
+        let lens = [
+            (1 << 6) - 1,
+            1 << 6,
+            (1 << 14) - 1,
+            1 << 14,
+            (1 << 30) - 1,
+            1 << 30,
+            (1 << 62) - 1,
+        ];
+        for len in lens {
+            assert_encoded_size(&Frame::Data(Zeros(len)));
+        }
+
+        // Payloads which are encoded, or allocated, stay short
+        for len in lens.into_iter().take(4) {
+            let payload = Bytes::from(vec![0; len]);
+            assert_encoded_size(&Frame::<Bytes>::Headers(payload.clone()));
+            assert_encoded_size(&Frame::<Bytes>::PushPromise(PushPromise {
+                id: len as u64,
+                encoded: payload.clone(),
+            }));
+            assert_encoded_size(&Frame::<Bytes>::Extension(
+                ExtensionFrame::new(VarInt(len as u64 + 0x21), payload).unwrap(),
+            ));
+            assert_encoded_size(&Frame::<Bytes>::Goaway(VarInt(len as u64)));
+            assert_encoded_size(&Frame::<Bytes>::MaxPushId(PushId(len as u64)));
+        }
+    }
+
+    #[test]
+    fn try_encode_into_fixed_buffer() {
+        let frame = Frame::<Bytes>::PushPromise(PushPromise {
+            id: 134,
+            encoded: Bytes::from("TODO QPACK"),
+        });
+        let size = frame.encoded_size();
+        let mut wire = BytesMut::new();
+        frame.encode(&mut wire);
+
+        let mut buf = [0xaa; 32];
+        let mut short = &mut buf[..size - 1];
+        assert_eq!(frame.try_encode(&mut short), Err(BufferTooShort(size)));
+        assert!(buf.iter().all(|b| *b == 0xaa));
+
+        let mut exact = &mut buf[..size];
+        assert_eq!(frame.try_encode(&mut exact), Ok(size));
+        assert_eq!(&buf[..size], &wire[..]);
+        assert_eq!(buf[size], 0xaa);
+    }
 }
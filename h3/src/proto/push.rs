@@ -2,10 +2,15 @@ use std::convert::TryFrom;
 use std::fmt::{self, Display};
 
 use super::varint::VarInt;
+use crate::error::{Code, Error, ErrorLevel};
 
+/// Identifies a server push, less than 2^62
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "u64"))]
 pub struct PushId(pub(crate) u64);
 
+/// Error returned when a push id is out of bounds
 #[derive(Debug, PartialEq)]
 pub struct InvalidPushId(u64);
 
@@ -37,8 +42,102 @@ impl From<PushId> for VarInt {
     }
 }
 
+#[cfg(any(test, feature = "arbitrary"))]
+impl<'a> arbitrary::Arbitrary<'a> for PushId {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(u.arbitrary::<VarInt>()?.into())
+    }
+}
+
 impl fmt::Display for PushId {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "push {}", self.0)
     }
 }
+
+/// The push ids allowed on a connection, raised by the MAX_PUSH_ID frames of the client
+///
+/// No push is allowed until the first MAX_PUSH_ID frame. A push stream, PUSH_PROMISE or
+/// CANCEL_PUSH frame referencing a push id above the limit is a connection error of type
+/// `H3_ID_ERROR`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PushIdLimit {
+    max: Option<PushId>,
+}
+
+impl PushIdLimit {
+    /// Whether `id` is within the limit
+    pub fn allows(&self, id: PushId) -> bool {
+        self.max.map_or(false, |max| id <= max)
+    }
+
+    /// Fails with a connection error if `id` is above the limit
+    pub fn check(&self, id: PushId) -> Result<(), Error> {
+        if self.allows(id) {
+            return Ok(());
+        }
+        Err(Code::H3_ID_ERROR.with_reason(
+            match self.max {
+                Some(max) => format!("{} above the maximum {}", id, max),
+                None => format!("{} while no push is allowed", id),
+            },
+            ErrorLevel::ConnectionError,
+        ))
+    }
+
+    /// Accounts for a MAX_PUSH_ID frame, which must not lower the limit
+    pub fn update(&mut self, max: PushId) -> Result<(), Error> {
+        //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.7
+        //# A MAX_PUSH_ID frame cannot reduce the maximum push
+        //# ID; receipt of a MAX_PUSH_ID frame that contains a smaller value than
+        //# previously received MUST be treated as a connection error of type
+        //# H3_ID_ERROR.
+        match self.max {
+            Some(current) if max < current => Err(Code::H3_ID_ERROR.with_reason(
+                format!("MAX_PUSH_ID lowered from {} to {}", current, max),
+                ErrorLevel::ConnectionError,
+            )),
+            _ => {
+                self.max = Some(max);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+
+    use super::*;
+    use crate::error::Kind;
+
+    #[test]
+    fn push_id_limit() {
+        let id_error = |e: Error| {
+            assert_matches!(
+                e.kind(),
+                Kind::Application {
+                    code: Code::H3_ID_ERROR,
+                    level: ErrorLevel::ConnectionError,
+                    ..
+                }
+            )
+        };
+
+        let mut limit = PushIdLimit::default();
+        id_error(limit.check(PushId(0)).unwrap_err());
+
+        limit.update(PushId(3)).unwrap();
+        assert!(limit.check(PushId(0)).is_ok());
+        assert!(limit.check(PushId(3)).is_ok());
+        id_error(limit.check(PushId(4)).unwrap_err());
+
+        // The same value again is not a decrease
+        limit.update(PushId(3)).unwrap();
+        limit.update(PushId(8)).unwrap();
+        assert!(limit.check(PushId(4)).is_ok());
+        id_error(limit.update(PushId(5)).unwrap_err());
+        assert!(limit.allows(PushId(8)));
+    }
+}
@@ -10,6 +10,8 @@ pub use super::coding::UnexpectedEnd;
 // It would be neat if we could express to Rust that the top two bits are available for use as enum
 // discriminants
 #[derive(Default, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "u64"))]
 pub struct VarInt(pub(crate) u64);
 
 impl Div<u64> for VarInt {
@@ -54,6 +56,38 @@ impl VarInt {
         self.0
     }
 
+    /// Adds `rhs`, returning `None` if the sum is not less than 2^62
+    ///
+    /// ```
+    /// use h3::codec::VarInt;
+    ///
+    /// assert_eq!(VarInt::from_u32(4).checked_add(VarInt::from_u32(2)), Some(VarInt::from_u32(6)));
+    /// assert_eq!(VarInt::MAX.checked_add(VarInt::from_u32(1)), None);
+    /// ```
+    pub const fn checked_add(self, rhs: VarInt) -> Option<Self> {
+        // Both are less than 2^62, their sum cannot overflow a u64
+        let sum = self.0 + rhs.0;
+        if sum <= Self::MAX.0 {
+            Some(VarInt(sum))
+        } else {
+            None
+        }
+    }
+
+    /// Adds `rhs`, clamping the sum to [`VarInt::MAX`]
+    ///
+    /// ```
+    /// use h3::codec::VarInt;
+    ///
+    /// assert_eq!(VarInt::MAX.saturating_add(VarInt::from_u32(1)), VarInt::MAX);
+    /// ```
+    pub const fn saturating_add(self, rhs: VarInt) -> Self {
+        match self.checked_add(rhs) {
+            Some(sum) => sum,
+            None => Self::MAX,
+        }
+    }
+
     /// Compute the number of bytes needed to encode this value
     pub fn size(self) -> usize {
         let x = self.0;
@@ -75,6 +109,7 @@ impl VarInt {
         2usize.pow((first >> 6) as u32)
     }
 
+    /// Decode a value from the start of `r`
     pub fn decode<B: Buf>(r: &mut B) -> Result<Self, UnexpectedEnd> {
         if !r.has_remaining() {
             return Err(UnexpectedEnd(0));
@@ -111,6 +146,24 @@ impl VarInt {
         Ok(VarInt(x))
     }
 
+    /// Decode a value, rejecting it if `minimal` is set and it was not encoded on as few bytes
+    /// as possible
+    ///
+    /// Receivers are allowed to accept non-minimal encodings, rejecting them is only meant for
+    /// strict conformance checks.
+    pub fn decode_with<B: Buf>(r: &mut B, minimal: bool) -> Result<Self, VarIntError> {
+        let encoded_size = match r.has_remaining() {
+            true => Self::encoded_size(r.chunk()[0]),
+            false => 0,
+        };
+        let x = Self::decode(r)?;
+        if minimal && encoded_size != x.size() {
+            return Err(VarIntError::NonMinimal(x));
+        }
+        Ok(x)
+    }
+
+    /// Encode this value, on as few bytes as possible
     pub fn encode<B: BufMut>(&self, w: &mut B) {
         let x = self.0;
         if x < 2u64.pow(6) {
@@ -167,6 +220,13 @@ impl std::convert::TryFrom<usize> for VarInt {
     }
 }
 
+#[cfg(any(test, feature = "arbitrary"))]
+impl<'a> arbitrary::Arbitrary<'a> for VarInt {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(VarInt(u.int_in_range(0..=Self::MAX.0)?))
+    }
+}
+
 impl fmt::Debug for VarInt {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         self.0.fmt(f)
@@ -180,12 +240,12 @@ impl fmt::Display for VarInt {
 }
 
 pub trait BufExt {
-    fn get_var(&mut self) -> Result<u64, UnexpectedEnd>;
+    fn get_var_with(&mut self, minimal: bool) -> Result<u64, VarIntError>;
 }
 
 impl<T: Buf> BufExt for T {
-    fn get_var(&mut self) -> Result<u64, UnexpectedEnd> {
-        Ok(VarInt::decode(self)?.into_inner())
+    fn get_var_with(&mut self, minimal: bool) -> Result<u64, VarIntError> {
+        Ok(VarInt::decode_with(self, minimal)?.into_inner())
     }
 }
 
@@ -201,3 +261,24 @@ impl<T: BufMut> BufMutExt for T {
 /// Error returned when constructing a `VarInt` from a value >= 2^62
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct VarIntBoundsExceeded(pub(crate) u64);
+
+impl fmt::Display for VarIntBoundsExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "value {} exceeds varint bounds", self.0)
+    }
+}
+
+/// Error returned when decoding a `VarInt` with [`VarInt::decode_with()`]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum VarIntError {
+    /// The buffer ended before the value
+    UnexpectedEnd(UnexpectedEnd),
+    /// The value could have been encoded on fewer bytes
+    NonMinimal(VarInt),
+}
+
+impl From<UnexpectedEnd> for VarIntError {
+    fn from(e: UnexpectedEnd) -> Self {
+        VarIntError::UnexpectedEnd(e)
+    }
+}
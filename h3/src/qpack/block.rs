@@ -165,6 +165,26 @@ impl HeaderPrefix {
         Ok((required, base))
     }
 
+    // Same as `get()`, rejecting an encoded Required Insert Count no table state could produce,
+    // as per 4.5.1.1. Required Insert Count
+    pub fn get_checked(
+        self,
+        total_inserted: usize,
+        max_table_size: usize,
+    ) -> Result<(usize, usize), ParseError> {
+        let encoded_insert_count = self.encoded_insert_count;
+        // The encoding wraps around twice the number of entries the table can hold
+        if encoded_insert_count > 2 * (max_table_size / 32) {
+            return Err(ParseError::InvalidInsertCount(encoded_insert_count));
+        }
+
+        let (required, base) = self.get(total_inserted, max_table_size)?;
+        if encoded_insert_count != 0 && required == 0 {
+            return Err(ParseError::InvalidInsertCount(encoded_insert_count));
+        }
+        Ok((required, base))
+    }
+
     // 4.5.1. Encoded Field Section Prefix
     //   0   1   2   3   4   5   6   7
     // +---+---+---+---+---+---+---+---+
@@ -0,0 +1,96 @@
+use crate::error::{Code, Error, ErrorLevel};
+
+/// Counts the request streams blocked on QPACK encoder instructions
+///
+/// A stream is blocked from when its field section refers to dynamic table entries not received
+/// yet, until they all are or the stream is dropped. The limit is the
+/// `SETTINGS_QPACK_BLOCKED_STREAMS` advertised to the peer, which defaults to none.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BlockedStreamTracker {
+    blocked: u64,
+    max: u64,
+}
+
+impl BlockedStreamTracker {
+    /// A tracker allowing up to `max` blocked streams
+    pub fn new(max: u64) -> Self {
+        Self { blocked: 0, max }
+    }
+
+    /// Number of streams currently blocked
+    pub fn blocked(&self) -> u64 {
+        self.blocked
+    }
+
+    /// Whether one more stream can be blocked
+    pub fn has_room(&self) -> bool {
+        self.blocked < self.max
+    }
+
+    /// Accounts for a stream getting blocked
+    ///
+    /// Past the limit, the stream is not counted and this fails with a connection error. See
+    /// section 2.1.2 of RFC 9204: a decoder which encounters more blocked streams than it promised
+    /// to support must treat it as a connection error of type QPACK_DECOMPRESSION_FAILED.
+    pub fn increment(&mut self) -> Result<(), Error> {
+        if !self.has_room() {
+            return Err(Code::QPACK_DECOMPRESSION_FAILED.with_reason(
+                format!("more than {} blocked streams", self.max),
+                ErrorLevel::ConnectionError,
+            ));
+        }
+        self.blocked += 1;
+        Ok(())
+    }
+
+    /// Accounts for a blocked stream getting unblocked, or dropped
+    pub fn decrement(&mut self) {
+        debug_assert!(self.blocked > 0, "no stream is blocked");
+        self.blocked = self.blocked.saturating_sub(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+
+    use super::*;
+    use crate::error::Kind;
+
+    #[test]
+    fn blocked_streams_within_limit() {
+        let mut tracker = BlockedStreamTracker::new(2);
+        tracker.increment().unwrap();
+        tracker.increment().unwrap();
+        assert_eq!(tracker.blocked(), 2);
+        assert!(!tracker.has_room());
+
+        // Room is made as streams get unblocked
+        tracker.decrement();
+        assert!(tracker.has_room());
+        tracker.increment().unwrap();
+        tracker.decrement();
+        tracker.decrement();
+        assert_eq!(tracker.blocked(), 0);
+    }
+
+    #[test]
+    fn blocked_streams_over_limit() {
+        // Nothing may block before the setting is sent
+        let err = BlockedStreamTracker::default().increment().unwrap_err();
+        assert_eq!(err.try_get_code(), Some(Code::QPACK_DECOMPRESSION_FAILED));
+
+        let mut tracker = BlockedStreamTracker::new(1);
+        tracker.increment().unwrap();
+        let err = tracker.increment().unwrap_err();
+        assert_eq!(err.try_get_code(), Some(Code::QPACK_DECOMPRESSION_FAILED));
+        assert_matches!(
+            err.kind(),
+            Kind::Application {
+                level: ErrorLevel::ConnectionError,
+                ..
+            }
+        );
+        assert_eq!(tracker.blocked(), 1);
+    }
+}
@@ -34,6 +34,7 @@ pub enum Error {
     UnknownPrefix(u8),
     MissingRefs(usize),
     BadBaseIndex(isize),
+    InvalidInsertCount(usize),
     UnexpectedEnd,
     HeaderTooLong(u64),
     BufSize(TryFromIntError),
@@ -52,6 +53,7 @@ impl std::fmt::Display for Error {
             Error::UnknownPrefix(p) => write!(f, "unknown instruction code: 0x{}", p),
             Error::MissingRefs(n) => write!(f, "missing {} refs to decode bloc", n),
             Error::BadBaseIndex(i) => write!(f, "out of bounds base index: {}", i),
+            Error::InvalidInsertCount(n) => write!(f, "invalid encoded insert count: {}", n),
             Error::UnexpectedEnd => write!(f, "unexpected end"),
             Error::HeaderTooLong(_) => write!(f, "header too long"),
             Error::BufSize(_) => write!(f, "number in buffer wrong size"),
@@ -77,6 +79,34 @@ pub struct Decoded {
     pub mem_size: u64,
 }
 
+/// The prefix of an encoded field section, as carried by a HEADERS frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldSectionPrefix {
+    /// Number of dynamic table insertions needed to decode the field section
+    pub required_insert_count: usize,
+    /// Absolute index the relative dynamic table indices of the field lines are based on
+    pub base: usize,
+}
+
+// Decode the prefix of a field section, without decoding its field lines.
+// https://www.rfc-editor.org/rfc/rfc9204.html#name-encoded-field-section-prefi
+//
+// `total_inserted` and `max_table_size` are the state of the decoder's dynamic table, the
+// Required Insert Count being encoded modulo twice the number of entries it can hold. The stream
+// is blocked while the decoded `required_insert_count` is greater than `total_inserted`.
+pub fn parse_prefix<B: Buf>(
+    buf: &mut B,
+    total_inserted: usize,
+    max_table_size: usize,
+) -> Result<FieldSectionPrefix, Error> {
+    let (required_insert_count, base) =
+        HeaderPrefix::decode(buf)?.get_checked(total_inserted, max_table_size)?;
+    Ok(FieldSectionPrefix {
+        required_insert_count,
+        base,
+    })
+}
+
 pub struct Decoder {
     table: DynamicTable,
 }
@@ -325,6 +355,7 @@ impl From<ParseError> for Error {
             ParseError::String(x) => Error::InvalidString(x),
             ParseError::InvalidPrefix(p) => Error::UnknownPrefix(p),
             ParseError::InvalidBase(b) => Error::BadBaseIndex(b),
+            ParseError::InvalidInsertCount(n) => Error::InvalidInsertCount(n),
         }
     }
 }
@@ -340,6 +371,46 @@ mod tests {
     use super::*;
     use crate::qpack::tests::helpers::{build_table_with_size, TABLE_SIZE};
 
+    #[test]
+    fn parse_field_section_prefix() {
+        // RFC 9204 B.2: 220 bytes of capacity, 2 entries inserted, S bit and Delta Base 0
+        let mut buf = &[0x03, 0x81][..];
+        assert_eq!(
+            parse_prefix(&mut buf, 2, 220),
+            Ok(FieldSectionPrefix {
+                required_insert_count: 2,
+                base: 0,
+            })
+        );
+
+        // Room for 3 entries: insert counts are encoded modulo 6, 10 entries inserted so far
+        let prefix = |bytes: &[u8], total| parse_prefix(&mut &bytes[..], total, 100);
+        let count = |bytes: &[u8], total| prefix(bytes, total).map(|p| p.required_insert_count);
+        assert_eq!(count(&[0x04, 0x00], 10), Ok(9));
+        // Wrapped around, the stream is blocked on 2 entries to come
+        assert_eq!(count(&[0x01, 0x00], 10), Ok(12));
+        assert_eq!(count(&[0x06, 0x00], 10), Ok(11));
+        assert_eq!(count(&[0x00, 0x00], 10), Ok(0));
+        assert_eq!(
+            prefix(&[0x04, 0x82], 10),
+            Ok(FieldSectionPrefix {
+                required_insert_count: 9,
+                base: 6,
+            })
+        );
+
+        assert_eq!(prefix(&[0x07, 0x00], 10), Err(Error::InvalidInsertCount(7)));
+        assert_eq!(prefix(&[0x01, 0x00], 0), Err(Error::InvalidInsertCount(1)));
+        assert_eq!(
+            parse_prefix(&mut &[0x01, 0x00][..], 0, 0),
+            Err(Error::InvalidInsertCount(1))
+        );
+        assert_eq!(
+            prefix(&[0x04], 10),
+            Err(Error::InvalidInteger(prefix_int::Error::UnexpectedEnd))
+        );
+    }
+
     #[test]
     fn test_header_too_long() {
         let mut trailers = http::HeaderMap::new();
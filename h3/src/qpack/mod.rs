@@ -5,6 +5,7 @@ pub use self::{
 };
 
 mod block;
+mod blocked;
 mod dynamic;
 mod field;
 mod parse_error;
@@ -38,7 +38,11 @@ pub fn decode<B: Buf>(size: u8, buf: &mut B) -> Result<(u8, u64), Error> {
     let mut power = 0usize;
     loop {
         let byte = buf.get::<u8>()? as u64;
-        value += (byte & 127) << power;
+        let bits = (byte & 127) << power;
+        if bits >> power != byte & 127 {
+            return Err(Error::Overflow);
+        }
+        value = value.checked_add(bits).ok_or(Error::Overflow)?;
         power += 7;
 
         if byte & 128 == 0 {
@@ -150,6 +154,18 @@ mod test {
         assert!(super::decode(8, &mut read).is_err());
     }
 
+    #[test]
+    fn overflow_on_last_byte() {
+        // Fits in 64 bits without the prefix, which then carries past them
+        let buf = vec![255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 1];
+        let mut read = Cursor::new(&buf);
+        assert_eq!(super::decode(8, &mut read), Err(super::Error::Overflow));
+
+        let buf = vec![255, 128, 128, 128, 128, 128, 128, 128, 128, 128, 2];
+        let mut read = Cursor::new(&buf);
+        assert_eq!(super::decode(8, &mut read), Err(super::Error::Overflow));
+    }
+
     #[test]
     fn number_never_ends_with_0x80() {
         check_codec(4, 0b0001, 143, &[31, 128, 1]);
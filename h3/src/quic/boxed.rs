@@ -0,0 +1,719 @@
+//! Type-erased wrappers around the QUIC traits
+//!
+//! The traits in [`crate::quic`] are generic and not object-safe, which forces the choice
+//! of a QUIC implementation into the types of everything built on top of it. The wrappers
+//! in this module erase the concrete implementation behind a `Box<dyn ...>`, so it can be
+//! selected at runtime. The cost is one dynamic call per trait method.
+//!
+//! All wrappers work with [`Bytes`] buffers and report errors as `Box<dyn quic::Error>`. The
+//! extension traits, such as [`EarlyDataExt`], are only forwarded by the constructors which
+//! require them, like [`BoxedConnection::with_extensions()`].
+
+use std::{
+    fmt,
+    marker::PhantomData,
+    task::{self, Poll},
+};
+
+use bytes::{Buf, Bytes};
+
+use crate::{error::Code, ext::Datagram};
+
+use super::{
+    BidiStream, Connection, EarlyDataExt, Error, OpenStreams, RecvDatagramExt, RecvStream,
+    SendDatagramExt, SendStream, StreamId, WriteBuf,
+};
+
+type BoxError = Box<dyn Error>;
+
+trait DynSendStream: Send {
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), BoxError>>;
+    fn send_data(&mut self, data: WriteBuf<Bytes>) -> Result<(), BoxError>;
+    fn poll_finish(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), BoxError>>;
+    fn reset(&mut self, reset_code: u64);
+    fn send_id(&self) -> StreamId;
+}
+
+impl<S> DynSendStream for S
+where
+    S: SendStream<Bytes> + Send,
+{
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), BoxError>> {
+        SendStream::poll_ready(self, cx).map_err(Into::into)
+    }
+
+    fn send_data(&mut self, data: WriteBuf<Bytes>) -> Result<(), BoxError> {
+        SendStream::send_data(self, data).map_err(Into::into)
+    }
+
+    fn poll_finish(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), BoxError>> {
+        SendStream::poll_finish(self, cx).map_err(Into::into)
+    }
+
+    fn reset(&mut self, reset_code: u64) {
+        SendStream::reset(self, reset_code)
+    }
+
+    fn send_id(&self) -> StreamId {
+        SendStream::send_id(self)
+    }
+}
+
+trait DynRecvStream: Send {
+    fn poll_data(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<Option<Bytes>, BoxError>>;
+    fn stop_sending(&mut self, error_code: u64);
+    fn recv_id(&self) -> StreamId;
+}
+
+impl<S> DynRecvStream for S
+where
+    S: RecvStream + Send,
+{
+    fn poll_data(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<Option<Bytes>, BoxError>> {
+        RecvStream::poll_data(self, cx)
+            .map_ok(|buf| buf.map(|mut b| b.copy_to_bytes(b.remaining())))
+            .map_err(Into::into)
+    }
+
+    fn stop_sending(&mut self, error_code: u64) {
+        RecvStream::stop_sending(self, error_code)
+    }
+
+    fn recv_id(&self) -> StreamId {
+        RecvStream::recv_id(self)
+    }
+}
+
+trait DynBidiStream: DynSendStream + DynRecvStream {
+    fn split(self: Box<Self>) -> (BoxedSendStream, BoxedRecvStream);
+}
+
+impl<S> DynBidiStream for S
+where
+    S: BidiStream<Bytes> + Send,
+    S::SendStream: Send + 'static,
+    S::RecvStream: Send + 'static,
+{
+    fn split(self: Box<Self>) -> (BoxedSendStream, BoxedRecvStream) {
+        let (send, recv) = BidiStream::split(*self);
+        (BoxedSendStream::new(send), BoxedRecvStream::new(recv))
+    }
+}
+
+/// Selects how the extension traits of an erased connection or opener are forwarded
+///
+/// [`Plain`] leaves them out, the connection then behaves as one which never sends early data
+/// and has no datagram support. [`Extended`] forwards them to the wrapped implementation.
+trait EarlyDataSupport<T>: Send + 'static {
+    fn is_early_data(inner: &T) -> bool;
+    fn poll_handshake(inner: &mut T, cx: &mut task::Context<'_>) -> Poll<bool>;
+}
+
+trait DatagramSupport<T>: Send + 'static {
+    fn send_datagram(inner: &mut T, data: Datagram<Bytes>) -> Result<(), BoxError>;
+    fn poll_accept_datagram(
+        inner: &mut T,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Result<Option<Bytes>, BoxError>>;
+}
+
+struct Plain;
+
+struct Extended;
+
+impl<T> EarlyDataSupport<T> for Plain {
+    fn is_early_data(_: &T) -> bool {
+        false
+    }
+
+    fn poll_handshake(_: &mut T, _: &mut task::Context<'_>) -> Poll<bool> {
+        Poll::Ready(true)
+    }
+}
+
+impl<T> DatagramSupport<T> for Plain {
+    fn send_datagram(_: &mut T, _: Datagram<Bytes>) -> Result<(), BoxError> {
+        Err(Box::new(DatagramsUnsupported))
+    }
+
+    fn poll_accept_datagram(
+        _: &mut T,
+        _: &mut task::Context<'_>,
+    ) -> Poll<Result<Option<Bytes>, BoxError>> {
+        Poll::Ready(Err(Box::new(DatagramsUnsupported)))
+    }
+}
+
+impl<T: EarlyDataExt> EarlyDataSupport<T> for Extended {
+    fn is_early_data(inner: &T) -> bool {
+        inner.is_early_data()
+    }
+
+    fn poll_handshake(inner: &mut T, cx: &mut task::Context<'_>) -> Poll<bool> {
+        inner.poll_handshake(cx)
+    }
+}
+
+impl<T> DatagramSupport<T> for Extended
+where
+    T: SendDatagramExt<Bytes> + RecvDatagramExt,
+{
+    fn send_datagram(inner: &mut T, data: Datagram<Bytes>) -> Result<(), BoxError> {
+        inner.send_datagram(data).map_err(Into::into)
+    }
+
+    fn poll_accept_datagram(
+        inner: &mut T,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Result<Option<Bytes>, BoxError>> {
+        inner
+            .poll_accept_datagram(cx)
+            .map_ok(|buf| buf.map(|mut b| b.copy_to_bytes(b.remaining())))
+            .map_err(Into::into)
+    }
+}
+
+/// The error of datagram operations on a connection boxed without its extensions
+#[derive(Debug)]
+struct DatagramsUnsupported;
+
+impl fmt::Display for DatagramsUnsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "datagrams are not supported by the boxed connection")
+    }
+}
+
+impl std::error::Error for DatagramsUnsupported {}
+
+impl Error for DatagramsUnsupported {
+    fn is_timeout(&self) -> bool {
+        false
+    }
+
+    fn err_code(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// An implementation, along with how its extension traits are forwarded
+struct Erased<T, X> {
+    inner: T,
+    _ext: PhantomData<fn() -> X>,
+}
+
+impl<T, X> Erased<T, X> {
+    fn new(inner: T) -> Self {
+        Self {
+            inner,
+            _ext: PhantomData,
+        }
+    }
+}
+
+trait DynOpenStreams: Send {
+    fn poll_open_bidi(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Result<BoxedBidiStream, BoxError>>;
+    fn poll_open_send(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Result<BoxedSendStream, BoxError>>;
+    fn poll_ready_bidi(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), BoxError>>;
+    fn close(&mut self, code: Code, reason: &[u8]);
+    fn clone_box(&self) -> Box<dyn DynOpenStreams>;
+    fn is_early_data(&self) -> bool;
+    fn poll_handshake(&mut self, cx: &mut task::Context<'_>) -> Poll<bool>;
+}
+
+impl<O, X> DynOpenStreams for Erased<O, X>
+where
+    O: OpenStreams<Bytes> + Clone + Send + 'static,
+    O::BidiStream: BidiStream<Bytes> + Send + 'static,
+    <O::BidiStream as BidiStream<Bytes>>::SendStream: Send + 'static,
+    <O::BidiStream as BidiStream<Bytes>>::RecvStream: Send + 'static,
+    O::SendStream: Send + 'static,
+    X: EarlyDataSupport<O>,
+{
+    fn poll_open_bidi(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Result<BoxedBidiStream, BoxError>> {
+        self.inner
+            .poll_open_bidi(cx)
+            .map_ok(BoxedBidiStream::new)
+            .map_err(Into::into)
+    }
+
+    fn poll_open_send(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Result<BoxedSendStream, BoxError>> {
+        self.inner
+            .poll_open_send(cx)
+            .map_ok(BoxedSendStream::new)
+            .map_err(Into::into)
+    }
+
+    fn poll_ready_bidi(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), BoxError>> {
+        self.inner.poll_ready_bidi(cx).map_err(Into::into)
+    }
+
+    fn close(&mut self, code: Code, reason: &[u8]) {
+        self.inner.close(code, reason)
+    }
+
+    fn clone_box(&self) -> Box<dyn DynOpenStreams> {
+        Box::new(Erased::<O, X>::new(self.inner.clone()))
+    }
+
+    fn is_early_data(&self) -> bool {
+        X::is_early_data(&self.inner)
+    }
+
+    fn poll_handshake(&mut self, cx: &mut task::Context<'_>) -> Poll<bool> {
+        X::poll_handshake(&mut self.inner, cx)
+    }
+}
+
+trait DynConnection: Send {
+    fn poll_accept_recv(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Result<Option<BoxedRecvStream>, BoxError>>;
+    fn poll_accept_bidi(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Result<Option<BoxedBidiStream>, BoxError>>;
+    fn poll_open_bidi(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Result<BoxedBidiStream, BoxError>>;
+    fn poll_open_send(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Result<BoxedSendStream, BoxError>>;
+    fn opener(&self) -> BoxedOpenStreams;
+    fn close(&mut self, code: Code, reason: &[u8]);
+    fn is_early_data(&self) -> bool;
+    fn poll_handshake(&mut self, cx: &mut task::Context<'_>) -> Poll<bool>;
+    fn send_datagram(&mut self, data: Datagram<Bytes>) -> Result<(), BoxError>;
+    fn poll_accept_datagram(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Result<Option<Bytes>, BoxError>>;
+}
+
+impl<C, X> DynConnection for Erased<C, X>
+where
+    C: Connection<Bytes> + Send + 'static,
+    C::BidiStream: BidiStream<Bytes> + Send + 'static,
+    <C::BidiStream as BidiStream<Bytes>>::SendStream: Send + 'static,
+    <C::BidiStream as BidiStream<Bytes>>::RecvStream: Send + 'static,
+    C::SendStream: Send + 'static,
+    C::RecvStream: Send + 'static,
+    C::OpenStreams: Clone + Send + 'static,
+    X: EarlyDataSupport<C> + EarlyDataSupport<C::OpenStreams> + DatagramSupport<C>,
+{
+    fn poll_accept_recv(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Result<Option<BoxedRecvStream>, BoxError>> {
+        self.inner
+            .poll_accept_recv(cx)
+            .map_ok(|s| s.map(BoxedRecvStream::new))
+            .map_err(Into::into)
+    }
+
+    fn poll_accept_bidi(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Result<Option<BoxedBidiStream>, BoxError>> {
+        self.inner
+            .poll_accept_bidi(cx)
+            .map_ok(|s| s.map(BoxedBidiStream::new))
+            .map_err(Into::into)
+    }
+
+    fn poll_open_bidi(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Result<BoxedBidiStream, BoxError>> {
+        Connection::poll_open_bidi(&mut self.inner, cx)
+            .map_ok(BoxedBidiStream::new)
+            .map_err(Into::into)
+    }
+
+    fn poll_open_send(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Result<BoxedSendStream, BoxError>> {
+        Connection::poll_open_send(&mut self.inner, cx)
+            .map_ok(BoxedSendStream::new)
+            .map_err(Into::into)
+    }
+
+    fn opener(&self) -> BoxedOpenStreams {
+        BoxedOpenStreams(Box::new(Erased::<C::OpenStreams, X>::new(
+            self.inner.opener(),
+        )))
+    }
+
+    fn close(&mut self, code: Code, reason: &[u8]) {
+        Connection::close(&mut self.inner, code, reason)
+    }
+
+    fn is_early_data(&self) -> bool {
+        <X as EarlyDataSupport<C>>::is_early_data(&self.inner)
+    }
+
+    fn poll_handshake(&mut self, cx: &mut task::Context<'_>) -> Poll<bool> {
+        <X as EarlyDataSupport<C>>::poll_handshake(&mut self.inner, cx)
+    }
+
+    fn send_datagram(&mut self, data: Datagram<Bytes>) -> Result<(), BoxError> {
+        X::send_datagram(&mut self.inner, data)
+    }
+
+    fn poll_accept_datagram(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Result<Option<Bytes>, BoxError>> {
+        X::poll_accept_datagram(&mut self.inner, cx)
+    }
+}
+
+/// A [`SendStream`] wrapping any implementation behind a trait object
+pub struct BoxedSendStream(Box<dyn DynSendStream>);
+
+impl BoxedSendStream {
+    /// Erase the type of a send stream
+    pub fn new<S>(stream: S) -> Self
+    where
+        S: SendStream<Bytes> + Send + 'static,
+    {
+        Self(Box::new(stream))
+    }
+}
+
+impl SendStream<Bytes> for BoxedSendStream {
+    type Error = BoxError;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn send_data<T: Into<WriteBuf<Bytes>>>(&mut self, data: T) -> Result<(), Self::Error> {
+        self.0.send_data(data.into())
+    }
+
+    fn poll_finish(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_finish(cx)
+    }
+
+    fn reset(&mut self, reset_code: u64) {
+        self.0.reset(reset_code)
+    }
+
+    fn send_id(&self) -> StreamId {
+        self.0.send_id()
+    }
+}
+
+/// A [`RecvStream`] wrapping any implementation behind a trait object
+pub struct BoxedRecvStream(Box<dyn DynRecvStream>);
+
+impl BoxedRecvStream {
+    /// Erase the type of a receive stream
+    pub fn new<S>(stream: S) -> Self
+    where
+        S: RecvStream + Send + 'static,
+    {
+        Self(Box::new(stream))
+    }
+}
+
+impl RecvStream for BoxedRecvStream {
+    type Buf = Bytes;
+    type Error = BoxError;
+
+    fn poll_data(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Result<Option<Self::Buf>, Self::Error>> {
+        self.0.poll_data(cx)
+    }
+
+    fn stop_sending(&mut self, error_code: u64) {
+        self.0.stop_sending(error_code)
+    }
+
+    fn recv_id(&self) -> StreamId {
+        self.0.recv_id()
+    }
+}
+
+/// A [`BidiStream`] wrapping any implementation behind a trait object
+pub struct BoxedBidiStream(Box<dyn DynBidiStream>);
+
+impl BoxedBidiStream {
+    /// Erase the type of a bidirectional stream
+    pub fn new<S>(stream: S) -> Self
+    where
+        S: BidiStream<Bytes> + Send + 'static,
+        S::SendStream: Send + 'static,
+        S::RecvStream: Send + 'static,
+    {
+        Self(Box::new(stream))
+    }
+}
+
+impl SendStream<Bytes> for BoxedBidiStream {
+    type Error = BoxError;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn send_data<T: Into<WriteBuf<Bytes>>>(&mut self, data: T) -> Result<(), Self::Error> {
+        self.0.send_data(data.into())
+    }
+
+    fn poll_finish(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_finish(cx)
+    }
+
+    fn reset(&mut self, reset_code: u64) {
+        self.0.reset(reset_code)
+    }
+
+    fn send_id(&self) -> StreamId {
+        self.0.send_id()
+    }
+}
+
+impl RecvStream for BoxedBidiStream {
+    type Buf = Bytes;
+    type Error = BoxError;
+
+    fn poll_data(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Result<Option<Self::Buf>, Self::Error>> {
+        self.0.poll_data(cx)
+    }
+
+    fn stop_sending(&mut self, error_code: u64) {
+        self.0.stop_sending(error_code)
+    }
+
+    fn recv_id(&self) -> StreamId {
+        self.0.recv_id()
+    }
+}
+
+impl BidiStream<Bytes> for BoxedBidiStream {
+    type SendStream = BoxedSendStream;
+    type RecvStream = BoxedRecvStream;
+
+    fn split(self) -> (Self::SendStream, Self::RecvStream) {
+        self.0.split()
+    }
+}
+
+/// An [`OpenStreams`] wrapping any implementation behind a trait object
+pub struct BoxedOpenStreams(Box<dyn DynOpenStreams>);
+
+impl BoxedOpenStreams {
+    /// Erase the type of a stream opener
+    ///
+    /// The opener reports it never sends early data, see [`BoxedOpenStreams::with_early_data()`]
+    /// to forward [`EarlyDataExt`].
+    pub fn new<O>(opener: O) -> Self
+    where
+        O: OpenStreams<Bytes> + Clone + Send + 'static,
+        O::BidiStream: BidiStream<Bytes> + Send + 'static,
+        <O::BidiStream as BidiStream<Bytes>>::SendStream: Send + 'static,
+        <O::BidiStream as BidiStream<Bytes>>::RecvStream: Send + 'static,
+        O::SendStream: Send + 'static,
+    {
+        Self(Box::new(Erased::<O, Plain>::new(opener)))
+    }
+
+    /// Erase the type of a stream opener, forwarding its [`EarlyDataExt`] implementation
+    pub fn with_early_data<O>(opener: O) -> Self
+    where
+        O: OpenStreams<Bytes> + EarlyDataExt + Clone + Send + 'static,
+        O::BidiStream: BidiStream<Bytes> + Send + 'static,
+        <O::BidiStream as BidiStream<Bytes>>::SendStream: Send + 'static,
+        <O::BidiStream as BidiStream<Bytes>>::RecvStream: Send + 'static,
+        O::SendStream: Send + 'static,
+    {
+        Self(Box::new(Erased::<O, Extended>::new(opener)))
+    }
+}
+
+impl Clone for BoxedOpenStreams {
+    fn clone(&self) -> Self {
+        Self(self.0.clone_box())
+    }
+}
+
+impl EarlyDataExt for BoxedOpenStreams {
+    fn is_early_data(&self) -> bool {
+        self.0.is_early_data()
+    }
+
+    fn poll_handshake(&mut self, cx: &mut task::Context<'_>) -> Poll<bool> {
+        self.0.poll_handshake(cx)
+    }
+}
+
+impl OpenStreams<Bytes> for BoxedOpenStreams {
+    type BidiStream = BoxedBidiStream;
+    type SendStream = BoxedSendStream;
+    type RecvStream = BoxedRecvStream;
+    type Error = BoxError;
+
+    fn poll_open_bidi(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Result<Self::BidiStream, Self::Error>> {
+        self.0.poll_open_bidi(cx)
+    }
+
+    fn poll_open_send(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Result<Self::SendStream, Self::Error>> {
+        self.0.poll_open_send(cx)
+    }
+
+    fn poll_ready_bidi(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready_bidi(cx)
+    }
+
+    fn close(&mut self, code: Code, reason: &[u8]) {
+        self.0.close(code, reason)
+    }
+}
+
+/// A [`Connection`] wrapping any implementation behind a trait object
+///
+/// Streams accepted or opened through it are boxed as well.
+pub struct BoxedConnection(Box<dyn DynConnection>);
+
+impl BoxedConnection {
+    /// Erase the type of a QUIC connection
+    ///
+    /// The extension traits are not forwarded: the connection reports it never sends early data,
+    /// and datagram operations fail. See [`BoxedConnection::with_extensions()`] to keep them.
+    pub fn new<C>(conn: C) -> Self
+    where
+        C: Connection<Bytes> + Send + 'static,
+        C::BidiStream: BidiStream<Bytes> + Send + 'static,
+        <C::BidiStream as BidiStream<Bytes>>::SendStream: Send + 'static,
+        <C::BidiStream as BidiStream<Bytes>>::RecvStream: Send + 'static,
+        C::SendStream: Send + 'static,
+        C::RecvStream: Send + 'static,
+        C::OpenStreams: Clone + Send + 'static,
+    {
+        Self(Box::new(Erased::<C, Plain>::new(conn)))
+    }
+
+    /// Erase the type of a QUIC connection, forwarding 0-RTT and datagram support
+    ///
+    /// [`EarlyDataExt`], [`SendDatagramExt`] and [`RecvDatagramExt`] are forwarded to `conn`,
+    /// and [`EarlyDataExt`] to the stream openers it returns.
+    pub fn with_extensions<C>(conn: C) -> Self
+    where
+        C: Connection<Bytes>
+            + EarlyDataExt
+            + SendDatagramExt<Bytes>
+            + RecvDatagramExt
+            + Send
+            + 'static,
+        C::BidiStream: BidiStream<Bytes> + Send + 'static,
+        <C::BidiStream as BidiStream<Bytes>>::SendStream: Send + 'static,
+        <C::BidiStream as BidiStream<Bytes>>::RecvStream: Send + 'static,
+        C::SendStream: Send + 'static,
+        C::RecvStream: Send + 'static,
+        C::OpenStreams: EarlyDataExt + Clone + Send + 'static,
+    {
+        Self(Box::new(Erased::<C, Extended>::new(conn)))
+    }
+}
+
+impl Connection<Bytes> for BoxedConnection {
+    type BidiStream = BoxedBidiStream;
+    type SendStream = BoxedSendStream;
+    type RecvStream = BoxedRecvStream;
+    type OpenStreams = BoxedOpenStreams;
+    type Error = BoxError;
+
+    fn poll_accept_recv(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Result<Option<Self::RecvStream>, Self::Error>> {
+        self.0.poll_accept_recv(cx)
+    }
+
+    fn poll_accept_bidi(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Result<Option<Self::BidiStream>, Self::Error>> {
+        self.0.poll_accept_bidi(cx)
+    }
+
+    fn poll_open_bidi(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Result<Self::BidiStream, Self::Error>> {
+        self.0.poll_open_bidi(cx)
+    }
+
+    fn poll_open_send(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Result<Self::SendStream, Self::Error>> {
+        self.0.poll_open_send(cx)
+    }
+
+    fn opener(&self) -> Self::OpenStreams {
+        self.0.opener()
+    }
+
+    fn close(&mut self, code: Code, reason: &[u8]) {
+        self.0.close(code, reason)
+    }
+}
+
+impl EarlyDataExt for BoxedConnection {
+    fn is_early_data(&self) -> bool {
+        self.0.is_early_data()
+    }
+
+    fn poll_handshake(&mut self, cx: &mut task::Context<'_>) -> Poll<bool> {
+        self.0.poll_handshake(cx)
+    }
+}
+
+impl SendDatagramExt<Bytes> for BoxedConnection {
+    type Error = BoxError;
+
+    fn send_datagram(&mut self, data: Datagram<Bytes>) -> Result<(), Self::Error> {
+        self.0.send_datagram(data)
+    }
+}
+
+impl RecvDatagramExt for BoxedConnection {
+    type Buf = Bytes;
+    type Error = BoxError;
+
+    fn poll_accept_datagram(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Result<Option<Self::Buf>, Self::Error>> {
+        self.0.poll_accept_datagram(cx)
+    }
+}
@@ -11,6 +11,11 @@ use crate::ext::Datagram;
 pub use crate::proto::stream::{InvalidStreamId, StreamId};
 pub use crate::stream::WriteBuf;
 
+mod boxed;
+pub use self::boxed::{
+    BoxedBidiStream, BoxedConnection, BoxedOpenStreams, BoxedRecvStream, BoxedSendStream,
+};
+
 // Unresolved questions:
 //
 // - Should the `poll_` methods be `Pin<&mut Self>`?
@@ -22,6 +27,13 @@ pub trait Error: std::error::Error + Send + Sync {
 
     /// Get the QUIC error code from connection close or stream stop
     fn err_code(&self) -> Option<u64>;
+
+    /// Whether the peer reset the stream, while the connection is still up
+    ///
+    /// Transports which cannot tell this apart from the loss of the connection return `false`.
+    fn is_stream_reset(&self) -> bool {
+        false
+    }
 }
 
 impl<'a, E: Error + 'a> From<E> for Box<dyn Error + 'a> {
@@ -110,6 +122,24 @@ pub trait RecvDatagramExt {
     ) -> Poll<Result<Option<Self::Buf>, Self::Error>>;
 }
 
+/// Extends the `Connection` and `OpenStreams` traits for 0-RTT
+///
+/// Until the handshake completes, a client can already send data protected with 0-RTT keys,
+/// which the server is free to reject.
+///
+/// See: <https://www.rfc-editor.org/rfc/rfc9001#section-4.6>
+pub trait EarlyDataExt {
+    /// Returns `true` while the handshake is in progress and data is exchanged in 0-RTT
+    fn is_early_data(&self) -> bool;
+
+    /// Poll for the handshake to complete
+    ///
+    /// Resolves to `true` if the data sent in 0-RTT was accepted by the peer, or if none was
+    /// sent. On `false`, everything sent on streams opened before the handshake completed has
+    /// been discarded by the peer.
+    fn poll_handshake(&mut self, cx: &mut task::Context<'_>) -> Poll<bool>;
+}
+
 /// Trait for opening outgoing streams
 pub trait OpenStreams<B: Buf> {
     /// The type produced by `poll_open_bidi()`
@@ -133,6 +163,16 @@ pub trait OpenStreams<B: Buf> {
         cx: &mut task::Context<'_>,
     ) -> Poll<Result<Self::SendStream, Self::Error>>;
 
+    /// Poll until a new bidirectional stream can be opened without waiting
+    ///
+    /// Implementations which override this must make the next call to `poll_open_bidi()` ready
+    /// once it resolves, as the peer's limit on the number of streams allows one more. The
+    /// default resolves right away, for implementations which cannot tell: it guarantees
+    /// nothing, and `poll_open_bidi()` then waits itself.
+    fn poll_ready_bidi(&mut self, _cx: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
     /// Close the connection immediately
     fn close(&mut self, code: crate::error::Code, reason: &[u8]);
 }
@@ -146,6 +186,11 @@ pub trait SendStream<B: Buf> {
     fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>>;
 
     /// Send more data on the stream.
+    ///
+    /// A frame header and its payload are handed over together in one [`WriteBuf`]. Its
+    /// [`Buf::chunks_vectored()`] yields them as separate slices without copying, so
+    /// implementations supporting `writev` or GSO can exploit this segmentation instead of
+    /// writing each [`Buf::chunk()`] in turn.
     fn send_data<T: Into<WriteBuf<B>>>(&mut self, data: T) -> Result<(), Self::Error>;
 
     /// Poll to finish the sending side of the stream.
@@ -0,0 +1,38 @@
+//! Runtime services, provided by the user of the crate
+//!
+//! Connections and streams are driven by polling only, and work under any executor. What they
+//! cannot do by polling alone, such as waiting for some time, goes through the traits of this
+//! module. The `tokio` feature implements them with the tokio runtime.
+
+use std::{future::Future, pin::Pin, time::Instant};
+
+/// A clock able to wake tasks at a given time
+pub trait Timer: Send + Sync {
+    /// The current time of this clock
+    fn now(&self) -> Instant;
+
+    /// Returns a future completing once the clock reaches `deadline`
+    fn sleep_until(&self, deadline: Instant) -> Pin<Box<dyn Sleep>>;
+}
+
+/// A future returned by [`Timer::sleep_until()`]
+pub trait Sleep: Future<Output = ()> + Send + Sync {}
+
+/// A [`Timer`] with the clock of [`tokio::time`], which a test can pause
+#[cfg(any(test, feature = "tokio"))]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokioTimer;
+
+#[cfg(any(test, feature = "tokio"))]
+impl Timer for TokioTimer {
+    fn now(&self) -> Instant {
+        tokio::time::Instant::now().into_std()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> Pin<Box<dyn Sleep>> {
+        Box::pin(tokio::time::sleep_until(deadline.into()))
+    }
+}
+
+#[cfg(any(test, feature = "tokio"))]
+impl Sleep for tokio::time::Sleep {}
@@ -0,0 +1,138 @@
+//! Request and response bodies as [`http_body::Body`]
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use bytes::{Buf, Bytes};
+use futures_util::FutureExt;
+use http::Response;
+use http_body::{Body, Frame, SizeHint};
+
+use crate::{
+    body::RecvBody,
+    error::{Code, ErrorLevel},
+    quic,
+    rt::{Sleep, Timer},
+    Error,
+};
+
+use super::RequestStream;
+
+/// The body of a request, read from the receiving side of a [`RequestStream`]
+///
+/// Data is yielded as it arrives, followed by the trailers if the client sent any.
+pub struct RequestBody<S, B> {
+    stream: RequestStream<S, B>,
+    recv: RecvBody,
+    // Fires if the data is not all received by then
+    deadline: Option<Pin<Box<dyn Sleep>>>,
+}
+
+impl<S, B> RequestBody<S, B> {
+    /// Reads the body from `stream`, which must not have received any data yet
+    pub fn new(stream: RequestStream<S, B>) -> Self {
+        Self {
+            stream,
+            recv: RecvBody::new(),
+            deadline: None,
+        }
+    }
+
+    /// Bounds the time the client has to send the whole body, measured with `timer`
+    ///
+    /// Unlike a timeout on each read, this holds however the body is split. Once `deadline` is
+    /// reached with data still to come, the client is told to stop sending and the body fails
+    /// with [`Code::H3_REQUEST_CANCELLED`]. The trailers are not waited for.
+    pub fn with_deadline(mut self, deadline: Instant, timer: &dyn Timer) -> Self {
+        self.deadline = Some(timer.sleep_until(deadline));
+        self
+    }
+
+    /// Returns the underlying stream
+    pub fn into_inner(self) -> RequestStream<S, B> {
+        self.stream
+    }
+}
+
+impl<S, B> RequestBody<S, B>
+where
+    S: quic::RecvStream,
+    B: Buf,
+{
+    /// Tell the client to stop sending the body
+    pub fn stop_sending(&mut self, error_code: Code) {
+        self.stream.stop_sending(error_code);
+        self.recv.stop();
+    }
+}
+
+impl<S, B> Unpin for RequestBody<S, B> {}
+
+impl<S, B> Body for RequestBody<S, B>
+where
+    S: quic::RecvStream,
+    B: Buf,
+{
+    type Data = Bytes;
+    type Error = Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+        if this.recv.is_data_received() {
+            this.deadline = None;
+        }
+        if let Some(deadline) = this.deadline.as_mut() {
+            if deadline.poll_unpin(cx).is_ready() {
+                this.deadline = None;
+                this.stop_sending(Code::H3_REQUEST_CANCELLED);
+                return Poll::Ready(Some(Err(Code::H3_REQUEST_CANCELLED.with_reason(
+                    "request body not received by the deadline",
+                    ErrorLevel::StreamError,
+                ))));
+            }
+        }
+        this.recv.poll_frame(&mut this.stream.inner, cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.recv.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.recv.size_hint()
+    }
+}
+
+impl<S, B> RequestStream<S, B>
+where
+    S: quic::SendStream<B>,
+    B: Buf,
+{
+    /// Send a whole response, then finish the stream
+    ///
+    /// The body is streamed in DATA frames followed by its trailers, with a `content-length`
+    /// header when its exact size is known. It is not sent at all in response to a HEAD request,
+    /// or with a status forbidding content. If the body fails, the stream is reset with
+    /// [`Code::H3_REQUEST_CANCELLED`].
+    pub async fn respond<T>(&mut self, resp: Response<T>) -> Result<(), Error>
+    where
+        T: Body<Data = B>,
+        T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let (mut parts, body) = resp.into_parts();
+        if !crate::body::response_has_body(self.head, parts.status) {
+            self.send_response(Response::from_parts(parts, ())).await?;
+            return self.finish().await;
+        }
+
+        crate::body::set_content_length(&mut parts.headers, &body.size_hint());
+        self.send_response(Response::from_parts(parts, ())).await?;
+        crate::body::send(&mut self.inner, body).await
+    }
+}
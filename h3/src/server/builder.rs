@@ -21,17 +21,19 @@
 //! }
 //! ```
 
-use std::{collections::HashSet, result::Result};
+use std::{collections::HashSet, ops::ControlFlow, result::Result, sync::Arc};
 
-use bytes::Buf;
+use bytes::{Buf, Bytes};
 
-use tokio::sync::mpsc;
+use futures_channel::mpsc;
 
 use crate::{
-    config::Config,
+    config::{Config, UnknownStreamAction},
     connection::{ConnectionInner, SharedStateRef},
-    error::Error,
-    quic::{self},
+    error::{Code, Error},
+    ext::UniStream,
+    proto::varint::VarInt,
+    quic::{self, StreamId},
 };
 
 use super::connection::Connection;
@@ -47,6 +49,7 @@ pub fn builder() -> Builder {
 /// Builder of HTTP/3 server connections.
 pub struct Builder {
     pub(crate) config: Config,
+    reject_early_unsafe: bool,
 }
 
 impl Builder {
@@ -54,6 +57,7 @@ impl Builder {
     pub(super) fn new() -> Self {
         Builder {
             config: Default::default(),
+            reject_early_unsafe: false,
         }
     }
 
@@ -73,6 +77,64 @@ impl Builder {
         self
     }
 
+    /// Decide what to do with incoming unidirectional streams of unknown type
+    ///
+    /// The handler is given the stream type. By default, reading is aborted with
+    /// `H3_STREAM_CREATION_ERROR`.
+    pub fn unknown_uni_stream(
+        &mut self,
+        handler: impl Fn(u64) -> UnknownStreamAction + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.config.unknown_uni_stream = Arc::new(handler);
+        self
+    }
+
+    /// Receive the frames of unknown type arriving on request streams
+    ///
+    /// The handler is given the stream id, the frame type and its payload, once the whole frame
+    /// is received. Returning `Break` stops the stream with the given code, and the request
+    /// fails. Frames of reserved types are passed too, which the handler can tell apart with
+    /// [`FrameType::is_reserved()`](crate::codec::FrameType::is_reserved). Without a handler,
+    /// these frames are skipped. It is called from the task reading the request stream, so it
+    /// must not block.
+    pub fn on_extension_frame(
+        &mut self,
+        handler: impl Fn(StreamId, u64, Bytes) -> ControlFlow<Code> + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.config.on_extension_frame = Some(Arc::new(handler));
+        self
+    }
+
+    /// Hand the incoming unidirectional streams of type `ty` to `handler`
+    ///
+    /// This lets an extension define its own stream types. The handler is given the stream id
+    /// and the stream, as soon as its type is received. It is called from the task driving the
+    /// connection, so it must not block: reading the stream is left to a task it spawns or
+    /// notifies. The connection receives the data of these streams while it is driven, so they
+    /// are only read as long as the connection is.
+    ///
+    /// Streams of a registered type are not subject to [`Builder::unknown_uni_stream()`], and
+    /// take precedence over types implemented here as extensions, such as WebTransport streams.
+    /// Registering the control, push or QPACK stream types fails.
+    pub fn register_uni_stream_handler(
+        &mut self,
+        ty: VarInt,
+        handler: impl Fn(StreamId, UniStream) + Send + Sync + 'static,
+    ) -> Result<&mut Self, Error> {
+        self.config.register_uni_stream_handler(ty, handler)?;
+        Ok(self)
+    }
+
+    /// Limit the number of unidirectional streams of unknown type discarded at once
+    ///
+    /// This counts the streams left open by [`UnknownStreamAction::Discard`] until they end,
+    /// stopped streams do not count. Past this, the connection is closed with
+    /// `H3_EXCESSIVE_LOAD`. Defaults to 64.
+    pub fn max_unknown_uni_streams(&mut self, value: usize) -> &mut Self {
+        self.config.max_unknown_uni_streams = value;
+        self
+    }
+
     /// Send grease values to the Client.
     /// See [setting](https://www.rfc-editor.org/rfc/rfc9114.html#settings-parameters), [frame](https://www.rfc-editor.org/rfc/rfc9114.html#frame-reserved) and [stream](https://www.rfc-editor.org/rfc/rfc9114.html#stream-grease) for more information.
     #[inline]
@@ -114,6 +176,18 @@ impl Builder {
         self.config.settings.enable_datagram = value;
         self
     }
+
+    /// Answer non-idempotent requests received in 0-RTT with `425 Too Early`
+    ///
+    /// Only applies to requests accepted with [`Connection::accept_early()`], which tells early
+    /// requests apart by whether the handshake is complete when their stream is accepted: one
+    /// still queued in the transport once it completes is not refused.
+    ///
+    /// See: <https://www.rfc-editor.org/rfc/rfc8470#section-5.2>
+    pub fn reject_early_unsafe(&mut self, value: bool) -> &mut Self {
+        self.reject_early_unsafe = value;
+        self
+    }
 }
 
 impl Builder {
@@ -125,9 +199,10 @@ impl Builder {
         C: quic::Connection<B>,
         B: Buf,
     {
-        let (sender, receiver) = mpsc::unbounded_channel();
+        let (sender, receiver) = mpsc::unbounded();
         Ok(Connection {
-            inner: ConnectionInner::new(conn, SharedStateRef::default(), self.config).await?,
+            inner: ConnectionInner::new(conn, SharedStateRef::default(), self.config.clone())
+                .await?,
             max_field_section_size: self.config.settings.max_field_section_size,
             request_end_send: sender,
             request_end_recv: receiver,
@@ -135,6 +210,7 @@ impl Builder {
             sent_closing: None,
             recv_closing: None,
             last_accepted_stream: None,
+            reject_early_unsafe: self.reject_early_unsafe,
         })
     }
 }
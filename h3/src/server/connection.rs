@@ -7,37 +7,37 @@ use std::{
     marker::PhantomData,
     option::Option,
     result::Result,
-    sync::Arc,
+    sync::{Arc, Mutex},
     task::{Context, Poll},
 };
 
-use bytes::Buf;
+use bytes::{Buf, Bytes};
+use futures_channel::mpsc;
 use futures_util::{
     future::{self},
-    ready,
+    ready, StreamExt,
 };
-use http::Request;
+use http::{Request, Response, StatusCode};
 use quic::RecvStream;
 use quic::StreamId;
-use tokio::sync::mpsc;
 
 use crate::{
-    connection::{self, ConnectionInner, ConnectionState, SharedStateRef},
+    connection::{ConnectionInner, ConnectionState, Goaway, RequestEnd, SharedStateRef},
     error::{Code, Error, ErrorLevel},
-    ext::Datagram,
+    ext::{Datagram, EarlyData},
     frame::{FrameStream, FrameStreamError},
     proto::{
         frame::{Frame, PayloadLen},
         push::PushId,
+        varint::VarInt,
     },
-    qpack,
     quic::{self, RecvDatagramExt, SendDatagramExt, SendStream as _},
     stream::BufRecvStream,
 };
 
-use crate::server::request::ResolveRequest;
+use crate::server::request::{RequestResolver, ResolveRequest};
 
-use tracing::{trace, warn};
+use tracing::{error, trace, warn};
 
 use super::stream::{ReadDatagram, RequestStream};
 
@@ -48,6 +48,14 @@ use super::stream::{ReadDatagram, RequestStream};
 /// Create a new Instance with [`Connection::new()`].
 /// Accept incoming requests with [`Connection::accept()`].
 /// And shutdown a connection with [`Connection::shutdown()`].
+///
+/// Accepting requests also processes the control and QPACK streams. While the application
+/// stops accepting for a while, the connection can be kept going with [`Connection::drive()`]
+/// instead, so the client's SETTINGS and GOAWAY frames are still handled. To accept requests
+/// while the connection is driven on another task, split it with [`Connection::split()`].
+///
+/// Dropping it tears the connection down: the client is sent `HTTP_NO_ERROR`, and ongoing
+/// [`RequestStream`]s fail with an error.
 pub struct Connection<C, B>
 where
     C: quic::Connection<B>,
@@ -67,6 +75,8 @@ where
     pub(super) recv_closing: Option<PushId>,
     // The id of the last stream received by this connection.
     pub(super) last_accepted_stream: Option<StreamId>,
+    // Answer non-idempotent requests received in 0-RTT with 425 Too Early.
+    pub(super) reject_early_unsafe: bool,
 }
 
 impl<C, B> ConnectionState for Connection<C, B>
@@ -109,42 +119,98 @@ where
     /// It returns a tuple with a [`http::Request`] and an [`RequestStream`].
     /// The [`http::Request`] is the received request from the client.
     /// The [`RequestStream`] can be used to send the response.
+    ///
+    /// The request headers are received before returning, so a client slow to send them
+    /// delays the requests accepted after it. See [`Connection::accept_resolver()`] to receive
+    /// them in the task handling the request instead.
     pub async fn accept(
         &mut self,
     ) -> Result<Option<(Request<()>, RequestStream<C::BidiStream, B>)>, Error> {
-        // Accept the incoming stream
-        let mut stream = match future::poll_fn(|cx| self.poll_accept_request(cx)).await {
-            Ok(Some(s)) => FrameStream::new(BufRecvStream::new(s)),
+        Ok(self
+            .accept_request(|_| false)
+            .await?
+            .map(|(req, stream, _)| (req, stream)))
+    }
+
+    /// Accept an incoming request stream, without waiting for its headers
+    ///
+    /// This returns as soon as the client opens a request stream. The request is then obtained
+    /// with [`RequestResolver::resolve_request()`], typically from the task handling it.
+    pub async fn accept_resolver(&mut self) -> Result<Option<RequestResolver<C, B>>, Error> {
+        Ok(self
+            .accept_stream(|_| false)
+            .await?
+            .map(|(resolver, _)| resolver))
+    }
+
+    /// Accept an incoming request stream, also telling if `is_early(conn)` held when it arrived
+    ///
+    /// The early state is read in the same poll as the stream is accepted from the transport.
+    async fn accept_stream(
+        &mut self,
+        is_early: fn(&C) -> bool,
+    ) -> Result<Option<(RequestResolver<C, B>, bool)>, Error> {
+        let accepted = future::poll_fn(|cx| {
+            self.poll_accept_resolver(cx)
+                .map_ok(|resolver| resolver.map(|r| (r, is_early(&self.inner.conn))))
+        })
+        .await;
+        match accepted {
             Ok(None) => {
                 // We always send a last GoAway frame to the client, so it knows which was the last
                 // non-rejected request.
                 self.shutdown(0).await?;
-                return Ok(None);
+                Ok(None)
             }
+            Err(err) if err.is_closed() => Ok(None),
+            res => res,
+        }
+    }
+
+    /// Poll for an incoming request stream
+    ///
+    /// Unlike [`Connection::accept_resolver()`], this leaves sending the last GoAway frame to
+    /// the caller, and reports a closed connection as an error.
+    pub(super) fn poll_accept_resolver(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<RequestResolver<C, B>>, Error>> {
+        // Accept the incoming stream
+        let stream = match ready!(self.poll_accept_request(cx)) {
+            Ok(Some(s)) => FrameStream::new(BufRecvStream::new(s)),
+            Ok(None) => return Poll::Ready(Ok(None)),
             Err(err) => {
-                match err.inner.kind {
-                    crate::error::Kind::Closed => return Ok(None),
+                return Poll::Ready(match err.inner.kind {
                     crate::error::Kind::Application {
                         code,
                         reason,
                         level: ErrorLevel::ConnectionError,
-                    } => {
-                        return Err(self.inner.close(
-                            code,
-                            reason.unwrap_or_else(|| String::into_boxed_str(String::from(""))),
-                        ))
-                    }
-                    _ => return Err(err),
-                };
+                    } => Err(self.inner.close(
+                        code,
+                        reason.unwrap_or_else(|| String::into_boxed_str(String::from(""))),
+                    )),
+                    _ => Err(err),
+                });
             }
         };
 
-        let frame = future::poll_fn(|cx| stream.poll_next(cx)).await;
-        let req = self.accept_with_frame(stream, frame)?;
-        if let Some(req) = req {
-            Ok(Some(req.resolve().await?))
-        } else {
-            Ok(None)
+        Poll::Ready(Ok(Some(self.resolver(stream))))
+    }
+
+    /// Accept an incoming request, also telling if it arrived before `is_early(conn)` became false
+    async fn accept_request(
+        &mut self,
+        is_early: fn(&C) -> bool,
+    ) -> Result<Option<(Request<()>, RequestStream<C::BidiStream, B>, bool)>, Error> {
+        let (resolver, early) = match self.accept_stream(is_early).await? {
+            Some(accepted) => accepted,
+            None => return Ok(None),
+        };
+
+        match resolver.resolve_request().await {
+            Ok((req, stream)) => Ok(Some((req, stream, early))),
+            Err(err) if err.is_closed() => Ok(None),
+            Err(err) => Err(err),
         }
     }
 
@@ -156,142 +222,87 @@ where
     /// may still want to be handled and passed to the user.
     pub fn accept_with_frame(
         &mut self,
-        mut stream: FrameStream<C::BidiStream, B>,
+        stream: FrameStream<C::BidiStream, B>,
         frame: Result<Option<Frame<PayloadLen>>, FrameStreamError>,
     ) -> Result<Option<ResolveRequest<C, B>>, Error> {
-        let mut encoded = match frame {
-            Ok(Some(Frame::Headers(h))) => h,
-
-            //= https://www.rfc-editor.org/rfc/rfc9114#section-4.1
-            //# If a client-initiated
-            //# stream terminates without enough of the HTTP message to provide a
-            //# complete response, the server SHOULD abort its response stream with
-            //# the error code H3_REQUEST_INCOMPLETE.
-            Ok(None) => {
-                return Err(self.inner.close(
-                    Code::H3_REQUEST_INCOMPLETE,
-                    "request stream closed before headers",
-                ));
-            }
-
-            //= https://www.rfc-editor.org/rfc/rfc9114#section-4.1
-            //# Receipt of an invalid sequence of frames MUST be treated as a
-            //# connection error of type H3_FRAME_UNEXPECTED.
-
-            //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.5
-            //# A server MUST treat the
-            //# receipt of a PUSH_PROMISE frame as a connection error of type
-            //# H3_FRAME_UNEXPECTED.
-            Ok(Some(_)) => {
-                //= https://www.rfc-editor.org/rfc/rfc9114#section-4.1
-                //# Receipt of an invalid sequence of frames MUST be treated as a
-                //# connection error of type H3_FRAME_UNEXPECTED.
-                // Close if the first frame is not a header frame
-                return Err(self.inner.close(
-                    Code::H3_FRAME_UNEXPECTED,
-                    "first request frame is not headers",
-                ));
-            }
-            Err(e) => {
-                let err: Error = e.into();
-                if err.is_closed() {
-                    return Ok(None);
-                }
-                match err.inner.kind {
-                    crate::error::Kind::Closed => return Ok(None),
-                    crate::error::Kind::Application {
-                        code,
-                        reason,
-                        level: ErrorLevel::ConnectionError,
-                    } => {
-                        return Err(self.inner.close(
-                            code,
-                            reason.unwrap_or_else(|| String::into_boxed_str(String::from(""))),
-                        ))
-                    }
-                    crate::error::Kind::Application {
-                        code,
-                        reason: _,
-                        level: ErrorLevel::StreamError,
-                    } => {
-                        stream.reset(code.into());
-                        return Err(err);
-                    }
-                    _ => return Err(err),
-                };
-            }
-        };
-
-        let mut request_stream = RequestStream {
-            request_end: Arc::new(RequestEnd {
-                request_end: self.request_end_send.clone(),
-                stream_id: stream.send_id(),
-            }),
-            inner: connection::RequestStream::new(
-                stream,
-                self.max_field_section_size,
-                self.inner.shared.clone(),
-                self.inner.send_grease_frame,
-            ),
-        };
+        self.resolver(stream).resolve_frame(frame)
+    }
 
-        let decoded = match qpack::decode_stateless(&mut encoded, self.max_field_section_size) {
-            //= https://www.rfc-editor.org/rfc/rfc9114#section-4.2.2
-            //# An HTTP/3 implementation MAY impose a limit on the maximum size of
-            //# the message header it will accept on an individual HTTP message.
-            Err(qpack::DecoderError::HeaderTooLong(cancel_size)) => Err(cancel_size),
-            Ok(decoded) => {
-                // send the grease frame only once
-                self.inner.send_grease_frame = false;
-                Ok(decoded)
+    fn resolver(&mut self, mut stream: FrameStream<C::BidiStream, B>) -> RequestResolver<C, B> {
+        stream.set_extension_handler(self.inner.config.on_extension_frame.clone());
+        let (request_end, stream_id) = (self.request_end_send.clone(), stream.send_id());
+        let request_end = Arc::new(RequestEnd::new(move || {
+            if let Err(e) = request_end.unbounded_send(stream_id) {
+                error!(
+                    "failed to notify connection of request end: {} {}",
+                    stream_id, e
+                );
             }
-            Err(e) => {
-                let err: Error = e.into();
-                if err.is_closed() {
-                    return Ok(None);
-                }
-                match err.inner.kind {
-                    crate::error::Kind::Closed => return Ok(None),
-                    crate::error::Kind::Application {
-                        code,
-                        reason,
-                        level: ErrorLevel::ConnectionError,
-                    } => {
-                        return Err(self.inner.close(
-                            code,
-                            reason.unwrap_or_else(|| String::into_boxed_str(String::from(""))),
-                        ))
-                    }
-                    crate::error::Kind::Application {
-                        code,
-                        reason: _,
-                        level: ErrorLevel::StreamError,
-                    } => {
-                        request_stream.stop_stream(code);
-                        return Err(err);
-                    }
-                    _ => return Err(err),
-                };
-            }
-        };
-
-        Ok(Some(ResolveRequest::new(
-            request_stream,
-            decoded,
+        }));
+        let resolver = RequestResolver::new(
+            stream,
+            request_end,
+            self.inner.conn.opener(),
+            self.inner.shared.clone(),
             self.max_field_section_size,
-        )))
+            self.inner.send_grease_frame,
+        );
+        // send the grease frame only once
+        self.inner.send_grease_frame = false;
+        resolver
     }
 
     /// Initiate a graceful shutdown, accepting `max_request` potentially still in-flight
     ///
     /// See [connection shutdown](https://www.rfc-editor.org/rfc/rfc9114.html#connection-shutdown) for more information.
     pub async fn shutdown(&mut self, max_requests: usize) -> Result<(), Error> {
-        let max_id = self
-            .last_accepted_stream
+        let max_id = self.goaway_id(max_requests);
+        self.inner.shutdown(&mut self.sent_closing, max_id).await
+    }
+
+    /// The last request stream accepted, once `max_requests` more are
+    ///
+    /// The id saturates at the last request stream id a varint holds, as no request can be sent
+    /// on a stream past it anyway.
+    fn goaway_id(&self, max_requests: usize) -> StreamId {
+        self.last_accepted_stream
             .map(|id| id + max_requests)
-            .unwrap_or(StreamId::FIRST_REQUEST);
+            .unwrap_or(StreamId::FIRST_REQUEST)
+    }
 
-        self.inner.shutdown(&mut self.sent_closing, max_id).await
+    /// Splits the connection into a driver and a handle accepting the requests
+    ///
+    /// The [`Driver`] processes the control and QPACK streams, and is meant to be spawned on
+    /// its own task. It hands the incoming request streams over to the [`Acceptor`], which can
+    /// be polled from elsewhere, as the application is able to take new requests.
+    pub fn split(self) -> (Driver<C, B>, Acceptor<C, B>) {
+        let (sender, resolvers) = mpsc::channel(0);
+        let failure = Arc::new(Mutex::new(None));
+        let shared = self.inner.shared.clone();
+        (
+            Driver {
+                conn: self,
+                resolvers: Some(sender),
+                drained: false,
+                failure: failure.clone(),
+            },
+            Acceptor {
+                resolvers,
+                failure,
+                shared,
+            },
+        )
+    }
+
+    /// Sends a frame of extension type `frame_type` on the control stream, followed by `payload`
+    ///
+    /// See [`ExtensionFrame::new()`](crate::codec::ExtensionFrame::new) for the types allowed.
+    pub async fn send_extension_frame(
+        &mut self,
+        frame_type: VarInt,
+        payload: Bytes,
+    ) -> Result<(), Error> {
+        self.inner.send_extension_frame(frame_type, payload).await
     }
 
     /// Accepts an incoming bidirectional stream.
@@ -346,6 +357,26 @@ where
         }
     }
 
+    /// Drive the connection without accepting requests, until it is closed
+    pub async fn drive(&mut self) -> Result<(), Error> {
+        future::poll_fn(|cx| self.poll_drive(cx)).await
+    }
+
+    /// Process the control stream and QPACK streams until the connection is closed
+    ///
+    /// Resolves to `Ok(())` if the client closed the connection with `HTTP_NO_ERROR`. Incoming
+    /// requests are left to [`Connection::accept()`].
+    pub fn poll_drive(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        let _ = self.poll_requests_completion(cx);
+        loop {
+            match ready!(self.poll_next_control(cx)) {
+                Ok(_) => (),
+                Err(e) if e.is_closed() => return Poll::Ready(Ok(())),
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+    }
+
     pub(crate) fn poll_control(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
         while (self.poll_next_control(cx)?).is_ready() {}
         Poll::Pending
@@ -359,24 +390,37 @@ where
 
         match &frame {
             Frame::Settings(w) => trace!("Got settings > {:?}", w),
-            &Frame::Goaway(id) => self.inner.process_goaway(&mut self.recv_closing, id)?,
-            f @ Frame::MaxPushId(_) | f @ Frame::CancelPush(_) => {
-                warn!("Control frame ignored {:?}", f);
+            &Frame::Goaway(id) => {
+                //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.6
+                //# In the client-to-server direction, the GOAWAY frame carries a push ID
+                //# encoded as a variable-length integer.
+                let id = Goaway::new(id).as_push_id();
+                self.inner.process_goaway(&mut self.recv_closing, id)?;
+            }
+            &Frame::MaxPushId(id) => {
+                // Server push is not implemented, the limit is only checked
+                self.inner.push_id_limit.update(id)?;
+                trace!("Got MAX_PUSH_ID frame for {}", id);
+            }
+            &Frame::CancelPush(id) => {
+                //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.3
+                //# If a CANCEL_PUSH frame is received that
+                //# references a push ID greater than currently allowed on the
+                //# connection, this MUST be treated as a connection error of type
+                //# H3_ID_ERROR.
+                self.inner.push_id_limit.check(id)?;
+                warn!("Control frame ignored {:?}", frame);
 
                 //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.3
                 //= type=TODO
                 //# If a server receives a CANCEL_PUSH frame for a push
                 //# ID that has not yet been mentioned by a PUSH_PROMISE frame, this MUST
                 //# be treated as a connection error of type H3_ID_ERROR.
-
-                //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.7
-                //= type=TODO
-                //# A MAX_PUSH_ID frame cannot reduce the maximum push
-                //# ID; receipt of a MAX_PUSH_ID frame that contains a smaller value than
-                //# previously received MUST be treated as a connection error of type
-                //# H3_ID_ERROR.
             }
 
+            // Only servers send ORIGIN frames, see RFC 9412, the origin set is the client's
+            Frame::Origin { .. } => trace!("ORIGIN frame ignored"),
+
             //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.5
             //# A server MUST treat the
             //# receipt of a PUSH_PROMISE frame as a connection error of type
@@ -393,7 +437,7 @@ where
 
     fn poll_requests_completion(&mut self, cx: &mut Context<'_>) -> Poll<()> {
         loop {
-            match self.request_end_recv.poll_recv(cx) {
+            match self.request_end_recv.poll_next_unpin(cx) {
                 // The channel is closed
                 Poll::Ready(None) => return Poll::Ready(()),
                 // A request has completed
@@ -414,6 +458,65 @@ where
     }
 }
 
+impl<C, B> Connection<C, B>
+where
+    C: quic::Connection<B> + quic::EarlyDataExt,
+    B: Buf,
+{
+    /// Accept an incoming request, which may have been sent in 0-RTT
+    ///
+    /// Requests whose stream was accepted before the handshake completed carry the
+    /// [`EarlyData`] extension. When [`Builder::reject_early_unsafe()`] is enabled,
+    /// those with a non-idempotent method are answered with `425 Too Early` instead of being
+    /// returned, so the client retries them after the handshake.
+    ///
+    /// The transport only tells whether the handshake is still in progress, not which streams
+    /// were received in 0-RTT. So a request is early if the handshake is not complete when its
+    /// stream is accepted from the transport: a stream received in 0-RTT but still queued when
+    /// the handshake completes is taken for a 1-RTT one. Call this often enough, as from an
+    /// accept loop, that requests do not wait in the transport.
+    ///
+    /// [`Builder::reject_early_unsafe()`]: super::Builder::reject_early_unsafe
+    pub async fn accept_early(
+        &mut self,
+    ) -> Result<Option<(Request<()>, RequestStream<C::BidiStream, B>)>, Error> {
+        loop {
+            let (mut req, mut stream, early) =
+                match self.accept_request(|conn| conn.is_early_data()).await? {
+                    Some(accepted) => accepted,
+                    None => return Ok(None),
+                };
+
+            if !early {
+                return Ok(Some((req, stream)));
+            }
+
+            if self.reject_early_unsafe && !req.method().is_idempotent() {
+                //= https://www.rfc-editor.org/rfc/rfc8470#section-5.2
+                //# A 425 (Too Early) status code indicates that the server is
+                //# unwilling to risk processing a request that might be replayed.
+                trace!("rejecting {} request received in 0-RTT", req.method());
+                let too_early = Response::builder()
+                    .status(StatusCode::TOO_EARLY)
+                    .body(())
+                    .expect("too early response");
+                // The failure of a single stream must not end the accept loop
+                let sent = match stream.send_response(too_early).await {
+                    Ok(()) => stream.finish().await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = sent {
+                    warn!("failed to answer a request received in 0-RTT: {}", e);
+                }
+                continue;
+            }
+
+            req.extensions_mut().insert(EarlyData);
+            return Ok(Some((req, stream)));
+        }
+    }
+}
+
 impl<C, B> Connection<C, B>
 where
     C: quic::Connection<B> + SendDatagramExt<B>,
@@ -454,6 +557,178 @@ where
     }
 }
 
+/// Server connection driver, split from the [`Acceptor`] with [`Connection::split()`]
+///
+/// It processes the control and QPACK streams, and accepts the request streams the [`Acceptor`]
+/// is ready to take. It needs to be polled continuously via [`Driver::poll_drive()`], so it is
+/// best spawned on its own task with [`Driver::drive()`]. This resolves to `Ok(())` once the
+/// connection is drained after a shutdown or closed by the client with `HTTP_NO_ERROR`, or
+/// `Err()` if a connection-level error occurred.
+///
+/// Dropping the driver tears the connection down: the client is sent `HTTP_NO_ERROR`, ongoing
+/// [`RequestStream`]s fail with an error, and [`Acceptor::accept()`] returns `None`.
+pub struct Driver<C, B>
+where
+    C: quic::Connection<B>,
+    B: Buf,
+{
+    conn: Connection<C, B>,
+    // Hands the request streams to the `Acceptor`, until it is gone or none is left to accept
+    resolvers: Option<mpsc::Sender<RequestResolver<C, B>>>,
+    // All the requests accepted are over, and no more will be
+    drained: bool,
+    // Reported by the `Acceptor` once the driver failed
+    failure: Arc<Mutex<Option<Error>>>,
+}
+
+impl<C, B> ConnectionState for Driver<C, B>
+where
+    C: quic::Connection<B>,
+    B: Buf,
+{
+    fn shared_state(&self) -> &SharedStateRef {
+        &self.conn.inner.shared
+    }
+}
+
+impl<C, B> Driver<C, B>
+where
+    C: quic::Connection<B>,
+    B: Buf,
+{
+    /// Drive the connection until it is closed, meant to be spawned on its own task
+    pub async fn drive(mut self) -> Result<(), Error> {
+        future::poll_fn(|cx| self.poll_drive(cx)).await
+    }
+
+    /// Process the control stream and QPACK streams, and accept requests, until the connection
+    /// is closed
+    pub fn poll_drive(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        let result = match self.poll_accept(cx) {
+            // The connection is torn down once dropped
+            Poll::Ready(Ok(())) => Ok(()),
+            Poll::Pending => ready!(self.conn.poll_drive(cx)),
+            Poll::Ready(Err(e)) if e.is_closed() => Ok(()),
+            Poll::Ready(Err(e)) => Err(e),
+        };
+
+        // The acceptor is told the connection is over
+        self.resolvers = None;
+        if let Err(e) = &result {
+            *self.failure.lock().unwrap() = Some(e.clone());
+        }
+        Poll::Ready(result)
+    }
+
+    /// Initiate a graceful shutdown, accepting `max_request` potentially still in-flight
+    ///
+    /// See [`Connection::shutdown()`].
+    pub async fn shutdown(&mut self, max_requests: usize) -> Result<(), Error> {
+        self.conn.shutdown(max_requests).await
+    }
+
+    /// Hands the incoming request streams over to the acceptor, as long as it takes them
+    ///
+    /// Resolves once the connection is drained, and the last GOAWAY frame sent.
+    fn poll_accept(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        while let Some(resolvers) = &mut self.resolvers {
+            match resolvers.poll_ready(cx) {
+                Poll::Ready(Ok(())) => (),
+                Poll::Ready(Err(_)) => {
+                    // The acceptor is gone, the request streams are left to the QUIC
+                    // implementation
+                    self.resolvers = None;
+                    break;
+                }
+                Poll::Pending => break,
+            }
+            match self.conn.poll_accept_resolver(cx) {
+                Poll::Ready(Ok(Some(resolver))) => {
+                    let _ = resolvers.start_send(resolver);
+                }
+                Poll::Ready(Ok(None)) => {
+                    // We always send a last GoAway frame to the client, so it knows which was the
+                    // last non-rejected request.
+                    self.resolvers = None;
+                    self.drained = true;
+                }
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => break,
+            }
+        }
+
+        if !self.drained {
+            return Poll::Pending;
+        }
+        let max_id = self.conn.goaway_id(0);
+        let conn = &mut self.conn;
+        conn.inner.poll_shutdown(cx, &mut conn.sent_closing, max_id)
+    }
+}
+
+/// Accepts the requests of a connection run by a [`Driver`]
+///
+/// Returned by [`Connection::split()`]. The driver only accepts a request stream once this is
+/// ready to take it, so the client is held back while the application does not accept any.
+/// Dropping it stops the driver from accepting request streams.
+pub struct Acceptor<C, B>
+where
+    C: quic::Connection<B>,
+    B: Buf,
+{
+    resolvers: mpsc::Receiver<RequestResolver<C, B>>,
+    failure: Arc<Mutex<Option<Error>>>,
+    shared: SharedStateRef,
+}
+
+impl<C, B> ConnectionState for Acceptor<C, B>
+where
+    C: quic::Connection<B>,
+    B: Buf,
+{
+    fn shared_state(&self) -> &SharedStateRef {
+        &self.shared
+    }
+}
+
+impl<C, B> Acceptor<C, B>
+where
+    C: quic::Connection<B>,
+    B: Buf,
+{
+    /// Accept an incoming request
+    ///
+    /// Like [`Connection::accept()`], this receives the request headers before returning.
+    /// `None` is returned once the connection is closed, or drained after a shutdown.
+    pub async fn accept(
+        &mut self,
+    ) -> Result<Option<(Request<()>, RequestStream<C::BidiStream, B>)>, Error> {
+        let resolver = match self.accept_resolver().await? {
+            Some(resolver) => resolver,
+            None => return Ok(None),
+        };
+
+        match resolver.resolve_request().await {
+            Ok(accepted) => Ok(Some(accepted)),
+            Err(err) if err.is_closed() => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Accept an incoming request stream, without waiting for its headers
+    ///
+    /// See [`Connection::accept_resolver()`].
+    pub async fn accept_resolver(&mut self) -> Result<Option<RequestResolver<C, B>>, Error> {
+        match self.resolvers.next().await {
+            Some(resolver) => Ok(Some(resolver)),
+            None => match self.failure.lock().unwrap().clone() {
+                Some(err) => Err(err),
+                None => Ok(None),
+            },
+        }
+    }
+}
+
 //= https://www.rfc-editor.org/rfc/rfc9114#section-6.1
 //= type=TODO
 //# In order to
@@ -466,8 +741,3 @@ where
 //# So as to not unnecessarily limit
 //# parallelism, at least 100 request streams SHOULD be permitted at a
 //# time.
-
-pub(super) struct RequestEnd {
-    pub(super) request_end: mpsc::UnboundedSender<StreamId>,
-    pub(super) stream_id: StreamId,
-}
@@ -50,13 +50,25 @@
 //! ## File server
 //! A ready-to-use example of a file server is available [here](https://github.com/hyperium/h3/blob/master/examples/server.rs)
 
+#[cfg(feature = "http-body")]
+mod body;
 mod builder;
 mod connection;
 mod request;
+#[cfg(feature = "tower")]
+mod service;
 mod stream;
 
+#[cfg(feature = "http-body")]
+pub use body::RequestBody;
 pub use builder::builder;
 pub use builder::Builder;
-pub use connection::Connection;
+pub use connection::{Acceptor, Connection, Driver};
+pub use request::RequestResolver;
+#[cfg(feature = "tower")]
+pub use service::{ErrorPolicy, Serve};
+#[cfg(any(test, feature = "tokio"))]
+pub use stream::BodyRead;
 pub use stream::ReadDatagram;
 pub use stream::RequestStream;
+pub use stream::ResponseWriter;
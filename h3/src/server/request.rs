@@ -1,12 +1,224 @@
-use std::convert::TryFrom;
+use std::{convert::TryFrom, sync::Arc};
 
 use bytes::Buf;
-use http::{Request, StatusCode};
+use futures_util::future;
+use http::{Method, Request, StatusCode};
 
-use crate::{error::Code, proto::headers::Header, qpack, quic, Error};
+use crate::{
+    connection::{self, RequestEnd, SharedStateRef},
+    error::{Code, ErrorLevel},
+    frame::{AppliedSettings, FrameStream, FrameStreamError},
+    proto::{
+        frame::{Frame, PayloadLen},
+        headers::Header,
+    },
+    qpack,
+    quic::{self, OpenStreams as _, SendStream as _, StreamId},
+    Error,
+};
 
 use super::stream::RequestStream;
 
+/// An accepted request stream whose headers are yet to be received
+///
+/// Returned by [`Connection::accept_resolver()`], it is meant to be moved to the task handling
+/// the request. Receiving and decoding the headers there keeps a slow client, or an oversized
+/// header section, from holding up the accept loop.
+///
+/// Dropping it before the request is resolved counts the request as complete.
+///
+/// [`Connection::accept_resolver()`]: super::Connection::accept_resolver
+pub struct RequestResolver<C: quic::Connection<B>, B: Buf> {
+    frame_stream: FrameStream<C::BidiStream, B>,
+    request_end: Arc<RequestEnd>,
+    // Closes the connection on a connection-level error
+    opener: C::OpenStreams,
+    shared: SharedStateRef,
+    max_field_section_size: u64,
+    send_grease_frame: bool,
+}
+
+impl<C, B> RequestResolver<C, B>
+where
+    C: quic::Connection<B>,
+    B: Buf,
+{
+    pub(super) fn new(
+        mut frame_stream: FrameStream<C::BidiStream, B>,
+        request_end: Arc<RequestEnd>,
+        opener: C::OpenStreams,
+        shared: SharedStateRef,
+        max_field_section_size: u64,
+        send_grease_frame: bool,
+    ) -> Self {
+        frame_stream.apply_settings(&AppliedSettings {
+            max_field_section_size,
+        });
+        Self {
+            frame_stream,
+            request_end,
+            opener,
+            shared,
+            max_field_section_size,
+            send_grease_frame,
+        }
+    }
+
+    /// Returns the id of the request stream
+    pub fn id(&self) -> StreamId {
+        self.frame_stream.send_id()
+    }
+
+    /// Receives the request headers and decodes them
+    ///
+    /// Fails with a closed connection error if the connection was closed before the headers
+    /// were received.
+    pub async fn resolve_request(
+        mut self,
+    ) -> Result<(Request<()>, RequestStream<C::BidiStream, B>), Error> {
+        let frame = future::poll_fn(|cx| self.frame_stream.poll_next(cx)).await;
+        match self.resolve_frame(frame)? {
+            Some(resolve) => resolve.resolve().await,
+            None => Err(Error::closed()),
+        }
+    }
+
+    /// Resolves the request from the first frame of the stream
+    pub(super) fn resolve_frame(
+        self,
+        frame: Result<Option<Frame<PayloadLen>>, FrameStreamError>,
+    ) -> Result<Option<ResolveRequest<C, B>>, Error> {
+        let Self {
+            frame_stream: mut stream,
+            request_end,
+            mut opener,
+            shared,
+            max_field_section_size,
+            send_grease_frame,
+        } = self;
+        let mut close = |code: Code, reason: &str| {
+            let err = code.with_reason(reason, ErrorLevel::ConnectionError);
+            shared.write("request resolver close").error = Some(err.clone());
+            opener.close(code, reason.as_bytes());
+            err
+        };
+
+        let encoded = match frame {
+            Ok(Some(Frame::Headers(h))) => Ok(h),
+
+            // Rejected by the frame stream before the payload was even buffered
+            Err(FrameStreamError::HeaderTooBig { size, .. }) => Err(size),
+
+            //= https://www.rfc-editor.org/rfc/rfc9114#section-4.1
+            //# If a client-initiated
+            //# stream terminates without enough of the HTTP message to provide a
+            //# complete response, the server SHOULD abort its response stream with
+            //# the error code H3_REQUEST_INCOMPLETE.
+            Ok(None) => {
+                return Err(close(
+                    Code::H3_REQUEST_INCOMPLETE,
+                    "request stream closed before headers",
+                ));
+            }
+
+            //= https://www.rfc-editor.org/rfc/rfc9114#section-4.1
+            //# Receipt of an invalid sequence of frames MUST be treated as a
+            //# connection error of type H3_FRAME_UNEXPECTED.
+
+            //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.5
+            //# A server MUST treat the
+            //# receipt of a PUSH_PROMISE frame as a connection error of type
+            //# H3_FRAME_UNEXPECTED.
+            Ok(Some(_)) => {
+                //= https://www.rfc-editor.org/rfc/rfc9114#section-4.1
+                //# Receipt of an invalid sequence of frames MUST be treated as a
+                //# connection error of type H3_FRAME_UNEXPECTED.
+                // Close if the first frame is not a header frame
+                return Err(close(
+                    Code::H3_FRAME_UNEXPECTED,
+                    "first request frame is not headers",
+                ));
+            }
+            Err(e) => {
+                let err: Error = e.into();
+                if err.is_closed() {
+                    return Ok(None);
+                }
+                match err.inner.kind {
+                    crate::error::Kind::Closed => return Ok(None),
+                    crate::error::Kind::Application {
+                        code,
+                        reason,
+                        level: ErrorLevel::ConnectionError,
+                    } => return Err(close(code, &reason.unwrap_or_default())),
+                    crate::error::Kind::Application {
+                        code,
+                        reason: _,
+                        level: ErrorLevel::StreamError,
+                    } => {
+                        stream.reset(code.into());
+                        return Err(err);
+                    }
+                    _ => return Err(err),
+                };
+            }
+        };
+
+        let mut request_stream = RequestStream {
+            head: false,
+            inner: connection::RequestStream::new(
+                stream,
+                max_field_section_size,
+                shared.clone(),
+                send_grease_frame,
+            ),
+        };
+        request_stream.inner.track_end(request_end);
+        request_stream.inner.headers_received();
+
+        let decoded = encoded
+            .map(|mut encoded| qpack::decode_stateless(&mut encoded, max_field_section_size));
+        let decoded = match decoded {
+            //= https://www.rfc-editor.org/rfc/rfc9114#section-4.2.2
+            //# An HTTP/3 implementation MAY impose a limit on the maximum size of
+            //# the message header it will accept on an individual HTTP message.
+            Err(cancel_size) | Ok(Err(qpack::DecoderError::HeaderTooLong(cancel_size))) => {
+                Err(cancel_size)
+            }
+            Ok(Ok(decoded)) => Ok(decoded),
+            Ok(Err(e)) => {
+                let err: Error = e.into();
+                if err.is_closed() {
+                    return Ok(None);
+                }
+                match err.inner.kind {
+                    crate::error::Kind::Closed => return Ok(None),
+                    crate::error::Kind::Application {
+                        code,
+                        reason,
+                        level: ErrorLevel::ConnectionError,
+                    } => return Err(close(code, &reason.unwrap_or_default())),
+                    crate::error::Kind::Application {
+                        code,
+                        reason: _,
+                        level: ErrorLevel::StreamError,
+                    } => {
+                        request_stream.stop_stream(code);
+                        return Err(err);
+                    }
+                    _ => return Err(err),
+                };
+            }
+        };
+
+        Ok(Some(ResolveRequest::new(
+            request_stream,
+            decoded,
+            max_field_section_size,
+        )))
+    }
+}
+
 pub struct ResolveRequest<C: quic::Connection<B>, B: Buf> {
     request_stream: RequestStream<C::BidiStream, B>,
     // Ok or `REQUEST_HEADER_FIELDS_TO_LARGE` which neeeds to be sent
@@ -76,6 +288,8 @@ impl<B: Buf, C: quic::Connection<B>> ResolveRequest<C, B> {
             }
         };
 
+        self.request_stream.head = method == Method::HEAD;
+
         //  request_stream.stop_stream(Code::H3_MESSAGE_ERROR).await;
         let mut req = http::Request::new(());
         *req.method_mut() = method;
@@ -0,0 +1,218 @@
+//! Serving a connection with a [`tower_service::Service`]
+
+use std::{future::Future, task::Poll};
+
+use bytes::Buf;
+use futures_util::{future, pin_mut, ready, stream::FuturesUnordered, StreamExt};
+use http::{Request, Response, StatusCode};
+use http_body::Body;
+use tower_service::Service;
+use tracing::{debug, error};
+
+use crate::{
+    error::{Code, ErrorLevel},
+    quic, Error,
+};
+
+use super::{Connection, RequestBody, RequestStream};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// What to do with a request the service failed to handle
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Respond with a `500 Internal Server Error`
+    #[default]
+    InternalServerError,
+    /// Reset the request stream with a code
+    Reset(Code),
+}
+
+/// Serves the requests of a [`Connection`] with a [`Service`]
+///
+/// Each request is handed to the service along with a [`RequestBody`], its
+/// [`StreamId`](crate::quic::StreamId) is available in the request extensions. The response body
+/// and trailers are then sent back, except for responses which cannot have a body.
+///
+/// No task is spawned: the requests are handled concurrently within [`Serve::serve()`].
+pub struct Serve<S> {
+    service: S,
+    on_error: ErrorPolicy,
+}
+
+enum Event<R, E> {
+    Accepted(Result<Option<R>, Error>),
+    Resolved(E),
+    NotReady(BoxError),
+    Shutdown,
+    Done,
+}
+
+impl<S> Serve<S> {
+    /// Serve requests with `service`
+    pub fn new(service: S) -> Self {
+        Self {
+            service,
+            on_error: ErrorPolicy::default(),
+        }
+    }
+
+    /// Set what to do when the service fails to handle a request
+    ///
+    /// Defaults to [`ErrorPolicy::InternalServerError`]. A response body failing once sent
+    /// resets the stream, as with [`RequestStream::respond()`].
+    pub fn on_error(mut self, policy: ErrorPolicy) -> Self {
+        self.on_error = policy;
+        self
+    }
+
+    /// Serve the requests of `conn` until it is closed
+    pub async fn serve<C, B, ResB>(&mut self, conn: &mut Connection<C, B>) -> Result<(), Error>
+    where
+        C: quic::Connection<B>,
+        C::BidiStream: quic::BidiStream<B>,
+        B: Buf,
+        S: Service<
+            Request<RequestBody<<C::BidiStream as quic::BidiStream<B>>::RecvStream, B>>,
+            Response = Response<ResB>,
+        >,
+        S::Error: Into<BoxError>,
+        ResB: Body<Data = B>,
+        ResB::Error: Into<BoxError>,
+    {
+        self.serve_with_shutdown(conn, future::pending()).await
+    }
+
+    /// Serve the requests of `conn` until it is closed, or drained after `signal` resolves
+    ///
+    /// Once `signal` resolves, a GoAway frame is sent to the client: requests already accepted
+    /// are still served, and newer ones are rejected until the client closes the connection.
+    pub async fn serve_with_shutdown<C, B, ResB, F>(
+        &mut self,
+        conn: &mut Connection<C, B>,
+        signal: F,
+    ) -> Result<(), Error>
+    where
+        C: quic::Connection<B>,
+        C::BidiStream: quic::BidiStream<B>,
+        B: Buf,
+        S: Service<
+            Request<RequestBody<<C::BidiStream as quic::BidiStream<B>>::RecvStream, B>>,
+            Response = Response<ResB>,
+        >,
+        S::Error: Into<BoxError>,
+        ResB: Body<Data = B>,
+        ResB::Error: Into<BoxError>,
+        F: Future<Output = ()>,
+    {
+        pin_mut!(signal);
+        let mut signal = Some(signal);
+        let mut accepting = true;
+        let mut resolving = FuturesUnordered::new();
+        let mut responding = FuturesUnordered::new();
+
+        loop {
+            let event = future::poll_fn(|cx| {
+                while let Poll::Ready(Some(())) = responding.poll_next_unpin(cx) {}
+
+                if !accepting && resolving.is_empty() && responding.is_empty() {
+                    return Poll::Ready(Event::Done);
+                }
+                if let Some(s) = signal.as_mut() {
+                    if s.as_mut().poll(cx).is_ready() {
+                        signal = None;
+                        return Poll::Ready(Event::Shutdown);
+                    }
+                }
+
+                // Backpressure: neither hand requests to the service nor accept new streams
+                // until it is ready.
+                if let Err(e) = ready!(self.service.poll_ready(cx)) {
+                    return Poll::Ready(Event::NotReady(e.into()));
+                }
+                if let Poll::Ready(Some(resolved)) = resolving.poll_next_unpin(cx) {
+                    return Poll::Ready(Event::Resolved(resolved));
+                }
+                if accepting {
+                    if let Poll::Ready(accepted) = conn.poll_accept_resolver(cx) {
+                        return Poll::Ready(Event::Accepted(accepted));
+                    }
+                }
+                Poll::Pending
+            })
+            .await;
+
+            match event {
+                Event::Accepted(Ok(Some(resolver))) => resolving.push(resolver.resolve_request()),
+                Event::Accepted(Ok(None)) => {
+                    accepting = false;
+                    // Let the client know which was the last non-rejected request
+                    match conn.shutdown(0).await {
+                        Err(e) if !e.is_closed() => return Err(e),
+                        _ => (),
+                    }
+                }
+                Event::Accepted(Err(e)) if e.is_closed() => accepting = false,
+                Event::Accepted(Err(e)) => return Err(e),
+                Event::Resolved(Ok((req, stream))) => {
+                    let (send, recv) = stream.split();
+                    let id = send.send_id();
+                    let mut req = req.map(|()| RequestBody::new(recv));
+                    req.extensions_mut().insert(id);
+                    responding.push(respond(send, self.service.call(req), self.on_error));
+                }
+                Event::Resolved(Err(e)) => match e.get_error_level() {
+                    ErrorLevel::ConnectionError if !e.is_closed() => return Err(e),
+                    _ => debug!("failed to receive a request: {}", e),
+                },
+                Event::NotReady(e) => {
+                    error!("service failed: {}", e);
+                    return Err(conn.close(Code::H3_INTERNAL_ERROR, "service failed"));
+                }
+                Event::Shutdown => conn.shutdown(0).await?,
+                Event::Done => return Ok(()),
+            }
+        }
+    }
+}
+
+async fn respond<S, B, F, ResB, E>(
+    mut stream: RequestStream<S, B>,
+    response: F,
+    on_error: ErrorPolicy,
+) where
+    S: quic::SendStream<B>,
+    B: Buf,
+    F: Future<Output = Result<Response<ResB>, E>>,
+    E: Into<BoxError>,
+    ResB: Body<Data = B>,
+    ResB::Error: Into<BoxError>,
+{
+    let id = stream.send_id();
+    let result = match response.await {
+        Ok(response) => stream.respond(response).await,
+        Err(e) => {
+            error!("service failed to handle request {}: {}", id, e.into());
+            match on_error {
+                ErrorPolicy::InternalServerError => {
+                    let response = Response::builder()
+                        .status(StatusCode::INTERNAL_SERVER_ERROR)
+                        .body(())
+                        .expect("static response");
+                    match stream.send_response(response).await {
+                        Ok(()) => stream.finish().await,
+                        Err(e) => Err(e),
+                    }
+                }
+                ErrorPolicy::Reset(code) => {
+                    stream.stop_stream(code);
+                    Ok(())
+                }
+            }
+        }
+    };
+
+    if let Err(e) = result {
+        debug!("failed to send response {}: {}", id, e);
+    }
+}
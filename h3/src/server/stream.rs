@@ -10,8 +10,8 @@ use crate::{
 };
 use pin_project_lite::pin_project;
 
-use super::connection::{Connection, RequestEnd};
-use std::{marker::PhantomData, sync::Arc};
+use super::connection::Connection;
+use std::marker::PhantomData;
 
 use std::{
     option::Option,
@@ -19,7 +19,7 @@ use std::{
     task::{Context, Poll},
 };
 
-use bytes::BytesMut;
+use bytes::{Bytes, BytesMut};
 use futures_util::{future::Future, ready};
 use http::{response, HeaderMap, Response};
 
@@ -27,21 +27,26 @@ use quic::StreamId;
 
 use crate::{
     error::Code,
-    proto::{frame::Frame, headers::Header},
+    proto::{frame::Frame, headers::Header, varint::VarInt},
     qpack,
     quic::SendStream as _,
     stream::{self},
 };
 
-use tracing::error;
-
 /// Manage request and response transfer for an incoming request
 ///
 /// The [`RequestStream`] struct is used to send and/or receive
 /// information from the client.
+///
+/// It is `Send` and `'static` whenever `S` and `B` are, as are its [split] halves, so it can be
+/// handled in a spawned task.
+///
+/// [split]: RequestStream::split
 pub struct RequestStream<S, B> {
     pub(super) inner: crate::connection::RequestStream<S, B>,
-    pub(super) request_end: Arc<RequestEnd>,
+    /// Responses to HEAD requests have no body
+    #[cfg_attr(not(feature = "http-body"), allow(dead_code))]
+    pub(super) head: bool,
 }
 
 impl<S, B> AsMut<crate::connection::RequestStream<S, B>> for RequestStream<S, B> {
@@ -62,6 +67,8 @@ where
     B: Buf,
 {
     /// Receive data sent from the client
+    ///
+    /// This is cancel safe, it can be used in `select!` without losing data.
     pub async fn recv_data(&mut self) -> Result<Option<impl Buf>, Error> {
         self.inner.recv_data().await
     }
@@ -74,19 +81,49 @@ where
         self.inner.poll_recv_data(cx)
     }
 
+    /// Receive the next length-delimited message of the request body, as gRPC frames them
+    ///
+    /// Each message starts with a 1-byte compression flag and a 4-byte big-endian length, returned
+    /// along with the payload. A message may span several DATA frames. Resolves to `None` once the
+    /// body is over, the trailers can then be received. The body is read from the same place as
+    /// with [`RequestStream::recv_data()`], so the two are not to be mixed.
+    ///
+    /// This is cancel safe, like [`RequestStream::recv_data()`].
+    pub async fn recv_message(&mut self) -> Result<Option<Bytes>, Error> {
+        self.inner.recv_message().await
+    }
+
+    /// Poll for the next length-delimited message of the request body
+    pub fn poll_recv_message(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<Bytes>, Error>> {
+        self.inner.poll_recv_message(cx)
+    }
+
     /// Receive an optional set of trailers for the request
+    ///
+    /// This is cancel safe, like [`RequestStream::recv_data()`].
     pub async fn recv_trailers(&mut self) -> Result<Option<HeaderMap>, Error> {
         self.inner.recv_trailers().await
     }
 
+    /// Poll for an optional set of trailers for the request
+    pub fn poll_recv_trailers(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Error>> {
+        self.inner.poll_recv_trailers(cx)
+    }
+
     /// Tell the peer to stop sending into the underlying QUIC stream
     pub fn stop_sending(&mut self, error_code: crate::error::Code) {
-        self.inner.stream.stop_sending(error_code)
+        self.inner.stream_mut().stop_sending(error_code)
     }
 
     /// Returns the underlying stream id
     pub fn id(&self) -> StreamId {
-        self.inner.stream.id()
+        self.inner.stream().id()
     }
 }
 
@@ -100,6 +137,7 @@ where
     /// This should be called before trying to send any data with
     /// [`RequestStream::send_data`].
     pub async fn send_response(&mut self, resp: Response<()>) -> Result<(), Error> {
+        self.inner.check_not_finished()?;
         let (parts, _) = resp.into_parts();
         let response::Parts {
             status, headers, ..
@@ -125,7 +163,12 @@ where
             return Err(Error::header_too_big(mem_size, max_mem_size));
         }
 
-        stream::write(&mut self.inner.stream, Frame::Headers(block.freeze()))
+        let frame = Frame::Headers(block.freeze());
+        self.inner.check_send_headers(&frame, false)?;
+        if status.is_informational() {
+            self.inner.interim_sent();
+        }
+        stream::write(self.inner.stream_mut(), frame)
             .await
             .map_err(|e| self.maybe_conn_err(e))?;
 
@@ -137,6 +180,17 @@ where
         self.inner.send_data(buf).await
     }
 
+    /// Send a frame of extension type `frame_type`, followed by `payload`
+    ///
+    /// See [`ExtensionFrame::new()`](crate::codec::ExtensionFrame::new) for the types allowed.
+    pub async fn send_extension_frame(
+        &mut self,
+        frame_type: VarInt,
+        payload: Bytes,
+    ) -> Result<(), Error> {
+        self.inner.send_extension_frame(frame_type, payload).await
+    }
+
     /// Stop a stream with an error code
     ///
     /// The code can be [`Code::H3_NO_ERROR`].
@@ -158,10 +212,33 @@ where
     /// Either [`RequestStream::finish`] or
     /// [`RequestStream::send_trailers`] must be called to finalize a
     /// request.
+    ///
+    /// Only the sending side is closed, the request body can still be received afterwards. Once
+    /// finished, sending anything else fails. This is cancel-safe, calling it again after
+    /// the future was dropped resumes finishing the stream.
     pub async fn finish(&mut self) -> Result<(), Error> {
         self.inner.finish().await
     }
 
+    /// Sets the code the stream is reset with when dropped without being finished
+    ///
+    /// Defaults to [`Code::H3_REQUEST_CANCELLED`] for the send half of a [split] stream, and to
+    /// `None` otherwise, which leaves the stream as is.
+    ///
+    /// [split]: RequestStream::split
+    pub fn reset_on_drop(&mut self, code: Option<Code>) {
+        self.inner.reset_on_drop(code);
+    }
+
+    /// Limits how many DATA frames the request body may be made of
+    ///
+    /// A client sending more, however small, fails [`RequestStream::recv_data()`] with
+    /// `H3_EXCESSIVE_LOAD`, and is asked to stop sending. `None`, the default, allows any
+    /// number.
+    pub fn limit_data_frames(&mut self, max: Option<usize>) {
+        self.inner.limit_data_frames(max);
+    }
+
     //= https://www.rfc-editor.org/rfc/rfc9114#section-4.1.1
     //= type=TODO
     //# Implementations SHOULD cancel requests by abruptly terminating any
@@ -172,7 +249,7 @@ where
 
     /// Returns the underlying stream id
     pub fn send_id(&self) -> StreamId {
-        self.inner.stream.send_id()
+        self.inner.stream().send_id()
     }
 }
 
@@ -193,25 +270,119 @@ where
         (
             RequestStream {
                 inner: send,
-                request_end: self.request_end.clone(),
+                head: self.head,
             },
             RequestStream {
                 inner: recv,
-                request_end: self.request_end,
+                head: self.head,
             },
         )
     }
 }
 
-impl Drop for RequestEnd {
-    fn drop(&mut self) {
-        if let Err(e) = self.request_end.send(self.stream_id) {
-            error!(
-                "failed to notify connection of request end: {} {}",
-                self.stream_id, e
-            );
+/// The body of a request as a [`tokio::io::AsyncRead`], to pipe it to any reader
+///
+/// Only the payload of the DATA frames is read, the end of the body being signaled as usual by a
+/// read of 0 bytes. The trailers, if any, are then received from the stream returned by
+/// [`BodyRead::into_inner()`]. Errors are [`std::io::Error`]s wrapping the [`Error`].
+#[cfg(any(test, feature = "tokio"))]
+pub struct BodyRead<S, B> {
+    stream: RequestStream<S, B>,
+    // Payload received which did not fit in the last read
+    chunk: Bytes,
+}
+
+#[cfg(any(test, feature = "tokio"))]
+impl<S, B> BodyRead<S, B> {
+    /// Reads the body from `stream`, which must not have received any data yet
+    pub fn new(stream: RequestStream<S, B>) -> Self {
+        Self {
+            stream,
+            chunk: Bytes::new(),
         }
     }
+
+    /// Returns the underlying stream
+    ///
+    /// Payload received but not read yet is lost.
+    pub fn into_inner(self) -> RequestStream<S, B> {
+        self.stream
+    }
+}
+
+#[cfg(any(test, feature = "tokio"))]
+impl<S, B> Unpin for BodyRead<S, B> {}
+
+#[cfg(any(test, feature = "tokio"))]
+impl<S, B> tokio::io::AsyncRead for BodyRead<S, B>
+where
+    S: quic::RecvStream,
+    B: Buf,
+{
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        while this.chunk.is_empty() {
+            match ready!(this.stream.poll_recv_data(cx)) {
+                Ok(Some(mut data)) => this.chunk = data.copy_to_bytes(data.remaining()),
+                Ok(None) => return Poll::Ready(Ok(())),
+                Err(e) => {
+                    return Poll::Ready(Err(std::io::Error::new(std::io::ErrorKind::Other, e)))
+                }
+            }
+        }
+        let len = this.chunk.len().min(buf.remaining());
+        buf.put_slice(&this.chunk.split_to(len));
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Sends a response in order: HEADERS, then DATA, then optionally trailing HEADERS, then the end
+///
+/// Writing out of this order, such as DATA after the trailers, fails with `H3_INTERNAL_ERROR` at
+/// the stream level without sending anything, as the client would otherwise close the connection.
+/// [`RequestStream`] checks the same order, this only makes the steps explicit.
+pub struct ResponseWriter<S, B = Bytes> {
+    stream: RequestStream<S, B>,
+}
+
+impl<S, B> ResponseWriter<S, B>
+where
+    S: quic::SendStream<B>,
+    B: Buf,
+{
+    /// Writes the response to `stream`, which must not have sent anything yet
+    pub fn new(stream: RequestStream<S, B>) -> Self {
+        Self { stream }
+    }
+
+    /// Sends the response HEADERS, which may follow interim responses but not a final one
+    pub async fn send_headers(&mut self, resp: Response<()>) -> Result<(), Error> {
+        self.stream.send_response(resp).await
+    }
+
+    /// Sends a DATA frame of the body, which must follow the final response HEADERS
+    pub async fn send_data(&mut self, buf: B) -> Result<(), Error> {
+        self.stream.send_data(buf).await
+    }
+
+    /// Sends the trailing HEADERS, after which only [`ResponseWriter::finish()`] is allowed
+    pub async fn send_trailers(&mut self, trailers: HeaderMap) -> Result<(), Error> {
+        self.stream.send_trailers(trailers).await
+    }
+
+    /// Ends the response, once its HEADERS are sent
+    pub async fn finish(&mut self) -> Result<(), Error> {
+        self.stream.finish().await
+    }
+
+    /// Returns the underlying stream, to receive the rest of the request on
+    pub fn into_inner(self) -> RequestStream<S, B> {
+        self.stream
+    }
 }
 
 pin_project! {
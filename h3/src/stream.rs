@@ -1,21 +1,29 @@
 use std::{
+    io::IoSlice,
     marker::PhantomData,
     pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
     task::{Context, Poll},
 };
 
 use bytes::{Buf, BufMut, Bytes};
+use futures_channel::mpsc;
 use futures_util::{future, ready};
 use pin_project_lite::pin_project;
-use tokio::io::ReadBuf;
 
 use crate::{
     buf::BufList,
+    config::{UniStreamHandler, UniStreamHandlers},
     error::{Code, ErrorLevel},
+    ext::{UniStream, UniStreamData},
     frame::FrameStream,
     proto::{
         coding::{Decode as _, Encode},
         frame::{Frame, Settings},
+        push::PushId,
         stream::StreamType,
         varint::VarInt,
     },
@@ -26,16 +34,48 @@ use crate::{
 
 #[inline]
 /// Transmits data by encoding in wire format.
+///
+/// Once `send_data()` succeeded, the transport may have accepted only part of the frame when
+/// `poll_ready()` fails. The peer could not make sense of what follows, so the stream is reset
+/// in this case rather than being left usable. It is not when `poll_ready()` fails before the
+/// frame was handed over.
 pub(crate) async fn write<S, D, B>(stream: &mut S, data: D) -> Result<(), Error>
 where
     S: SendStream<B>,
     D: Into<WriteBuf<B>>,
     B: Buf,
 {
+    // Data handed over without waiting, such as request headers, may still be pending
+    future::poll_fn(|cx| poll_flush(stream, cx)).await?;
     stream.send_data(data)?;
-    future::poll_fn(|cx| stream.poll_ready(cx)).await?;
+    future::poll_fn(|cx| poll_flush_or_reset(stream, cx)).await
+}
 
-    Ok(())
+/// Polls for the data handed to `stream` to be sent
+pub(crate) fn poll_flush<S, B>(stream: &mut S, cx: &mut Context<'_>) -> Poll<Result<(), Error>>
+where
+    S: SendStream<B>,
+    B: Buf,
+{
+    stream.poll_ready(cx).map_err(|e| Error::from(e.into()))
+}
+
+/// Polls for a frame just handed to `stream` to be sent, resetting it on failure
+///
+/// The frame may have been partially written then, see [`write()`].
+pub(crate) fn poll_flush_or_reset<S, B>(
+    stream: &mut S,
+    cx: &mut Context<'_>,
+) -> Poll<Result<(), Error>>
+where
+    S: SendStream<B>,
+    B: Buf,
+{
+    let res = ready!(poll_flush(stream, cx));
+    if res.is_err() {
+        stream.reset(Code::H3_INTERNAL_ERROR.value());
+    }
+    Poll::Ready(res)
 }
 
 const WRITE_BUF_ENCODE_SIZE: usize = StreamType::MAX_ENCODED_SIZE + Frame::MAX_ENCODED_SIZE;
@@ -61,6 +101,17 @@ impl<B> WriteBuf<B>
 where
     B: Buf,
 {
+    /// Bytes already encoded, written as they are
+    pub(crate) fn encoded(buf: B) -> Self {
+        // Without its header, a DATA frame writes only its payload
+        Self {
+            buf: [0; WRITE_BUF_ENCODE_SIZE],
+            len: 0,
+            pos: 0,
+            frame: Some(Frame::Data(buf)),
+        }
+    }
+
     fn encode_stream_type(&mut self, ty: StreamType) {
         let mut buf_mut = &mut self.buf[self.len..];
 
@@ -242,23 +293,55 @@ where
             payload.advance(cnt);
         }
     }
+
+    fn chunks_vectored<'a>(&'a self, dst: &mut [IoSlice<'a>]) -> usize {
+        if dst.is_empty() {
+            return 0;
+        }
+
+        let mut vecs = 0;
+        if self.len - self.pos > 0 {
+            dst[0] = IoSlice::new(&self.buf[self.pos..self.len]);
+            vecs += 1;
+        }
+
+        if let Some(payload) = self.frame.as_ref().and_then(|f| f.payload()) {
+            vecs += payload.chunks_vectored(&mut dst[vecs..]);
+        }
+        vecs
+    }
 }
 
+/// An incoming unidirectional stream, once its type is known
 pub(super) enum AcceptedRecvStream<S, B>
 where
     S: quic::RecvStream,
     B: Buf,
 {
     Control(FrameStream<S, B>),
-    Push(FrameStream<S, B>),
+    Push(PushId, FrameStream<S, B>),
     Encoder(BufRecvStream<S, B>),
     Decoder(BufRecvStream<S, B>),
     WebTransportUni(SessionId, BufRecvStream<S, B>),
     Reserved,
+    Unknown(StreamType, BufRecvStream<S, B>),
+    Registered(UniStreamHandler, StreamType, BufRecvStream<S, B>),
 }
 
-/// Resolves an incoming streams type as well as `PUSH_ID`s and `SESSION_ID`s
-pub(super) struct AcceptRecvStream<S, B> {
+/// The unidirectional streams a peer may open only once per connection
+#[derive(Debug, Default)]
+pub(super) struct CriticalStreams {
+    control: bool,
+    encoder: bool,
+    decoder: bool,
+}
+
+/// Resolves an incoming streams type as well as `PUSH_ID`s and `SESSION_ID`s, then dispatches
+/// the stream to its handler
+///
+/// The type and id may arrive in any number of pieces, bytes received past them are kept for
+/// the handler.
+pub(super) struct UniStreamDispatcher<S, B> {
     stream: BufRecvStream<S, B>,
     ty: Option<StreamType>,
     /// push_id or session_id
@@ -266,7 +349,7 @@ pub(super) struct AcceptRecvStream<S, B> {
     expected: Option<usize>,
 }
 
-impl<S, B> AcceptRecvStream<S, B>
+impl<S, B> UniStreamDispatcher<S, B>
 where
     S: RecvStream,
     B: Buf,
@@ -280,10 +363,55 @@ where
         }
     }
 
-    pub fn into_stream(self) -> Result<AcceptedRecvStream<S, B>, Error> {
-        Ok(match self.ty.expect("Stream type not resolved yet") {
-            StreamType::CONTROL => AcceptedRecvStream::Control(FrameStream::new(self.stream)),
-            StreamType::PUSH => AcceptedRecvStream::Push(FrameStream::new(self.stream)),
+    /// Turns the stream into the handler for its type, once [`Self::poll_type()`] resolved it
+    ///
+    /// `critical` tracks the streams of the connection which must be unique, a second one fails
+    /// with `H3_STREAM_CREATION_ERROR`.
+    pub fn dispatch(
+        self,
+        critical: &mut CriticalStreams,
+        handlers: &UniStreamHandlers,
+    ) -> Result<AcceptedRecvStream<S, B>, Error> {
+        let ty = self.ty.expect("Stream type not resolved yet");
+
+        //= https://www.rfc-editor.org/rfc/rfc9114#section-6.2.1
+        //# Only one control stream per peer is permitted;
+        //# receipt of a second stream claiming to be a control stream MUST be
+        //# treated as a connection error of type H3_STREAM_CREATION_ERROR.
+
+        // QPACK allows a single encoder and decoder stream as well, see RFC 9204, Section 4.2
+        let seen = match ty {
+            StreamType::CONTROL => Some((&mut critical.control, "got two control streams")),
+            StreamType::ENCODER => Some((&mut critical.encoder, "got two encoder streams")),
+            StreamType::DECODER => Some((&mut critical.decoder, "got two decoder streams")),
+            _ => None,
+        };
+        if let Some((seen, reason)) = seen {
+            if std::mem::replace(seen, true) {
+                return Err(
+                    Code::H3_STREAM_CREATION_ERROR.with_reason(reason, ErrorLevel::ConnectionError)
+                );
+            }
+        }
+
+        if let Some(handler) = handlers.get(&ty.value()) {
+            return Ok(AcceptedRecvStream::Registered(
+                handler.clone(),
+                ty,
+                self.stream,
+            ));
+        }
+
+        Ok(match ty {
+            StreamType::CONTROL => {
+                let mut stream = FrameStream::new(self.stream);
+                stream.accept_origin();
+                AcceptedRecvStream::Control(stream)
+            }
+            StreamType::PUSH => AcceptedRecvStream::Push(
+                self.id.expect("Push ID not resolved yet").into(),
+                FrameStream::new(self.stream),
+            ),
             StreamType::ENCODER => AcceptedRecvStream::Encoder(self.stream),
             StreamType::DECODER => AcceptedRecvStream::Decoder(self.stream),
             StreamType::WEBTRANSPORT_UNI => AcceptedRecvStream::WebTransportUni(
@@ -291,34 +419,22 @@ where
                 self.stream,
             ),
             t if t.value() > 0x21 && (t.value() - 0x21) % 0x1f == 0 => AcceptedRecvStream::Reserved,
-
-            //= https://www.rfc-editor.org/rfc/rfc9114#section-6.2
-            //# Recipients of unknown stream types MUST
-            //# either abort reading of the stream or discard incoming data without
-            //# further processing.
-
-            //= https://www.rfc-editor.org/rfc/rfc9114#section-6.2
-            //# If reading is aborted, the recipient SHOULD use
-            //# the H3_STREAM_CREATION_ERROR error code or a reserved error code
-            //# (Section 8.1).
-
-            //= https://www.rfc-editor.org/rfc/rfc9114#section-6.2
-            //= type=implication
-            //# The recipient MUST NOT consider unknown stream types
-            //# to be a connection error of any kind.
-            t => {
-                return Err(Code::H3_STREAM_CREATION_ERROR.with_reason(
-                    format!("unknown stream type 0x{:x}", t.value()),
-                    crate::error::ErrorLevel::ConnectionError,
-                ))
-            }
+            t => AcceptedRecvStream::Unknown(t, self.stream),
         })
     }
 
-    pub fn poll_type(&mut self, cx: &mut Context) -> Poll<Result<(), Error>> {
+    /// Receives the stream type, and the id following it for the types having one
+    ///
+    /// Types registered in `handlers` have no id, whatever this crate makes of them otherwise.
+    pub fn poll_type(
+        &mut self,
+        cx: &mut Context,
+        handlers: &UniStreamHandlers,
+    ) -> Poll<Result<(), Error>> {
         loop {
             // Return if all identification data is met
-            match self.ty {
+            match &self.ty {
+                Some(ty) if handlers.contains_key(&ty.value()) => return Poll::Ready(Ok(())),
                 Some(StreamType::PUSH | StreamType::WEBTRANSPORT_UNI) => {
                     if self.id.is_some() {
                         return Poll::Ready(Ok(()));
@@ -328,27 +444,27 @@ where
                 None => (),
             };
 
-            if ready!(self.stream.poll_read(cx))? {
-                return Poll::Ready(Err(Code::H3_STREAM_CREATION_ERROR.with_reason(
-                    "Stream closed before type received",
-                    ErrorLevel::ConnectionError,
-                )));
-            };
-
-            let mut buf = self.stream.buf_mut();
+            let buf = self.stream.buf_mut();
             if self.expected.is_none() && buf.remaining() >= 1 {
                 self.expected = Some(VarInt::encoded_size(buf.chunk()[0]));
             }
 
-            if let Some(expected) = self.expected {
-                // Poll for more data
-                if buf.remaining() < expected {
+            // Only read from the stream while the next varint is not entirely buffered, it may
+            // have been received along with the previous one
+            match self.expected {
+                Some(expected) if buf.remaining() >= expected => (),
+                _ => {
+                    if ready!(self.stream.poll_read(cx))? {
+                        return Poll::Ready(Err(Code::H3_STREAM_CREATION_ERROR.with_reason(
+                            "Stream closed before type received",
+                            ErrorLevel::ConnectionError,
+                        )));
+                    };
                     continue;
                 }
-            } else {
-                continue;
             }
 
+            let mut buf = self.stream.buf_mut();
             // Parse ty and then id
             if self.ty.is_none() {
                 // Parse StreamType
@@ -373,6 +489,59 @@ where
     }
 }
 
+/// Hands the data of a stream of a registered type over to its [`UniStream`]
+pub(super) struct UniStreamForwarder<S, B> {
+    stream: BufRecvStream<S, B>,
+    data: mpsc::Sender<UniStreamData>,
+    stop_code: Arc<AtomicU64>,
+}
+
+impl<S, B> UniStreamForwarder<S, B>
+where
+    S: RecvStream,
+{
+    /// Chunks received ahead of the reads of the application
+    const BUFFERED_CHUNKS: usize = 4;
+
+    pub fn new(ty: StreamType, stream: BufRecvStream<S, B>) -> (Self, UniStream) {
+        let (sender, receiver) = mpsc::channel(Self::BUFFERED_CHUNKS);
+        let stop_code = Arc::new(AtomicU64::new(Code::H3_NO_ERROR.value()));
+        let handed = UniStream::new(stream.recv_id(), ty.value(), receiver, stop_code.clone());
+        let forwarder = Self {
+            stream,
+            data: sender,
+            stop_code,
+        };
+        (forwarder, handed)
+    }
+
+    /// Passes the received data along, resolving once the stream ends or is stopped
+    pub fn poll_forward(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        loop {
+            if ready!(self.data.poll_ready(cx)).is_err() {
+                // The application dropped the stream or stopped it
+                self.stream
+                    .stop_sending(self.stop_code.load(Ordering::Acquire));
+                return Poll::Ready(());
+            }
+
+            if let Some(chunk) = self.stream.take_chunk(usize::MAX) {
+                let _ = self.data.start_send(Ok(chunk));
+                continue;
+            }
+            if self.stream.is_eos() {
+                // Dropping the sender ends the stream for the application
+                return Poll::Ready(());
+            }
+
+            if let Err(e) = ready!(self.stream.poll_read(cx)) {
+                let _ = self.data.start_send(Err(e.into()));
+                return Poll::Ready(());
+            }
+        }
+    }
+}
+
 pin_project! {
     /// A stream which allows partial reading of the data without data loss.
     ///
@@ -457,6 +626,16 @@ impl<B, S: RecvStream> BufRecvStream<S, B> {
     pub fn is_eos(&self) -> bool {
         self.eos
     }
+
+    /// Stops the stream with `error_code`, dropping the buffered data
+    ///
+    /// The stream then reads as if it ended.
+    pub(crate) fn abort(&mut self, error_code: u64) {
+        self.stream.stop_sending(error_code);
+        let len = self.buf.remaining();
+        self.buf.advance(len);
+        self.eos = true;
+    }
 }
 
 impl<S: RecvStream, B> RecvStream for BufRecvStream<S, B> {
@@ -598,6 +777,7 @@ where
     }
 }
 
+#[cfg(feature = "tokio")]
 impl<S, B> tokio::io::AsyncRead for BufRecvStream<S, B>
 where
     B: Buf,
@@ -607,7 +787,7 @@ where
     fn poll_read(
         mut self: Pin<&mut Self>,
         cx: &mut Context<'_>,
-        buf: &mut ReadBuf<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
     ) -> Poll<futures_util::io::Result<()>> {
         let p = &mut *self;
         // Poll for data if the buffer is empty
@@ -658,6 +838,7 @@ where
     }
 }
 
+#[cfg(feature = "tokio")]
 impl<S, B> tokio::io::AsyncWrite for BufRecvStream<S, B>
 where
     B: Buf,
@@ -685,6 +866,8 @@ where
 
 #[cfg(test)]
 mod tests {
+    use assert_matches::assert_matches;
+    use futures_util::FutureExt;
     use quinn_proto::coding::BufExt;
 
     use super::*;
@@ -749,4 +932,316 @@ mod tests {
         wbuf.advance(4);
         assert_eq!(wbuf.chunk(), b"ey");
     }
+
+    #[test]
+    fn write_buf_chunks_vectored() {
+        let mut wbuf =
+            WriteBuf::<Bytes>::from((StreamType::ENCODER, Frame::Data(Bytes::from("hey"))));
+
+        let mut dst = [IoSlice::new(&[]); 4];
+        assert_eq!(wbuf.chunks_vectored(&mut dst), 2);
+        assert_eq!(&*dst[0], b"\x02\x00\x03");
+        assert_eq!(&*dst[1], b"hey");
+
+        wbuf.advance(4);
+        let mut dst = [IoSlice::new(&[]); 4];
+        assert_eq!(wbuf.chunks_vectored(&mut dst), 1);
+        assert_eq!(&*dst[0], b"ey");
+    }
+
+    /// Send stream recording the slices it was offered by each `send_data()` call
+    #[derive(Default)]
+    struct VectoredSendStream {
+        writes: Vec<Vec<(*const u8, Vec<u8>)>>,
+    }
+
+    impl SendStream<Bytes> for VectoredSendStream {
+        type Error = FakeError;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn send_data<T: Into<WriteBuf<Bytes>>>(&mut self, data: T) -> Result<(), Self::Error> {
+            let data = data.into();
+            let mut dst = [IoSlice::new(&[]); 8];
+            let n = data.chunks_vectored(&mut dst);
+            self.writes
+                .push(dst[..n].iter().map(|s| (s.as_ptr(), s.to_vec())).collect());
+            Ok(())
+        }
+
+        fn poll_finish(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn reset(&mut self, _: u64) {}
+
+        fn send_id(&self) -> quic::StreamId {
+            quic::StreamId::try_from(0).unwrap()
+        }
+    }
+
+    #[derive(Debug)]
+    struct FakeError;
+
+    impl quic::Error for FakeError {
+        fn is_timeout(&self) -> bool {
+            false
+        }
+
+        fn err_code(&self) -> Option<u64> {
+            None
+        }
+    }
+
+    impl std::error::Error for FakeError {}
+    impl std::fmt::Display for FakeError {
+        fn fmt(&self, _: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            unimplemented!()
+        }
+    }
+
+    #[tokio::test]
+    async fn write_offers_header_and_payload_in_one_call() {
+        let payload = Bytes::from(vec![0xaa; 1024]);
+        let payload_ptr = payload.as_ptr();
+
+        let mut stream = VectoredSendStream::default();
+        write(&mut stream, Frame::Data(payload)).await.unwrap();
+
+        assert_eq!(stream.writes.len(), 1);
+        let slices = &stream.writes[0];
+        assert_eq!(slices.len(), 2);
+        assert_eq!(slices[0].1, b"\x00\x44\x00");
+        // payload is handed over as-is, without being copied next to the header
+        assert_eq!(slices[1].0, payload_ptr);
+        assert_eq!(slices[1].1.len(), 1024);
+    }
+
+    /// Send stream accepting data, then failing to transmit it, or failing from the start if broken
+    #[derive(Default)]
+    struct FailingSendStream {
+        broken: bool,
+        sent: bool,
+        reset: Option<u64>,
+    }
+
+    impl SendStream<Bytes> for FailingSendStream {
+        type Error = FakeError;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            if self.broken || self.sent {
+                Poll::Ready(Err(FakeError))
+            } else {
+                Poll::Ready(Ok(()))
+            }
+        }
+
+        fn send_data<T: Into<WriteBuf<Bytes>>>(&mut self, _: T) -> Result<(), Self::Error> {
+            self.sent = true;
+            Ok(())
+        }
+
+        fn poll_finish(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn reset(&mut self, reset_code: u64) {
+            self.reset = Some(reset_code);
+        }
+
+        fn send_id(&self) -> quic::StreamId {
+            quic::StreamId::try_from(0).unwrap()
+        }
+    }
+
+    #[tokio::test]
+    async fn write_resets_stream_when_not_ready_after_send() {
+        let mut stream = FailingSendStream::default();
+        let res = write(&mut stream, Frame::Data(Bytes::from("body"))).await;
+
+        assert!(res.is_err());
+        assert_eq!(stream.reset, Some(Code::H3_INTERNAL_ERROR.value()));
+    }
+
+    #[tokio::test]
+    async fn write_keeps_stream_when_not_ready_before_send() {
+        let mut stream = FailingSendStream {
+            broken: true,
+            ..Default::default()
+        };
+        let res = write(&mut stream, Frame::Data(Bytes::from("body"))).await;
+
+        assert!(res.is_err());
+        assert!(!stream.sent);
+        assert_eq!(stream.reset, None);
+    }
+
+    /// Receives `chunks`, then ends
+    struct ChunkedRecvStream(std::collections::VecDeque<Bytes>);
+
+    impl RecvStream for ChunkedRecvStream {
+        type Buf = Bytes;
+        type Error = FakeError;
+
+        fn poll_data(
+            &mut self,
+            _: &mut Context<'_>,
+        ) -> Poll<Result<Option<Self::Buf>, Self::Error>> {
+            Poll::Ready(Ok(self.0.pop_front()))
+        }
+
+        fn stop_sending(&mut self, _: u64) {}
+
+        fn recv_id(&self) -> quic::StreamId {
+            quic::StreamId::try_from(3).unwrap()
+        }
+    }
+
+    /// Dispatches a stream receiving `data` split at every possible point
+    async fn dispatch_split(
+        data: &[u8],
+        handlers: &UniStreamHandlers,
+        mut check: impl FnMut(AcceptedRecvStream<ChunkedRecvStream, Bytes>),
+    ) {
+        for at in 0..=data.len() {
+            let (head, tail) = data.split_at(at);
+            let chunks = [head, tail]
+                .iter()
+                .filter(|c| !c.is_empty())
+                .map(|c| Bytes::copy_from_slice(c))
+                .collect();
+            let mut dispatcher = UniStreamDispatcher::<_, Bytes>::new(ChunkedRecvStream(chunks));
+            future::poll_fn(|cx| dispatcher.poll_type(cx, handlers))
+                .await
+                .unwrap_or_else(|e| panic!("split at {}: {}", at, e));
+            check(
+                dispatcher
+                    .dispatch(&mut CriticalStreams::default(), handlers)
+                    .unwrap(),
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn dispatch_control_stream() {
+        let mut data = vec![0x00];
+        let mut settings = Settings::default();
+        settings
+            .insert(crate::proto::frame::SettingId::MAX_HEADER_LIST_SIZE, 4096)
+            .unwrap();
+        Frame::<Bytes>::Settings(settings).encode(&mut data);
+
+        dispatch_split(&data, &UniStreamHandlers::new(), |stream| match stream {
+            AcceptedRecvStream::Control(mut s) => {
+                let frame = future::poll_fn(|cx| s.poll_next(cx)).now_or_never();
+                assert_matches!(frame, Some(Ok(Some(Frame::Settings(_)))));
+            }
+            _ => panic!("not dispatched as a control stream"),
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn dispatch_push_stream() {
+        // Push id 37 on two bytes, then a HEADERS frame
+        let mut data = vec![0x01, 0x40, 0x25];
+        Frame::headers("pushed").encode(&mut data);
+        data.extend_from_slice(b"pushed");
+
+        dispatch_split(&data, &UniStreamHandlers::new(), |stream| match stream {
+            AcceptedRecvStream::Push(id, mut s) => {
+                assert_eq!(id, PushId(37));
+                let frame = future::poll_fn(|cx| s.poll_next(cx)).now_or_never();
+                assert_matches!(frame, Some(Ok(Some(Frame::Headers(_)))));
+            }
+            _ => panic!("not dispatched as a push stream"),
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn dispatch_rejects_second_critical_stream() {
+        let mut critical = CriticalStreams::default();
+        for (ty, second) in [
+            (0x00, false),
+            (0x02, false),
+            (0x00, true),
+            (0x03, false),
+            (0x02, true),
+        ] {
+            let chunks = [Bytes::copy_from_slice(&[ty])].into_iter().collect();
+            let mut dispatcher = UniStreamDispatcher::<_, Bytes>::new(ChunkedRecvStream(chunks));
+            future::poll_fn(|cx| dispatcher.poll_type(cx, &UniStreamHandlers::new()))
+                .await
+                .unwrap();
+
+            match dispatcher.dispatch(&mut critical, &UniStreamHandlers::new()) {
+                Err(e) if second => assert_matches!(
+                    e.kind(),
+                    crate::error::Kind::Application {
+                        code: Code::H3_STREAM_CREATION_ERROR,
+                        level: ErrorLevel::ConnectionError,
+                        ..
+                    }
+                ),
+                Ok(_) if !second => (),
+                _ => panic!("stream type {:#x} dispatched wrongly", ty),
+            }
+        }
+    }
+
+    //= https://www.rfc-editor.org/rfc/rfc9114#section-6.2
+    //= type=test
+    //# The recipient MUST NOT consider unknown stream types
+    //# to be a connection error of any kind.
+    #[tokio::test]
+    async fn dispatch_unknown_stream_type() {
+        // Type 0x1234567 on four bytes, then data which has no meaning to this crate
+        let data = [0x81, 0x23, 0x45, 0x67, 0x06, 0x08];
+        dispatch_split(&data, &UniStreamHandlers::new(), |stream| match stream {
+            // Left to the connection to stop, with the code of its configuration
+            AcceptedRecvStream::Unknown(ty, _) => assert_eq!(ty.value(), 0x1234567),
+            _ => panic!("not dispatched as an unknown stream"),
+        })
+        .await;
+
+        // Reserved types are ignored without being stopped
+        let data = [0x40, 0x21 + 0x1f, 0x06];
+        dispatch_split(&data, &UniStreamHandlers::new(), |stream| {
+            assert!(matches!(stream, AcceptedRecvStream::Reserved))
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn dispatch_registered_stream() {
+        let mut handlers = UniStreamHandlers::new();
+        handlers.insert(StreamType::WEBTRANSPORT_UNI.value(), Arc::new(|_, _| {}));
+
+        // No session id is expected after the type
+        let data = [0x40, 0x54, 0x06, 0x08];
+        dispatch_split(&data, &handlers, |stream| match stream {
+            AcceptedRecvStream::Registered(_, ty, s) => {
+                assert_eq!(ty, StreamType::WEBTRANSPORT_UNI);
+                let (mut forwarder, mut stream) = UniStreamForwarder::new(ty, s);
+                assert_eq!(
+                    future::poll_fn(|cx| forwarder.poll_forward(cx)).now_or_never(),
+                    Some(())
+                );
+                drop(forwarder);
+
+                let mut received = Vec::new();
+                while let Some(Ok(Some(chunk))) =
+                    future::poll_fn(|cx| stream.poll_data(cx)).now_or_never()
+                {
+                    received.extend_from_slice(&chunk);
+                }
+                assert_eq!(received, [0x06, 0x08]);
+            }
+            _ => panic!("not dispatched to the handler"),
+        })
+        .await;
+    }
 }
@@ -0,0 +1,846 @@
+//! In-memory QUIC transport for tests
+//!
+//! [`pair()`] returns both ends of a connection whose streams are carried by in-memory buffers,
+//! so clients, servers and request handlers can be exercised without sockets nor TLS:
+//!
+//! ```rust
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! let (client, server) = h3::test::pair();
+//!
+//! let client = async {
+//!     let (mut driver, mut send_request) = h3::client::new(client).await.unwrap();
+//!     let request = async {
+//!         let req = http::Request::get("https://example.com/").body(()).unwrap();
+//!         let mut stream = send_request.send_request(req).await.unwrap();
+//!         stream.finish().await.unwrap();
+//!         stream.recv_response().await.unwrap()
+//!     };
+//!     tokio::select! {
+//!         resp = request => resp,
+//!         _ = futures::future::poll_fn(|cx| driver.poll_close(cx)) => unreachable!(),
+//!     }
+//! };
+//!
+//! let server = async {
+//!     let mut conn = h3::server::Connection::new(server).await.unwrap();
+//!     let (_req, mut stream) = conn.accept().await.unwrap().unwrap();
+//!     let resp = http::Response::builder().status(204).body(()).unwrap();
+//!     stream.send_response(resp).await.unwrap();
+//!     stream.finish().await.unwrap();
+//!     conn
+//! };
+//!
+//! let (resp, _conn) = tokio::join!(client, server);
+//! assert_eq!(resp.status(), 204);
+//! # }
+//! ```
+//!
+//! The data sent by each end goes through a [`Link`], whose conditions can be changed at any
+//! time from a [`Handle`] to the end, for instance to deliver the data in small pieces, or to
+//! cut a direction altogether. A [`Handle`] also injects stream resets.
+//!
+//! All streams carry [`Bytes`]. Flow control is not simulated: sending never waits for the peer
+//! to read. Only the number of bidirectional streams open at once can be limited, see
+//! [`Handle::set_max_bidi_streams()`].
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+use bytes::{Buf, Bytes};
+use futures_util::ready;
+
+use crate::{
+    error::Code,
+    quic::{self, StreamId, WriteBuf},
+    rt::{Sleep, Timer, TokioTimer},
+};
+
+const CLIENT: usize = 0;
+const SERVER: usize = 1;
+
+/// Returns the client and server ends of a new in-memory connection
+pub fn pair() -> (Connection, Connection) {
+    let shared = Arc::new(Mutex::new(Shared::default()));
+    (
+        Connection::new(shared.clone(), CLIENT),
+        Connection::new(shared, SERVER),
+    )
+}
+
+/// Returns the ends of a new in-memory connection, erased behind [`quic::BoxedConnection`]
+pub fn boxed_pair() -> (quic::BoxedConnection, quic::BoxedConnection) {
+    let (client, server) = pair();
+    (
+        quic::BoxedConnection::new(client),
+        quic::BoxedConnection::new(server),
+    )
+}
+
+/// Conditions applied to the data sent by one end of a connection
+///
+/// The default link delivers everything as sent, immediately.
+#[derive(Clone, Debug, Default)]
+pub struct Link {
+    chunk_size: Option<usize>,
+    pending: bool,
+    delay: Option<Duration>,
+    discard: bool,
+}
+
+impl Link {
+    /// Delivers the data in pieces of at most `size` bytes
+    ///
+    /// # Panics
+    ///
+    /// If `size` is zero.
+    pub fn chunk_size(mut self, size: usize) -> Self {
+        assert!(size > 0, "chunk size must not be zero");
+        self.chunk_size = Some(size);
+        self
+    }
+
+    /// Makes the receiver's poll return `Pending` once before each piece of data
+    pub fn pending(mut self, pending: bool) -> Self {
+        self.pending = pending;
+        self
+    }
+
+    /// Delivers each piece of data `delay` after it was sent
+    ///
+    /// The delay is measured with [`TokioTimer`], so a paused clock makes it instant.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    /// Silently discards the data, the stream ends and the resets sent, as if the direction
+    /// was cut
+    ///
+    /// New streams are still announced to the peer, and closing the connection still works.
+    pub fn discard(mut self, discard: bool) -> Self {
+        self.discard = discard;
+        self
+    }
+}
+
+/// An error of the in-memory transport
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The peer closed the connection
+    ConnectionClosed {
+        /// The code the connection was closed with
+        code: u64,
+        /// The reason the connection was closed with
+        reason: Bytes,
+    },
+    /// This end closed the connection
+    LocallyClosed,
+    /// The peer reset the stream
+    Reset(u64),
+    /// The peer asked to stop sending on the stream
+    Stopped(u64),
+    /// The stream was already finished or reset by this end
+    Finished,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ConnectionClosed { code, .. } => {
+                write!(f, "connection closed by peer with code {:#x}", code)
+            }
+            Error::LocallyClosed => write!(f, "connection closed locally"),
+            Error::Reset(code) => write!(f, "stream reset with code {:#x}", code),
+            Error::Stopped(code) => write!(f, "stream stopped with code {:#x}", code),
+            Error::Finished => write!(f, "stream already finished"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl quic::Error for Error {
+    fn is_timeout(&self) -> bool {
+        false
+    }
+
+    fn err_code(&self) -> Option<u64> {
+        match self {
+            Error::ConnectionClosed { code, .. } | Error::Reset(code) | Error::Stopped(code) => {
+                Some(*code)
+            }
+            Error::LocallyClosed | Error::Finished => None,
+        }
+    }
+
+    fn is_stream_reset(&self) -> bool {
+        matches!(self, Error::Reset(_))
+    }
+}
+
+/// State of both ends of a connection
+#[derive(Default)]
+struct Shared {
+    ends: [End; 2],
+    /// Keyed by stream id and sending end
+    pipes: HashMap<(u64, usize), Pipe>,
+    /// The end which closed the connection, with the code and reason
+    closed: Option<(usize, u64, Bytes)>,
+}
+
+#[derive(Default)]
+struct End {
+    link: Link,
+    /// Number of live `Connection` and `OpenStreams`, the connection is closed when it drops to 0
+    handles: usize,
+    next_bidi: u64,
+    next_uni: u64,
+    incoming_bidi: VecDeque<u64>,
+    incoming_uni: VecDeque<u64>,
+    accept_bidi: Option<Waker>,
+    accept_uni: Option<Waker>,
+    /// Bidirectional streams this end may have open at once, unlimited if `None`
+    max_bidi: Option<u64>,
+    /// Bidirectional streams opened by this end and not gone from both ends yet
+    open_bidi: u64,
+    /// Waiting for the number of open bidirectional streams to drop below `max_bidi`
+    open_bidi_wakers: Vec<Waker>,
+}
+
+/// One direction of a stream
+struct Pipe {
+    chunks: VecDeque<(Bytes, Option<Instant>)>,
+    finished: bool,
+    reset: Option<u64>,
+    stopped: Option<u64>,
+    waker: Option<Waker>,
+    send_open: bool,
+    recv_open: bool,
+}
+
+impl Default for Pipe {
+    fn default() -> Self {
+        Self {
+            chunks: VecDeque::new(),
+            finished: false,
+            reset: None,
+            stopped: None,
+            waker: None,
+            send_open: true,
+            recv_open: true,
+        }
+    }
+}
+
+impl Shared {
+    fn check_open(&self, side: usize) -> Result<(), Error> {
+        match &self.closed {
+            None => Ok(()),
+            Some((closer, _, _)) if *closer == side => Err(Error::LocallyClosed),
+            Some((_, code, reason)) => Err(Error::ConnectionClosed {
+                code: *code,
+                reason: reason.clone(),
+            }),
+        }
+    }
+
+    fn close(&mut self, side: usize, code: u64, reason: Bytes) {
+        if self.closed.is_some() {
+            return;
+        }
+        self.closed = Some((side, code, reason));
+        for end in &mut self.ends {
+            wake(&mut end.accept_bidi);
+            wake(&mut end.accept_uni);
+            end.open_bidi_wakers.drain(..).for_each(Waker::wake);
+        }
+        self.pipes
+            .values_mut()
+            .for_each(|pipe| wake(&mut pipe.waker));
+    }
+
+    /// Resolves once `side` may open a bidirectional stream
+    fn poll_bidi_credit(&mut self, cx: &mut Context<'_>, side: usize) -> Poll<Result<(), Error>> {
+        self.check_open(side)?;
+        let end = &mut self.ends[side];
+        if end.max_bidi.map_or(true, |max| end.open_bidi < max) {
+            return Poll::Ready(Ok(()));
+        }
+        end.open_bidi_wakers.push(cx.waker().clone());
+        Poll::Pending
+    }
+
+    /// Registers a new stream opened by `side`, returns its id
+    fn open(&mut self, side: usize, bidi: bool) -> Result<u64, Error> {
+        self.check_open(side)?;
+        let peer = side ^ 1;
+        let end = &mut self.ends[side];
+        let id = if bidi {
+            end.open_bidi += 1;
+            end.next_bidi += 1;
+            (end.next_bidi - 1) << 2 | side as u64
+        } else {
+            end.next_uni += 1;
+            (end.next_uni - 1) << 2 | 0x2 | side as u64
+        };
+
+        self.pipes.insert((id, side), Pipe::default());
+        let peer_end = &mut self.ends[peer];
+        if bidi {
+            self.pipes.insert((id, peer), Pipe::default());
+            peer_end.incoming_bidi.push_back(id);
+            wake(&mut peer_end.accept_bidi);
+        } else {
+            peer_end.incoming_uni.push_back(id);
+            wake(&mut peer_end.accept_uni);
+        }
+        Ok(id)
+    }
+
+    fn remove_if_unused(&mut self, key: (u64, usize)) {
+        if let Some(pipe) = self.pipes.get(&key) {
+            if !pipe.send_open && !pipe.recv_open {
+                self.pipes.remove(&key);
+                let (id, side) = key;
+                // A bidirectional stream stops counting once both directions are gone
+                if id & 0x2 == 0 && !self.pipes.contains_key(&(id, side ^ 1)) {
+                    let opener = &mut self.ends[(id & 0x1) as usize];
+                    opener.open_bidi -= 1;
+                    opener.open_bidi_wakers.drain(..).for_each(Waker::wake);
+                }
+            }
+        }
+    }
+}
+
+fn lock(shared: &Mutex<Shared>) -> MutexGuard<'_, Shared> {
+    // Do not poison every stream of the connection when a test fails
+    shared.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn wake(waker: &mut Option<Waker>) {
+    if let Some(waker) = waker.take() {
+        waker.wake();
+    }
+}
+
+fn stream_id(id: u64) -> StreamId {
+    StreamId::try_from(id).expect("stream id out of range")
+}
+
+/// A handle to one end of a connection, for changing its conditions
+///
+/// It does not keep the connection open.
+#[derive(Clone)]
+pub struct Handle {
+    shared: Arc<Mutex<Shared>>,
+    side: usize,
+}
+
+impl Handle {
+    /// Sets the conditions applied to the data this end sends from now on
+    ///
+    /// Data already sent is not affected.
+    pub fn set_link(&self, link: Link) {
+        lock(&self.shared).ends[self.side].link = link;
+    }
+
+    /// Resets the receiving side of stream `id` at this end, as if the peer sent a
+    /// `RESET_STREAM` with `code`
+    ///
+    /// Data not yet read is discarded. Does nothing if the stream is gone.
+    pub fn reset(&self, id: StreamId, code: u64) {
+        let mut shared = lock(&self.shared);
+        if let Some(pipe) = shared.pipes.get_mut(&(id.into_inner(), self.side ^ 1)) {
+            pipe.chunks.clear();
+            pipe.reset.get_or_insert(code);
+            wake(&mut pipe.waker);
+        }
+    }
+
+    /// Limits the bidirectional streams this end may have open at once, as the peer would with
+    /// `MAX_STREAMS` frames
+    ///
+    /// A stream counts until both its directions are finished or reset, and read or dropped, at
+    /// both ends. Opening one past the limit waits for another to be over. `None`, the default,
+    /// lifts the limit.
+    pub fn set_max_bidi_streams(&self, max: Option<u64>) {
+        let mut shared = lock(&self.shared);
+        let end = &mut shared.ends[self.side];
+        end.max_bidi = max;
+        end.open_bidi_wakers.drain(..).for_each(Waker::wake);
+    }
+
+    /// Stops the sending side of stream `id` at this end, as if the peer sent a
+    /// `STOP_SENDING` with `code`
+    ///
+    /// Does nothing if the stream is gone.
+    pub fn stop_sending(&self, id: StreamId, code: u64) {
+        let mut shared = lock(&self.shared);
+        if let Some(pipe) = shared.pipes.get_mut(&(id.into_inner(), self.side)) {
+            pipe.stopped.get_or_insert(code);
+        }
+    }
+}
+
+/// Keeps an end of the connection open while alive
+struct EndRef {
+    shared: Arc<Mutex<Shared>>,
+    side: usize,
+}
+
+impl EndRef {
+    fn new(shared: Arc<Mutex<Shared>>, side: usize) -> Self {
+        lock(&shared).ends[side].handles += 1;
+        Self { shared, side }
+    }
+}
+
+impl Clone for EndRef {
+    fn clone(&self) -> Self {
+        Self::new(self.shared.clone(), self.side)
+    }
+}
+
+impl Drop for EndRef {
+    fn drop(&mut self) {
+        let mut shared = lock(&self.shared);
+        shared.ends[self.side].handles -= 1;
+        if shared.ends[self.side].handles == 0 {
+            shared.close(self.side, 0, Bytes::new());
+        }
+    }
+}
+
+/// One end of an in-memory connection
+///
+/// Like a QUIC connection, it is closed with code 0 once it and all its [`OpenStreams`] are
+/// dropped.
+pub struct Connection {
+    end: EndRef,
+}
+
+impl Connection {
+    fn new(shared: Arc<Mutex<Shared>>, side: usize) -> Self {
+        Self {
+            end: EndRef::new(shared, side),
+        }
+    }
+
+    /// Returns a handle to change the conditions of this end
+    pub fn handle(&self) -> Handle {
+        Handle {
+            shared: self.end.shared.clone(),
+            side: self.end.side,
+        }
+    }
+
+    fn poll_accept(
+        &mut self,
+        cx: &mut Context<'_>,
+        bidi: bool,
+    ) -> Poll<Result<Option<u64>, Error>> {
+        let mut shared = lock(&self.end.shared);
+        let closed = shared.check_open(self.end.side);
+        let end = &mut shared.ends[self.end.side];
+        let (incoming, waker) = if bidi {
+            (&mut end.incoming_bidi, &mut end.accept_bidi)
+        } else {
+            (&mut end.incoming_uni, &mut end.accept_uni)
+        };
+        match incoming.pop_front() {
+            // Like data, streams opened before the connection was closed can still be accepted
+            Some(id) => Poll::Ready(Ok(Some(id))),
+            None => {
+                closed?;
+                *waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl quic::Connection<Bytes> for Connection {
+    type BidiStream = BidiStream;
+    type SendStream = SendStream;
+    type RecvStream = RecvStream;
+    type OpenStreams = OpenStreams;
+    type Error = Error;
+
+    fn poll_accept_recv(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<Self::RecvStream>, Self::Error>> {
+        let id = ready!(self.poll_accept(cx, false))?;
+        let peer = self.end.side ^ 1;
+        Poll::Ready(Ok(
+            id.map(|id| RecvStream::new(self.end.shared.clone(), id, peer))
+        ))
+    }
+
+    fn poll_accept_bidi(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<Self::BidiStream>, Self::Error>> {
+        let id = ready!(self.poll_accept(cx, true))?;
+        Poll::Ready(Ok(
+            id.map(|id| BidiStream::new(&self.end.shared, id, self.end.side))
+        ))
+    }
+
+    fn poll_open_bidi(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::BidiStream, Self::Error>> {
+        let mut opener = <Self as quic::Connection<Bytes>>::opener(self);
+        quic::OpenStreams::<Bytes>::poll_open_bidi(&mut opener, cx)
+    }
+
+    fn poll_open_send(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::SendStream, Self::Error>> {
+        let mut opener = <Self as quic::Connection<Bytes>>::opener(self);
+        quic::OpenStreams::<Bytes>::poll_open_send(&mut opener, cx)
+    }
+
+    fn opener(&self) -> Self::OpenStreams {
+        OpenStreams {
+            end: self.end.clone(),
+        }
+    }
+
+    fn close(&mut self, code: Code, reason: &[u8]) {
+        lock(&self.end.shared).close(self.end.side, code.value(), Bytes::copy_from_slice(reason));
+    }
+}
+
+/// Opens streams on an in-memory [`Connection`]
+#[derive(Clone)]
+pub struct OpenStreams {
+    end: EndRef,
+}
+
+impl quic::OpenStreams<Bytes> for OpenStreams {
+    type BidiStream = BidiStream;
+    type SendStream = SendStream;
+    type RecvStream = RecvStream;
+    type Error = Error;
+
+    fn poll_open_bidi(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::BidiStream, Self::Error>> {
+        let mut shared = lock(&self.end.shared);
+        ready!(shared.poll_bidi_credit(cx, self.end.side))?;
+        let id = shared.open(self.end.side, true)?;
+        drop(shared);
+        Poll::Ready(Ok(BidiStream::new(&self.end.shared, id, self.end.side)))
+    }
+
+    fn poll_open_send(
+        &mut self,
+        _: &mut Context<'_>,
+    ) -> Poll<Result<Self::SendStream, Self::Error>> {
+        let id = lock(&self.end.shared).open(self.end.side, false)?;
+        Poll::Ready(Ok(SendStream::new(
+            self.end.shared.clone(),
+            id,
+            self.end.side,
+        )))
+    }
+
+    fn poll_ready_bidi(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        lock(&self.end.shared).poll_bidi_credit(cx, self.end.side)
+    }
+
+    fn close(&mut self, code: Code, reason: &[u8]) {
+        lock(&self.end.shared).close(self.end.side, code.value(), Bytes::copy_from_slice(reason));
+    }
+}
+
+/// The sending side of an in-memory stream
+pub struct SendStream {
+    shared: Arc<Mutex<Shared>>,
+    id: u64,
+    side: usize,
+    /// Finished or reset
+    done: bool,
+}
+
+impl SendStream {
+    fn new(shared: Arc<Mutex<Shared>>, id: u64, side: usize) -> Self {
+        Self {
+            shared,
+            id,
+            side,
+            done: false,
+        }
+    }
+
+    /// Checks the stream can still send, then runs `f` on it unless the link discards it all
+    fn with_pipe<F>(&mut self, f: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut Pipe, &Link),
+    {
+        let mut shared = lock(&self.shared);
+        shared.check_open(self.side)?;
+        let Shared { ends, pipes, .. } = &mut *shared;
+        let pipe = pipes
+            .get_mut(&(self.id, self.side))
+            .expect("pipe of a live stream");
+        if let Some(code) = pipe.stopped {
+            return Err(Error::Stopped(code));
+        }
+        let link = &ends[self.side].link;
+        if !link.discard {
+            f(pipe, link);
+            wake(&mut pipe.waker);
+        }
+        Ok(())
+    }
+}
+
+impl quic::SendStream<Bytes> for SendStream {
+    type Error = Error;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if self.done {
+            return Poll::Ready(Err(Error::Finished));
+        }
+        Poll::Ready(self.with_pipe(|_, _| ()))
+    }
+
+    fn send_data<T: Into<WriteBuf<Bytes>>>(&mut self, data: T) -> Result<(), Self::Error> {
+        if self.done {
+            return Err(Error::Finished);
+        }
+        let mut data = data.into();
+        let mut data = data.copy_to_bytes(data.remaining());
+        self.with_pipe(|pipe, link| {
+            let at = link.delay.map(|delay| TokioTimer.now() + delay);
+            let size = link.chunk_size.unwrap_or(usize::MAX);
+            while data.has_remaining() {
+                let chunk = data.split_to(size.min(data.len()));
+                pipe.chunks.push_back((chunk, at));
+            }
+        })
+    }
+
+    fn poll_finish(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if self.done {
+            return Poll::Ready(Ok(()));
+        }
+        self.with_pipe(|pipe, _| pipe.finished = true)?;
+        self.done = true;
+        Poll::Ready(Ok(()))
+    }
+
+    fn reset(&mut self, reset_code: u64) {
+        if self.done {
+            return;
+        }
+        self.done = true;
+        let _ = self.with_pipe(|pipe, _| {
+            pipe.chunks.clear();
+            pipe.reset = Some(reset_code);
+        });
+    }
+
+    fn send_id(&self) -> StreamId {
+        stream_id(self.id)
+    }
+}
+
+impl Drop for SendStream {
+    fn drop(&mut self) {
+        // The stream is left open rather than finished implicitly, so that dropping a
+        // connection does not look like its critical streams were closed first
+        let mut shared = lock(&self.shared);
+        let key = (self.id, self.side);
+        if let Some(pipe) = shared.pipes.get_mut(&key) {
+            pipe.send_open = false;
+        }
+        shared.remove_if_unused(key);
+    }
+}
+
+/// The receiving side of an in-memory stream
+pub struct RecvStream {
+    shared: Arc<Mutex<Shared>>,
+    id: u64,
+    /// The end sending on this stream
+    peer: usize,
+    /// Whether `Pending` was returned in front of the next piece of data
+    yielded: bool,
+    sleep: Option<Pin<Box<dyn Sleep>>>,
+    /// All data was read, or the stream was reset
+    done: bool,
+}
+
+impl RecvStream {
+    fn new(shared: Arc<Mutex<Shared>>, id: u64, peer: usize) -> Self {
+        Self {
+            shared,
+            id,
+            peer,
+            yielded: false,
+            sleep: None,
+            done: false,
+        }
+    }
+}
+
+impl quic::RecvStream for RecvStream {
+    type Buf = Bytes;
+    type Error = Error;
+
+    fn poll_data(&mut self, cx: &mut Context<'_>) -> Poll<Result<Option<Self::Buf>, Self::Error>> {
+        loop {
+            if let Some(sleep) = self.sleep.as_mut() {
+                ready!(sleep.as_mut().poll(cx));
+                self.sleep = None;
+            }
+
+            let mut shared = lock(&self.shared);
+            let closed = shared.check_open(self.peer ^ 1);
+            let pending = shared.ends[self.peer].link.pending;
+            let pipe = shared
+                .pipes
+                .get_mut(&(self.id, self.peer))
+                .expect("pipe of a live stream");
+            if let Some(code) = pipe.reset {
+                self.done = true;
+                return Poll::Ready(Err(Error::Reset(code)));
+            }
+
+            match pipe.chunks.front() {
+                Some((_, Some(at))) if *at > TokioTimer.now() => {
+                    self.sleep = Some(TokioTimer.sleep_until(*at));
+                    continue;
+                }
+                Some(_) if pending && !self.yielded => {
+                    self.yielded = true;
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+                Some(_) => {
+                    self.yielded = false;
+                    return Poll::Ready(Ok(pipe.chunks.pop_front().map(|(chunk, _)| chunk)));
+                }
+                None if pipe.finished => {
+                    self.done = true;
+                    return Poll::Ready(Ok(None));
+                }
+                None => {
+                    // What arrived before the connection was closed can still be read
+                    closed?;
+                    pipe.waker = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+            }
+        }
+    }
+
+    fn stop_sending(&mut self, error_code: u64) {
+        let mut shared = lock(&self.shared);
+        if shared.ends[self.peer ^ 1].link.discard {
+            return;
+        }
+        if let Some(pipe) = shared.pipes.get_mut(&(self.id, self.peer)) {
+            pipe.chunks.clear();
+            pipe.stopped.get_or_insert(error_code);
+        }
+    }
+
+    fn recv_id(&self) -> StreamId {
+        stream_id(self.id)
+    }
+}
+
+impl Drop for RecvStream {
+    fn drop(&mut self) {
+        // Like QUIC stacks, stop the stream implicitly if it was not read to the end
+        if !self.done {
+            quic::RecvStream::stop_sending(self, 0);
+        }
+        let mut shared = lock(&self.shared);
+        let key = (self.id, self.peer);
+        if let Some(pipe) = shared.pipes.get_mut(&key) {
+            pipe.recv_open = false;
+        }
+        shared.remove_if_unused(key);
+    }
+}
+
+/// A bidirectional in-memory stream
+pub struct BidiStream {
+    send: SendStream,
+    recv: RecvStream,
+}
+
+impl BidiStream {
+    fn new(shared: &Arc<Mutex<Shared>>, id: u64, side: usize) -> Self {
+        Self {
+            send: SendStream::new(shared.clone(), id, side),
+            recv: RecvStream::new(shared.clone(), id, side ^ 1),
+        }
+    }
+}
+
+impl quic::BidiStream<Bytes> for BidiStream {
+    type SendStream = SendStream;
+    type RecvStream = RecvStream;
+
+    fn split(self) -> (Self::SendStream, Self::RecvStream) {
+        (self.send, self.recv)
+    }
+}
+
+impl quic::SendStream<Bytes> for BidiStream {
+    type Error = Error;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        quic::SendStream::<Bytes>::poll_ready(&mut self.send, cx)
+    }
+
+    fn send_data<T: Into<WriteBuf<Bytes>>>(&mut self, data: T) -> Result<(), Self::Error> {
+        quic::SendStream::<Bytes>::send_data(&mut self.send, data)
+    }
+
+    fn poll_finish(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        quic::SendStream::<Bytes>::poll_finish(&mut self.send, cx)
+    }
+
+    fn reset(&mut self, reset_code: u64) {
+        quic::SendStream::<Bytes>::reset(&mut self.send, reset_code)
+    }
+
+    fn send_id(&self) -> StreamId {
+        quic::SendStream::<Bytes>::send_id(&self.send)
+    }
+}
+
+impl quic::RecvStream for BidiStream {
+    type Buf = Bytes;
+    type Error = Error;
+
+    fn poll_data(&mut self, cx: &mut Context<'_>) -> Poll<Result<Option<Self::Buf>, Self::Error>> {
+        self.recv.poll_data(cx)
+    }
+
+    fn stop_sending(&mut self, error_code: u64) {
+        self.recv.stop_sending(error_code)
+    }
+
+    fn recv_id(&self) -> StreamId {
+        self.recv.recv_id()
+    }
+}
@@ -0,0 +1,199 @@
+use std::{convert::Infallible, time::Duration};
+
+use bytes::Bytes;
+use futures::{channel::mpsc, SinkExt};
+use futures_util::future;
+use http::{HeaderMap, Request, Response, StatusCode};
+use http_body::{Body, Frame};
+use http_body_util::{BodyExt, Full, StreamBody};
+
+use crate::{
+    client,
+    error::Code,
+    rt::{Timer, TokioTimer},
+    server::{self, RequestBody},
+    test,
+};
+
+use super::init_tracing;
+
+/// Sends `body` in a request echoed back by the server, along with its `content-length`
+async fn echo<R, F>(body: R, content_length: Option<&str>, feed: F) -> (Bytes, Option<HeaderMap>)
+where
+    R: Body<Data = Bytes> + Send + 'static,
+    R::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    F: std::future::Future<Output = ()>,
+{
+    init_tracing();
+    let (client_conn, server_conn) = test::pair();
+
+    let client_fut = async {
+        let (mut driver, mut client) = client::new(client_conn).await.expect("client init");
+        let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
+        let req_fut = async {
+            let request = Request::post("http://localhost/echo").body(body).unwrap();
+            let (response, ()) = tokio::join!(client.send(request), feed);
+            let response = response.expect("send");
+            assert_eq!(response.status(), StatusCode::OK);
+            let collected = response.into_body().collect().await.expect("collect");
+            let trailers = collected.trailers().cloned();
+            (collected.to_bytes(), trailers)
+        };
+        tokio::select! {
+            res = req_fut => res,
+            _ = drive_fut => panic!("client driver ended"),
+        }
+    };
+
+    let server_fut = async {
+        let mut incoming = server::Connection::new(server_conn).await.unwrap();
+        let (request, stream) = incoming.accept().await.expect("accept").unwrap();
+        assert_eq!(
+            request
+                .headers()
+                .get("content-length")
+                .map(|v| v.to_str().unwrap()),
+            content_length
+        );
+        let (mut send, recv) = stream.split();
+        send.respond(Response::new(RequestBody::new(recv)))
+            .await
+            .expect("respond");
+        incoming
+    };
+
+    let (_incoming, echoed) = tokio::join!(server_fut, client_fut);
+    echoed
+}
+
+#[tokio::test]
+async fn send_and_respond_full_body() {
+    let (body, trailers) = echo(
+        Full::new(Bytes::from("wonderful hypertext")),
+        Some("19"),
+        async {},
+    )
+    .await;
+    assert_eq!(body, "wonderful hypertext");
+    assert!(trailers.is_none());
+}
+
+#[tokio::test]
+async fn send_and_respond_streaming_body() {
+    let (mut tx, rx) = mpsc::channel::<Result<Frame<Bytes>, Infallible>>(1);
+    let feed = async move {
+        for chunk in ["wonderful ", "hyper", "text"] {
+            tx.send(Ok(Frame::data(chunk.into()))).await.unwrap();
+        }
+    };
+    let (body, trailers) = echo(StreamBody::new(rx), None, feed).await;
+    assert_eq!(body, "wonderful hypertext");
+    assert!(trailers.is_none());
+}
+
+#[tokio::test]
+async fn send_and_respond_body_with_trailers() {
+    let mut trailers = HeaderMap::new();
+    trailers.insert("trailer", "value".parse().unwrap());
+    let frames: Vec<Result<_, Infallible>> = vec![
+        Ok(Frame::data(Bytes::from("wonderful hypertext"))),
+        Ok(Frame::trailers(trailers)),
+    ];
+    let (body, trailers) = echo(
+        StreamBody::new(futures::stream::iter(frames)),
+        None,
+        async {},
+    )
+    .await;
+    assert_eq!(body, "wonderful hypertext");
+    assert_eq!(trailers.expect("trailers").get("trailer").unwrap(), "value");
+}
+
+#[tokio::test(start_paused = true)]
+async fn request_body_deadline() {
+    init_tracing();
+    let (client_conn, server_conn) = test::pair();
+
+    let client_fut = async {
+        let (mut driver, mut client) = client::new(client_conn).await.expect("client init");
+        let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
+        let req_fut = async {
+            let mut request_stream = client
+                .send_request(Request::post("http://localhost/slow").body(()).unwrap())
+                .await
+                .expect("request");
+            request_stream
+                .send_data(Bytes::from("slow"))
+                .await
+                .expect("send_data");
+            // Far past the deadline
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            let _ = request_stream.send_data(Bytes::from("body")).await;
+        };
+        tokio::select! { _ = req_fut => (), _ = drive_fut => () }
+    };
+
+    let server_fut = async {
+        let mut incoming = server::Connection::new(server_conn).await.unwrap();
+        let (_, stream) = incoming.accept().await.expect("accept").unwrap();
+        let (_send, recv) = stream.split();
+        let start = TokioTimer.now();
+        let mut body =
+            RequestBody::new(recv).with_deadline(start + Duration::from_secs(1), &TokioTimer);
+
+        let frame = body.frame().await.unwrap().unwrap();
+        assert_eq!(frame.into_data().unwrap(), "slow");
+        let err = body.frame().await.unwrap().unwrap_err();
+        assert_eq!(err.try_get_code(), Some(Code::H3_REQUEST_CANCELLED));
+        assert_eq!(TokioTimer.now() - start, Duration::from_secs(1));
+        assert!(body.frame().await.is_none());
+        incoming
+    };
+
+    tokio::select! { _ = server_fut => (), _ = client_fut => panic!("client resolved first") };
+}
+
+#[tokio::test]
+async fn response_received_while_sending_the_request_body() {
+    init_tracing();
+    let (client_conn, server_conn) = test::pair();
+
+    let client_fut = async {
+        let (mut driver, mut client) = client::new(client_conn).await.expect("client init");
+        let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
+        let req_fut = async {
+            let (mut tx, rx) = mpsc::channel::<Result<Frame<Bytes>, Infallible>>(1);
+            let request = Request::post("http://localhost/echo")
+                .body(StreamBody::new(rx))
+                .unwrap();
+            // Nothing was sent in the body yet
+            let response = client.send(request).await.expect("send");
+            assert_eq!(response.status(), StatusCode::OK);
+            let mut body = response.into_body();
+
+            for chunk in ["ping", "pong"] {
+                tx.send(Ok(Frame::data(chunk.into()))).await.unwrap();
+                let frame = body.frame().await.unwrap().expect("frame");
+                assert_eq!(frame.into_data().unwrap(), chunk);
+            }
+            drop(tx);
+            assert!(body.frame().await.is_none());
+        };
+        tokio::select! {
+            res = req_fut => res,
+            _ = drive_fut => panic!("client driver ended"),
+        }
+    };
+
+    let server_fut = async {
+        let mut incoming = server::Connection::new(server_conn).await.unwrap();
+        let (_, stream) = incoming.accept().await.expect("accept").unwrap();
+        let (mut send, recv) = stream.split();
+        send.respond(Response::new(RequestBody::new(recv)))
+            .await
+            .expect("respond");
+        incoming
+    };
+
+    tokio::join!(server_fut, client_fut);
+}
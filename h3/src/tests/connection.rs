@@ -1,475 +1,1174 @@
 // identity_op: we write out how test values are computed
 #![allow(clippy::identity_op)]
 
-use std::{borrow::BorrowMut, time::Duration};
+use std::{
+    borrow::BorrowMut,
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
 
 use assert_matches::assert_matches;
 use bytes::{Buf, Bytes, BytesMut};
-use futures_util::future;
+use futures_util::{future, FutureExt};
 use http::{Request, Response, StatusCode};
+use tokio::sync::oneshot;
 
 use crate::client::SendRequest;
-use crate::{client, server};
+use crate::{client, server, test, UnknownStreamAction};
 use crate::{
-    connection::ConnectionState,
-    error::{Code, Error, Kind},
+    codec::decode_varint,
+    connection::{ConnectionState, Goaway, SharedStateRef},
+    error::{Code, Error, ErrorLevel, Kind},
+    ext::{cancel_push, send_priority_update, Priority, UniStream},
     proto::{
-        coding::Encode as _,
-        frame::{Frame, Settings},
+        coding::Decode as _,
+        frame::{Frame, FrameType, PayloadLen, SettingId, Settings},
         push::PushId,
-        stream::StreamType,
+        stream::{StreamId, StreamType},
         varint::VarInt,
     },
     quic::{self, SendStream},
 };
 
-use super::h3_quinn;
 use super::{init_tracing, Pair};
 
-#[tokio::test]
-async fn connect() {
-    let mut pair = Pair::default();
-    let mut server = pair.server();
+transport_test! {
+    #[tokio::test]
+    async fn connect() {
+        let (client_conn, server_conn) = test::pair();
 
-    let client_fut = async {
-        let _ = client::new(pair.client().await).await.expect("client init");
-    };
+        let client_fut = async { client::new(wrap(client_conn)).await.expect("client init") };
 
-    let server_fut = async {
-        let conn = server.next().await;
-        let _ = server::Connection::new(conn).await.unwrap();
-    };
+        let server_fut = async { server::Connection::new(wrap(server_conn)).await.unwrap() };
 
-    tokio::join!(server_fut, client_fut);
+        // Both ends are kept until initialized
+        let (_incoming, _client) = tokio::join!(server_fut, client_fut);
+    }
 }
 
-#[tokio::test]
-async fn accept_request_end_on_client_close() {
-    let mut pair = Pair::default();
-    let mut server = pair.server();
+transport_test! {
+    #[tokio::test]
+    async fn accept_request_end_on_client_close() {
+        let (client_conn, server_conn) = test::pair();
 
-    let client_fut = async {
-        let _ = client::new(pair.client().await).await.expect("client init");
-        // client is dropped, it will send H3_NO_ERROR
-    };
+        let client_fut = async {
+            let _ = client::new(wrap(client_conn)).await.expect("client init");
+            // client is dropped, it will send H3_NO_ERROR
+        };
 
-    let server_fut = async {
-        let conn = server.next().await;
-        let mut incoming = server::Connection::new(conn).await.unwrap();
-        // Accept returns Ok(None)
-        assert!(incoming.accept().await.unwrap().is_none());
-    };
+        let server_fut = async {
+            let mut incoming = server::Connection::new(wrap(server_conn)).await.unwrap();
+            // Accept returns Ok(None)
+            assert!(incoming.accept().await.unwrap().is_none());
+        };
 
-    tokio::join!(server_fut, client_fut);
+        tokio::join!(server_fut, client_fut);
+    }
 }
 
-#[tokio::test]
-async fn server_drop_close() {
-    let mut pair = Pair::default();
-    let mut server = pair.server();
-
-    let server_fut = async {
-        let conn = server.next().await;
-        let _ = server::Connection::new(conn).await.unwrap();
-    };
+transport_test! {
+    #[tokio::test]
+    async fn server_drop_close() {
+        let (client_conn, server_conn) = test::pair();
 
-    let (mut conn, mut send) = client::new(pair.client().await).await.expect("client init");
-    let client_fut = async {
-        let request_fut = async move {
-            let mut request_stream = send
-                .send_request(Request::get("http://no.way").body(()).unwrap())
-                .await
-                .unwrap();
-            let response = request_stream.recv_response().await;
-            assert_matches!(response.unwrap_err().kind(), Kind::Closed);
+        let server_fut = async {
+            let mut incoming = server::Connection::new(wrap(server_conn)).await.unwrap();
+            // Dropped along with the connection, without a response
+            let _request = incoming.accept().await.unwrap().unwrap();
         };
 
-        let drive_fut = async {
-            let drive = future::poll_fn(|cx| conn.poll_close(cx)).await;
-            assert_matches!(drive, Ok(()));
+        let (mut conn, mut send) = client::new(wrap(client_conn)).await.expect("client init");
+        let client_fut = async {
+            let request_fut = async move {
+                let mut request_stream = send
+                    .send_request(Request::get("http://no.way").body(()).unwrap())
+                    .await
+                    .unwrap();
+                let response = request_stream.recv_response().await;
+                assert_matches!(response.unwrap_err().kind(), Kind::Closed);
+            };
+
+            let drive_fut = async {
+                let drive = future::poll_fn(|cx| conn.poll_close(cx)).await;
+                assert_matches!(drive, Ok(()));
+            };
+            tokio::select! {biased; _ = request_fut => (), _ = drive_fut => () }
         };
-        tokio::select! {biased; _ = request_fut => (), _ = drive_fut => () }
-    };
-    tokio::join!(server_fut, client_fut);
+        tokio::join!(server_fut, client_fut);
+    }
 }
 
 // In this test the client calls send_data() without doing a finish(),
 // i.e client keeps the body stream open. And cient expects server to
 // read_data() and send a response
-#[tokio::test]
-async fn server_send_data_without_finish() {
-    let mut pair = Pair::default();
-    let mut server = pair.server();
+transport_test! {
+    #[tokio::test]
+    async fn server_send_data_without_finish() {
+        let (client_conn, server_conn) = test::pair();
 
-    let client_fut = async {
-        let (_driver, mut send_request) = client::new(pair.client().await).await.unwrap();
+        let client_fut = async {
+            let (_driver, mut send_request) = client::new(wrap(client_conn)).await.unwrap();
 
-        let mut req = send_request
-            .send_request(Request::get("http://no.way").body(()).unwrap())
-            .await
-            .unwrap();
-        let data = vec![0; 100];
-        req.send_data(bytes::Bytes::copy_from_slice(&data))
-            .await
-            .unwrap();
-        let _ = req.recv_response().await.unwrap();
-    };
+            let mut req = send_request
+                .send_request(Request::get("http://no.way").body(()).unwrap())
+                .await
+                .unwrap();
+            let data = vec![0; 100];
+            req.send_data(bytes::Bytes::copy_from_slice(&data))
+                .await
+                .unwrap();
+            let _ = req.recv_response().await.unwrap();
+        };
 
-    let server_fut = async {
-        let conn = server.next().await;
-        let mut incoming = server::Connection::new(conn).await.unwrap();
-        let (_, mut stream) = incoming.accept().await.unwrap().unwrap();
-        let mut data = stream.recv_data().await.unwrap().unwrap();
-        let data = data.copy_to_bytes(data.remaining());
-        assert_eq!(data.len(), 100);
-        response(stream).await;
-        server.endpoint.wait_idle().await;
-    };
+        let server_fut = async {
+            let mut incoming = server::Connection::new(wrap(server_conn)).await.unwrap();
+            let (_, mut stream) = incoming.accept().await.unwrap().unwrap();
+            let mut data = stream.recv_data().await.unwrap().unwrap();
+            let data = data.copy_to_bytes(data.remaining());
+            assert_eq!(data.len(), 100);
+            response(stream).await;
+            incoming
+        };
 
-    tokio::join!(server_fut, client_fut);
+        tokio::join!(server_fut, client_fut);
+    }
 }
 
-#[tokio::test]
-async fn client_close_only_on_last_sender_drop() {
-    let mut pair = Pair::default();
-    let mut server = pair.server();
+transport_test! {
+    #[tokio::test]
+    async fn client_close_only_on_last_sender_drop() {
+        let (client_conn, server_conn) = test::pair();
+        let (accepted_tx, accepted_rx) = oneshot::channel();
+
+        let server_fut = async {
+            let mut incoming = server::Connection::new(wrap(server_conn)).await.unwrap();
+            assert!(incoming.accept().await.unwrap().is_some());
+            assert!(incoming.accept().await.unwrap().is_some());
+            accepted_tx.send(()).unwrap();
+            assert!(incoming.accept().await.unwrap().is_none());
+        };
 
-    let server_fut = async {
-        let conn = server.next().await;
-        let mut incoming = server::Connection::new(conn).await.unwrap();
-        assert!(incoming.accept().await.unwrap().is_some());
-        assert!(incoming.accept().await.unwrap().is_some());
-        assert!(incoming.accept().await.unwrap().is_none());
-    };
+        let client_fut = async {
+            let (mut conn, mut send1) = client::new(wrap(client_conn)).await.expect("client init");
+            let mut send2 = send1.clone();
+            let _ = send1
+                .send_request(Request::get("http://no.way").body(()).unwrap())
+                .await
+                .unwrap()
+                .finish()
+                .await;
+            let _ = send2
+                .send_request(Request::get("http://no.way").body(()).unwrap())
+                .await
+                .unwrap()
+                .finish()
+                .await;
+            accepted_rx.await.unwrap();
 
-    let client_fut = async {
-        let (mut conn, mut send1) = client::new(pair.client().await).await.expect("client init");
-        let mut send2 = send1.clone();
-        let _ = send1
-            .send_request(Request::get("http://no.way").body(()).unwrap())
-            .await
-            .unwrap()
-            .finish()
-            .await;
-        let _ = send2
-            .send_request(Request::get("http://no.way").body(()).unwrap())
-            .await
-            .unwrap()
-            .finish()
-            .await;
-        drop(send1);
-        drop(send2);
-
-        let drive = future::poll_fn(|cx| conn.poll_close(cx)).await;
-        assert_matches!(drive, Ok(()));
-    };
+            drop(send1);
+            assert!(future::poll_fn(|cx| conn.poll_close(cx))
+                .now_or_never()
+                .is_none());
+            drop(send2);
 
-    tokio::join!(server_fut, client_fut);
+            let drive = future::poll_fn(|cx| conn.poll_close(cx)).await;
+            assert_matches!(drive, Ok(()));
+        };
+
+        tokio::join!(server_fut, client_fut);
+    }
 }
 
-#[tokio::test]
-async fn settings_exchange_client() {
-    init_tracing();
-    let mut pair = Pair::default();
-    let mut server = pair.server();
+transport_test! {
+    #[tokio::test]
+    async fn concurrent_requests_from_cloned_senders() {
+        init_tracing();
+        let (client_conn, server_conn) = test::pair();
+        const REQUESTS: usize = 48;
+
+        let client_fut = async {
+            let (mut conn, send_request) = client::new(wrap(client_conn)).await.unwrap();
+            let tasks: Vec<_> = (0..REQUESTS)
+                .map(|_| {
+                    let mut send_request = send_request.clone();
+                    tokio::spawn(async move {
+                        let mut stream = send_request
+                            .send_request(Request::get("http://no.way").body(()).unwrap())
+                            .await
+                            .unwrap();
+                        stream.finish().await.unwrap();
+                        let response = stream.recv_response().await.unwrap();
+                        assert_eq!(response.status(), StatusCode::IM_A_TEAPOT);
+                        stream.id()
+                    })
+                })
+                .collect();
+            drop(send_request);
+
+            let (ids, drive) = tokio::join!(
+                future::join_all(tasks),
+                future::poll_fn(|cx| conn.poll_close(cx))
+            );
+            let ids: HashSet<_> = ids.into_iter().map(Result::unwrap).collect();
+            assert_eq!(ids.len(), REQUESTS);
+            assert_matches!(drive, Ok(()));
+        };
 
-    let client_fut = async {
-        let (mut conn, client) = client::new(pair.client().await).await.expect("client init");
-        let settings_change = async {
-            for _ in 0..10 {
-                if client
-                    .shared_state()
-                    .read("client")
-                    .peer_config
-                    .max_field_section_size
-                    == 12
-                {
-                    return;
-                }
-                tokio::time::sleep(Duration::from_millis(2)).await;
+        let server_fut = async {
+            let mut incoming = server::Connection::new(wrap(server_conn)).await.unwrap();
+            for _ in 0..REQUESTS {
+                let (_, stream) = incoming.accept().await.unwrap().unwrap();
+                response(stream).await;
             }
-            panic!("peer's max_field_section_size didn't change");
+            assert!(incoming.accept().await.unwrap().is_none());
         };
 
-        let drive = async move {
-            future::poll_fn(|cx| conn.poll_close(cx)).await.unwrap();
-        };
+        tokio::join!(server_fut, client_fut);
+    }
+}
 
-        tokio::select! { _ = settings_change => (), _ = drive => panic!("driver resolved first") };
-    };
+transport_test! {
+    #[tokio::test]
+    async fn client_close_after_last_request_in_flight() {
+        init_tracing();
+        let (client_conn, server_conn) = test::pair();
 
-    let server_fut = async {
-        let conn = server.next().await;
-        let mut incoming = server::builder()
-            .max_field_section_size(12)
-            .build(conn)
-            .await
-            .unwrap();
-        incoming.accept().await.unwrap()
-    };
+        let client_fut = async {
+            let (mut conn, mut send_request) = client::new(wrap(client_conn)).await.unwrap();
+            let mut stream = send_request
+                .send_request(Request::get("http://no.way").body(()).unwrap())
+                .await
+                .unwrap();
+            stream.finish().await.unwrap();
+            // The request is still carried on without any sender
+            drop(send_request);
+
+            let request_fut = async move {
+                let response = stream.recv_response().await.unwrap();
+                assert_eq!(response.status(), StatusCode::IM_A_TEAPOT);
+            };
+            let (_, drive) = tokio::join!(request_fut, future::poll_fn(|cx| conn.poll_close(cx)));
+            assert_matches!(drive, Ok(()));
+        };
+
+        let server_fut = async {
+            let mut incoming = server::Connection::new(wrap(server_conn)).await.unwrap();
+            let (_, stream) = incoming.accept().await.unwrap().unwrap();
+            response(stream).await;
+            assert!(incoming.accept().await.unwrap().is_none());
+        };
 
-    tokio::select! { _ = server_fut => panic!("server resolved first"), _ = client_fut => () };
+        tokio::join!(server_fut, client_fut);
+    }
 }
 
-#[tokio::test]
-async fn settings_exchange_server() {
-    init_tracing();
-    let mut pair = Pair::default();
-    let mut server = pair.server();
+transport_test! {
+    #[tokio::test]
+    async fn split_server_accepts_apart_from_driver() {
+        init_tracing();
+        let (client_conn, server_conn) = test::pair();
+
+        let client_fut = async {
+            let (mut driver, mut send_request) = client::new(wrap(client_conn)).await.unwrap();
+            let req_fut = async {
+                for _ in 0..2 {
+                    let response = request(&mut send_request).await.unwrap();
+                    assert_eq!(response.status(), StatusCode::IM_A_TEAPOT);
+                }
+            };
+            tokio::select! {
+                _ = req_fut => (),
+                _ = future::poll_fn(|cx| driver.poll_close(cx)) => panic!("driver resolved first"),
+            };
+            driver.shutdown(0).await.unwrap();
+            future::poll_fn(|cx| driver.poll_close(cx)).await
+        };
 
-    let client_fut = async {
-        let (mut conn, _client) = client::builder()
-            .max_field_section_size(12)
-            .build::<_, _, Bytes>(pair.client().await)
-            .await
-            .expect("client init");
-        let drive = async move {
-            future::poll_fn(|cx| conn.poll_close(cx)).await.unwrap();
+        let server_fut = async {
+            let (driver, mut acceptor) = server::Connection::new(wrap(server_conn)).await.unwrap().split();
+            let driver = tokio::spawn(driver.drive());
+            while let Some((_, stream)) = acceptor.accept().await.unwrap() {
+                response(stream).await;
+            }
+            assert_matches!(driver.await.unwrap(), Ok(()));
         };
 
-        tokio::select! { _ = drive => () };
-    };
+        let (client, _) = tokio::join!(client_fut, server_fut);
+        assert_matches!(client, Ok(()));
+    }
+}
 
-    let server_fut = async {
-        let conn = server.next().await;
-        let mut incoming = server::Connection::new(conn).await.unwrap();
+transport_test! {
+    #[tokio::test]
+    async fn split_server_driver_processes_control_stream_alone() {
+        init_tracing();
+        let (client_conn, server_conn) = test::pair();
+
+        let client_fut = async {
+            let (mut driver, _send_request) = client::new(wrap(client_conn)).await.unwrap();
+            driver.shutdown(0).await.unwrap();
+            // The server closes the connection once drained
+            future::poll_fn(|cx| driver.poll_drive(cx)).await.unwrap();
+        };
 
-        let state = incoming.shared_state().clone();
-        let accept = async { incoming.accept().await.unwrap() };
+        let server_fut = async {
+            let (driver, mut acceptor) = server::Connection::new(wrap(server_conn)).await.unwrap().split();
+            let driver = tokio::spawn(driver.drive());
 
-        let settings_change = async {
-            for _ in 0..10 {
-                if state
-                    .read("setting_change")
-                    .peer_config
-                    .max_field_section_size
-                    == 12
-                {
-                    return;
-                }
-                tokio::time::sleep(Duration::from_millis(2)).await;
-            }
-            panic!("peer's max_field_section_size didn't change");
+            // No request is accepted while the client sends GOAWAY
+            wait_closing(acceptor.shared_state()).await;
+            assert_matches!(driver.await.unwrap(), Ok(()));
+            assert!(acceptor.accept().await.unwrap().is_none());
         };
-        tokio::select! { _ = accept => panic!("server resolved first"), _ = settings_change => () };
-    };
 
-    tokio::select! { _ = server_fut => (), _ = client_fut => () };
+        tokio::join!(server_fut, client_fut);
+    }
 }
 
-#[tokio::test]
-async fn client_error_on_bidi_recv() {
-    let mut pair = Pair::default();
-    let server = pair.server();
+transport_test! {
+    #[tokio::test]
+    async fn poll_ready_waits_for_stream_limit() {
+        init_tracing();
+        let (client_conn, server_conn) = test::pair();
+        // The server allows a single request stream at once
+        client_conn.handle().set_max_bidi_streams(Some(1));
+
+        let client_fut = async {
+            let (mut conn, mut send_request) = client::new(wrap(client_conn)).await.unwrap();
+            let requests = async {
+                let get = || Request::get("http://no.way").body(()).unwrap();
+                let mut stream = send_request.try_send_request(get()).unwrap();
+                assert!(future::poll_fn(|cx| send_request.poll_ready(cx))
+                    .now_or_never()
+                    .is_none());
+                match send_request.try_send_request(get()) {
+                    Err(e) => assert!(e.is_not_ready(), "{}", e),
+                    Ok(_) => panic!("request sent over the stream limit"),
+                }
 
-    macro_rules! check_err {
-        ($e:expr) => {
-            assert_matches!(
-                $e.map(|_| ()).unwrap_err().kind(),
-                Kind::Application { reason: Some(reason), code: Code::H3_STREAM_CREATION_ERROR, .. }
-                if *reason == *"client received a bidirectional stream");
-        }
+                stream.finish().await.unwrap();
+                let response = stream.recv_response().await.unwrap();
+                assert_eq!(response.status(), StatusCode::IM_A_TEAPOT);
+                assert!(stream.recv_data().await.unwrap().is_none());
+                drop(stream);
+
+                // Once the server is done with the stream too
+                future::poll_fn(|cx| send_request.poll_ready(cx))
+                    .await
+                    .unwrap();
+                let response = request(&mut send_request).await.unwrap();
+                assert_eq!(response.status(), StatusCode::IM_A_TEAPOT);
+            };
+            tokio::select! {
+                _ = requests => (),
+                _ = future::poll_fn(|cx| conn.poll_close(cx)) => panic!("connection closed"),
+            }
+        };
+
+        let server_fut = async {
+            let mut incoming = server::Connection::new(wrap(server_conn)).await.unwrap();
+            for _ in 0..2 {
+                let (_, stream) = incoming.accept().await.unwrap().unwrap();
+                response(stream).await;
+            }
+            incoming
+        };
+
+        tokio::join!(server_fut, client_fut);
     }
+}
 
-    let client_fut = async {
-        let (mut conn, mut send) = client::new(pair.client().await).await.expect("client init");
-
-        //= https://www.rfc-editor.org/rfc/rfc9114#section-6.1
-        //= type=test
-        //# Clients MUST treat
-        //# receipt of a server-initiated bidirectional stream as a connection
-        //# error of type H3_STREAM_CREATION_ERROR unless such an extension has
-        //# been negotiated.
-        let driver = future::poll_fn(|cx| conn.poll_close(cx));
-        check_err!(driver.await);
-        check_err!(
-            send.send_request(Request::get("http://no.way").body(()).unwrap())
+transport_test! {
+    #[tokio::test]
+    async fn poll_ready_waits_for_requests_in_flight() {
+        init_tracing();
+        let (client_conn, server_conn) = test::pair();
+
+        let client_fut = async {
+            let (mut conn, mut send_request) = client::builder()
+                .max_concurrent_requests(1)
+                .build::<_, _, Bytes>(wrap(client_conn))
                 .await
-        );
-    };
+                .unwrap();
+            let mut other = send_request.clone();
+            let requests = async {
+                let mut stream = send_request
+                    .send_request(Request::get("http://no.way").body(()).unwrap())
+                    .await
+                    .unwrap();
+                stream.finish().await.unwrap();
+                stream.recv_response().await.unwrap();
+                assert!(future::poll_fn(|cx| other.poll_ready(cx))
+                    .now_or_never()
+                    .is_none());
+
+                // The waiting handle is woken up once the request is dropped
+                let (ready, _) = tokio::join!(future::poll_fn(|cx| other.poll_ready(cx)), async {
+                    tokio::task::yield_now().await;
+                    drop(stream);
+                });
+                ready.unwrap();
+                let response = request(&mut other).await.unwrap();
+                assert_eq!(response.status(), StatusCode::IM_A_TEAPOT);
+            };
+            tokio::select! {
+                _ = requests => (),
+                _ = future::poll_fn(|cx| conn.poll_close(cx)) => panic!("connection closed"),
+            }
+        };
 
-    let server_fut = async {
-        let connection = server.endpoint.accept().await.unwrap().await.unwrap();
-        let (mut send, _recv) = connection.open_bi().await.unwrap();
-        for _ in 0..100 {
-            match send.write(b"I'm not really a server").await {
-                Err(quinn::WriteError::ConnectionLost(
-                    quinn::ConnectionError::ApplicationClosed(quinn::ApplicationClose {
-                        error_code,
-                        ..
-                    }),
-                )) if Code::H3_STREAM_CREATION_ERROR == error_code.into_inner() => break,
-                Err(e) => panic!("got err: {}", e),
-                Ok(_) => tokio::time::sleep(Duration::from_millis(1)).await,
+        let server_fut = async {
+            let mut incoming = server::Connection::new(wrap(server_conn)).await.unwrap();
+            for _ in 0..2 {
+                let (_, stream) = incoming.accept().await.unwrap().unwrap();
+                response(stream).await;
             }
-        }
-    };
+            incoming
+        };
 
-    tokio::join!(server_fut, client_fut);
+        tokio::join!(server_fut, client_fut);
+    }
 }
 
-#[tokio::test]
-async fn two_control_streams() {
-    init_tracing();
-    let mut pair = Pair::default();
-    let mut server = pair.server();
+transport_test! {
+    #[tokio::test]
+    async fn settings_exchange_client() {
+        init_tracing();
+        let (client_conn, server_conn) = test::pair();
+
+        let client_fut = async {
+            let (mut conn, client) = client::new(wrap(client_conn)).await.expect("client init");
+            let settings_change = async {
+                for _ in 0..10 {
+                    if client
+                        .shared_state()
+                        .read("client")
+                        .peer_config
+                        .max_field_section_size
+                        == 12
+                    {
+                        return;
+                    }
+                    tokio::time::sleep(Duration::from_millis(2)).await;
+                }
+                panic!("peer's max_field_section_size didn't change");
+            };
 
-    let client_fut = async {
-        let connection = pair.client_inner().await;
-
-        //= https://www.rfc-editor.org/rfc/rfc9114#section-6.2.1
-        //= type=test
-        //# Only one control stream per peer is permitted;
-        //# receipt of a second stream claiming to be a control stream MUST be
-        //# treated as a connection error of type H3_STREAM_CREATION_ERROR.
-        for _ in 0..=1 {
-            let mut control_stream = connection.open_uni().await.unwrap();
-            let mut buf = BytesMut::new();
-            StreamType::CONTROL.encode(&mut buf);
-            control_stream.write_all(&buf[..]).await.unwrap();
-        }
+            let drive = async move {
+                future::poll_fn(|cx| conn.poll_close(cx)).await.unwrap();
+            };
 
-        tokio::time::sleep(Duration::from_secs(10)).await;
-    };
+            tokio::select! { _ = settings_change => (), _ = drive => panic!("driver resolved first") };
+        };
 
-    let server_fut = async {
-        let conn = server.next().await;
-        let mut incoming = server::Connection::new(conn).await.unwrap();
-        assert_matches!(
-            incoming.accept().await.map(|_| ()).unwrap_err().kind(),
-            Kind::Application {
-                code: Code::H3_STREAM_CREATION_ERROR,
-                ..
-            }
-        );
-    };
+        let server_fut = async {
+            let mut incoming = server::builder()
+                .max_field_section_size(12)
+                .build(wrap(server_conn))
+                .await
+                .unwrap();
+            incoming.accept().await.unwrap()
+        };
 
-    tokio::select! { _ = server_fut => (), _ = client_fut => panic!("client resolved first") };
+        tokio::select! { _ = server_fut => panic!("server resolved first"), _ = client_fut => () };
+    }
 }
 
-#[tokio::test]
-async fn control_close_send_error() {
-    init_tracing();
-    let mut pair = Pair::default();
-    let mut server = pair.server();
+transport_test! {
+    #[tokio::test]
+    async fn settings_exchange_server() {
+        init_tracing();
+        let (client_conn, server_conn) = test::pair();
 
-    let client_fut = async {
-        let connection = pair.client_inner().await;
-        let mut control_stream = connection.open_uni().await.unwrap();
+        let client_fut = async {
+            let (mut conn, _client) = client::builder()
+                .max_field_section_size(12)
+                .build::<_, _, Bytes>(wrap(client_conn))
+                .await
+                .expect("client init");
+            let drive = async move {
+                future::poll_fn(|cx| conn.poll_close(cx)).await.unwrap();
+            };
+
+            tokio::select! { _ = drive => () };
+        };
 
-        let mut buf = BytesMut::new();
-        StreamType::CONTROL.encode(&mut buf);
-        control_stream.write_all(&buf[..]).await.unwrap();
+        let server_fut = async {
+            let mut incoming = server::Connection::new(wrap(server_conn)).await.unwrap();
+
+            let state = incoming.shared_state().clone();
+            let accept = async { incoming.accept().await.unwrap() };
+
+            let settings_change = async {
+                for _ in 0..10 {
+                    if state
+                        .read("setting_change")
+                        .peer_config
+                        .max_field_section_size
+                        == 12
+                    {
+                        return;
+                    }
+                    tokio::time::sleep(Duration::from_millis(2)).await;
+                }
+                panic!("peer's max_field_section_size didn't change");
+            };
+            tokio::select! { _ = accept => panic!("server resolved first"), _ = settings_change => () };
+        };
 
-        //= https://www.rfc-editor.org/rfc/rfc9114#section-6.2.1
-        //= type=test
-        //# If either control
-        //# stream is closed at any point, this MUST be treated as a connection
-        //# error of type H3_CLOSED_CRITICAL_STREAM.
-        control_stream.finish().await.unwrap(); // close the client control stream immediately
+        tokio::select! { _ = server_fut => (), _ = client_fut => () };
+    }
+}
 
-        let (mut driver, _send) = client::new(h3_quinn::Connection::new(connection))
-            .await
-            .unwrap();
+transport_test! {
+    #[tokio::test]
+    async fn client_error_on_bidi_recv() {
+        let (client_conn, mut server_conn) = test::pair();
+
+        macro_rules! check_err {
+            ($e:expr) => {
+                assert_matches!(
+                    $e.map(|_| ()).unwrap_err().kind(),
+                    Kind::Application { reason: Some(reason), code: Code::H3_STREAM_CREATION_ERROR, .. }
+                    if *reason == *"client received a bidirectional stream");
+            }
+        }
 
-        future::poll_fn(|cx| driver.poll_close(cx)).await
-    };
+        let client_fut = async {
+            let (mut conn, mut send) = client::new(wrap(client_conn)).await.expect("client init");
+
+            //= https://www.rfc-editor.org/rfc/rfc9114#section-6.1
+            //= type=test
+            //# Clients MUST treat
+            //# receipt of a server-initiated bidirectional stream as a connection
+            //# error of type H3_STREAM_CREATION_ERROR unless such an extension has
+            //# been negotiated.
+            let driver = future::poll_fn(|cx| conn.poll_close(cx));
+            check_err!(driver.await);
+            check_err!(
+                send.send_request(Request::get("http://no.way").body(()).unwrap())
+                    .await
+            );
+        };
 
-    let server_fut = async {
-        let conn = server.next().await;
-        let mut incoming = server::Connection::new(conn).await.unwrap();
-        // Driver detects that the receiving side of the control stream has been closed
-        assert_matches!(
-            incoming.accept().await.map(|_| ()).unwrap_err().kind(),
-            Kind::Application { reason: Some(reason), code: Code::H3_CLOSED_CRITICAL_STREAM, .. }
-            if *reason == *"control stream closed");
-        // Poll it once again returns the previously stored error
-        assert_matches!(
-            incoming.accept().await.map(|_| ()).unwrap_err().kind(),
-            Kind::Application { reason: Some(reason), code: Code::H3_CLOSED_CRITICAL_STREAM, .. }
-            if *reason == *"control stream closed");
-    };
+        let server_fut = async {
+            let mut stream =
+                future::poll_fn(|cx| quic::Connection::poll_open_bidi(&mut server_conn, cx))
+                    .await
+                    .unwrap();
+            for _ in 0..100 {
+                match stream.send_data(Frame::Data(Bytes::from("I'm not really a server"))) {
+                    Err(test::Error::ConnectionClosed { code, .. })
+                        if Code::H3_STREAM_CREATION_ERROR == code =>
+                    {
+                        break
+                    }
+                    Err(e) => panic!("got err: {}", e),
+                    Ok(_) => tokio::time::sleep(Duration::from_millis(1)).await,
+                }
+            }
+        };
 
-    tokio::select! { _ = server_fut => (), _ = client_fut => panic!("client resolved first") };
+        tokio::join!(server_fut, client_fut);
+    }
 }
 
-#[tokio::test]
-async fn missing_settings() {
-    init_tracing();
-    let mut pair = Pair::default();
-    let mut server = pair.server();
+transport_test! {
+    #[tokio::test]
+    async fn two_control_streams() {
+        init_tracing();
+        let (mut client_conn, server_conn) = test::pair();
+
+        let client_fut = async {
+            //= https://www.rfc-editor.org/rfc/rfc9114#section-6.2.1
+            //= type=test
+            //# Only one control stream per peer is permitted;
+            //# receipt of a second stream claiming to be a control stream MUST be
+            //# treated as a connection error of type H3_STREAM_CREATION_ERROR.
+            let mut control_streams = vec![];
+            for _ in 0..=1 {
+                let mut control_stream = open_uni(&mut client_conn).await;
+                control_stream.send_data(StreamType::CONTROL).unwrap();
+                control_streams.push(control_stream);
+            }
 
-    let client_fut = async {
-        let connection = pair.client_inner().await;
-        let mut control_stream = connection.open_uni().await.unwrap();
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        };
+
+        let server_fut = async {
+            let mut incoming = server::Connection::new(wrap(server_conn)).await.unwrap();
+            assert_matches!(
+                incoming.accept().await.map(|_| ()).unwrap_err().kind(),
+                Kind::Application {
+                    code: Code::H3_STREAM_CREATION_ERROR,
+                    ..
+                }
+            );
+        };
 
-        let mut buf = BytesMut::new();
-        StreamType::CONTROL.encode(&mut buf);
+        tokio::select! { _ = server_fut => (), _ = client_fut => panic!("client resolved first") };
+    }
+}
 
-        //= https://www.rfc-editor.org/rfc/rfc9114#section-6.2.1
-        //= type=test
-        //# If the first frame of the control stream is any other frame
-        //# type, this MUST be treated as a connection error of type
-        //# H3_MISSING_SETTINGS.
-        Frame::<Bytes>::CancelPush(PushId(0)).encode(&mut buf);
-        control_stream.write_all(&buf[..]).await.unwrap();
+transport_test! {
+    #[tokio::test]
+    async fn unknown_uni_streams_ignored() {
+        init_tracing();
+        let (mut client_conn, server_conn) = test::pair();
+
+        let client_fut = async {
+            // Unknown types, then a reserved one
+            let mut uni_streams = vec![];
+            for ty in [0x42, 0x43, 0x44, 0x21 + 0x1f] {
+                uni_streams.push(open_unknown_uni_stream(&mut client_conn, ty, false).await);
+            }
 
-        tokio::time::sleep(Duration::from_secs(10)).await;
-    };
+            let (mut driver, mut send_request) = client::new(wrap(client_conn)).await.unwrap();
+            let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
+            let req_fut = async {
+                for _ in 0..2 {
+                    let response = request(&mut send_request).await.unwrap();
+                    assert_eq!(response.status(), StatusCode::IM_A_TEAPOT);
+                }
+                for stream in &mut uni_streams[..3] {
+                    let code = loop {
+                        match future::poll_fn(|cx| stream.poll_ready(cx)).await {
+                            Err(test::Error::Stopped(code)) => break code,
+                            Err(e) => panic!("got err: {}", e),
+                            Ok(()) => tokio::time::sleep(Duration::from_millis(1)).await,
+                        }
+                    };
+                    assert!(Code::H3_STREAM_CREATION_ERROR == code);
+                }
+            };
+            tokio::select! { _ = req_fut => (), _ = drive_fut => panic!("driver resolved first") }
+        };
 
-    let server_fut = async {
-        let conn = server.next().await;
-        let mut incoming = server::Connection::new(conn).await.unwrap();
-        assert_matches!(
-            incoming.accept().await.map(|_| ()).unwrap_err().kind(),
-            Kind::Application {
-                code: Code::H3_MISSING_SETTINGS,
-                ..
+        let server_fut = async {
+            let mut incoming = server::Connection::new(wrap(server_conn)).await.unwrap();
+            for _ in 0..2 {
+                let (_, stream) = incoming.accept().await.unwrap().unwrap();
+                response(stream).await;
             }
-        );
-    };
+            incoming
+        };
 
-    tokio::select! { _ = server_fut => (), _ = client_fut => panic!("client resolved first") };
+        tokio::join!(server_fut, client_fut);
+    }
 }
 
-#[tokio::test]
-async fn control_stream_frame_unexpected() {
-    init_tracing();
-    let mut pair = Pair::default();
-    let mut server = pair.server();
+transport_test! {
+    #[tokio::test]
+    async fn registered_uni_stream_handler() {
+        init_tracing();
+        let (sender, handed) = oneshot::channel();
+        let sender = Mutex::new(Some(sender));
+        let handler = move |id, stream: UniStream| {
+            let sender = sender.lock().unwrap().take().expect("handled once");
+            sender.send((id, stream)).unwrap();
+        };
 
-    let client_fut = async {
-        let connection = pair.client_inner().await;
-        let mut control_stream = connection.open_uni().await.unwrap();
-
-        //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.1
-        //= type=test
-        //# If
-        //# a DATA frame is received on a control stream, the recipient MUST
-        //# respond with a connection error of type H3_FRAME_UNEXPECTED.
-        let mut buf = BytesMut::new();
-        StreamType::CONTROL.encode(&mut buf);
-        Frame::Data(Bytes::from("")).encode(&mut buf);
-        control_stream.write_all(&buf[..]).await.unwrap();
+        let (mut client_conn, server_conn) = test::pair();
+
+        let client_fut = async {
+            // The stream type and the payload are received in pieces
+            client_conn
+                .handle()
+                .set_link(test::Link::default().chunk_size(4));
+            let mut stream = open_uni(&mut client_conn).await;
+            stream
+                .send_data((
+                    StreamType::WEBTRANSPORT_UNI,
+                    Frame::Data(Bytes::from_static(b"registered")),
+                ))
+                .unwrap();
+            future::poll_fn(|cx| stream.poll_finish(cx)).await.unwrap();
+            client_conn.handle().set_link(test::Link::default());
 
-        tokio::time::sleep(Duration::from_secs(10)).await;
-    };
+            let (mut driver, _send_request) = client::new(wrap(client_conn)).await.unwrap();
+            future::poll_fn(|cx| driver.poll_close(cx)).await
+        };
 
-    let server_fut = async {
-        let conn = server.next().await;
-        let mut incoming = server::Connection::new(conn).await.unwrap();
+        let server_fut = async {
+            let mut incoming = server::builder()
+                .register_uni_stream_handler(VarInt(0x54), handler)
+                .unwrap()
+                .build(wrap(server_conn))
+                .await
+                .unwrap();
+            let drive_fut = async { future::poll_fn(|cx| incoming.poll_drive(cx)).await };
+            let recv_fut = async {
+                let (id, mut stream) = handed.await.unwrap();
+                assert_eq!(id, quic::RecvStream::recv_id(&stream));
+                assert_eq!(stream.stream_type(), 0x54);
+                let mut received = BytesMut::new();
+                while let Some(chunk) =
+                    future::poll_fn(|cx| quic::RecvStream::poll_data(&mut stream, cx))
+                        .await
+                        .unwrap()
+                {
+                    received.extend_from_slice(&chunk);
+                }
+                // Everything after the stream type, starting with the DATA frame header
+                assert_eq!(&received[..], b"\x00\x0aregistered");
+            };
+            tokio::select! { _ = recv_fut => (), _ = drive_fut => panic!("driver resolved first") }
+        };
+
+        tokio::select! { _ = server_fut => (), _ = client_fut => panic!("client resolved first") };
+    }
+}
+
+#[test]
+fn critical_uni_stream_types_cannot_be_registered() {
+    fn handler(_: quic::StreamId, _: UniStream) {}
+    for ty in [0x00, 0x01, 0x02, 0x03] {
         assert_matches!(
-            incoming.accept().await.map(|_| ()).unwrap_err().kind(),
-            Kind::Application {
-                code: Code::H3_FRAME_UNEXPECTED,
-                ..
-            }
+            server::builder()
+                .register_uni_stream_handler(VarInt(ty), handler)
+                .map(|_| ())
+                .unwrap_err()
+                .kind(),
+            Kind::ForbiddenStreamType(t) if t == ty
         );
-    };
+        assert!(client::builder()
+            .register_uni_stream_handler(VarInt(ty), handler)
+            .is_err());
+    }
+    assert!(server::builder()
+        .register_uni_stream_handler(VarInt(0x54), handler)
+        .is_ok());
+}
 
-    tokio::select! { _ = server_fut => (), _ = client_fut => panic!("client resolved first") };
+transport_test! {
+    #[tokio::test]
+    async fn too_many_unknown_uni_streams() {
+        init_tracing();
+        let (mut client_conn, server_conn) = test::pair();
+
+        let client_fut = async {
+            // Left open, the streams keep being discarded
+            let mut streams = vec![];
+            for _ in 0..3 {
+                streams.push(open_unknown_uni_stream(&mut client_conn, 0x42, false).await);
+            }
+            future::pending::<()>().await;
+        };
+
+        let server_fut = async {
+            let mut incoming = server::builder()
+                .unknown_uni_stream(|_| UnknownStreamAction::Discard)
+                .max_unknown_uni_streams(2)
+                .build(wrap(server_conn))
+                .await
+                .unwrap();
+            assert_matches!(
+                incoming.accept().await.map(|_| ()).unwrap_err().kind(),
+                Kind::Application {
+                    code: Code::H3_EXCESSIVE_LOAD,
+                    ..
+                }
+            );
+        };
+
+        tokio::select! { _ = server_fut => (), _ = client_fut => panic!("client resolved first") };
+    }
+}
+
+transport_test! {
+    #[tokio::test]
+    async fn ended_unknown_uni_streams_do_not_count() {
+        init_tracing();
+        let (mut client_conn, server_conn) = test::pair();
+        let seen = Arc::new(AtomicUsize::new(0));
+
+        let client_fut = async {
+            let mut streams = vec![];
+            for ty in [0x42, 0x43, 0x44] {
+                streams.push(open_unknown_uni_stream(&mut client_conn, ty, true).await);
+            }
+
+            let (mut driver, mut send_request) = client::new(wrap(client_conn)).await.unwrap();
+            let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
+            let req_fut = async {
+                for _ in 0..2 {
+                    let response = request(&mut send_request).await.unwrap();
+                    assert_eq!(response.status(), StatusCode::IM_A_TEAPOT);
+                }
+            };
+            tokio::select! { _ = req_fut => (), _ = drive_fut => panic!("driver resolved first") }
+        };
+
+        let server_fut = async {
+            let seen = seen.clone();
+            let mut incoming = server::builder()
+                .unknown_uni_stream(move |_| {
+                    seen.fetch_add(1, Ordering::Relaxed);
+                    UnknownStreamAction::Discard
+                })
+                .max_unknown_uni_streams(1)
+                .build(wrap(server_conn))
+                .await
+                .unwrap();
+            for _ in 0..2 {
+                let (_, stream) = incoming.accept().await.unwrap().unwrap();
+                response(stream).await;
+            }
+            incoming
+        };
+
+        let (_incoming, _) = tokio::join!(server_fut, client_fut);
+        assert_eq!(seen.load(Ordering::Relaxed), 3);
+    }
+}
+
+transport_test! {
+    #[tokio::test]
+    async fn control_close_send_error() {
+        init_tracing();
+        let (mut client_conn, server_conn) = test::pair();
+
+        let client_fut = async {
+            let mut control_stream = open_uni(&mut client_conn).await;
+
+            control_stream.send_data(StreamType::CONTROL).unwrap();
+
+            //= https://www.rfc-editor.org/rfc/rfc9114#section-6.2.1
+            //= type=test
+            //# If either control
+            //# stream is closed at any point, this MUST be treated as a connection
+            //# error of type H3_CLOSED_CRITICAL_STREAM.
+            // Close the client control stream immediately
+            future::poll_fn(|cx| control_stream.poll_finish(cx))
+                .await
+                .unwrap();
+
+            // No other control stream is opened, the connection is kept open
+            future::pending::<()>().await;
+        };
+
+        let server_fut = async {
+            let mut incoming = server::Connection::new(wrap(server_conn)).await.unwrap();
+            // Driver detects that the receiving side of the control stream has been closed
+            assert_matches!(
+                incoming.accept().await.map(|_| ()).unwrap_err().kind(),
+                Kind::Application { reason: Some(reason), code: Code::H3_CLOSED_CRITICAL_STREAM, .. }
+                if *reason == *"control stream closed");
+            // Poll it once again returns the previously stored error
+            assert_matches!(
+                incoming.accept().await.map(|_| ()).unwrap_err().kind(),
+                Kind::Application { reason: Some(reason), code: Code::H3_CLOSED_CRITICAL_STREAM, .. }
+                if *reason == *"control stream closed");
+        };
+
+        tokio::select! { _ = server_fut => (), _ = client_fut => panic!("client resolved first") };
+    }
+}
+
+transport_test! {
+    #[tokio::test]
+    async fn control_stream_reset() {
+        init_tracing();
+        let (mut client_conn, server_conn) = test::pair();
+        let (settings_tx, settings_rx) = oneshot::channel();
+
+        let client_fut = async {
+            let mut control_stream = open_uni(&mut client_conn).await;
+
+            let mut settings = Settings::default();
+            settings
+                .insert(SettingId::MAX_HEADER_LIST_SIZE, 1234)
+                .unwrap();
+            control_stream.send_data(StreamType::CONTROL).unwrap();
+            control_stream
+                .send_data(Frame::<Bytes>::Settings(settings))
+                .unwrap();
+            // A reset discards what is not read yet, wait for the server to have the SETTINGS frame
+            settings_rx.await.unwrap();
+
+            //= https://www.rfc-editor.org/rfc/rfc9114#section-6.2.1
+            //= type=test
+            //# If either control
+            //# stream is closed at any point, this MUST be treated as a connection
+            //# error of type H3_CLOSED_CRITICAL_STREAM.
+            control_stream.reset(Code::H3_INTERNAL_ERROR.value());
+
+            future::pending::<()>().await;
+        };
+
+        let server_fut = async {
+            let mut incoming = server::Connection::new(wrap(server_conn)).await.unwrap();
+            let shared = incoming.shared_state().clone();
+            let mut settings_tx = Some(settings_tx);
+            let accept = incoming.accept();
+            tokio::pin!(accept);
+            let err = future::poll_fn(|cx| {
+                let res = accept.poll_unpin(cx);
+                if shared.read("test").peer_config.max_field_section_size == 1234 {
+                    if let Some(tx) = settings_tx.take() {
+                        tx.send(()).unwrap();
+                    }
+                }
+                res
+            })
+            .await
+            .map(|_| ())
+            .unwrap_err();
+            // Not the code of the reset, which applies to the stream only
+            assert_matches!(
+                err.kind(),
+                Kind::Application { reason: Some(reason), code: Code::H3_CLOSED_CRITICAL_STREAM, .. }
+                if *reason == *"control stream reset");
+        };
+
+        tokio::select! { _ = server_fut => (), _ = client_fut => panic!("client resolved first") };
+    }
+}
+
+transport_test! {
+    #[tokio::test]
+    async fn missing_settings() {
+        init_tracing();
+        let (mut client_conn, server_conn) = test::pair();
+
+        let client_fut = async {
+            let mut control_stream = open_uni(&mut client_conn).await;
+
+            control_stream.send_data(StreamType::CONTROL).unwrap();
+
+            //= https://www.rfc-editor.org/rfc/rfc9114#section-6.2.1
+            //= type=test
+            //# If the first frame of the control stream is any other frame
+            //# type, this MUST be treated as a connection error of type
+            //# H3_MISSING_SETTINGS.
+            control_stream
+                .send_data(Frame::<Bytes>::CancelPush(PushId(0)))
+                .unwrap();
+
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        };
+
+        let server_fut = async {
+            let mut incoming = server::Connection::new(wrap(server_conn)).await.unwrap();
+            assert_matches!(
+                incoming.accept().await.map(|_| ()).unwrap_err().kind(),
+                Kind::Application {
+                    code: Code::H3_MISSING_SETTINGS,
+                    ..
+                }
+            );
+        };
+
+        tokio::select! { _ = server_fut => (), _ = client_fut => panic!("client resolved first") };
+    }
+}
+
+transport_test! {
+    #[tokio::test]
+    async fn control_stream_frame_unexpected() {
+        init_tracing();
+        let (mut client_conn, server_conn) = test::pair();
+
+        let client_fut = async {
+            let mut control_stream = open_uni(&mut client_conn).await;
+
+            //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.1
+            //= type=test
+            //# If
+            //# a DATA frame is received on a control stream, the recipient MUST
+            //# respond with a connection error of type H3_FRAME_UNEXPECTED.
+            control_stream.send_data(StreamType::CONTROL).unwrap();
+            control_stream
+                .send_data(Frame::Data(Bytes::from("")))
+                .unwrap();
+
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        };
+
+        let server_fut = async {
+            let mut incoming = server::Connection::new(wrap(server_conn)).await.unwrap();
+            assert_matches!(
+                incoming.accept().await.map(|_| ()).unwrap_err().kind(),
+                Kind::Application {
+                    code: Code::H3_FRAME_UNEXPECTED,
+                    ..
+                }
+            );
+        };
+
+        tokio::select! { _ = server_fut => (), _ = client_fut => panic!("client resolved first") };
+    }
+}
+
+transport_test! {
+    #[tokio::test]
+    async fn control_stream_second_settings() {
+        init_tracing();
+        let (mut client_conn, server_conn) = test::pair();
+
+        let client_fut = async {
+            let mut control_stream = open_uni(&mut client_conn).await;
+
+            control_stream.send_data(StreamType::CONTROL).unwrap();
+            control_stream
+                .send_data(Frame::<Bytes>::Settings(Settings::default()))
+                .unwrap();
+
+            //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.4
+            //= type=test
+            //# If an endpoint receives a second SETTINGS
+            //# frame on the control stream, the endpoint MUST respond with a
+            //# connection error of type H3_FRAME_UNEXPECTED.
+            control_stream
+                .send_data(Frame::<Bytes>::Settings(Settings::default()))
+                .unwrap();
+
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        };
+
+        let server_fut = async {
+            let mut incoming = server::Connection::new(wrap(server_conn)).await.unwrap();
+            // The first SETTINGS is taken, or the error would be about it missing
+            assert_matches!(
+                incoming.accept().await.map(|_| ()).unwrap_err().kind(),
+                Kind::Application {
+                    code: Code::H3_FRAME_UNEXPECTED,
+                    reason: Some(reason),
+                    ..
+                } if reason.contains("Settings")
+            );
+        };
+
+        tokio::select! { _ = server_fut => (), _ = client_fut => panic!("client resolved first") };
+    }
+}
+
+transport_test! {
+    #[tokio::test]
+    async fn control_stream_decreasing_max_push_id() {
+        init_tracing();
+        let (mut client_conn, server_conn) = test::pair();
+
+        let client_fut = async {
+            let mut control_stream = open_uni(&mut client_conn).await;
+
+            control_stream.send_data(StreamType::CONTROL).unwrap();
+            control_stream
+                .send_data(Frame::<Bytes>::Settings(Settings::default()))
+                .unwrap();
+            control_stream
+                .send_data(Frame::<Bytes>::MaxPushId(PushId(4)))
+                .unwrap();
+            control_stream
+                .send_data(Frame::<Bytes>::CancelPush(PushId(4)))
+                .unwrap();
+            control_stream
+                .send_data(Frame::<Bytes>::MaxPushId(PushId(3)))
+                .unwrap();
+
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        };
+
+        let server_fut = async {
+            let mut incoming = server::Connection::new(wrap(server_conn)).await.unwrap();
+            // The CANCEL_PUSH is within the first limit
+            assert_matches!(
+                incoming.accept().await.map(|_| ()).unwrap_err().kind(),
+                Kind::Application {
+                    code: Code::H3_ID_ERROR,
+                    reason: Some(reason),
+                    ..
+                } if reason.contains("MAX_PUSH_ID lowered")
+            );
+        };
+
+        tokio::select! { _ = server_fut => (), _ = client_fut => panic!("client resolved first") };
+    }
+}
+
+transport_test! {
+    #[tokio::test]
+    async fn control_stream_cancel_push_above_limit() {
+        init_tracing();
+        let (mut client_conn, server_conn) = test::pair();
+
+        let client_fut = async {
+            let mut control_stream = open_uni(&mut client_conn).await;
+
+            control_stream.send_data(StreamType::CONTROL).unwrap();
+            control_stream
+                .send_data(Frame::<Bytes>::Settings(Settings::default()))
+                .unwrap();
+            control_stream
+                .send_data(Frame::<Bytes>::MaxPushId(PushId(4)))
+                .unwrap();
+            control_stream
+                .send_data(Frame::<Bytes>::CancelPush(PushId(5)))
+                .unwrap();
+
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        };
+
+        let server_fut = async {
+            let mut incoming = server::Connection::new(wrap(server_conn)).await.unwrap();
+            assert_matches!(
+                incoming.accept().await.map(|_| ()).unwrap_err().kind(),
+                Kind::Application {
+                    code: Code::H3_ID_ERROR,
+                    reason: Some(reason),
+                    ..
+                } if reason.contains("above the maximum")
+            );
+        };
+
+        tokio::select! { _ = server_fut => (), _ = client_fut => panic!("client resolved first") };
+    }
+}
+
+transport_test! {
+    #[tokio::test]
+    async fn client_control_stream_cancel_push_above_limit() {
+        init_tracing();
+        let (client_conn, mut server_conn) = test::pair();
+
+        let client_fut = async {
+            let (mut driver, _send) = client::new(wrap(client_conn)).await.unwrap();
+            // Not H3_FRAME_UNEXPECTED, CANCEL_PUSH is allowed from the server
+            assert_matches!(
+                future::poll_fn(|cx| driver.poll_close(cx))
+                    .await
+                    .unwrap_err()
+                    .kind(),
+                Kind::Application {
+                    code: Code::H3_ID_ERROR,
+                    reason: Some(reason),
+                    ..
+                } if reason.contains("no push is allowed")
+            );
+        };
+
+        let server_fut = async {
+            let mut control_stream = open_uni(&mut server_conn).await;
+            control_stream
+                .send_data((StreamType::CONTROL, Frame::Settings(Settings::default())))
+                .unwrap();
+            // The client sends no MAX_PUSH_ID frame
+            control_stream
+                .send_data(Frame::<Bytes>::CancelPush(PushId(0)))
+                .unwrap();
+
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        };
+
+        tokio::select! { _ = server_fut => panic!("client resolved first"), _ = client_fut => () };
+    }
 }
 
+// The in-memory transport has no idle timeout
 #[tokio::test]
 async fn timeout_on_control_frame_read() {
     init_tracing();
@@ -495,232 +1194,603 @@ async fn timeout_on_control_frame_read() {
     tokio::join!(server_fut, client_fut);
 }
 
-#[tokio::test]
-async fn goaway_from_server_not_request_id() {
-    init_tracing();
-    let mut pair = Pair::default();
-    let server = pair.server_inner();
-
-    let client_fut = async {
-        let connection = pair.client_inner().await;
-        let mut control_stream = connection.open_uni().await.unwrap();
-
-        let mut buf = BytesMut::new();
-        StreamType::CONTROL.encode(&mut buf);
-        control_stream.write_all(&buf[..]).await.unwrap();
-        control_stream.finish().await.unwrap(); // close the client control stream immediately
-
-        let (mut driver, _send) = client::new(h3_quinn::Connection::new(connection))
-            .await
-            .unwrap();
-
+#[test]
+fn goaway_ids() {
+    let goaway = Goaway::new(VarInt::from_u32(8));
+    assert_eq!(
+        goaway.as_stream_id().unwrap(),
+        StreamId::from(VarInt::from_u32(8))
+    );
+    assert_eq!(goaway.as_push_id(), PushId(8));
+
+    // Client-initiated unidirectional, then server-initiated bidirectional
+    for id in [2, 5] {
+        let goaway = Goaway::new(VarInt::from_u32(id));
         assert_matches!(
-            future::poll_fn(|cx| driver.poll_close(cx))
-                .await
-                .unwrap_err()
-                .kind(),
+            goaway.as_stream_id().unwrap_err().kind(),
             Kind::Application {
-                // The sent in the GoAway frame from the client is not a Request:
                 code: Code::H3_ID_ERROR,
+                level: ErrorLevel::ConnectionError,
                 ..
             }
-        )
-    };
+        );
+        // Any value is a valid push id
+        assert_eq!(goaway.as_push_id(), PushId(id as u64));
+    }
 
-    let server_fut = async {
-        let conn = server.accept().await.unwrap().await.unwrap();
-        let mut control_stream = conn.open_uni().await.unwrap();
+    // Request stream ids saturate at the last one a varint holds
+    let last = StreamId::from(VarInt::from_u32(8)) + usize::MAX;
+    assert_eq!(
+        last,
+        StreamId::from(VarInt::from_u64(VarInt::MAX.into_inner() & !3).unwrap())
+    );
+    assert!(last.is_request());
+}
 
-        let mut buf = BytesMut::new();
-        StreamType::CONTROL.encode(&mut buf);
-        Frame::<Bytes>::Settings(Settings::default()).encode(&mut buf);
+transport_test! {
+    #[tokio::test]
+    async fn goaway_from_server_not_request_id() {
+        init_tracing();
+        let (mut client_conn, mut server_conn) = test::pair();
+
+        let client_fut = async {
+            let mut control_stream = open_uni(&mut client_conn).await;
+            control_stream.send_data(StreamType::CONTROL).unwrap();
+            // Close the client control stream immediately
+            future::poll_fn(|cx| control_stream.poll_finish(cx))
+                .await
+                .unwrap();
 
-        //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.6
-        //= type=test
-        //# A client MUST treat receipt of a GOAWAY frame containing a stream ID
-        //# of any other type as a connection error of type H3_ID_ERROR.
+            let (mut driver, _send) = client::new(wrap(client_conn)).await.unwrap();
 
-        // StreamId(index=0 << 2 | dir=Uni << 1 | initiator=Server as u64)
-        Frame::<Bytes>::Goaway(VarInt(0u64 << 2 | 0 << 1 | 1)).encode(&mut buf);
-        control_stream.write_all(&buf[..]).await.unwrap();
+            assert_matches!(
+                future::poll_fn(|cx| driver.poll_close(cx))
+                    .await
+                    .unwrap_err()
+                    .kind(),
+                Kind::Application {
+                    // The sent in the GoAway frame from the client is not a Request:
+                    code: Code::H3_ID_ERROR,
+                    ..
+                }
+            )
+        };
 
-        tokio::time::sleep(Duration::from_secs(10)).await;
-    };
+        let server_fut = async {
+            let mut control_stream = open_uni(&mut server_conn).await;
+            control_stream
+                .send_data((StreamType::CONTROL, Frame::Settings(Settings::default())))
+                .unwrap();
+
+            //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.6
+            //= type=test
+            //# A client MUST treat receipt of a GOAWAY frame containing a stream ID
+            //# of any other type as a connection error of type H3_ID_ERROR.
+
+            // StreamId(index=0 << 2 | dir=Uni << 1 | initiator=Server as u64)
+            control_stream
+                .send_data(Frame::Goaway(VarInt(0u64 << 2 | 0 << 1 | 1)))
+                .unwrap();
+
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        };
 
-    tokio::select! { _ = server_fut => panic!("client resolved first"), _ = client_fut => () };
+        tokio::select! { _ = server_fut => panic!("client resolved first"), _ = client_fut => () };
+    }
 }
 
-#[tokio::test]
-async fn graceful_shutdown_server_rejects() {
-    init_tracing();
-    let mut pair = Pair::default();
-    let mut server = pair.server();
+transport_test! {
+    #[tokio::test]
+    async fn goaway_from_client_push_id() {
+        init_tracing();
+        let (mut client_conn, server_conn) = test::pair();
 
-    let client_fut = async {
-        let (_driver, mut send_request) = client::new(pair.client().await).await.unwrap();
+        let client_fut = async {
+            let mut control_stream = open_uni(&mut client_conn).await;
+            control_stream
+                .send_data((StreamType::CONTROL, Frame::Settings(Settings::default())))
+                .unwrap();
 
-        let mut first = send_request
-            .send_request(Request::get("http://no.way").body(()).unwrap())
-            .await
-            .unwrap();
-        let mut rejected = send_request
-            .send_request(Request::get("http://no.way").body(()).unwrap())
-            .await
-            .unwrap();
-        let first = first.recv_response().await;
-        let rejected = rejected.recv_response().await;
+            //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.6
+            //= type=test
+            //# In the client-to-server direction, the GOAWAY frame carries a push ID
+            //# encoded as a variable-length integer.
 
-        assert_matches!(first, Ok(_));
-        assert_matches!(
-            rejected.unwrap_err().kind(),
-            Kind::Application {
-                code: Code::H3_REQUEST_REJECTED,
-                ..
-            }
-        );
-    };
+            // Not the id of a request stream, but a valid push id
+            control_stream
+                .send_data(Frame::<Bytes>::Goaway(VarInt(5)))
+                .unwrap();
+            control_stream
+                .send_data(Frame::<Bytes>::Goaway(VarInt(6)))
+                .unwrap();
 
-    let server_fut = async {
-        let conn = server.next().await;
-        let mut incoming = server::Connection::new(conn).await.unwrap();
-        let (_, stream) = incoming.accept().await.unwrap().unwrap();
-        response(stream).await;
-        incoming.shutdown(0).await.unwrap();
-        assert_matches!(incoming.accept().await.map(|x| x.map(|_| ())), Ok(None));
-        server.endpoint.wait_idle().await;
-    };
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        };
 
-    tokio::join!(server_fut, client_fut);
+        let server_fut = async {
+            let mut incoming = server::Connection::new(wrap(server_conn)).await.unwrap();
+            // Only the second GOAWAY is refused, for raising the push id
+            assert_matches!(
+                incoming.accept().await.map(|_| ()).unwrap_err().kind(),
+                Kind::Application {
+                    code: Code::H3_ID_ERROR,
+                    reason: Some(reason),
+                    ..
+                } if reason.contains("GoAway(6) greater than the former one (5)")
+            );
+        };
+
+        tokio::select! { _ = server_fut => (), _ = client_fut => panic!("client resolved first") };
+    }
 }
 
-#[tokio::test]
-async fn graceful_shutdown_grace_interval() {
-    init_tracing();
-    let mut pair = Pair::default();
-    let mut server = pair.server();
+transport_test! {
+    #[tokio::test]
+    async fn graceful_shutdown_server_rejects() {
+        init_tracing();
+        let (client_conn, server_conn) = test::pair();
 
-    let client_fut = async {
-        let (mut driver, mut send_request) = client::new(pair.client().await).await.unwrap();
+        let client_fut = async {
+            let (_driver, mut send_request) = client::new(wrap(client_conn)).await.unwrap();
 
-        // Sent as the connection is not shutting down
-        let mut first = send_request
-            .send_request(Request::get("http://no.way").body(()).unwrap())
-            .await
-            .unwrap();
-        // Sent as the connection is shutting down, but GoAway has not been received yet
-        let mut in_flight = send_request
-            .send_request(Request::get("http://no.way").body(()).unwrap())
-            .await
-            .unwrap();
-        let first = first.recv_response().await;
-        let in_flight = in_flight.recv_response().await;
+            let mut first = send_request
+                .send_request(Request::get("http://no.way").body(()).unwrap())
+                .await
+                .unwrap();
+            let mut rejected = send_request
+                .send_request(Request::get("http://no.way").body(()).unwrap())
+                .await
+                .unwrap();
+            let first = first.recv_response().await;
+            let rejected = rejected.recv_response().await;
+
+            assert_matches!(first, Ok(_));
+            assert_matches!(
+                rejected.unwrap_err().kind(),
+                Kind::Application {
+                    code: Code::H3_REQUEST_REJECTED,
+                    ..
+                }
+            );
+        };
 
-        // Will not be sent as client's driver already received the GoAway
-        let too_late = async move {
-            tokio::time::sleep(Duration::from_millis(15)).await;
-            request(send_request).await
+        let server_fut = async {
+            let mut incoming = server::Connection::new(wrap(server_conn)).await.unwrap();
+            let (_, stream) = incoming.accept().await.unwrap().unwrap();
+            response(stream).await;
+            incoming.shutdown(0).await.unwrap();
+            assert_matches!(incoming.accept().await.map(|x| x.map(|_| ())), Ok(None));
+            incoming
         };
-        let driver = future::poll_fn(|cx| driver.poll_close(cx));
 
-        let (too_late, driver) = tokio::join!(too_late, driver);
-        assert_matches!(first, Ok(_));
-        assert_matches!(in_flight, Ok(_));
-        assert_matches!(too_late.unwrap_err().kind(), Kind::Closing);
-        assert_matches!(driver, Ok(_));
-    };
+        tokio::join!(server_fut, client_fut);
+    }
+}
 
-    let server_fut = async {
-        let conn = server.next().await;
-        let mut incoming = server::Connection::new(conn).await.unwrap();
-        let (_, first) = incoming.accept().await.unwrap().unwrap();
-        incoming.shutdown(1).await.unwrap();
-        let (_, in_flight) = incoming.accept().await.unwrap().unwrap();
-        response(first).await;
-        response(in_flight).await;
+transport_test! {
+    #[tokio::test]
+    async fn graceful_shutdown_grace_interval() {
+        init_tracing();
+        let (client_conn, server_conn) = test::pair();
 
-        while let Ok(Some((_, stream))) = incoming.accept().await {
-            response(stream).await;
-        }
-        // Ensure `too_late` request is executed as the connection is still
-        // closing (no QUIC `Close` frame has been fired yet)
-        tokio::time::sleep(Duration::from_millis(50)).await;
-    };
+        let client_fut = async {
+            let (mut driver, mut send_request) = client::new(wrap(client_conn)).await.unwrap();
 
-    tokio::join!(server_fut, client_fut);
+            // Sent as the connection is not shutting down
+            let mut first = send_request
+                .send_request(Request::get("http://no.way").body(()).unwrap())
+                .await
+                .unwrap();
+            // Sent as the connection is shutting down, but GoAway has not been received yet
+            let mut in_flight = send_request
+                .send_request(Request::get("http://no.way").body(()).unwrap())
+                .await
+                .unwrap();
+            let first_response = first.recv_response().await;
+            let in_flight_response = in_flight.recv_response().await;
+            // Requests are over, so the driver closes the connection once `send_request` is dropped
+            drop((first, in_flight));
+
+            // Will not be sent as client's driver already received the GoAway
+            let too_late = async move {
+                tokio::time::sleep(Duration::from_millis(15)).await;
+                request(send_request).await
+            };
+            let driver = future::poll_fn(|cx| driver.poll_close(cx));
+
+            let (too_late, driver) = tokio::join!(too_late, driver);
+            assert_matches!(first_response, Ok(_));
+            assert_matches!(in_flight_response, Ok(_));
+            assert_matches!(too_late.unwrap_err().kind(), Kind::Closing);
+            assert_matches!(driver, Ok(_));
+        };
+
+        let server_fut = async {
+            let mut incoming = server::Connection::new(wrap(server_conn)).await.unwrap();
+            let (_, first) = incoming.accept().await.unwrap().unwrap();
+            incoming.shutdown(1).await.unwrap();
+            let (_, in_flight) = incoming.accept().await.unwrap().unwrap();
+            response(first).await;
+            response(in_flight).await;
+
+            while let Ok(Some((_, stream))) = incoming.accept().await {
+                response(stream).await;
+            }
+            // Ensure `too_late` request is executed as the connection is still
+            // closing (no QUIC `Close` frame has been fired yet)
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        };
+
+        tokio::join!(server_fut, client_fut);
+    }
 }
 
-#[tokio::test]
-async fn graceful_shutdown_closes_when_idle() {
-    init_tracing();
-    let mut pair = Pair::default();
-    let mut server = pair.server();
+transport_test! {
+    #[tokio::test]
+    async fn graceful_shutdown_closes_when_idle() {
+        init_tracing();
+        let (client_conn, server_conn) = test::pair();
 
-    let client_fut = async {
-        let (mut driver, mut send_request) = client::new(pair.client().await).await.unwrap();
+        let client_fut = async {
+            let (mut driver, mut send_request) = client::new(wrap(client_conn)).await.unwrap();
 
-        // Make continuous requests, ignoring GoAway because the connection is not driven
-        while request(&mut send_request).await.is_ok() {
-            tokio::task::yield_now().await;
-        }
-        assert_matches!(
-            future::poll_fn(|cx| {
-                println!("client drive");
-                driver.poll_close(cx)
-            })
-            .await,
-            Ok(())
-        );
-    };
+            // Make continuous requests, ignoring GoAway because the connection is not driven
+            while request(&mut send_request).await.is_ok() {
+                tokio::task::yield_now().await;
+            }
+            assert_matches!(
+                future::poll_fn(|cx| {
+                    println!("client drive");
+                    driver.poll_close(cx)
+                })
+                .await,
+                Ok(())
+            );
+        };
 
-    let server_fut = async {
-        let conn = server.next().await;
-        let mut incoming = server::Connection::new(conn).await.unwrap();
+        let server_fut = async {
+            let mut incoming = server::Connection::new(wrap(server_conn)).await.unwrap();
 
-        let mut count = 0;
+            let mut count = 0;
 
-        while let Ok(Some((_, stream))) = incoming.accept().await {
-            count += 1;
-            if count == 4 {
-                incoming.shutdown(2).await.unwrap();
+            while let Ok(Some((_, stream))) = incoming.accept().await {
+                count += 1;
+                if count == 4 {
+                    incoming.shutdown(2).await.unwrap();
+                }
+
+                response(stream).await;
             }
+        };
 
-            response(stream).await;
-        }
+        tokio::select! {
+            _ = client_fut => (),
+            r = tokio::time::timeout(Duration::from_millis(100), server_fut)
+                => assert_matches!(r, Ok(())),
+        };
+    }
+}
+
+transport_test! {
+    #[tokio::test]
+    async fn graceful_shutdown_client() {
+        init_tracing();
+        let (client_conn, server_conn) = test::pair();
+
+        let client_fut = async {
+            let (mut driver, mut _send_request) = client::new(wrap(client_conn)).await.unwrap();
+            driver.shutdown(0).await.unwrap();
+            assert_matches!(
+                future::poll_fn(|cx| {
+                    println!("client drive");
+                    driver.poll_close(cx)
+                })
+                .await,
+                Ok(())
+            );
+        };
+
+        let server_fut = async {
+            let mut incoming = server::Connection::new(wrap(server_conn)).await.unwrap();
+            assert!(incoming.accept().await.unwrap().is_none());
+        };
+
+        tokio::join!(server_fut, client_fut);
+    }
+}
+
+transport_test! {
+    #[tokio::test]
+    async fn graceful_shutdown_waits_for_the_request_body() {
+        init_tracing();
+        let (client_conn, server_conn) = test::pair();
+
+        let client_fut = async {
+            let (mut driver, mut send_request) = client::new(wrap(client_conn)).await.unwrap();
+            let req_fut = async {
+                let mut stream = send_request
+                    .send_request(Request::post("http://no.way").body(()).unwrap())
+                    .await
+                    .unwrap();
+                let response = stream.recv_response().await.unwrap();
+                assert_eq!(response.status(), StatusCode::IM_A_TEAPOT);
+                assert!(stream.recv_data().await.unwrap().is_none());
+
+                // The server is done sending, the request is still ongoing
+                driver.shutdown(0).await.unwrap();
+                tokio::task::yield_now().await;
+                stream.send_data(Bytes::from("body")).await.unwrap();
+                stream.finish().await.unwrap();
+                stream
+            };
+            let _stream = req_fut.await;
+            let _ = future::poll_fn(|cx| driver.poll_close(cx)).await;
+        };
+
+        let server_fut = async {
+            let mut incoming = server::Connection::new(wrap(server_conn)).await.unwrap();
+            let (_, mut stream) = incoming.accept().await.unwrap().unwrap();
+            stream
+                .send_response(
+                    Response::builder()
+                        .status(StatusCode::IM_A_TEAPOT)
+                        .body(())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            stream.finish().await.unwrap();
+
+            let body_received = std::cell::Cell::new(false);
+            let recv_body = async {
+                let body = stream.recv_data().await.unwrap().unwrap();
+                assert_eq!(body.chunk(), b"body");
+                assert!(stream.recv_data().await.unwrap().is_none());
+                body_received.set(true);
+            };
+            let accept = async {
+                // Resolves once both directions are done, though the stream is still held
+                assert!(incoming.accept().await.unwrap().is_none());
+                assert!(body_received.get(), "request ended before its body");
+            };
+            tokio::join!(recv_body, accept);
+            drop(stream);
+        };
+
+        tokio::join!(server_fut, client_fut);
+    }
+}
+
+transport_test! {
+    #[tokio::test]
+    async fn idle_client_waits_for_the_response() {
+        init_tracing();
+        let (client_conn, server_conn) = test::pair();
+
+        let client_fut = async {
+            let (mut driver, mut send_request) = client::new(wrap(client_conn)).await.unwrap();
+            let mut stream = send_request
+                .send_request(Request::post("http://no.way").body(()).unwrap())
+                .await
+                .unwrap();
+            stream.send_data(Bytes::from("body")).await.unwrap();
+            stream.finish().await.unwrap();
+            // Done sending, and no other request can be sent
+            drop(send_request);
+
+            let response_received = std::cell::Cell::new(false);
+            let recv_response = async {
+                let response = stream.recv_response().await.unwrap();
+                assert_eq!(response.status(), StatusCode::IM_A_TEAPOT);
+                let mut body = BytesMut::new();
+                while let Some(chunk) = stream.recv_data().await.unwrap() {
+                    body.extend_from_slice(chunk.chunk());
+                }
+                assert_eq!(&body[..], b"one, two");
+                response_received.set(true);
+            };
+            let drive = async {
+                // Resolves once both directions are done, though the stream is still held
+                assert_matches!(future::poll_fn(|cx| driver.poll_close(cx)).await, Ok(()));
+                assert!(response_received.get(), "request ended before its response");
+            };
+            tokio::join!(recv_response, drive);
+            drop(stream);
+        };
+
+        let server_fut = async {
+            let mut incoming = server::Connection::new(wrap(server_conn)).await.unwrap();
+            let (_, mut stream) = incoming.accept().await.unwrap().unwrap();
+            assert!(stream.recv_data().await.unwrap().is_some());
+            assert!(stream.recv_data().await.unwrap().is_none());
+            stream
+                .send_response(
+                    Response::builder()
+                        .status(StatusCode::IM_A_TEAPOT)
+                        .body(())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            for chunk in ["one, ", "two"] {
+                stream.send_data(Bytes::from(chunk)).await.unwrap();
+                tokio::task::yield_now().await;
+            }
+            stream.finish().await.unwrap();
+            let _ = incoming.accept().await;
+        };
+
+        tokio::join!(server_fut, client_fut);
+    }
+}
+
+transport_test! {
+    #[tokio::test]
+    async fn client_driver_processes_control_stream_alone() {
+        init_tracing();
+        let (client_conn, server_conn) = test::pair();
+        let (goaway_tx, goaway_rx) = tokio::sync::oneshot::channel();
+
+        let client_fut = async {
+            let (driver, mut send_request) = client::new(wrap(client_conn)).await.unwrap();
+            let driver = tokio::spawn(driver.drive());
+
+            // No request is polled while the server sends GOAWAY
+            goaway_rx.await.unwrap();
+            wait_closing(send_request.shared_state()).await;
+            assert_matches!(
+                request(&mut send_request).await.unwrap_err().kind(),
+                Kind::Closing
+            );
+
+            drop(send_request);
+            assert_matches!(driver.await.unwrap(), Ok(()));
+        };
+
+        let server_fut = async {
+            let mut incoming = server::Connection::new(wrap(server_conn)).await.unwrap();
+            incoming.shutdown(0).await.unwrap();
+            goaway_tx.send(()).unwrap();
+            assert!(incoming.accept().await.unwrap().is_none());
+        };
+
+        tokio::join!(server_fut, client_fut);
+    }
+}
+
+transport_test! {
+    #[tokio::test]
+    async fn server_drive_without_accepting() {
+        init_tracing();
+        let (client_conn, server_conn) = test::pair();
+        let (done_tx, done_rx) = tokio::sync::oneshot::channel();
+
+        let client_fut = async {
+            let (mut driver, _send_request) = client::new(wrap(client_conn)).await.unwrap();
+            driver.shutdown(0).await.unwrap();
+            let drive = async { future::poll_fn(|cx| driver.poll_drive(cx)).await };
+            tokio::select! {
+                _ = done_rx => (),
+                _ = drive => panic!("driver resolved first"),
+            };
+            // Dropping the driver closes the connection
+        };
+
+        let server_fut = async {
+            let mut incoming = server::Connection::new(wrap(server_conn)).await.unwrap();
+            let shared = incoming.shared_state().clone();
+
+            tokio::select! {
+                _ = wait_closing(&shared) => (),
+                _ = incoming.drive() => panic!("driver resolved first"),
+            };
+            done_tx.send(()).unwrap();
+            assert_matches!(incoming.drive().await, Ok(()));
+        };
+
+        tokio::join!(server_fut, client_fut);
+    }
+}
+
+#[tokio::test]
+async fn send_priority_update_frame() {
+    init_tracing();
+    let (mut client_conn, mut server_conn) = test::pair();
+
+    let client_fut = async {
+        let mut control = open_uni(&mut client_conn).await;
+        let priority = Priority {
+            urgency: 5,
+            incremental: true,
+        };
+        send_priority_update(&mut control, 8, &priority)
+            .await
+            .unwrap();
+        future::poll_fn(|cx| control.poll_finish(cx)).await.unwrap();
+        tokio::time::sleep(Duration::from_secs(10)).await;
     };
 
-    tokio::select! {
-        _ = client_fut => (),
-        r = tokio::time::timeout(Duration::from_millis(100), server_fut)
-            => assert_matches!(r, Ok(())),
+    let server_fut = async {
+        let mut buf = read_uni(&mut server_conn).await;
+        let ty = decode_varint(&mut buf).unwrap().into_inner();
+        assert_eq!(ty, FrameType::PRIORITY_UPDATE_REQUEST.into_inner());
+        let len = decode_varint(&mut buf).unwrap().into_inner();
+        assert_eq!(len as usize, buf.len());
+        assert_eq!(decode_varint(&mut buf).unwrap().into_inner(), 8);
+        assert_eq!(buf, "u=5, i");
     };
+
+    tokio::select! { _ = server_fut => (), _ = client_fut => panic!("client resolved first") };
 }
 
 #[tokio::test]
-async fn graceful_shutdown_client() {
+async fn send_cancel_push_frame() {
     init_tracing();
-    let mut pair = Pair::default();
-    let mut server = pair.server();
+    let (mut client_conn, mut server_conn) = test::pair();
 
     let client_fut = async {
-        let (mut driver, mut _send_request) = client::new(pair.client().await).await.unwrap();
-        driver.shutdown(0).await.unwrap();
-        assert_matches!(
-            future::poll_fn(|cx| {
-                println!("client drive");
-                driver.poll_close(cx)
-            })
-            .await,
-            Ok(())
-        );
+        let mut control = open_uni(&mut client_conn).await;
+        cancel_push(&mut control, 7).await.unwrap();
+        future::poll_fn(|cx| control.poll_finish(cx)).await.unwrap();
+        tokio::time::sleep(Duration::from_secs(10)).await;
     };
 
     let server_fut = async {
-        let conn = server.next().await;
-        let mut incoming = server::Connection::new(conn).await.unwrap();
-        assert!(incoming.accept().await.unwrap().is_none());
+        let mut buf = read_uni(&mut server_conn).await;
+        assert_eq!(
+            Frame::<PayloadLen>::decode(&mut buf).unwrap(),
+            Frame::<Bytes>::CancelPush(PushId(7))
+        );
+        assert!(buf.is_empty());
     };
 
-    tokio::join!(server_fut, client_fut);
+    tokio::select! { _ = server_fut => (), _ = client_fut => panic!("client resolved first") };
+}
+
+async fn open_uni(conn: &mut test::Connection) -> test::SendStream {
+    future::poll_fn(|cx| quic::Connection::poll_open_send(conn, cx))
+        .await
+        .unwrap()
+}
+
+/// Accepts the next unidirectional stream, and reads it to the end
+async fn read_uni(conn: &mut test::Connection) -> Bytes {
+    let mut stream = future::poll_fn(|cx| quic::Connection::poll_accept_recv(conn, cx))
+        .await
+        .unwrap()
+        .expect("uni stream");
+    let mut buf = BytesMut::new();
+    while let Some(chunk) = future::poll_fn(|cx| quic::RecvStream::poll_data(&mut stream, cx))
+        .await
+        .unwrap()
+    {
+        buf.extend_from_slice(&chunk);
+    }
+    buf.freeze()
+}
+
+async fn open_unknown_uni_stream(
+    conn: &mut test::Connection,
+    ty: u32,
+    finish: bool,
+) -> test::SendStream {
+    let mut stream = open_uni(conn).await;
+    let mut buf = BytesMut::new();
+    VarInt::from_u32(ty).encode(&mut buf);
+    let ty = StreamType::decode(&mut buf).unwrap();
+    stream
+        .send_data((ty, Frame::Data(Bytes::from_static(b"meaningless"))))
+        .unwrap();
+    if finish {
+        future::poll_fn(|cx| stream.poll_finish(cx)).await.unwrap();
+    }
+    stream
+}
+
+async fn wait_closing(shared: &SharedStateRef) {
+    while !shared.read("wait closing").closing {
+        tokio::time::sleep(Duration::from_millis(2)).await;
+    }
 }
 
 async fn request<T, O, B>(mut send_request: T) -> Result<Response<()>, Error>
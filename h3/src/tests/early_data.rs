@@ -0,0 +1,460 @@
+use std::{
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use bytes::{Buf, Bytes};
+use futures_util::future;
+use http::{Method, Request, Response, StatusCode};
+
+use crate::{
+    client,
+    error::Code,
+    ext::{AllowEarlyData, EarlyData},
+    quic::{
+        self, BoxedBidiStream, BoxedConnection, BoxedOpenStreams, BoxedRecvStream, BoxedSendStream,
+        StreamId, WriteBuf,
+    },
+    server,
+};
+
+use super::h3_quinn;
+use super::{init_tracing, Pair};
+
+/// Simulated 0-RTT state, the handshake completes on the first `poll_handshake()`
+struct Handshake {
+    complete: bool,
+    accepted: bool,
+}
+
+type SharedHandshake = Arc<Mutex<Handshake>>;
+
+fn handshake(accepted: bool) -> SharedHandshake {
+    Arc::new(Mutex::new(Handshake {
+        complete: false,
+        accepted,
+    }))
+}
+
+fn is_early_data(handshake: &SharedHandshake) -> bool {
+    !handshake.lock().unwrap().complete
+}
+
+fn poll_handshake(handshake: &SharedHandshake) -> Poll<bool> {
+    let mut handshake = handshake.lock().unwrap();
+    handshake.complete = true;
+    Poll::Ready(handshake.accepted)
+}
+
+/// Wraps a quinn connection, streams opened in 0-RTT are lost if early data is rejected
+struct EarlyConnection {
+    inner: BoxedConnection,
+    handshake: SharedHandshake,
+}
+
+impl EarlyConnection {
+    fn new(conn: h3_quinn::Connection, handshake: SharedHandshake) -> Self {
+        Self {
+            inner: conn.into(),
+            handshake,
+        }
+    }
+}
+
+impl quic::Connection<Bytes> for EarlyConnection {
+    type BidiStream = BoxedBidiStream;
+    type SendStream = BoxedSendStream;
+    type RecvStream = BoxedRecvStream;
+    type OpenStreams = EarlyOpenStreams;
+    type Error = Box<dyn quic::Error>;
+
+    fn poll_accept_recv(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<Self::RecvStream>, Self::Error>> {
+        self.inner.poll_accept_recv(cx)
+    }
+
+    fn poll_accept_bidi(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<Self::BidiStream>, Self::Error>> {
+        self.inner.poll_accept_bidi(cx)
+    }
+
+    fn poll_open_bidi(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::BidiStream, Self::Error>> {
+        self.inner.poll_open_bidi(cx)
+    }
+
+    fn poll_open_send(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::SendStream, Self::Error>> {
+        self.inner.poll_open_send(cx)
+    }
+
+    fn opener(&self) -> Self::OpenStreams {
+        EarlyOpenStreams {
+            inner: self.inner.opener(),
+            handshake: self.handshake.clone(),
+        }
+    }
+
+    fn close(&mut self, code: Code, reason: &[u8]) {
+        self.inner.close(code, reason)
+    }
+}
+
+impl quic::EarlyDataExt for EarlyConnection {
+    fn is_early_data(&self) -> bool {
+        is_early_data(&self.handshake)
+    }
+
+    fn poll_handshake(&mut self, _: &mut Context<'_>) -> Poll<bool> {
+        poll_handshake(&self.handshake)
+    }
+}
+
+#[derive(Clone)]
+struct EarlyOpenStreams {
+    inner: BoxedOpenStreams,
+    handshake: SharedHandshake,
+}
+
+impl quic::OpenStreams<Bytes> for EarlyOpenStreams {
+    type BidiStream = BoxedBidiStream;
+    type SendStream = BoxedSendStream;
+    type RecvStream = BoxedRecvStream;
+    type Error = Box<dyn quic::Error>;
+
+    fn poll_open_bidi(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::BidiStream, Self::Error>> {
+        let lost = {
+            let handshake = self.handshake.lock().unwrap();
+            !handshake.complete && !handshake.accepted
+        };
+        if lost {
+            return Poll::Ready(Ok(BoxedBidiStream::new(LostStream)));
+        }
+        self.inner.poll_open_bidi(cx)
+    }
+
+    fn poll_open_send(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::SendStream, Self::Error>> {
+        self.inner.poll_open_send(cx)
+    }
+
+    fn close(&mut self, code: Code, reason: &[u8]) {
+        self.inner.close(code, reason)
+    }
+}
+
+impl quic::EarlyDataExt for EarlyOpenStreams {
+    fn is_early_data(&self) -> bool {
+        is_early_data(&self.handshake)
+    }
+
+    fn poll_handshake(&mut self, _: &mut Context<'_>) -> Poll<bool> {
+        poll_handshake(&self.handshake)
+    }
+}
+
+/// A stream sent in rejected 0-RTT data: the server never sees it
+struct LostStream;
+
+impl quic::SendStream<Bytes> for LostStream {
+    type Error = Box<dyn quic::Error>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn send_data<T: Into<WriteBuf<Bytes>>>(&mut self, _: T) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn poll_finish(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn reset(&mut self, _: u64) {}
+
+    fn send_id(&self) -> StreamId {
+        StreamId::FIRST_REQUEST
+    }
+}
+
+impl quic::RecvStream for LostStream {
+    type Buf = Bytes;
+    type Error = Box<dyn quic::Error>;
+
+    fn poll_data(&mut self, _: &mut Context<'_>) -> Poll<Result<Option<Self::Buf>, Self::Error>> {
+        Poll::Pending
+    }
+
+    fn stop_sending(&mut self, _: u64) {}
+
+    fn recv_id(&self) -> StreamId {
+        StreamId::FIRST_REQUEST
+    }
+}
+
+impl quic::BidiStream<Bytes> for LostStream {
+    type SendStream = LostStream;
+    type RecvStream = LostStream;
+
+    fn split(self) -> (Self::SendStream, Self::RecvStream) {
+        (LostStream, LostStream)
+    }
+}
+
+async fn early_request(accepted: bool) {
+    init_tracing();
+    let mut pair = Pair::default();
+    let mut server = pair.server();
+
+    let client_fut = async {
+        let handshake = handshake(accepted);
+        let conn = EarlyConnection::new(pair.client().await, handshake.clone());
+        let (mut driver, mut client) = client::new(conn).await.expect("client init");
+        let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
+        let req_fut = async {
+            let mut request_stream = client
+                .send_request_early(Request::get("http://localhost/salut").body(()).unwrap())
+                .await
+                .expect("request");
+            // The body is sent before the handshake completes too
+            request_stream
+                .send_data(Bytes::from("early body"))
+                .await
+                .expect("send_data");
+            request_stream.finish().await.expect("client finish");
+            assert!(is_early_data(&handshake));
+
+            if !accepted {
+                // The lost request fails, rather than waiting for a response forever
+                let err = request_stream.recv_response().await.unwrap_err();
+                assert!(err.is_early_data_rejected(), "{:?}", err);
+            }
+
+            let replayed = client
+                .confirm_early(&mut request_stream)
+                .await
+                .expect("confirm early");
+            assert_eq!(replayed, !accepted);
+            if replayed {
+                request_stream
+                    .send_data(Bytes::from("early body"))
+                    .await
+                    .expect("send_data");
+                request_stream.finish().await.expect("client finish");
+            }
+
+            let response = request_stream.recv_response().await.expect("recv response");
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = request_stream
+                .recv_data()
+                .await
+                .expect("recv data")
+                .expect("body");
+            assert_eq!(body.chunk(), b"wonderful hypertext");
+        };
+        tokio::select! { _ = req_fut => (), _ = drive_fut => () }
+    };
+
+    let server_fut = async {
+        let conn = server.next().await;
+        let mut incoming = server::Connection::new(conn).await.unwrap();
+
+        let (request, mut request_stream) = incoming.accept().await.expect("accept").unwrap();
+        assert_eq!(request.uri().path(), "/salut");
+        let body = request_stream
+            .recv_data()
+            .await
+            .expect("recv data")
+            .expect("body");
+        assert_eq!(body.chunk(), b"early body");
+        request_stream
+            .send_response(Response::builder().status(200).body(()).unwrap())
+            .await
+            .expect("send_response");
+        request_stream
+            .send_data("wonderful hypertext".into())
+            .await
+            .expect("send_data");
+        request_stream.finish().await.expect("finish");
+
+        // A replayed request must not be received twice
+        assert!(matches!(incoming.accept().await, Ok(None)));
+    };
+
+    tokio::join!(server_fut, client_fut);
+}
+
+#[tokio::test]
+async fn early_data_accepted() {
+    early_request(true).await;
+}
+
+#[tokio::test]
+async fn early_data_rejected_is_replayed() {
+    early_request(false).await;
+}
+
+#[tokio::test]
+async fn early_data_rejected_split_stream() {
+    init_tracing();
+    let mut pair = Pair::default();
+    let _server = pair.server();
+
+    let handshake = handshake(false);
+    let conn = EarlyConnection::new(pair.client().await, handshake.clone());
+    let (_driver, mut client) = client::new(conn).await.expect("client init");
+
+    let request_stream = client
+        .send_request_early(Request::get("http://localhost/salut").body(()).unwrap())
+        .await
+        .expect("request");
+    assert!(is_early_data(&handshake));
+    let (mut send, mut recv) = request_stream.split();
+    send.finish().await.expect("client finish");
+
+    // The lost request still fails on the receiving half
+    let err = recv.recv_response().await.unwrap_err();
+    assert!(err.is_early_data_rejected(), "{:?}", err);
+}
+
+#[tokio::test]
+async fn early_non_idempotent_refused() {
+    init_tracing();
+    let mut pair = Pair::default();
+    let _server = pair.server();
+
+    let conn = EarlyConnection::new(pair.client().await, handshake(true));
+    let (_driver, mut client) = client::new(conn).await.expect("client init");
+
+    let err = client
+        .send_request_early(Request::post("http://localhost/salut").body(()).unwrap())
+        .await
+        .map(|_| ())
+        .unwrap_err();
+    assert_eq!(err.try_get_code(), Some(Code::H3_REQUEST_REJECTED));
+
+    client
+        .send_request_early(
+            Request::post("http://localhost/salut")
+                .extension(AllowEarlyData)
+                .body(())
+                .unwrap(),
+        )
+        .await
+        .expect("request allowed early");
+}
+
+#[tokio::test]
+async fn server_marks_and_rejects_early_requests() {
+    init_tracing();
+    let mut pair = Pair::default();
+    let server = pair.server();
+
+    let client_fut = async {
+        let (mut driver, mut client) = client::new(pair.client().await).await.expect("client init");
+        let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
+        let req_fut = async {
+            for (method, status) in [
+                (Method::POST, StatusCode::TOO_EARLY),
+                (Method::GET, StatusCode::OK),
+            ] {
+                let mut request_stream = client
+                    .send_request(
+                        Request::builder()
+                            .method(method)
+                            .uri("http://localhost/salut")
+                            .body(())
+                            .unwrap(),
+                    )
+                    .await
+                    .expect("request");
+                request_stream.finish().await.expect("client finish");
+                let response = request_stream.recv_response().await.expect("recv response");
+                assert_eq!(response.status(), status);
+            }
+        };
+        tokio::select! { _ = req_fut => (), _ = drive_fut => () }
+    };
+
+    let server_fut = async {
+        let conn = server.endpoint.accept().await.unwrap().await.unwrap();
+        let conn = EarlyConnection::new(h3_quinn::Connection::new(conn), handshake(true));
+        let mut incoming = server::builder()
+            .reject_early_unsafe(true)
+            .build(conn)
+            .await
+            .unwrap();
+
+        let (request, mut request_stream) = incoming.accept_early().await.expect("accept").unwrap();
+        assert_eq!(request.method(), Method::GET);
+        assert_eq!(request.extensions().get::<EarlyData>(), Some(&EarlyData));
+        request_stream
+            .send_response(Response::builder().status(200).body(()).unwrap())
+            .await
+            .expect("send_response");
+        request_stream.finish().await.expect("finish");
+    };
+
+    tokio::join!(server_fut, client_fut);
+}
+
+#[tokio::test]
+async fn server_handshake_complete_before_accept() {
+    init_tracing();
+    let mut pair = Pair::default();
+    let server = pair.server();
+
+    let client_fut = async {
+        let (mut driver, mut client) = client::new(pair.client().await).await.expect("client init");
+        let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
+        let req_fut = async {
+            let mut request_stream = client
+                .send_request(Request::post("http://localhost/salut").body(()).unwrap())
+                .await
+                .expect("request");
+            request_stream.finish().await.expect("client finish");
+            let response = request_stream.recv_response().await.expect("recv response");
+            assert_eq!(response.status(), StatusCode::OK);
+        };
+        tokio::select! { _ = req_fut => (), _ = drive_fut => () }
+    };
+
+    let server_fut = async {
+        let conn = server.endpoint.accept().await.unwrap().await.unwrap();
+        let handshake = handshake(true);
+        let conn = EarlyConnection::new(h3_quinn::Connection::new(conn), handshake.clone());
+        let mut incoming = server::builder()
+            .reject_early_unsafe(true)
+            .build(conn)
+            .await
+            .unwrap();
+
+        // Whether the request arrived in 0-RTT is only known from the handshake state when its
+        // stream is accepted, it is taken for a 1-RTT one once the handshake is complete
+        assert_eq!(poll_handshake(&handshake), Poll::Ready(true));
+        let (request, mut request_stream) = incoming.accept_early().await.expect("accept").unwrap();
+        assert_eq!(request.method(), Method::POST);
+        assert_eq!(request.extensions().get::<EarlyData>(), None);
+        request_stream
+            .send_response(Response::builder().status(200).body(()).unwrap())
+            .await
+            .expect("send_response");
+        request_stream.finish().await.expect("finish");
+    };
+
+    tokio::join!(server_fut, client_fut);
+}
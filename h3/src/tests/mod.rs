@@ -8,8 +8,44 @@
 #[path = "../../../h3-quinn/src/lib.rs"]
 mod h3_quinn;
 
+/// Defines a test running over the in-memory transport, both as is and erased behind
+/// [`quic::BoxedConnection`]
+///
+/// The test `name` becomes `name::plain` and `name::boxed`. Its body hands the ends of
+/// [`test::pair()`](crate::test::pair) to h3 through `wrap()`, which boxes them or not.
+macro_rules! transport_test {
+    ($(#[$attr:meta])* async fn $name:ident() $body:block) => {
+        mod $name {
+            use super::*;
+
+            $(#[$attr])*
+            async fn plain() {
+                #[allow(dead_code)]
+                fn wrap(conn: crate::test::Connection) -> crate::test::Connection {
+                    conn
+                }
+                $body
+            }
+
+            $(#[$attr])*
+            async fn boxed() {
+                #[allow(dead_code)]
+                fn wrap(conn: crate::test::Connection) -> crate::quic::BoxedConnection {
+                    crate::quic::BoxedConnection::new(conn)
+                }
+                $body
+            }
+        }
+    };
+}
+
+#[cfg(feature = "http-body")]
+mod body;
 mod connection;
+mod early_data;
 mod request;
+#[cfg(feature = "tower")]
+mod service;
 
 use std::{
     convert::TryInto,
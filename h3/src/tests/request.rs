@@ -1,14 +1,21 @@
-use std::time::Duration;
+use std::{
+    collections::VecDeque,
+    ops::ControlFlow,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
 
 use assert_matches::assert_matches;
 use bytes::{Buf, BufMut, Bytes, BytesMut};
-use futures_util::future;
+use futures_util::{future, FutureExt};
 use http::{request, HeaderMap, Request, Response, StatusCode};
 
 use crate::{
     client,
-    connection::ConnectionState,
-    error::{Code, Error, Kind},
+    connection::{self, ConnectionState, SharedStateRef},
+    error::{Code, Error, ErrorLevel, Kind},
+    frame::FrameStream,
     proto::{
         coding::Encode,
         frame::{self, Frame, FrameType},
@@ -16,7 +23,11 @@ use crate::{
         push::PushId,
         varint::VarInt,
     },
-    qpack, server,
+    qpack,
+    quic::{self, StreamId, WriteBuf},
+    server,
+    stream::BufRecvStream,
+    test,
 };
 
 use super::h3_quinn;
@@ -24,12 +35,21 @@ use super::{init_tracing, Pair};
 
 #[tokio::test]
 async fn get() {
+    let (client_conn, server_conn) = test::pair();
+    get_on(client_conn, server_conn).await;
+}
+
+#[tokio::test]
+async fn get_boxed() {
+    let (client_conn, server_conn) = test::boxed_pair();
+    get_on(client_conn, server_conn).await;
+}
+
+async fn get_on<C: quic::Connection<Bytes>>(client_conn: C, server_conn: C) {
     init_tracing();
-    let mut pair = Pair::default();
-    let mut server = pair.server();
 
     let client_fut = async {
-        let (mut driver, mut client) = client::new(pair.client().await).await.expect("client init");
+        let (mut driver, mut client) = client::new(client_conn).await.expect("client init");
         let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
         let req_fut = async {
             let mut request_stream = client
@@ -51,8 +71,7 @@ async fn get() {
     };
 
     let server_fut = async {
-        let conn = server.next().await;
-        let mut incoming_req = server::Connection::new(conn).await.unwrap();
+        let mut incoming_req = server::Connection::new(server_conn).await.unwrap();
 
         let (_request, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
         request_stream
@@ -75,61 +94,51 @@ async fn get() {
 }
 
 #[tokio::test]
-async fn get_with_trailers_unknown_content_type() {
+async fn interim_responses_before_final() {
     init_tracing();
-    let mut pair = Pair::default();
-    let mut server = pair.server();
+    let (client_conn, server_conn) = test::pair();
 
     let client_fut = async {
-        let (mut driver, mut client) = client::new(pair.client().await).await.expect("client init");
+        let (mut driver, mut client) = client::new(client_conn).await.expect("client init");
         let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
         let req_fut = async {
             let mut request_stream = client
                 .send_request(Request::get("http://localhost/salut").body(()).unwrap())
                 .await
                 .expect("request");
-            request_stream.recv_response().await.expect("recv response");
-            request_stream
+            request_stream.finish().await.expect("finish");
+
+            for status in [
+                StatusCode::CONTINUE,
+                StatusCode::EARLY_HINTS,
+                StatusCode::OK,
+            ] {
+                let response = request_stream.recv_response().await.expect("recv response");
+                assert_eq!(response.status(), status);
+            }
+            let body = request_stream
                 .recv_data()
                 .await
                 .expect("recv data")
                 .expect("body");
-
-            assert!(request_stream.recv_data().await.unwrap().is_none());
-            let trailers = request_stream
-                .recv_trailers()
-                .await
-                .expect("recv trailers")
-                .expect("trailers none");
-            assert_eq!(trailers.get("trailer").unwrap(), &"value");
+            assert_eq!(body.chunk(), b"wonderful hypertext");
         };
         tokio::select! { _ = req_fut => (), _ = drive_fut => () }
     };
 
     let server_fut = async {
-        let conn = server.next().await;
-        let mut incoming_req = server::Connection::new(conn).await.unwrap();
-
-        let (_, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
-        request_stream
-            .send_response(
-                Response::builder()
-                    .status(200)
-                    .body(())
-                    .expect("build response"),
-            )
-            .await
-            .expect("send_response");
+        let mut incoming_req = server::Connection::new(server_conn).await.unwrap();
+        let (_request, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
+        for status in [100, 103, 200] {
+            request_stream
+                .send_response(Response::builder().status(status).body(()).unwrap())
+                .await
+                .expect("send_response");
+        }
         request_stream
             .send_data("wonderful hypertext".into())
             .await
             .expect("send_data");
-        let mut trailers = HeaderMap::new();
-        trailers.insert("trailer", "value".parse().unwrap());
-        request_stream
-            .send_trailers(trailers)
-            .await
-            .expect("send_trailers");
         request_stream.finish().await.expect("finish");
     };
 
@@ -137,410 +146,409 @@ async fn get_with_trailers_unknown_content_type() {
 }
 
 #[tokio::test]
-async fn get_with_trailers_known_content_type() {
+async fn recv_response_twice() {
     init_tracing();
-    let mut pair = Pair::default();
-    let mut server = pair.server();
+    let (client_conn, server_conn) = test::pair();
 
     let client_fut = async {
-        let (mut driver, mut client) = client::new(pair.client().await).await.expect("client init");
+        let (mut driver, mut client) = client::new(client_conn).await.expect("client init");
         let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
         let req_fut = async {
             let mut request_stream = client
                 .send_request(Request::get("http://localhost/salut").body(()).unwrap())
                 .await
                 .expect("request");
-            request_stream.recv_response().await.expect("recv response");
-            request_stream
-                .recv_data()
-                .await
-                .expect("recv data")
-                .expect("body");
+            request_stream.finish().await.expect("finish");
 
-            let trailers = request_stream
-                .recv_trailers()
-                .await
-                .expect("recv trailers")
-                .expect("trailers none");
-            assert_eq!(trailers.get("trailer").unwrap(), &"value");
+            let response = request_stream.recv_response().await.expect("recv response");
+            assert_eq!(response.status(), StatusCode::OK);
+            // The body follows, not another response
+            let err = request_stream.recv_response().await.unwrap_err();
+            assert_eq!(err.try_get_code(), Some(Code::H3_FRAME_UNEXPECTED));
         };
         tokio::select! { _ = req_fut => (), _ = drive_fut => () }
     };
 
     let server_fut = async {
-        let conn = server.next().await;
-        let mut incoming_req = server::Connection::new(conn).await.unwrap();
-
-        let (_, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
+        let mut incoming_req = server::Connection::new(server_conn).await.unwrap();
+        let (_request, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
         request_stream
-            .send_response(
-                Response::builder()
-                    .status(200)
-                    .body(())
-                    .expect("build response"),
-            )
+            .send_response(Response::builder().status(200).body(()).unwrap())
             .await
             .expect("send_response");
         request_stream
             .send_data("wonderful hypertext".into())
             .await
             .expect("send_data");
-
-        let mut trailers = HeaderMap::new();
-        trailers.insert("trailer", "value".parse().unwrap());
-        request_stream
-            .send_trailers(trailers)
-            .await
-            .expect("send_trailers");
-        request_stream.finish().await.expect("finish");
+        // The client closes the connection once it fails
+        let _ = request_stream.finish().await;
     };
 
     tokio::join!(server_fut, client_fut);
 }
 
 #[tokio::test]
-async fn post() {
+async fn response_frames_sent_in_order() {
     init_tracing();
-    let mut pair = Pair::default();
-    let mut server = pair.server();
+    let (client_conn, server_conn) = test::pair();
 
     let client_fut = async {
-        let (mut driver, mut client) = client::new(pair.client().await).await.expect("client init");
+        let (mut driver, mut client) = client::new(client_conn).await.expect("client init");
         let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
         let req_fut = async {
             let mut request_stream = client
                 .send_request(Request::get("http://localhost/salut").body(()).unwrap())
                 .await
                 .expect("request");
+            request_stream.finish().await.expect("finish");
 
-            request_stream
-                .send_data("wonderful json".into())
-                .await
-                .expect("send_data");
-            request_stream.finish().await.expect("client finish");
-
-            request_stream.recv_response().await.expect("recv response");
+            let response = request_stream.recv_response().await.expect("recv response");
+            assert_eq!(response.status(), StatusCode::OK);
+            let mut body = BytesMut::new();
+            while let Some(data) = request_stream.recv_data().await.expect("recv data") {
+                body.put(data);
+            }
+            assert_eq!(&body[..], b"hypertext");
+            let trailers = request_stream.recv_trailers().await.expect("trailers");
+            assert_eq!(trailers.expect("trailers")["trailer"], "tail");
         };
         tokio::select! { _ = req_fut => (), _ = drive_fut => () }
     };
 
     let server_fut = async {
-        let conn = server.next().await;
-        let mut incoming_req = server::Connection::new(conn).await.unwrap();
+        let mut incoming_req = server::Connection::new(server_conn).await.unwrap();
+        let (_request, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
+        let out_of_order = |e: Error| {
+            assert_matches!(
+                e.kind(),
+                Kind::Application {
+                    code: Code::H3_INTERNAL_ERROR,
+                    level: ErrorLevel::StreamError,
+                    ..
+                }
+            )
+        };
+        let mut trailers = HeaderMap::new();
+        trailers.insert("trailer", "tail".parse().unwrap());
+
+        out_of_order(request_stream.send_data("body".into()).await.unwrap_err());
+        out_of_order(request_stream.finish().await.unwrap_err());
 
-        let (_, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
         request_stream
-            .send_response(
-                Response::builder()
-                    .status(200)
-                    .body(())
-                    .expect("build response"),
-            )
+            .send_response(Response::builder().status(200).body(()).unwrap())
             .await
             .expect("send_response");
-
-        let request_body = request_stream
-            .recv_data()
+        request_stream
+            .send_data("hyper".into())
             .await
-            .expect("recv data")
-            .expect("server recv body");
-        assert_eq!(request_body.chunk(), b"wonderful json");
-        request_stream.finish().await.expect("client finish");
+            .expect("send_data");
+        request_stream
+            .send_data("text".into())
+            .await
+            .expect("send_data");
+        request_stream
+            .send_trailers(trailers.clone())
+            .await
+            .expect("send_trailers");
+
+        // Nothing is sent after the trailers but the end of the stream
+        out_of_order(request_stream.send_data("late".into()).await.unwrap_err());
+        out_of_order(request_stream.send_trailers(trailers).await.unwrap_err());
+        request_stream.finish().await.expect("finish");
     };
 
     tokio::join!(server_fut, client_fut);
 }
 
 #[tokio::test]
-async fn header_too_big_response_from_server() {
+async fn response_writer_sends_in_order() {
     init_tracing();
-    let mut pair = Pair::default();
-    let mut server = pair.server();
+    let (client_conn, server_conn) = test::pair();
 
     let client_fut = async {
-        let (mut driver, mut client) = client::new(pair.client().await).await.expect("client init");
+        let (mut driver, mut client) = client::new(client_conn).await.expect("client init");
         let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
         let req_fut = async {
             let mut request_stream = client
                 .send_request(Request::get("http://localhost/salut").body(()).unwrap())
                 .await
                 .expect("request");
-            request_stream.finish().await.expect("client finish");
-            let response = request_stream.recv_response().await.unwrap();
-            assert_eq!(
-                response.status(),
-                StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
-            );
+            request_stream.finish().await.expect("finish");
+
+            let response = request_stream.recv_response().await.expect("recv response");
+            assert_eq!(response.status(), StatusCode::OK);
+            let mut body = BytesMut::new();
+            while let Some(data) = request_stream.recv_data().await.expect("recv data") {
+                body.put(data);
+            }
+            assert_eq!(&body[..], b"hypertext");
+            let trailers = request_stream.recv_trailers().await.expect("trailers");
+            assert_eq!(trailers.expect("trailers")["trailer"], "tail");
         };
-        tokio::select! {biased; _ = req_fut => (), _ = drive_fut => () }
+        tokio::select! { _ = req_fut => (), _ = drive_fut => () }
     };
 
     let server_fut = async {
-        let conn = server.next().await;
-        //= https://www.rfc-editor.org/rfc/rfc9114#section-4.2.2
-        //= type=test
-        //# An HTTP/3 implementation MAY impose a limit on the maximum size of
-        //# the message header it will accept on an individual HTTP message.
-        let mut incoming_req = server::builder()
-            .max_field_section_size(12)
-            .build(conn)
-            .await
-            .unwrap();
+        let mut incoming_req = server::Connection::new(server_conn).await.unwrap();
+        let (_request, request_stream) = incoming_req.accept().await.expect("accept").unwrap();
+        let mut writer = server::ResponseWriter::new(request_stream);
+        let mut trailers = HeaderMap::new();
+        trailers.insert("trailer", "tail".parse().unwrap());
 
-        let err_kind = incoming_req.accept().await.map(|_| ()).unwrap_err().kind();
-        assert_matches!(
-            err_kind,
-            Kind::HeaderTooBig {
-                actual_size: 42,
-                max_size: 12,
-                ..
-            }
-        );
-        let _ = incoming_req.accept().await;
+        writer
+            .send_headers(Response::builder().status(200).body(()).unwrap())
+            .await
+            .expect("send_headers");
+        writer.send_data("hyper".into()).await.expect("send_data");
+        writer.send_data("text".into()).await.expect("send_data");
+        writer.send_trailers(trailers).await.expect("send_trailers");
+        writer.finish().await.expect("finish");
     };
 
     tokio::join!(server_fut, client_fut);
 }
 
 #[tokio::test]
-async fn header_too_big_response_from_server_trailers() {
+async fn response_writer_rejects_data_after_trailers() {
     init_tracing();
-    let mut pair = Pair::default();
-    let mut server = pair.server();
+    let (client_conn, server_conn) = test::pair();
 
     let client_fut = async {
-        let (mut driver, mut client) = client::new(pair.client().await).await.expect("client init");
+        let (mut driver, mut client) = client::new(client_conn).await.expect("client init");
         let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
         let req_fut = async {
             let mut request_stream = client
                 .send_request(Request::get("http://localhost/salut").body(()).unwrap())
                 .await
                 .expect("request");
-            request_stream
-                .send_data("wonderful json".into())
-                .await
-                .expect("send_data");
+            request_stream.finish().await.expect("finish");
 
-            let mut trailers = HeaderMap::new();
-            trailers.insert("trailer", "A".repeat(200).parse().unwrap());
-            request_stream
-                .send_trailers(trailers)
-                .await
-                .expect("send trailers");
-            request_stream.finish().await.expect("client finish");
+            let response = request_stream.recv_response().await.expect("recv response");
+            assert_eq!(response.status(), StatusCode::OK);
+            let data = request_stream.recv_data().await.expect("recv data");
+            assert_eq!(data.expect("body").chunk(), b"body");
+            assert!(request_stream.recv_data().await.expect("end").is_none());
+            let trailers = request_stream.recv_trailers().await.expect("trailers");
+            assert_eq!(trailers.expect("trailers")["trailer"], "tail");
         };
-        tokio::select! {biased; _ = req_fut => (), _ = drive_fut => () }
+        tokio::select! { _ = req_fut => (), _ = drive_fut => () }
     };
 
     let server_fut = async {
-        let conn = server.next().await;
-        //= https://www.rfc-editor.org/rfc/rfc9114#section-4.2.2
-        //= type=test
-        //# An HTTP/3 implementation MAY impose a limit on the maximum size of
-        //# the message header it will accept on an individual HTTP message.
-        let mut incoming_req = server::builder()
-            .max_field_section_size(207)
-            .build(conn)
-            .await
-            .unwrap();
+        let mut incoming_req = server::Connection::new(server_conn).await.unwrap();
+        let (_request, request_stream) = incoming_req.accept().await.expect("accept").unwrap();
+        let mut writer = server::ResponseWriter::new(request_stream);
+        let out_of_order = |e: Error| {
+            assert_matches!(
+                e.kind(),
+                Kind::Application {
+                    code: Code::H3_INTERNAL_ERROR,
+                    level: ErrorLevel::StreamError,
+                    ..
+                }
+            )
+        };
+        let mut trailers = HeaderMap::new();
+        trailers.insert("trailer", "tail".parse().unwrap());
 
-        let (_request, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
-        let _ = request_stream
-            .recv_data()
+        out_of_order(writer.send_data("body".into()).await.unwrap_err());
+        out_of_order(writer.send_trailers(trailers.clone()).await.unwrap_err());
+        out_of_order(writer.finish().await.unwrap_err());
+
+        writer
+            .send_headers(Response::builder().status(200).body(()).unwrap())
             .await
-            .expect("recv data")
-            .expect("body");
-        let err_kind = request_stream.recv_trailers().await.unwrap_err().kind();
-        assert_matches!(
-            err_kind,
-            Kind::HeaderTooBig {
-                actual_size: 239,
-                max_size: 207,
-                ..
-            }
+            .expect("send_headers");
+        out_of_order(
+            writer
+                .send_headers(Response::builder().status(200).body(()).unwrap())
+                .await
+                .unwrap_err(),
         );
-        let _ = incoming_req.accept().await;
+        writer.send_data("body".into()).await.expect("send_data");
+        writer
+            .send_trailers(trailers.clone())
+            .await
+            .expect("send_trailers");
+
+        // Nothing is sent after the trailers but the end of the stream
+        out_of_order(writer.send_data("late".into()).await.unwrap_err());
+        out_of_order(writer.send_trailers(trailers).await.unwrap_err());
+        writer.finish().await.expect("finish");
     };
 
     tokio::join!(server_fut, client_fut);
 }
 
-#[tokio::test]
-async fn header_too_big_client_error() {
-    init_tracing();
-    let mut pair = Pair::default();
-    let mut server = pair.server();
+/// Polling is all connections need, any executor drives them
+#[test]
+fn get_without_runtime() {
+    let (client_conn, server_conn) = test::pair();
 
     let client_fut = async {
-        let (mut driver, mut client) = client::new(pair.client().await).await.expect("client init");
-        let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
+        let (mut driver, mut client) = client::new(client_conn).await.expect("client init");
+        let drive_fut = future::poll_fn(|cx| driver.poll_close(cx));
         let req_fut = async {
-            // pretend client already received server's settings
-            client
-                .shared_state()
-                .write("client")
-                .peer_config
-                .max_field_section_size = 12;
+            let mut request_stream = client
+                .send_request(Request::get("http://localhost/salut").body(()).unwrap())
+                .await
+                .expect("request");
+            request_stream.finish().await.expect("finish");
 
-            let req = Request::get("http://localhost/salut").body(()).unwrap();
-            let err_kind = client
-                .send_request(req)
+            let response = request_stream.recv_response().await.expect("recv response");
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = request_stream
+                .recv_data()
                 .await
-                .map(|_| ())
-                .unwrap_err()
-                .kind();
-            assert_matches!(
-                err_kind,
-                Kind::HeaderTooBig {
-                    actual_size: 179,
-                    max_size: 12,
-                    ..
-                }
-            );
+                .expect("recv data")
+                .expect("body");
+            assert_eq!(body.chunk(), b"wonderful hypertext");
         };
-        tokio::select! {biased; _ = req_fut => (),_ = drive_fut => () }
+        futures_util::pin_mut!(drive_fut, req_fut);
+        future::select(req_fut, drive_fut).await;
     };
 
     let server_fut = async {
-        let conn = server.next().await;
-        //= https://www.rfc-editor.org/rfc/rfc9114#section-4.2.2
-        //= type=test
-        //# An HTTP/3 implementation MAY impose a limit on the maximum size of
-        //# the message header it will accept on an individual HTTP message.
-        server::builder()
-            .max_field_section_size(12)
-            .build(conn)
+        let mut incoming_req = server::Connection::new(server_conn).await.unwrap();
+
+        let (_request, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
+        request_stream
+            .send_response(Response::new(()))
             .await
-            .unwrap();
+            .expect("send_response");
+        request_stream
+            .send_data("wonderful hypertext".into())
+            .await
+            .expect("send_data");
+        request_stream.finish().await.expect("finish");
+        incoming_req
     };
 
-    tokio::join!(server_fut, client_fut);
+    futures::executor::block_on(future::join(server_fut, client_fut));
 }
 
 #[tokio::test]
-async fn header_too_big_client_error_trailer() {
+async fn get_through_boxed_connection() {
     init_tracing();
     let mut pair = Pair::default();
-    let mut server = pair.server();
+    let server = pair.server();
 
     let client_fut = async {
-        let (mut driver, mut client) = client::new(pair.client().await).await.expect("client init");
+        let conn = quic::BoxedConnection::from(pair.client().await);
+        let (mut driver, mut client) = client::new(conn).await.expect("client init");
         let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
         let req_fut = async {
-            client
-                .shared_state()
-                .write("client")
-                .peer_config
-                .max_field_section_size = 200;
-
             let mut request_stream = client
                 .send_request(Request::get("http://localhost/salut").body(()).unwrap())
                 .await
                 .expect("request");
-            request_stream
-                .send_data("wonderful json".into())
-                .await
-                .expect("send_data");
 
-            let mut trailers = HeaderMap::new();
-            trailers.insert("trailer", "A".repeat(200).parse().unwrap());
+            let response = request_stream.recv_response().await.expect("recv response");
+            assert_eq!(response.status(), StatusCode::OK);
 
-            let err_kind = request_stream
-                .send_trailers(trailers)
+            let body = request_stream
+                .recv_data()
                 .await
-                .unwrap_err()
-                .kind();
-            assert_matches!(
-                err_kind,
-                Kind::HeaderTooBig {
-                    actual_size: 239,
-                    max_size: 200,
-                    ..
-                }
-            );
-
-            request_stream.finish().await.expect("client finish");
+                .expect("recv data")
+                .expect("body");
+            assert_eq!(body.chunk(), b"wonderful hypertext");
         };
-        tokio::select! {biased; _ = req_fut => (), _ = drive_fut => () }
+        tokio::select! { _ = req_fut => (), _ = drive_fut => () }
     };
 
     let server_fut = async {
-        let conn = server.next().await;
-        //= https://www.rfc-editor.org/rfc/rfc9114#section-4.2.2
-        //= type=test
-        //# An HTTP/3 implementation MAY impose a limit on the maximum size of
-        //# the message header it will accept on an individual HTTP message.
-        let mut incoming_req = server::builder()
-            .max_field_section_size(207)
-            .build(conn)
-            .await
-            .unwrap();
+        let conn = server.endpoint.accept().await.unwrap().await.unwrap();
+        let conn = quic::BoxedConnection::from(h3_quinn::Connection::new(conn));
+        let mut incoming_req = server::Connection::new(conn).await.unwrap();
 
         let (_request, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
-        let _ = request_stream
-            .recv_data()
+        request_stream
+            .send_response(
+                Response::builder()
+                    .status(200)
+                    .body(())
+                    .expect("build response"),
+            )
             .await
-            .expect("recv data")
-            .expect("body");
-        let _ = incoming_req.accept().await;
+            .expect("send_response");
+        request_stream
+            .send_data("wonderful hypertext".into())
+            .await
+            .expect("send_data");
+        request_stream.finish().await.expect("finish");
     };
 
     tokio::join!(server_fut, client_fut);
 }
 
+#[test]
+fn boxed_connection_extensions() {
+    use quic::{EarlyDataExt, RecvDatagramExt, SendDatagramExt};
+
+    let (conn, _peer) = test::pair();
+    let mut conn = quic::BoxedConnection::new(conn);
+    let mut opener = quic::Connection::opener(&conn);
+    let mut cx = Context::from_waker(futures_util::task::noop_waker_ref());
+
+    // Without the extensions, the connection behaves as one never sending early data
+    assert!(!conn.is_early_data());
+    assert_eq!(conn.poll_handshake(&mut cx), Poll::Ready(true));
+    assert!(!opener.is_early_data());
+    assert_eq!(opener.poll_handshake(&mut cx), Poll::Ready(true));
+
+    let datagram = crate::ext::Datagram::new(StreamId::FIRST_REQUEST, Bytes::from("data"));
+    assert!(conn.send_datagram(datagram).is_err());
+    assert_matches!(conn.poll_accept_datagram(&mut cx), Poll::Ready(Err(_)));
+}
+
 #[tokio::test]
-async fn header_too_big_discard_from_client() {
+async fn get_with_trailers_unknown_content_type() {
+    let (client_conn, server_conn) = test::pair();
+    get_with_trailers_unknown_content_type_on(client_conn, server_conn).await;
+}
+
+#[tokio::test]
+async fn get_with_trailers_unknown_content_type_boxed() {
+    let (client_conn, server_conn) = test::boxed_pair();
+    get_with_trailers_unknown_content_type_on(client_conn, server_conn).await;
+}
+
+async fn get_with_trailers_unknown_content_type_on<C: quic::Connection<Bytes>>(
+    client_conn: C,
+    server_conn: C,
+) {
     init_tracing();
-    let mut pair = Pair::default();
-    let mut server = pair.server();
 
     let client_fut = async {
-        //= https://www.rfc-editor.org/rfc/rfc9114#section-4.2.2
-        //= type=test
-        //# An implementation that
-        //# has received this parameter SHOULD NOT send an HTTP message header
-        //# that exceeds the indicated size, as the peer will likely refuse to
-        //# process it.
-
-        let (mut driver, mut client) = client::builder()
-            .max_field_section_size(12)
-            // Don't send settings, so server doesn't know about the low max_field_section_size
-            .send_settings(false)
-            .build::<_, _, Bytes>(pair.client().await)
-            .await
-            .expect("client init");
+        let (mut driver, mut client) = client::new(client_conn).await.expect("client init");
         let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
         let req_fut = async {
             let mut request_stream = client
                 .send_request(Request::get("http://localhost/salut").body(()).unwrap())
                 .await
                 .expect("request");
-            request_stream.finish().await.expect("client finish");
-            let err_kind = request_stream.recv_response().await.unwrap_err().kind();
-            assert_matches!(
-                err_kind,
-                Kind::HeaderTooBig {
-                    actual_size: 42,
-                    max_size: 12,
-                    ..
-                }
-            );
+            request_stream.recv_response().await.expect("recv response");
+            request_stream
+                .recv_data()
+                .await
+                .expect("recv data")
+                .expect("body");
 
-            let mut request_stream = client
-                .send_request(Request::get("http://localhost/salut").body(()).unwrap())
+            assert!(request_stream.recv_data().await.unwrap().is_none());
+            let trailers = request_stream
+                .recv_trailers()
                 .await
-                .expect("request");
-            request_stream.finish().await.expect("client finish");
-            let _ = request_stream.recv_response().await.unwrap_err();
+                .expect("recv trailers")
+                .expect("trailers none");
+            assert_eq!(trailers.get("trailer").unwrap(), &"value");
         };
-        tokio::select! {biased; _ = req_fut => (), _ = drive_fut => () }
+        tokio::select! { _ = req_fut => (), _ = drive_fut => () }
     };
 
     let server_fut = async {
-        let conn = server.next().await;
-        let mut incoming_req = server::Connection::new(conn).await.unwrap();
+        let mut incoming_req = server::Connection::new(server_conn).await.unwrap();
 
-        let (_request, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
+        let (_, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
         request_stream
             .send_response(
                 Response::builder()
@@ -550,51 +558,42 @@ async fn header_too_big_discard_from_client() {
             )
             .await
             .expect("send_response");
-
-        // Keep sending: wait for the stream to be cancelled by the client
-        let mut err = None;
-        for _ in 0..100 {
-            if let Err(e) = request_stream.send_data("some data".into()).await {
-                err = Some(e);
-                break;
-            }
-            tokio::time::sleep(Duration::from_millis(2)).await;
-        }
-        assert_matches!(
-            err.as_ref().unwrap().kind(),
-            Kind::Application {
-                code: Code::H3_REQUEST_CANCELLED,
-                ..
-            }
-        );
-        let _ = incoming_req.accept().await;
+        request_stream
+            .send_data("wonderful hypertext".into())
+            .await
+            .expect("send_data");
+        let mut trailers = HeaderMap::new();
+        trailers.insert("trailer", "value".parse().unwrap());
+        request_stream
+            .send_trailers(trailers)
+            .await
+            .expect("send_trailers");
+        request_stream.finish().await.expect("finish");
     };
 
     tokio::join!(server_fut, client_fut);
 }
 
 #[tokio::test]
-async fn header_too_big_discard_from_client_trailers() {
-    init_tracing();
-    let mut pair = Pair::default();
-    let mut server = pair.server();
+async fn get_with_trailers_known_content_type() {
+    let (client_conn, server_conn) = test::pair();
+    get_with_trailers_known_content_type_on(client_conn, server_conn).await;
+}
 
-    let client_fut = async {
-        //= https://www.rfc-editor.org/rfc/rfc9114#section-4.2.2
-        //= type=test
-        //# An implementation that
-        //# has received this parameter SHOULD NOT send an HTTP message header
-        //# that exceeds the indicated size, as the peer will likely refuse to
-        //# process it.
+#[tokio::test]
+async fn get_with_trailers_known_content_type_boxed() {
+    let (client_conn, server_conn) = test::boxed_pair();
+    get_with_trailers_known_content_type_on(client_conn, server_conn).await;
+}
 
-        let (mut driver, mut client) = client::builder()
-            .max_field_section_size(200)
-            // Don't send settings, so server doesn't know about the low max_field_section_size
-            .send_settings(false)
-            .build::<_, _, Bytes>(pair.client().await)
-            .await
-            .expect("client init");
+async fn get_with_trailers_known_content_type_on<C: quic::Connection<Bytes>>(
+    client_conn: C,
+    server_conn: C,
+) {
+    init_tracing();
 
+    let client_fut = async {
+        let (mut driver, mut client) = client::new(client_conn).await.expect("client init");
         let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
         let req_fut = async {
             let mut request_stream = client
@@ -602,28 +601,26 @@ async fn header_too_big_discard_from_client_trailers() {
                 .await
                 .expect("request");
             request_stream.recv_response().await.expect("recv response");
-            request_stream.recv_data().await.expect("recv data");
+            request_stream
+                .recv_data()
+                .await
+                .expect("recv data")
+                .expect("body");
 
-            let err_kind = request_stream.recv_trailers().await.unwrap_err().kind();
-            assert_matches!(
-                err_kind,
-                Kind::HeaderTooBig {
-                    actual_size: 539,
-                    max_size: 200,
-                    ..
-                }
-            );
-            request_stream.finish().await.expect("client finish");
+            let trailers = request_stream
+                .recv_trailers()
+                .await
+                .expect("recv trailers")
+                .expect("trailers none");
+            assert_eq!(trailers.get("trailer").unwrap(), &"value");
         };
-        tokio::select! {biased; _ = req_fut => (), _ = drive_fut => () }
+        tokio::select! { _ = req_fut => (), _ = drive_fut => () }
     };
 
     let server_fut = async {
-        let conn = server.next().await;
-        let mut incoming_req = server::Connection::new(conn).await.unwrap();
-
-        let (_request, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
+        let mut incoming_req = server::Connection::new(server_conn).await.unwrap();
 
+        let (_, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
         request_stream
             .send_response(
                 Response::builder()
@@ -633,70 +630,63 @@ async fn header_too_big_discard_from_client_trailers() {
             )
             .await
             .expect("send_response");
-
         request_stream
             .send_data("wonderful hypertext".into())
             .await
             .expect("send_data");
 
         let mut trailers = HeaderMap::new();
-        trailers.insert("trailer", "value".repeat(100).parse().unwrap());
+        trailers.insert("trailer", "value".parse().unwrap());
         request_stream
             .send_trailers(trailers)
             .await
             .expect("send_trailers");
         request_stream.finish().await.expect("finish");
-
-        let _ = incoming_req.accept().await;
     };
 
     tokio::join!(server_fut, client_fut);
 }
 
 #[tokio::test]
-async fn header_too_big_server_error() {
+async fn post() {
+    let (client_conn, server_conn) = test::pair();
+    post_on(client_conn, server_conn).await;
+}
+
+#[tokio::test]
+async fn post_boxed() {
+    let (client_conn, server_conn) = test::boxed_pair();
+    post_on(client_conn, server_conn).await;
+}
+
+async fn post_on<C: quic::Connection<Bytes>>(client_conn: C, server_conn: C) {
     init_tracing();
-    let mut pair = Pair::default();
-    let mut server = pair.server();
 
     let client_fut = async {
-        let (mut driver, mut client) = client::new(pair.client().await) // header size limit faked for brevity
-            .await
-            .expect("client init");
+        let (mut driver, mut client) = client::new(client_conn).await.expect("client init");
         let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
         let req_fut = async {
-            let req = Request::get("http://localhost/salut").body(()).unwrap();
-            let _ = client
-                .send_request(req)
+            let mut request_stream = client
+                .send_request(Request::get("http://localhost/salut").body(()).unwrap())
                 .await
-                .unwrap()
-                .recv_response()
-                .await;
+                .expect("request");
+
+            request_stream
+                .send_data("wonderful json".into())
+                .await
+                .expect("send_data");
+            request_stream.finish().await.expect("client finish");
+
+            request_stream.recv_response().await.expect("recv response");
         };
         tokio::select! { _ = req_fut => (), _ = drive_fut => () }
     };
 
     let server_fut = async {
-        let conn = server.next().await;
-        let mut incoming_req = server::Connection::new(conn).await.unwrap();
-
-        let (_request, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
-
-        //= https://www.rfc-editor.org/rfc/rfc9114#section-4.2.2
-        //= type=test
-        //# An implementation that
-        //# has received this parameter SHOULD NOT send an HTTP message header
-        //# that exceeds the indicated size, as the peer will likely refuse to
-        //# process it.
-
-        // pretend the server received a smaller max_field_section_size
-        incoming_req
-            .shared_state()
-            .write("server")
-            .peer_config
-            .max_field_section_size = 12;
+        let mut incoming_req = server::Connection::new(server_conn).await.unwrap();
 
-        let err_kind = request_stream
+        let (_, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
+        request_stream
             .send_response(
                 Response::builder()
                     .status(200)
@@ -704,284 +694,1646 @@ async fn header_too_big_server_error() {
                     .expect("build response"),
             )
             .await
-            .map(|_| ())
-            .unwrap_err()
-            .kind();
+            .expect("send_response");
 
-        assert_matches!(
-            err_kind,
-            Kind::HeaderTooBig {
-                actual_size: 42,
-                max_size: 12,
-                ..
-            }
-        );
+        let request_body = request_stream
+            .recv_data()
+            .await
+            .expect("recv data")
+            .expect("server recv body");
+        assert_eq!(request_body.chunk(), b"wonderful json");
+        request_stream.finish().await.expect("client finish");
     };
 
     tokio::join!(server_fut, client_fut);
 }
 
 #[tokio::test]
-async fn header_too_big_server_error_trailers() {
+async fn post_then_receive_streamed_response() {
+    let (client_conn, server_conn) = test::pair();
+    post_then_receive_streamed_response_on(client_conn, server_conn).await;
+}
+
+#[tokio::test]
+async fn post_then_receive_streamed_response_boxed() {
+    let (client_conn, server_conn) = test::boxed_pair();
+    post_then_receive_streamed_response_on(client_conn, server_conn).await;
+}
+
+async fn post_then_receive_streamed_response_on<C: quic::Connection<Bytes>>(
+    client_conn: C,
+    server_conn: C,
+) {
     init_tracing();
-    let mut pair = Pair::default();
-    let mut server = pair.server();
 
     let client_fut = async {
-        let (mut driver, mut client) = client::new(pair.client().await) // header size limit faked for brevity
-            .await
-            .expect("client init");
+        let (mut driver, mut client) = client::new(client_conn).await.expect("client init");
         let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
         let req_fut = async {
-            let req = Request::get("http://localhost/salut").body(()).unwrap();
-            let _ = client
-                .send_request(req)
+            let mut request_stream = client
+                .send_request(Request::post("http://localhost/salut").body(()).unwrap())
                 .await
-                .unwrap()
-                .recv_response()
-                .await;
+                .expect("request");
+            request_stream
+                .send_data("wonderful json".into())
+                .await
+                .expect("send_data");
+            request_stream.finish().await.expect("client finish");
+
+            // The receiving side is still open
+            let response = request_stream.recv_response().await.expect("recv response");
+            assert_eq!(response.status(), StatusCode::OK);
+            let mut body = BytesMut::new();
+            while let Some(mut chunk) = request_stream.recv_data().await.expect("recv data") {
+                body.put(&mut chunk);
+            }
+            assert_eq!(&body[..], b"one, two, three");
+            let trailers = request_stream
+                .recv_trailers()
+                .await
+                .expect("recv trailers")
+                .expect("trailers");
+            assert_eq!(trailers.get("trailer").unwrap(), "value");
         };
         tokio::select! { _ = req_fut => (), _ = drive_fut => () }
     };
 
     let server_fut = async {
-        let conn = server.next().await;
-        let mut incoming_req = server::Connection::new(conn).await.unwrap();
+        let mut incoming_req = server::Connection::new(server_conn).await.unwrap();
 
-        let (_request, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
-        request_stream
-            .send_response(
-                Response::builder()
-                    .status(200)
-                    .body(())
-                    .expect("build response"),
-            )
+        let (_, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
+        let request_body = request_stream
+            .recv_data()
             .await
-            .unwrap();
-        request_stream
-            .send_data("wonderful hypertext".into())
+            .expect("recv data")
+            .expect("server recv body");
+        assert_eq!(request_body.chunk(), b"wonderful json");
+        assert!(request_stream
+            .recv_data()
             .await
-            .expect("send_data");
-
-        //= https://www.rfc-editor.org/rfc/rfc9114#section-4.2.2
-        //= type=test
-        //# An implementation that
-        //# has received this parameter SHOULD NOT send an HTTP message header
-        //# that exceeds the indicated size, as the peer will likely refuse to
-        //# process it.
-
-        // pretend the server already received client's settings
-        incoming_req
-            .shared_state()
-            .write("write")
-            .peer_config
-            .max_field_section_size = 200;
+            .expect("recv end")
+            .is_none());
 
+        request_stream
+            .send_response(Response::builder().status(200).body(()).unwrap())
+            .await
+            .expect("send_response");
+        for chunk in ["one, ", "two, ", "three"] {
+            request_stream
+                .send_data(chunk.into())
+                .await
+                .expect("send_data");
+        }
         let mut trailers = HeaderMap::new();
-        trailers.insert("trailer", "value".repeat(100).parse().unwrap());
-        let err_kind = request_stream
+        trailers.insert("trailer", "value".parse().unwrap());
+        request_stream
             .send_trailers(trailers)
             .await
-            .unwrap_err()
-            .kind();
-        assert_matches!(
-            err_kind,
-            Kind::HeaderTooBig {
-                actual_size: 539,
-                max_size: 200,
-                ..
-            }
-        );
+            .expect("send_trailers");
+        request_stream.finish().await.expect("server finish");
     };
 
     tokio::join!(server_fut, client_fut);
 }
 
 #[tokio::test]
-async fn get_timeout_client_recv_response() {
+async fn respond_then_receive_request_body() {
+    let (client_conn, server_conn) = test::pair();
+    respond_then_receive_request_body_on(client_conn, server_conn).await;
+}
+
+#[tokio::test]
+async fn respond_then_receive_request_body_boxed() {
+    let (client_conn, server_conn) = test::boxed_pair();
+    respond_then_receive_request_body_on(client_conn, server_conn).await;
+}
+
+async fn respond_then_receive_request_body_on<C: quic::Connection<Bytes>>(
+    client_conn: C,
+    server_conn: C,
+) {
     init_tracing();
-    let mut pair = Pair::default();
-    pair.with_timeout(Duration::from_millis(100));
-    let mut server = pair.server();
 
     let client_fut = async {
-        let (mut conn, mut client) = client::new(pair.client().await).await.expect("client init");
-        let request_fut = async {
+        let (mut driver, mut client) = client::new(client_conn).await.expect("client init");
+        let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
+        let req_fut = async {
             let mut request_stream = client
-                .send_request(Request::get("http://localhost/salut").body(()).unwrap())
+                .send_request(Request::post("http://localhost/salut").body(()).unwrap())
                 .await
                 .expect("request");
 
-            let response = request_stream.recv_response().await;
-            assert_matches!(response.unwrap_err().kind(), Kind::Timeout);
-        };
-
-        let drive_fut = async move {
-            let result = future::poll_fn(|cx| conn.poll_close(cx)).await;
-            assert_matches!(result.unwrap_err().kind(), Kind::Timeout);
+            // The whole response is received before the request body is sent
+            let response = request_stream.recv_response().await.expect("recv response");
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = request_stream
+                .recv_data()
+                .await
+                .expect("recv data")
+                .expect("body");
+            assert_eq!(body.chunk(), b"go ahead");
+            assert!(request_stream
+                .recv_data()
+                .await
+                .expect("recv end")
+                .is_none());
+
+            for chunk in ["one, ", "two, ", "three"] {
+                request_stream
+                    .send_data(chunk.into())
+                    .await
+                    .expect("send_data");
+            }
+            request_stream.finish().await.expect("client finish");
         };
-
-        tokio::join!(drive_fut, request_fut);
+        tokio::select! { _ = req_fut => (), _ = drive_fut => () }
     };
 
     let server_fut = async {
-        let conn = server.next().await;
-        let mut incoming_req = server::Connection::new(conn).await.unwrap();
+        let mut incoming_req = server::Connection::new(server_conn).await.unwrap();
 
-        // _req must not be dropped, else the connection will be closed and the timeout
-        // wont be triggered
-        let _req = incoming_req.accept().await.expect("accept").unwrap();
-        tokio::time::sleep(Duration::from_millis(500)).await;
+        let (_, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
+        request_stream
+            .send_response(Response::builder().status(200).body(()).unwrap())
+            .await
+            .expect("send_response");
+        request_stream
+            .send_data("go ahead".into())
+            .await
+            .expect("send_data");
+        request_stream.finish().await.expect("server finish");
+
+        let mut body = BytesMut::new();
+        while let Some(mut chunk) = request_stream.recv_data().await.expect("recv data") {
+            body.put(&mut chunk);
+        }
+        assert_eq!(&body[..], b"one, two, three");
+
+        // Receiving can still be aborted once sending is done
+        request_stream.stop_sending(Code::H3_NO_ERROR);
     };
 
     tokio::join!(server_fut, client_fut);
 }
 
 #[tokio::test]
-async fn get_timeout_client_recv_data() {
+async fn get_over_chunked_pending_link() {
     init_tracing();
-    let mut pair = Pair::default();
-    pair.with_timeout(Duration::from_millis(200));
-    let mut server = pair.server();
+    let (client_conn, server_conn) = test::pair();
+    // Every frame arrives one byte at a time, each one behind a `Pending`
+    let link = test::Link::default().chunk_size(1).pending(true);
+    client_conn.handle().set_link(link.clone());
+    server_conn.handle().set_link(link);
 
     let client_fut = async {
-        let (mut conn, mut client) = client::new(pair.client().await).await.expect("client init");
-        let request_fut = async {
+        let (mut driver, mut client) = client::new(client_conn).await.expect("client init");
+        let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
+        let req_fut = async {
             let mut request_stream = client
                 .send_request(Request::get("http://localhost/salut").body(()).unwrap())
                 .await
                 .expect("request");
+            request_stream.finish().await.expect("client finish");
 
-            let _ = request_stream.recv_response().await.unwrap();
-            let data = request_stream.recv_data().await;
-            assert_matches!(data.map(|_| ()).unwrap_err().kind(), Kind::Timeout);
-        };
-
-        let drive_fut = async move {
-            let result = future::poll_fn(|cx| conn.poll_close(cx)).await;
-            assert_matches!(result.unwrap_err().kind(), Kind::Timeout);
+            let response = request_stream.recv_response().await.expect("recv response");
+            assert_eq!(response.status(), StatusCode::OK);
+            let mut body = BytesMut::new();
+            while let Some(mut chunk) = request_stream.recv_data().await.expect("recv data") {
+                body.put(&mut chunk);
+            }
+            assert_eq!(&body[..], b"wonderful hypertext");
+            let trailers = request_stream
+                .recv_trailers()
+                .await
+                .expect("recv trailers")
+                .expect("trailers");
+            assert_eq!(trailers.get("trailer").unwrap(), "value");
         };
-
-        tokio::join!(drive_fut, request_fut);
+        tokio::select! { _ = req_fut => (), _ = drive_fut => () }
     };
 
     let server_fut = async {
-        let conn = server.next().await;
-        let mut incoming_req = server::Connection::new(conn).await.unwrap();
+        let mut incoming_req = server::Connection::new(server_conn).await.unwrap();
 
-        let (_request, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
+        let (request, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
+        assert_eq!(request.uri().path(), "/salut");
         request_stream
-            .send_response(
-                Response::builder()
-                    .status(200)
-                    .body(())
-                    .expect("build response"),
-            )
+            .send_response(Response::builder().status(200).body(()).unwrap())
             .await
             .expect("send_response");
-        tokio::time::sleep(Duration::from_millis(500)).await;
+        request_stream
+            .send_data("wonderful hypertext".into())
+            .await
+            .expect("send_data");
+        let mut trailers = HeaderMap::new();
+        trailers.insert("trailer", "value".parse().unwrap());
+        request_stream
+            .send_trailers(trailers)
+            .await
+            .expect("send_trailers");
+        request_stream.finish().await.expect("finish");
     };
 
     tokio::join!(server_fut, client_fut);
 }
 
 #[tokio::test]
-async fn get_timeout_server_accept() {
+async fn delayed_link_holds_the_response() {
     init_tracing();
-    let mut pair = Pair::default();
-    pair.with_timeout(Duration::from_millis(200));
-    let mut server = pair.server();
+    let (client_conn, server_conn) = test::pair();
+    let delay = Duration::from_millis(50);
 
     let client_fut = async {
-        let (mut conn, _client) = client::new(pair.client().await).await.expect("client init");
-        let request_fut = async {
-            tokio::time::sleep(Duration::from_millis(500)).await;
-        };
-
-        let drive_fut = async move {
-            let result = future::poll_fn(|cx| conn.poll_close(cx)).await;
-            assert_matches!(result.unwrap_err().kind(), Kind::Timeout);
-        };
+        let (mut driver, mut client) = client::new(client_conn).await.expect("client init");
+        let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
+        let req_fut = async {
+            let mut request_stream = client
+                .send_request(Request::get("http://localhost/salut").body(()).unwrap())
+                .await
+                .expect("request");
+            request_stream.finish().await.expect("client finish");
+            let start = tokio::time::Instant::now();
+            request_stream.recv_response().await.expect("recv response");
+            assert!(start.elapsed() >= delay);
+        };
+        tokio::select! { _ = req_fut => (), _ = drive_fut => () }
+    };
 
-        tokio::join!(drive_fut, request_fut);
+    let server_fut = async {
+        let handle = server_conn.handle();
+        let mut incoming_req = server::Connection::new(server_conn).await.unwrap();
+
+        let (_, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
+        handle.set_link(test::Link::default().delay(delay));
+        request_stream
+            .send_response(Response::builder().status(200).body(()).unwrap())
+            .await
+            .expect("send_response");
+        request_stream.finish().await.expect("finish");
+        incoming_req
+    };
+
+    tokio::join!(server_fut, client_fut);
+}
+
+#[tokio::test]
+async fn discarded_link_never_delivers_the_request() {
+    init_tracing();
+    let (client_conn, server_conn) = test::pair();
+    let handle = client_conn.handle();
+
+    let client_fut = async {
+        let (mut driver, mut client) = client::new(client_conn).await.expect("client init");
+        let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
+        let req_fut = async {
+            handle.set_link(test::Link::default().discard(true));
+            let mut request_stream = client
+                .send_request(Request::get("http://localhost/salut").body(()).unwrap())
+                .await
+                .expect("request");
+            request_stream.finish().await.expect("client finish");
+            future::pending::<()>().await;
+        };
+        tokio::select! { _ = req_fut => (), _ = drive_fut => () }
     };
 
     let server_fut = async {
-        let conn = server.next().await;
-        let mut incoming_req = server::Connection::new(conn).await.unwrap();
+        let mut incoming_req = server::Connection::new(server_conn).await.unwrap();
+        let accepted = tokio::time::timeout(Duration::from_millis(50), incoming_req.accept()).await;
+        assert!(accepted.is_err(), "request headers went through");
+    };
+
+    tokio::select! { _ = server_fut => (), _ = client_fut => panic!("client resolved first") };
+}
+
+#[tokio::test]
+async fn injected_reset_fails_recv_data() {
+    init_tracing();
+    let (client_conn, server_conn) = test::pair();
+    let handle = server_conn.handle();
+
+    let client_fut = async {
+        let (mut driver, mut client) = client::new(client_conn).await.expect("client init");
+        let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
+        let req_fut = async {
+            let mut request_stream = client
+                .send_request(Request::post("http://localhost/salut").body(()).unwrap())
+                .await
+                .expect("request");
+            request_stream
+                .send_data("wonderful json".into())
+                .await
+                .expect("send_data");
+            future::pending::<()>().await;
+        };
+        tokio::select! { _ = req_fut => (), _ = drive_fut => () }
+    };
 
+    let server_fut = async {
+        let mut incoming_req = server::Connection::new(server_conn).await.unwrap();
+
+        let (_, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
+        handle.reset(request_stream.id(), Code::H3_REQUEST_CANCELLED.value());
+        let err = request_stream.recv_data().await.map(|_| ()).unwrap_err();
         assert_matches!(
-            incoming_req.accept().await.map(|_| ()).unwrap_err().kind(),
-            Kind::Timeout
+            err.kind(),
+            Kind::Application {
+                code: Code::H3_REQUEST_CANCELLED,
+                ..
+            }
+        );
+    };
+
+    tokio::select! { _ = server_fut => (), _ = client_fut => panic!("client resolved first") };
+}
+
+#[tokio::test]
+async fn header_too_big_response_from_server() {
+    let (client_conn, server_conn) = test::pair();
+    header_too_big_response_from_server_on(client_conn, server_conn).await;
+}
+
+#[tokio::test]
+async fn header_too_big_response_from_server_boxed() {
+    let (client_conn, server_conn) = test::boxed_pair();
+    header_too_big_response_from_server_on(client_conn, server_conn).await;
+}
+
+async fn header_too_big_response_from_server_on<C: quic::Connection<Bytes>>(
+    client_conn: C,
+    server_conn: C,
+) {
+    init_tracing();
+
+    let client_fut = async {
+        let (mut driver, mut client) = client::new(client_conn).await.expect("client init");
+        let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
+        let req_fut = async {
+            let mut request_stream = client
+                .send_request(Request::get("http://localhost/salut").body(()).unwrap())
+                .await
+                .expect("request");
+            request_stream.finish().await.expect("client finish");
+            let response = request_stream.recv_response().await.unwrap();
+            assert_eq!(
+                response.status(),
+                StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
+            );
+        };
+        tokio::select! {biased; _ = req_fut => (), _ = drive_fut => () }
+    };
+
+    let server_fut = async {
+        //= https://www.rfc-editor.org/rfc/rfc9114#section-4.2.2
+        //= type=test
+        //# An HTTP/3 implementation MAY impose a limit on the maximum size of
+        //# the message header it will accept on an individual HTTP message.
+        let mut incoming_req = server::builder()
+            .max_field_section_size(12)
+            .build(server_conn)
+            .await
+            .unwrap();
+
+        let err_kind = incoming_req.accept().await.map(|_| ()).unwrap_err().kind();
+        assert_matches!(
+            err_kind,
+            Kind::HeaderTooBig {
+                actual_size: 42,
+                max_size: 12,
+                ..
+            }
         );
+        let _ = incoming_req.accept().await;
     };
 
     tokio::join!(server_fut, client_fut);
 }
 
 #[tokio::test]
-async fn post_timeout_server_recv_data() {
+async fn header_too_big_response_from_server_trailers() {
     init_tracing();
     let mut pair = Pair::default();
-    pair.with_timeout(Duration::from_millis(100));
     let mut server = pair.server();
 
     let client_fut = async {
-        let (_conn, mut client) = client::new(pair.client().await).await.expect("client init");
-        let _request_stream = client
-            .send_request(Request::post("http://localhost/salut").body(()).unwrap())
-            .await
-            .expect("request");
-        tokio::time::sleep(Duration::from_millis(500)).await;
+        let (mut driver, mut client) = client::new(pair.client().await).await.expect("client init");
+        let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
+        let req_fut = async {
+            let mut request_stream = client
+                .send_request(Request::get("http://localhost/salut").body(()).unwrap())
+                .await
+                .expect("request");
+            request_stream
+                .send_data("wonderful json".into())
+                .await
+                .expect("send_data");
+
+            let mut trailers = HeaderMap::new();
+            trailers.insert("trailer", "A".repeat(200).parse().unwrap());
+            request_stream
+                .send_trailers(trailers)
+                .await
+                .expect("send trailers");
+            request_stream.finish().await.expect("client finish");
+        };
+        tokio::select! {biased; _ = req_fut => (), _ = drive_fut => () }
     };
 
     let server_fut = async {
         let conn = server.next().await;
-        let mut incoming_req = server::Connection::new(conn).await.unwrap();
+        //= https://www.rfc-editor.org/rfc/rfc9114#section-4.2.2
+        //= type=test
+        //# An HTTP/3 implementation MAY impose a limit on the maximum size of
+        //# the message header it will accept on an individual HTTP message.
+        let mut incoming_req = server::builder()
+            .max_field_section_size(207)
+            .build(conn)
+            .await
+            .unwrap();
 
-        let (_, mut req_stream) = incoming_req.accept().await.expect("accept").unwrap();
+        let (_request, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
+        let _ = request_stream
+            .recv_data()
+            .await
+            .expect("recv data")
+            .expect("body");
+        let err_kind = request_stream.recv_trailers().await.unwrap_err().kind();
         assert_matches!(
-            req_stream.recv_data().await.map(|_| ()).unwrap_err().kind(),
-            Kind::Timeout
+            err_kind,
+            Kind::HeaderTooBig {
+                actual_size: 239,
+                max_size: 207,
+                ..
+            }
         );
+        let _ = incoming_req.accept().await;
     };
 
     tokio::join!(server_fut, client_fut);
 }
 
-// 4.1. HTTP Message Exchanges
+#[tokio::test]
+async fn header_too_big_client_error() {
+    init_tracing();
+    let mut pair = Pair::default();
+    let mut server = pair.server();
 
-// An HTTP message (request or response) consists of:
-// * the header section, sent as a single HEADERS frame (see Section 7.2.2),
-// * optionally, the content, if present, sent as a series of DATA frames (see Section 7.2.1),
-// * and optionally, the trailer section, if present, sent as a single HEADERS frame.
+    let client_fut = async {
+        let (mut driver, mut client) = client::new(pair.client().await).await.expect("client init");
+        let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
+        let req_fut = async {
+            // pretend client already received server's settings
+            client
+                .shared_state()
+                .write("client")
+                .peer_config
+                .max_field_section_size = 12;
+
+            let req = Request::get("http://localhost/salut").body(()).unwrap();
+            let err_kind = client
+                .send_request(req)
+                .await
+                .map(|_| ())
+                .unwrap_err()
+                .kind();
+            assert_matches!(
+                err_kind,
+                Kind::HeaderTooBig {
+                    actual_size: 179,
+                    max_size: 12,
+                    ..
+                }
+            );
+        };
+        tokio::select! {biased; _ = req_fut => (),_ = drive_fut => () }
+    };
+
+    let server_fut = async {
+        let conn = server.next().await;
+        //= https://www.rfc-editor.org/rfc/rfc9114#section-4.2.2
+        //= type=test
+        //# An HTTP/3 implementation MAY impose a limit on the maximum size of
+        //# the message header it will accept on an individual HTTP message.
+        server::builder()
+            .max_field_section_size(12)
+            .build(conn)
+            .await
+            .unwrap();
+    };
+
+    tokio::join!(server_fut, client_fut);
+}
 
 #[tokio::test]
-async fn request_valid_one_header() {
-    request_sequence_ok(|mut buf| {
+async fn header_too_big_client_error_trailer() {
+    init_tracing();
+    let mut pair = Pair::default();
+    let mut server = pair.server();
+
+    let client_fut = async {
+        let (mut driver, mut client) = client::new(pair.client().await).await.expect("client init");
+        let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
+        let req_fut = async {
+            client
+                .shared_state()
+                .write("client")
+                .peer_config
+                .max_field_section_size = 200;
+
+            let mut request_stream = client
+                .send_request(Request::get("http://localhost/salut").body(()).unwrap())
+                .await
+                .expect("request");
+            request_stream
+                .send_data("wonderful json".into())
+                .await
+                .expect("send_data");
+
+            let mut trailers = HeaderMap::new();
+            trailers.insert("trailer", "A".repeat(200).parse().unwrap());
+
+            let err_kind = request_stream
+                .send_trailers(trailers)
+                .await
+                .unwrap_err()
+                .kind();
+            assert_matches!(
+                err_kind,
+                Kind::HeaderTooBig {
+                    actual_size: 239,
+                    max_size: 200,
+                    ..
+                }
+            );
+
+            request_stream.finish().await.expect("client finish");
+        };
+        tokio::select! {biased; _ = req_fut => (), _ = drive_fut => () }
+    };
+
+    let server_fut = async {
+        let conn = server.next().await;
+        //= https://www.rfc-editor.org/rfc/rfc9114#section-4.2.2
+        //= type=test
+        //# An HTTP/3 implementation MAY impose a limit on the maximum size of
+        //# the message header it will accept on an individual HTTP message.
+        let mut incoming_req = server::builder()
+            .max_field_section_size(207)
+            .build(conn)
+            .await
+            .unwrap();
+
+        let (_request, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
+        let _ = request_stream
+            .recv_data()
+            .await
+            .expect("recv data")
+            .expect("body");
+        let _ = incoming_req.accept().await;
+    };
+
+    tokio::join!(server_fut, client_fut);
+}
+
+#[tokio::test]
+async fn header_too_big_discard_from_client() {
+    let (client_conn, server_conn) = test::pair();
+    header_too_big_discard_from_client_on(client_conn, server_conn).await;
+}
+
+#[tokio::test]
+async fn header_too_big_discard_from_client_boxed() {
+    let (client_conn, server_conn) = test::boxed_pair();
+    header_too_big_discard_from_client_on(client_conn, server_conn).await;
+}
+
+async fn header_too_big_discard_from_client_on<C: quic::Connection<Bytes>>(
+    client_conn: C,
+    server_conn: C,
+) {
+    init_tracing();
+
+    let client_fut = async {
+        //= https://www.rfc-editor.org/rfc/rfc9114#section-4.2.2
+        //= type=test
+        //# An implementation that
+        //# has received this parameter SHOULD NOT send an HTTP message header
+        //# that exceeds the indicated size, as the peer will likely refuse to
+        //# process it.
+
+        let (mut driver, mut client) = client::builder()
+            .max_field_section_size(12)
+            // Don't send settings, so server doesn't know about the low max_field_section_size
+            .send_settings(false)
+            .build::<_, _, Bytes>(client_conn)
+            .await
+            .expect("client init");
+        let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
+        let req_fut = async {
+            let mut request_stream = client
+                .send_request(Request::get("http://localhost/salut").body(()).unwrap())
+                .await
+                .expect("request");
+            request_stream.finish().await.expect("client finish");
+            let err_kind = request_stream.recv_response().await.unwrap_err().kind();
+            assert_matches!(
+                err_kind,
+                Kind::HeaderTooBig {
+                    actual_size: 42,
+                    max_size: 12,
+                    ..
+                }
+            );
+
+            let mut request_stream = client
+                .send_request(Request::get("http://localhost/salut").body(()).unwrap())
+                .await
+                .expect("request");
+            request_stream.finish().await.expect("client finish");
+            let _ = request_stream.recv_response().await.unwrap_err();
+        };
+        tokio::select! {biased; _ = req_fut => (), _ = drive_fut => () }
+    };
+
+    let server_fut = async {
+        let mut incoming_req = server::Connection::new(server_conn).await.unwrap();
+
+        let (_request, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
+        request_stream
+            .send_response(
+                Response::builder()
+                    .status(200)
+                    .body(())
+                    .expect("build response"),
+            )
+            .await
+            .expect("send_response");
+
+        // Keep sending: wait for the stream to be cancelled by the client
+        let mut err = None;
+        for _ in 0..100 {
+            if let Err(e) = request_stream.send_data("some data".into()).await {
+                err = Some(e);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(2)).await;
+        }
+        assert_matches!(
+            err.as_ref().unwrap().kind(),
+            Kind::Application {
+                code: Code::H3_REQUEST_CANCELLED,
+                ..
+            }
+        );
+        let _ = incoming_req.accept().await;
+    };
+
+    tokio::join!(server_fut, client_fut);
+}
+
+#[tokio::test]
+async fn header_too_big_discard_from_client_trailers() {
+    let (client_conn, server_conn) = test::pair();
+    header_too_big_discard_from_client_trailers_on(client_conn, server_conn).await;
+}
+
+#[tokio::test]
+async fn header_too_big_discard_from_client_trailers_boxed() {
+    let (client_conn, server_conn) = test::boxed_pair();
+    header_too_big_discard_from_client_trailers_on(client_conn, server_conn).await;
+}
+
+async fn header_too_big_discard_from_client_trailers_on<C: quic::Connection<Bytes>>(
+    client_conn: C,
+    server_conn: C,
+) {
+    init_tracing();
+
+    let client_fut = async {
+        //= https://www.rfc-editor.org/rfc/rfc9114#section-4.2.2
+        //= type=test
+        //# An implementation that
+        //# has received this parameter SHOULD NOT send an HTTP message header
+        //# that exceeds the indicated size, as the peer will likely refuse to
+        //# process it.
+
+        let (mut driver, mut client) = client::builder()
+            .max_field_section_size(200)
+            // Don't send settings, so server doesn't know about the low max_field_section_size
+            .send_settings(false)
+            .build::<_, _, Bytes>(client_conn)
+            .await
+            .expect("client init");
+
+        let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
+        let req_fut = async {
+            let mut request_stream = client
+                .send_request(Request::get("http://localhost/salut").body(()).unwrap())
+                .await
+                .expect("request");
+            request_stream.recv_response().await.expect("recv response");
+            request_stream.recv_data().await.expect("recv data");
+
+            let err_kind = request_stream.recv_trailers().await.unwrap_err().kind();
+            assert_matches!(
+                err_kind,
+                Kind::HeaderTooBig {
+                    actual_size: 539,
+                    max_size: 200,
+                    ..
+                }
+            );
+            request_stream.finish().await.expect("client finish");
+        };
+        tokio::select! {biased; _ = req_fut => (), _ = drive_fut => () }
+    };
+
+    let server_fut = async {
+        let mut incoming_req = server::Connection::new(server_conn).await.unwrap();
+
+        let (_request, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
+
+        request_stream
+            .send_response(
+                Response::builder()
+                    .status(200)
+                    .body(())
+                    .expect("build response"),
+            )
+            .await
+            .expect("send_response");
+
+        request_stream
+            .send_data("wonderful hypertext".into())
+            .await
+            .expect("send_data");
+
+        let mut trailers = HeaderMap::new();
+        trailers.insert("trailer", "value".repeat(100).parse().unwrap());
+        request_stream
+            .send_trailers(trailers)
+            .await
+            .expect("send_trailers");
+        request_stream.finish().await.expect("finish");
+
+        let _ = incoming_req.accept().await;
+    };
+
+    tokio::join!(server_fut, client_fut);
+}
+
+#[tokio::test]
+async fn header_too_big_server_error() {
+    let (client_conn, server_conn) = test::pair();
+    header_too_big_server_error_on(client_conn, server_conn).await;
+}
+
+#[tokio::test]
+async fn header_too_big_server_error_boxed() {
+    let (client_conn, server_conn) = test::boxed_pair();
+    header_too_big_server_error_on(client_conn, server_conn).await;
+}
+
+async fn header_too_big_server_error_on<C: quic::Connection<Bytes>>(
+    client_conn: C,
+    server_conn: C,
+) {
+    init_tracing();
+
+    let client_fut = async {
+        let (mut driver, mut client) = client::new(client_conn) // header size limit faked for brevity
+            .await
+            .expect("client init");
+        let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
+        let req_fut = async {
+            let req = Request::get("http://localhost/salut").body(()).unwrap();
+            let _ = client
+                .send_request(req)
+                .await
+                .unwrap()
+                .recv_response()
+                .await;
+        };
+        tokio::select! { _ = req_fut => (), _ = drive_fut => () }
+    };
+
+    let server_fut = async {
+        let mut incoming_req = server::Connection::new(server_conn).await.unwrap();
+
+        let (_request, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
+
+        //= https://www.rfc-editor.org/rfc/rfc9114#section-4.2.2
+        //= type=test
+        //# An implementation that
+        //# has received this parameter SHOULD NOT send an HTTP message header
+        //# that exceeds the indicated size, as the peer will likely refuse to
+        //# process it.
+
+        // pretend the server received a smaller max_field_section_size
+        incoming_req
+            .shared_state()
+            .write("server")
+            .peer_config
+            .max_field_section_size = 12;
+
+        let err_kind = request_stream
+            .send_response(
+                Response::builder()
+                    .status(200)
+                    .body(())
+                    .expect("build response"),
+            )
+            .await
+            .map(|_| ())
+            .unwrap_err()
+            .kind();
+
+        assert_matches!(
+            err_kind,
+            Kind::HeaderTooBig {
+                actual_size: 42,
+                max_size: 12,
+                ..
+            }
+        );
+    };
+
+    tokio::join!(server_fut, client_fut);
+}
+
+#[tokio::test]
+async fn header_too_big_server_error_trailers() {
+    let (client_conn, server_conn) = test::pair();
+    header_too_big_server_error_trailers_on(client_conn, server_conn).await;
+}
+
+#[tokio::test]
+async fn header_too_big_server_error_trailers_boxed() {
+    let (client_conn, server_conn) = test::boxed_pair();
+    header_too_big_server_error_trailers_on(client_conn, server_conn).await;
+}
+
+async fn header_too_big_server_error_trailers_on<C: quic::Connection<Bytes>>(
+    client_conn: C,
+    server_conn: C,
+) {
+    init_tracing();
+
+    let client_fut = async {
+        let (mut driver, mut client) = client::new(client_conn) // header size limit faked for brevity
+            .await
+            .expect("client init");
+        let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
+        let req_fut = async {
+            let req = Request::get("http://localhost/salut").body(()).unwrap();
+            let _ = client
+                .send_request(req)
+                .await
+                .unwrap()
+                .recv_response()
+                .await;
+        };
+        tokio::select! { _ = req_fut => (), _ = drive_fut => () }
+    };
+
+    let server_fut = async {
+        let mut incoming_req = server::Connection::new(server_conn).await.unwrap();
+
+        let (_request, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
+        request_stream
+            .send_response(
+                Response::builder()
+                    .status(200)
+                    .body(())
+                    .expect("build response"),
+            )
+            .await
+            .unwrap();
+        request_stream
+            .send_data("wonderful hypertext".into())
+            .await
+            .expect("send_data");
+
+        //= https://www.rfc-editor.org/rfc/rfc9114#section-4.2.2
+        //= type=test
+        //# An implementation that
+        //# has received this parameter SHOULD NOT send an HTTP message header
+        //# that exceeds the indicated size, as the peer will likely refuse to
+        //# process it.
+
+        // pretend the server already received client's settings
+        incoming_req
+            .shared_state()
+            .write("write")
+            .peer_config
+            .max_field_section_size = 200;
+
+        let mut trailers = HeaderMap::new();
+        trailers.insert("trailer", "value".repeat(100).parse().unwrap());
+        let err_kind = request_stream
+            .send_trailers(trailers)
+            .await
+            .unwrap_err()
+            .kind();
+        assert_matches!(
+            err_kind,
+            Kind::HeaderTooBig {
+                actual_size: 539,
+                max_size: 200,
+                ..
+            }
+        );
+    };
+
+    tokio::join!(server_fut, client_fut);
+}
+
+#[tokio::test]
+async fn body_read_to_end() {
+    use tokio::io::AsyncReadExt;
+
+    init_tracing();
+    let (client_conn, server_conn) = test::pair();
+
+    let client_fut = async {
+        let (mut driver, mut client) = client::new(client_conn).await.expect("client init");
+        let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
+        let req_fut = async {
+            let mut request_stream = client
+                .send_request(Request::post("http://localhost/salut").body(()).unwrap())
+                .await
+                .expect("request");
+            for data in ["hyper", "", "text transfer"] {
+                request_stream.send_data(data.into()).await.expect("data");
+            }
+            let mut trailers = HeaderMap::new();
+            trailers.insert("trailer", "tail".parse().unwrap());
+            request_stream
+                .send_trailers(trailers)
+                .await
+                .expect("trailers");
+            request_stream.finish().await.expect("finish");
+            request_stream.recv_response().await.expect("recv response");
+        };
+        tokio::select! { _ = req_fut => (), _ = drive_fut => () }
+    };
+
+    let server_fut = async {
+        let mut incoming_req = server::Connection::new(server_conn).await.unwrap();
+        let (_request, request_stream) = incoming_req.accept().await.expect("accept").unwrap();
+        let mut body = server::BodyRead::new(request_stream);
+
+        // Reads shorter than the DATA frames
+        let mut start = [0; 4];
+        body.read_exact(&mut start).await.unwrap();
+        assert_eq!(&start, b"hype");
+        let mut rest = Vec::new();
+        body.read_to_end(&mut rest).await.unwrap();
+        assert_eq!(rest, b"rtext transfer");
+
+        let mut request_stream = body.into_inner();
+        let trailers = request_stream.recv_trailers().await.expect("trailers");
+        assert_eq!(trailers.expect("trailers")["trailer"], "tail");
+        request_stream
+            .send_response(Response::builder().status(200).body(()).unwrap())
+            .await
+            .expect("send_response");
+        request_stream.finish().await.expect("finish");
+    };
+
+    tokio::join!(server_fut, client_fut);
+}
+
+#[tokio::test]
+async fn recv_message_across_data_frames() {
+    init_tracing();
+    let (client_conn, server_conn) = test::pair();
+
+    let client_fut = async {
+        let (mut driver, mut client) = client::new(client_conn).await.expect("client init");
+        let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
+        let req_fut = async {
+            let mut request_stream = client
+                .send_request(Request::post("http://localhost/salut").body(()).unwrap())
+                .await
+                .expect("request");
+            // A message split in the middle of its prefix, then one cut short by the end
+            let mut body = BytesMut::new();
+            body.put_u8(0);
+            body.put_u32(5);
+            body.put_slice(b"hello");
+            body.put_u8(0);
+            body.put_u32(10);
+            body.put_slice(b"01234");
+            let mut body = body.freeze();
+            request_stream
+                .send_data(body.split_to(3))
+                .await
+                .expect("data");
+            request_stream.send_data(body).await.expect("data");
+            request_stream.finish().await.expect("finish");
+            request_stream.recv_response().await.expect("recv response");
+        };
+        tokio::select! { _ = req_fut => (), _ = drive_fut => () }
+    };
+
+    let server_fut = async {
+        let mut incoming_req = server::Connection::new(server_conn).await.unwrap();
+        let (_request, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
+
+        assert_matches!(
+            request_stream.recv_message().await,
+            Ok(Some(m)) if &m[..] == b"\0\0\0\0\x05hello"
+        );
+        let err = request_stream.recv_message().await.unwrap_err();
+        assert_matches!(
+            err.kind(),
+            Kind::Application {
+                code: Code::H3_MESSAGE_ERROR,
+                level: ErrorLevel::StreamError,
+                ..
+            }
+        );
+        request_stream
+            .send_response(Response::builder().status(200).body(()).unwrap())
+            .await
+            .expect("send_response");
+        request_stream.finish().await.expect("finish");
+    };
+
+    tokio::join!(server_fut, client_fut);
+}
+
+#[tokio::test]
+async fn peer_field_section_size_from_client_settings() {
+    init_tracing();
+    let (client_conn, server_conn) = test::pair();
+
+    let client_fut = async {
+        let (mut driver, mut client) = client::builder()
+            .max_field_section_size(100)
+            .build::<_, _, Bytes>(client_conn)
+            .await
+            .expect("client init");
+        let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
+        let req_fut = async {
+            let mut request_stream = client
+                .send_request(Request::get("http://localhost/salut").body(()).unwrap())
+                .await
+                .expect("request");
+            request_stream.finish().await.expect("finish");
+
+            // Only what fits the limit was sent, or the client would refuse it
+            let response = request_stream.recv_response().await.expect("recv response");
+            assert_eq!(response.status(), StatusCode::OK);
+            assert!(response.headers().is_empty());
+            let body = request_stream
+                .recv_data()
+                .await
+                .expect("recv data")
+                .expect("body");
+            assert_eq!(body.chunk(), b"wonderful hypertext");
+            let trailers = request_stream.recv_trailers().await.expect("trailers");
+            assert_eq!(trailers.expect("trailers")["trailer"], "tail");
+        };
+        tokio::select! { _ = req_fut => (), _ = drive_fut => () }
+    };
+
+    let server_fut = async {
+        let mut incoming_req = server::Connection::new(server_conn).await.unwrap();
+        let (_request, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
+
+        // The limit applies once the client SETTINGS are received
+        future::poll_fn(|cx| {
+            let _ = incoming_req.poll_control(cx);
+            let state = incoming_req.shared_state().read("test");
+            if state.peer_config.max_field_section_size == 100 {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+
+        //= https://www.rfc-editor.org/rfc/rfc9114#section-4.2.2
+        //= type=test
+        //# An implementation that
+        //# has received this parameter SHOULD NOT send an HTTP message header
+        //# that exceeds the indicated size, as the peer will likely refuse to
+        //# process it.
+        let response = Response::builder()
+            .status(200)
+            .header("x-big", "A".repeat(100))
+            .body(())
+            .unwrap();
+        assert_matches!(
+            request_stream
+                .send_response(response)
+                .await
+                .unwrap_err()
+                .kind(),
+            Kind::HeaderTooBig {
+                actual_size: 179,
+                max_size: 100,
+            }
+        );
+        request_stream
+            .send_response(Response::builder().status(200).body(()).unwrap())
+            .await
+            .expect("send_response");
+        request_stream
+            .send_data("wonderful hypertext".into())
+            .await
+            .expect("send_data");
+
+        let mut trailers = HeaderMap::new();
+        trailers.insert("trailer", "A".repeat(100).parse().unwrap());
+        assert_matches!(
+            request_stream
+                .send_trailers(trailers)
+                .await
+                .unwrap_err()
+                .kind(),
+            Kind::HeaderTooBig {
+                actual_size: 139,
+                max_size: 100,
+            }
+        );
+        let mut trailers = HeaderMap::new();
+        trailers.insert("trailer", "tail".parse().unwrap());
+        request_stream
+            .send_trailers(trailers)
+            .await
+            .expect("send_trailers");
+        request_stream.finish().await.expect("finish");
+    };
+
+    tokio::join!(server_fut, client_fut);
+}
+
+#[tokio::test]
+async fn get_timeout_client_recv_response() {
+    init_tracing();
+    let mut pair = Pair::default();
+    pair.with_timeout(Duration::from_millis(100));
+    let mut server = pair.server();
+
+    let client_fut = async {
+        let (mut conn, mut client) = client::new(pair.client().await).await.expect("client init");
+        let request_fut = async {
+            let mut request_stream = client
+                .send_request(Request::get("http://localhost/salut").body(()).unwrap())
+                .await
+                .expect("request");
+
+            let response = request_stream.recv_response().await;
+            assert_matches!(response.unwrap_err().kind(), Kind::Timeout);
+        };
+
+        let drive_fut = async move {
+            let result = future::poll_fn(|cx| conn.poll_close(cx)).await;
+            assert_matches!(result.unwrap_err().kind(), Kind::Timeout);
+        };
+
+        tokio::join!(drive_fut, request_fut);
+    };
+
+    let server_fut = async {
+        let conn = server.next().await;
+        let mut incoming_req = server::Connection::new(conn).await.unwrap();
+
+        // _req must not be dropped, else the connection will be closed and the timeout
+        // wont be triggered
+        let _req = incoming_req.accept().await.expect("accept").unwrap();
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    };
+
+    tokio::join!(server_fut, client_fut);
+}
+
+#[tokio::test]
+async fn get_timeout_client_recv_data() {
+    init_tracing();
+    let mut pair = Pair::default();
+    pair.with_timeout(Duration::from_millis(200));
+    let mut server = pair.server();
+
+    let client_fut = async {
+        let (mut conn, mut client) = client::new(pair.client().await).await.expect("client init");
+        let request_fut = async {
+            let mut request_stream = client
+                .send_request(Request::get("http://localhost/salut").body(()).unwrap())
+                .await
+                .expect("request");
+
+            let _ = request_stream.recv_response().await.unwrap();
+            let data = request_stream.recv_data().await;
+            assert_matches!(data.map(|_| ()).unwrap_err().kind(), Kind::Timeout);
+        };
+
+        let drive_fut = async move {
+            let result = future::poll_fn(|cx| conn.poll_close(cx)).await;
+            assert_matches!(result.unwrap_err().kind(), Kind::Timeout);
+        };
+
+        tokio::join!(drive_fut, request_fut);
+    };
+
+    let server_fut = async {
+        let conn = server.next().await;
+        let mut incoming_req = server::Connection::new(conn).await.unwrap();
+
+        let (_request, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
+        request_stream
+            .send_response(
+                Response::builder()
+                    .status(200)
+                    .body(())
+                    .expect("build response"),
+            )
+            .await
+            .expect("send_response");
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    };
+
+    tokio::join!(server_fut, client_fut);
+}
+
+#[tokio::test]
+async fn get_timeout_server_accept() {
+    init_tracing();
+    let mut pair = Pair::default();
+    pair.with_timeout(Duration::from_millis(200));
+    let mut server = pair.server();
+
+    let client_fut = async {
+        let (mut conn, _client) = client::new(pair.client().await).await.expect("client init");
+        let request_fut = async {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        };
+
+        let drive_fut = async move {
+            let result = future::poll_fn(|cx| conn.poll_close(cx)).await;
+            assert_matches!(result.unwrap_err().kind(), Kind::Timeout);
+        };
+
+        tokio::join!(drive_fut, request_fut);
+    };
+
+    let server_fut = async {
+        let conn = server.next().await;
+        let mut incoming_req = server::Connection::new(conn).await.unwrap();
+
+        assert_matches!(
+            incoming_req.accept().await.map(|_| ()).unwrap_err().kind(),
+            Kind::Timeout
+        );
+    };
+
+    tokio::join!(server_fut, client_fut);
+}
+
+#[tokio::test]
+async fn post_timeout_server_recv_data() {
+    init_tracing();
+    let mut pair = Pair::default();
+    pair.with_timeout(Duration::from_millis(100));
+    let mut server = pair.server();
+
+    let client_fut = async {
+        let (_conn, mut client) = client::new(pair.client().await).await.expect("client init");
+        let _request_stream = client
+            .send_request(Request::post("http://localhost/salut").body(()).unwrap())
+            .await
+            .expect("request");
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    };
+
+    let server_fut = async {
+        let conn = server.next().await;
+        let mut incoming_req = server::Connection::new(conn).await.unwrap();
+
+        let (_, mut req_stream) = incoming_req.accept().await.expect("accept").unwrap();
+        assert_matches!(
+            req_stream.recv_data().await.map(|_| ()).unwrap_err().kind(),
+            Kind::Timeout
+        );
+    };
+
+    tokio::join!(server_fut, client_fut);
+}
+
+// 4.1. HTTP Message Exchanges
+
+// An HTTP message (request or response) consists of:
+// * the header section, sent as a single HEADERS frame (see Section 7.2.2),
+// * optionally, the content, if present, sent as a series of DATA frames (see Section 7.2.1),
+// * and optionally, the trailer section, if present, sent as a single HEADERS frame.
+
+#[tokio::test]
+async fn request_valid_one_header() {
+    request_sequence_ok(|mut buf| {
+        request_encode(
+            &mut buf,
+            Request::post("http://localhost/salut").body(()).unwrap(),
+        );
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn request_valid_header_data() {
+    request_sequence_ok(|mut buf| {
+        request_encode(
+            &mut buf,
+            Request::post("http://localhost/salut").body(()).unwrap(),
+        );
+        Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn request_valid_header_data_trailer() {
+    request_sequence_ok(|mut buf| {
+        request_encode(
+            &mut buf,
+            Request::post("http://localhost/salut").body(()).unwrap(),
+        );
+        Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
+        let mut trailers = HeaderMap::new();
+        trailers.insert("trailer", "value".parse().unwrap());
+        trailers_encode(buf, trailers);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn request_valid_header_multiple_data_trailer() {
+    request_sequence_ok(|mut buf| {
+        request_encode(
+            &mut buf,
+            Request::post("http://localhost/salut").body(()).unwrap(),
+        );
+        Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
+        Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
+        Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
+        let mut trailers = HeaderMap::new();
+        trailers.insert("trailer", "value".parse().unwrap());
+        trailers_encode(buf, trailers);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn request_valid_header_trailer() {
+    request_sequence_ok(|mut buf| {
+        request_encode(
+            &mut buf,
+            Request::post("http://localhost/salut").body(()).unwrap(),
+        );
+        let mut trailers = HeaderMap::new();
+        trailers.insert("trailer", "value".parse().unwrap());
+        trailers_encode(buf, trailers);
+    })
+    .await;
+}
+
+// Frames of unknown types (Section 9), including reserved frames (Section
+// 7.2.8) MAY be sent on a request or push stream before, after, or interleaved
+// with other frames described in this section.
+
+#[tokio::test]
+async fn request_valid_unkown_frame_before() {
+    request_sequence_ok(|mut buf| {
+        unknown_frame_encode(buf);
+        request_encode(
+            &mut buf,
+            Request::post("http://localhost/salut").body(()).unwrap(),
+        );
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn request_valid_unkown_frame_after_one_header() {
+    request_sequence_ok(|mut buf| {
+        request_encode(
+            &mut buf,
+            Request::post("http://localhost/salut").body(()).unwrap(),
+        );
+        unknown_frame_encode(buf);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn request_valid_unkown_frame_interleaved_after_header() {
+    request_sequence_ok(|mut buf| {
+        request_encode(
+            &mut buf,
+            Request::post("http://localhost/salut").body(()).unwrap(),
+        );
+        unknown_frame_encode(buf);
+        Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn request_valid_unkown_frame_interleaved_between_data() {
+    request_sequence_ok(|mut buf| {
+        request_encode(
+            &mut buf,
+            Request::post("http://localhost/salut").body(()).unwrap(),
+        );
+        Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
+        unknown_frame_encode(buf);
+        Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn request_valid_unkown_frame_interleaved_after_data() {
+    request_sequence_ok(|mut buf| {
+        request_encode(
+            &mut buf,
+            Request::post("http://localhost/salut").body(()).unwrap(),
+        );
+        Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
+        unknown_frame_encode(buf);
+        Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn request_valid_unkown_frame_interleaved_before_trailers() {
+    request_sequence_ok(|mut buf| {
+        request_encode(
+            &mut buf,
+            Request::post("http://localhost/salut").body(()).unwrap(),
+        );
+        Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
+        unknown_frame_encode(buf);
+        let mut trailers = HeaderMap::new();
+        trailers.insert("trailer", "value".parse().unwrap());
+        trailers_encode(buf, trailers);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn request_valid_unkown_frame_after_trailers() {
+    request_sequence_ok(|mut buf| {
+        request_encode(
+            &mut buf,
+            Request::post("http://localhost/salut").body(()).unwrap(),
+        );
+        Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
+        let mut trailers = HeaderMap::new();
+        trailers.insert("trailer", "value".parse().unwrap());
+        trailers_encode(buf, trailers);
+        unknown_frame_encode(buf);
+    })
+    .await;
+}
+
+// ORIGIN frames belong on the control stream, and are ignored elsewhere
+#[tokio::test]
+async fn request_valid_origin_frame_ignored() {
+    request_sequence_ok(|mut buf| {
+        let origin = || Frame::<Bytes>::Origin {
+            entries: vec![frame::OriginEntry::new(Bytes::from("https://localhost")).unwrap()],
+        };
+        origin().encode(buf);
+        request_encode(
+            &mut buf,
+            Request::post("http://localhost/salut").body(()).unwrap(),
+        );
+        origin().encode(buf);
+        Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
+        origin().encode(buf);
+    })
+    .await;
+}
+
+//= https://www.rfc-editor.org/rfc/rfc9114#section-4.1
+//= type=test
+//# Receipt of an invalid sequence of frames MUST be treated as a
+//# connection error of type H3_FRAME_UNEXPECTED.
+fn invalid_request_frames() -> Vec<Frame<Bytes>> {
+    vec![
+        Frame::CancelPush(PushId(0)),
+        Frame::Settings(frame::Settings::default()),
+        Frame::Goaway(VarInt(1)),
+        Frame::MaxPushId(PushId(1)),
+    ]
+}
+
+#[tokio::test]
+async fn request_invalid_frame_first() {
+    //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.3
+    //= type=test
+    //# Receiving a
+    //# CANCEL_PUSH frame on a stream other than the control stream MUST be
+    //# treated as a connection error of type H3_FRAME_UNEXPECTED.
+
+    //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.4
+    //= type=test
+    //# If an endpoint receives a SETTINGS frame on a different
+    //# stream, the endpoint MUST respond with a connection error of type
+    //# H3_FRAME_UNEXPECTED.
+
+    //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.6
+    //= type=test
+    //# A client MUST treat a GOAWAY frame on a stream other than
+    //# the control stream as a connection error of type H3_FRAME_UNEXPECTED.
+
+    //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.7
+    //= type=test
+    //# The MAX_PUSH_ID frame is always sent on the control stream.  Receipt
+    //# of a MAX_PUSH_ID frame on any other stream MUST be treated as a
+    //# connection error of type H3_FRAME_UNEXPECTED.
+    for frame in invalid_request_frames() {
+        request_sequence_unexpected(|mut buf| frame.encode(&mut buf)).await;
+    }
+}
+
+#[tokio::test]
+async fn request_invalid_frame_after_header() {
+    for frame in invalid_request_frames() {
+        request_sequence_unexpected(|mut buf| {
+            request_encode(
+                &mut buf,
+                Request::post("http://localhost/salut").body(()).unwrap(),
+            );
+            frame.encode(&mut buf);
+        })
+        .await;
+    }
+}
+
+#[tokio::test]
+async fn request_invalid_frame_after_data() {
+    for frame in invalid_request_frames() {
+        request_sequence_unexpected(|mut buf| {
+            request_encode(
+                &mut buf,
+                Request::post("http://localhost/salut").body(()).unwrap(),
+            );
+            Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
+            frame.encode(&mut buf);
+        })
+        .await;
+    }
+}
+
+#[tokio::test]
+async fn request_invalid_frame_after_trailers() {
+    for frame in invalid_request_frames() {
+        request_sequence_unexpected(|mut buf| {
+            request_encode(
+                &mut buf,
+                Request::post("http://localhost/salut").body(()).unwrap(),
+            );
+            Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
+            let mut trailers = HeaderMap::new();
+            trailers.insert("trailer", "value".parse().unwrap());
+            trailers_encode(buf, trailers);
+            frame.encode(&mut buf);
+        })
+        .await;
+    }
+}
+
+#[tokio::test]
+async fn request_invalid_data_after_trailers() {
+    request_sequence_unexpected(|mut buf| {
         request_encode(
             &mut buf,
             Request::post("http://localhost/salut").body(()).unwrap(),
         );
+        let mut trailers = HeaderMap::new();
+        trailers.insert("trailer", "value".parse().unwrap());
+        trailers_encode(buf, trailers);
+        Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
     })
     .await;
 }
 
 #[tokio::test]
-async fn request_valid_header_data() {
-    request_sequence_ok(|mut buf| {
-        request_encode(
-            &mut buf,
-            Request::post("http://localhost/salut").body(()).unwrap(),
-        );
+async fn request_invalid_data_first() {
+    request_sequence_unexpected(|mut buf| {
         Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
     })
     .await;
 }
 
 #[tokio::test]
-async fn request_valid_header_data_trailer() {
-    request_sequence_ok(|mut buf| {
+async fn request_invalid_two_trailers() {
+    request_sequence_unexpected(|mut buf| {
         request_encode(
             &mut buf,
             Request::post("http://localhost/salut").body(()).unwrap(),
@@ -989,35 +2341,51 @@ async fn request_valid_header_data_trailer() {
         Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
         let mut trailers = HeaderMap::new();
         trailers.insert("trailer", "value".parse().unwrap());
+        trailers_encode(buf, trailers.clone());
         trailers_encode(buf, trailers);
     })
     .await;
 }
 
 #[tokio::test]
-async fn request_valid_header_multiple_data_trailer() {
-    request_sequence_ok(|mut buf| {
+async fn request_invalid_trailing_byte() {
+    request_sequence_frame_error(|mut buf| {
         request_encode(
             &mut buf,
             Request::post("http://localhost/salut").body(()).unwrap(),
         );
         Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
-        Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
-        Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
         let mut trailers = HeaderMap::new();
         trailers.insert("trailer", "value".parse().unwrap());
         trailers_encode(buf, trailers);
+
+        //= https://www.rfc-editor.org/rfc/rfc9114#section-7.1
+        //= type=test
+        //# A frame payload that contains additional bytes
+        //# after the identified fields or a frame payload that terminates before
+        //# the end of the identified fields MUST be treated as a connection
+        //# error of type H3_FRAME_ERROR.
+        buf.put_u8(255);
     })
     .await;
 }
 
 #[tokio::test]
-async fn request_valid_header_trailer() {
-    request_sequence_ok(|mut buf| {
+async fn request_invalid_data_frame_length_too_large() {
+    request_sequence_frame_error(|mut buf| {
         request_encode(
             &mut buf,
             Request::post("http://localhost/salut").body(()).unwrap(),
         );
+        FrameType::DATA.encode(&mut buf);
+
+        //= https://www.rfc-editor.org/rfc/rfc9114#section-7.1
+        //= type=test
+        //# In particular, redundant length
+        //# encodings MUST be verified to be self-consistent; see Section 10.8.
+        VarInt::from(5u32).encode(&mut buf);
+        buf.put_slice(b"fada");
+
         let mut trailers = HeaderMap::new();
         trailers.insert("trailer", "value".parse().unwrap());
         trailers_encode(buf, trailers);
@@ -1025,298 +2393,611 @@ async fn request_valid_header_trailer() {
     .await;
 }
 
-// Frames of unknown types (Section 9), including reserved frames (Section
-// 7.2.8) MAY be sent on a request or push stream before, after, or interleaved
-// with other frames described in this section.
-
 #[tokio::test]
-async fn request_valid_unkown_frame_before() {
-    request_sequence_ok(|mut buf| {
-        unknown_frame_encode(buf);
+async fn request_invalid_data_frame_length_too_short() {
+    request_sequence_frame_error(|mut buf| {
         request_encode(
             &mut buf,
             Request::post("http://localhost/salut").body(()).unwrap(),
         );
+        FrameType::DATA.encode(&mut buf);
+
+        //= https://www.rfc-editor.org/rfc/rfc9114#section-7.1
+        //= type=test
+        //# In particular, redundant length
+        //# encodings MUST be verified to be self-consistent; see Section 10.8.
+        VarInt::from(3u32).encode(&mut buf);
+        buf.put_slice(b"fada");
     })
     .await;
 }
 
 #[tokio::test]
-async fn request_valid_unkown_frame_after_one_header() {
-    request_sequence_ok(|mut buf| {
-        request_encode(
-            &mut buf,
-            Request::post("http://localhost/salut").body(()).unwrap(),
-        );
-        unknown_frame_encode(buf);
-    })
-    .await;
+async fn finish_sends_fin_once() {
+    let record = SendRecord::default();
+    let mut stream = record.request_stream();
+
+    stream.finish().await.expect("finish");
+    stream.finish().await.expect("finish again");
+    assert_eq!(record.0.lock().unwrap().finished, 1);
+
+    assert_matches!(
+        stream
+            .send_data(Bytes::from("late"))
+            .await
+            .unwrap_err()
+            .kind(),
+        Kind::AlreadyFinished
+    );
+    assert_matches!(
+        stream
+            .send_trailers(HeaderMap::new())
+            .await
+            .unwrap_err()
+            .kind(),
+        Kind::AlreadyFinished
+    );
+
+    drop(stream);
+    assert_eq!(record.0.lock().unwrap().reset, None);
 }
 
 #[tokio::test]
-async fn request_valid_unkown_frame_interleaved_after_header() {
-    request_sequence_ok(|mut buf| {
-        request_encode(
-            &mut buf,
-            Request::post("http://localhost/salut").body(()).unwrap(),
-        );
-        unknown_frame_encode(buf);
-        Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
-    })
-    .await;
+async fn dropped_send_half_is_reset() {
+    let record = SendRecord::default();
+    let (send, _recv) = record.request_stream().split();
+    drop(send);
+    assert_eq!(
+        record.0.lock().unwrap().reset,
+        Some(Code::H3_REQUEST_CANCELLED.value())
+    );
+
+    let record = SendRecord::default();
+    let (mut send, _recv) = record.request_stream().split();
+    send.finish().await.expect("finish");
+    drop(send);
+    assert_eq!(record.0.lock().unwrap().reset, None);
+
+    let record = SendRecord::default();
+    let (mut send, _recv) = record.request_stream().split();
+    send.reset_on_drop(None);
+    drop(send);
+    assert_eq!(record.0.lock().unwrap().reset, None);
 }
 
 #[tokio::test]
-async fn request_valid_unkown_frame_interleaved_between_data() {
-    request_sequence_ok(|mut buf| {
-        request_encode(
-            &mut buf,
-            Request::post("http://localhost/salut").body(()).unwrap(),
-        );
-        Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
-        unknown_frame_encode(buf);
-        Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
-    })
-    .await;
+async fn extension_frames_are_sent_in_order() {
+    let record = SendRecord::default();
+    let mut stream = record.request_stream();
+
+    stream.send_data(Bytes::from("body")).await.expect("data");
+    stream
+        .send_extension_frame(VarInt(0x4d), Bytes::from("ext"))
+        .await
+        .expect("extension");
+    stream.send_data(Bytes::from("more")).await.expect("data");
+    stream
+        .send_extension_frame(VarInt(0x1f * 5 + 0x21), Bytes::new())
+        .await
+        .expect("empty extension");
+    stream
+        .send_trailers(HeaderMap::new())
+        .await
+        .expect("trailers");
+
+    assert_eq!(
+        &record.0.lock().unwrap().sent[..],
+        &[
+            0x00, 0x04, b'b', b'o', b'd', b'y', // DATA
+            0x40, 0x4d, 0x03, b'e', b'x', b't', // extension
+            0x00, 0x04, b'm', b'o', b'r', b'e', // DATA
+            0x40, 0xbc, 0x00, // empty extension
+            0x01, 0x02, 0x00, 0x00, // HEADERS
+        ][..]
+    );
 }
 
 #[tokio::test]
-async fn request_valid_unkown_frame_interleaved_after_data() {
-    request_sequence_ok(|mut buf| {
-        request_encode(
-            &mut buf,
-            Request::post("http://localhost/salut").body(()).unwrap(),
+async fn forbidden_extension_frame_types() {
+    let record = SendRecord::default();
+    let mut stream = record.request_stream();
+
+    for ty in [
+        FrameType::DATA,
+        FrameType::SETTINGS,
+        FrameType::H2_PING,
+        FrameType::PRIORITY_UPDATE_REQUEST,
+        FrameType::WEBTRANSPORT_BI_STREAM,
+    ] {
+        assert_matches!(
+            stream
+                .send_extension_frame(VarInt(ty.into_inner()), Bytes::from("ext"))
+                .await
+                .unwrap_err()
+                .kind(),
+            Kind::ForbiddenFrameType(t) if t == ty.into_inner()
         );
-        Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
-        unknown_frame_encode(buf);
-        Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
-    })
-    .await;
+    }
+    assert!(record.0.lock().unwrap().sent.is_empty());
 }
 
 #[tokio::test]
-async fn request_valid_unkown_frame_interleaved_before_trailers() {
-    request_sequence_ok(|mut buf| {
-        request_encode(
-            &mut buf,
-            Request::post("http://localhost/salut").body(()).unwrap(),
+async fn extension_frames_are_ignored() {
+    let (client_conn, server_conn) = test::pair();
+    extension_frames_are_ignored_on(client_conn, server_conn).await;
+}
+
+#[tokio::test]
+async fn extension_frames_are_ignored_boxed() {
+    let (client_conn, server_conn) = test::boxed_pair();
+    extension_frames_are_ignored_on(client_conn, server_conn).await;
+}
+
+async fn extension_frames_are_ignored_on<C: quic::Connection<Bytes>>(
+    client_conn: C,
+    server_conn: C,
+) {
+    init_tracing();
+
+    let client_fut = async {
+        let (mut driver, mut client) = client::new(client_conn).await.expect("client init");
+        driver
+            .send_extension_frame(VarInt(0x4d), Bytes::from("control"))
+            .await
+            .expect("control extension");
+        let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
+        let req_fut = async {
+            let mut request_stream = client
+                .send_request(Request::post("http://localhost/salut").body(()).unwrap())
+                .await
+                .expect("request");
+            request_stream
+                .send_extension_frame(VarInt(0x4d), Bytes::from("request"))
+                .await
+                .expect("request extension");
+            request_stream
+                .send_data("hypertext".into())
+                .await
+                .expect("send_data");
+            request_stream.finish().await.expect("finish");
+
+            let response = request_stream.recv_response().await.expect("recv response");
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = request_stream
+                .recv_data()
+                .await
+                .expect("recv data")
+                .expect("body");
+            assert_eq!(body.chunk(), b"wonderful");
+            assert!(request_stream.recv_data().await.expect("end").is_none());
+        };
+        tokio::select! { _ = req_fut => (), _ = drive_fut => () }
+    };
+
+    let server_fut = async {
+        let mut incoming_req = server::Connection::new(server_conn).await.unwrap();
+        incoming_req
+            .send_extension_frame(VarInt(0x4d), Bytes::from("control"))
+            .await
+            .expect("control extension");
+
+        let (_request, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
+        let body = request_stream
+            .recv_data()
+            .await
+            .expect("recv data")
+            .expect("body");
+        assert_eq!(body.chunk(), b"hypertext");
+        assert!(request_stream.recv_data().await.expect("end").is_none());
+
+        request_stream
+            .send_response(Response::new(()))
+            .await
+            .expect("send_response");
+        request_stream
+            .send_extension_frame(VarInt(0x4d), Bytes::from("response"))
+            .await
+            .expect("response extension");
+        request_stream
+            .send_data("wonderful".into())
+            .await
+            .expect("send_data");
+        request_stream.finish().await.expect("finish");
+        incoming_req
+    };
+
+    tokio::join!(server_fut, client_fut);
+}
+
+#[tokio::test]
+async fn extension_frames_reach_the_handler() {
+    init_tracing();
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let (client_conn, server_conn) = test::pair();
+
+    let client_fut = async {
+        let handled = received.clone();
+        let (mut driver, mut client) = client::builder()
+            .on_extension_frame(move |id: StreamId, ty, payload| {
+                // The server greases the request stream
+                if !FrameType::from(VarInt::from_u64(ty).unwrap()).is_reserved() {
+                    handled.lock().unwrap().push((id, ty, payload));
+                }
+                ControlFlow::Continue(())
+            })
+            .build::<_, _, Bytes>(client_conn)
+            .await
+            .expect("client init");
+        let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
+        let req_fut = async {
+            let mut request_stream = client
+                .send_request(Request::get("http://localhost/salut").body(()).unwrap())
+                .await
+                .expect("request");
+            request_stream.finish().await.expect("finish");
+            request_stream.recv_response().await.expect("recv response");
+
+            let mut body = BytesMut::new();
+            while let Some(mut data) = request_stream.recv_data().await.expect("recv data") {
+                body.put(&mut data);
+            }
+            assert_eq!(&body[..], b"hypertext");
+            assert_eq!(
+                *received.lock().unwrap(),
+                [(request_stream.id(), 0x4d, Bytes::from("between"))]
+            );
+        };
+        tokio::select! { _ = req_fut => (), _ = drive_fut => () }
+    };
+
+    let server_fut = async {
+        let mut incoming_req = server::Connection::new(server_conn).await.unwrap();
+        let (_request, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
+        request_stream
+            .send_response(Response::new(()))
+            .await
+            .expect("send_response");
+        request_stream
+            .send_data("hyper".into())
+            .await
+            .expect("send_data");
+        request_stream
+            .send_extension_frame(VarInt(0x4d), Bytes::from("between"))
+            .await
+            .expect("extension");
+        request_stream
+            .send_data("text".into())
+            .await
+            .expect("send_data");
+        request_stream.finish().await.expect("finish");
+        incoming_req
+    };
+
+    tokio::join!(server_fut, client_fut);
+}
+
+#[tokio::test]
+async fn extension_frame_rejected_by_the_handler() {
+    init_tracing();
+    let (client_conn, server_conn) = test::pair();
+
+    let client_fut = async {
+        let (mut driver, mut client) = client::builder()
+            .send_grease(false)
+            .build::<_, _, Bytes>(client_conn)
+            .await
+            .expect("client init");
+        let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
+        let req_fut = async {
+            let mut request_stream = client
+                .send_request(Request::post("http://localhost/salut").body(()).unwrap())
+                .await
+                .expect("request");
+            request_stream
+                .send_extension_frame(VarInt(0x4d), Bytes::from("refused"))
+                .await
+                .expect("extension");
+            let _ = request_stream.send_data("hypertext".into()).await;
+            let _ = request_stream.finish().await;
+            let _ = request_stream.recv_response().await;
+        };
+        tokio::select! { _ = req_fut => (), _ = drive_fut => () }
+    };
+
+    let server_fut = async {
+        let mut incoming_req = server::builder()
+            .on_extension_frame(|_, _, _| ControlFlow::Break(Code::H3_REQUEST_REJECTED))
+            .build(server_conn)
+            .await
+            .unwrap();
+        let (_request, mut request_stream) = incoming_req.accept().await.expect("accept").unwrap();
+        let err = request_stream.recv_data().await.map(|_| ()).unwrap_err();
+        assert_matches!(
+            err.kind(),
+            Kind::Application {
+                code: Code::H3_REQUEST_REJECTED,
+                ..
+            }
         );
-        Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
-        unknown_frame_encode(buf);
-        let mut trailers = HeaderMap::new();
-        trailers.insert("trailer", "value".parse().unwrap());
-        trailers_encode(buf, trailers);
-    })
-    .await;
+        request_stream
+            .send_response(Response::new(()))
+            .await
+            .expect("send_response");
+        incoming_req
+    };
+
+    tokio::join!(server_fut, client_fut);
 }
 
 #[tokio::test]
-async fn request_valid_unkown_frame_after_trailers() {
-    request_sequence_ok(|mut buf| {
+async fn slow_headers_do_not_hold_up_accept() {
+    init_tracing();
+    let mut pair = Pair::default();
+    let mut server = pair.server();
+    let (served_tx, served_rx) = tokio::sync::oneshot::channel();
+
+    let client_fut = async {
+        let connection = pair.client_inner().await;
+        let mut headers = BytesMut::new();
         request_encode(
-            &mut buf,
-            Request::post("http://localhost/salut").body(()).unwrap(),
+            &mut headers,
+            Request::get("http://localhost/salut").body(()).unwrap(),
         );
-        Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
-        let mut trailers = HeaderMap::new();
-        trailers.insert("trailer", "value".parse().unwrap());
-        trailers_encode(buf, trailers);
-        unknown_frame_encode(buf);
-    })
-    .await;
-}
 
-//= https://www.rfc-editor.org/rfc/rfc9114#section-4.1
-//= type=test
-//# Receipt of an invalid sequence of frames MUST be treated as a
-//# connection error of type H3_FRAME_UNEXPECTED.
-fn invalid_request_frames() -> Vec<Frame<Bytes>> {
-    vec![
-        Frame::CancelPush(PushId(0)),
-        Frame::Settings(frame::Settings::default()),
-        Frame::Goaway(VarInt(1)),
-        Frame::MaxPushId(PushId(1)),
-    ]
-}
+        // The first request's headers are only partially sent
+        let (mut slow_send, mut slow_recv) = connection.open_bi().await.unwrap();
+        slow_send.write_all(&headers[..1]).await.unwrap();
+        let (mut fast_send, mut fast_recv) = connection.open_bi().await.unwrap();
+        fast_send.write_all(&headers[..]).await.unwrap();
+        fast_send.finish().await.unwrap();
 
-#[tokio::test]
-async fn request_invalid_frame_first() {
-    //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.3
-    //= type=test
-    //# Receiving a
-    //# CANCEL_PUSH frame on a stream other than the control stream MUST be
-    //# treated as a connection error of type H3_FRAME_UNEXPECTED.
+        assert!(!fast_recv.read_to_end(1024).await.unwrap().is_empty());
+        served_rx.await.unwrap();
 
-    //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.4
-    //= type=test
-    //# If an endpoint receives a SETTINGS frame on a different
-    //# stream, the endpoint MUST respond with a connection error of type
-    //# H3_FRAME_UNEXPECTED.
+        slow_send.write_all(&headers[1..]).await.unwrap();
+        slow_send.finish().await.unwrap();
+        assert!(!slow_recv.read_to_end(1024).await.unwrap().is_empty());
+    };
 
-    //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.6
-    //= type=test
-    //# A client MUST treat a GOAWAY frame on a stream other than
-    //# the control stream as a connection error of type H3_FRAME_UNEXPECTED.
+    let server_fut = async {
+        let conn = server.next().await;
+        let mut incoming = server::Connection::new(conn).await.unwrap();
+        let slow = incoming.accept_resolver().await.unwrap().unwrap();
+        let fast = incoming.accept_resolver().await.unwrap().unwrap();
+        assert_eq!(slow.id(), StreamId::FIRST_REQUEST);
+
+        let serve = |resolver: server::RequestResolver<_, _>| async move {
+            let (request, mut stream) = resolver.resolve_request().await.unwrap();
+            assert_eq!(request.uri().path(), "/salut");
+            stream
+                .send_response(Response::builder().status(200).body(()).unwrap())
+                .await
+                .unwrap();
+            stream.finish().await.unwrap();
+        };
 
-    //= https://www.rfc-editor.org/rfc/rfc9114#section-7.2.7
-    //= type=test
-    //# The MAX_PUSH_ID frame is always sent on the control stream.  Receipt
-    //# of a MAX_PUSH_ID frame on any other stream MUST be treated as a
-    //# connection error of type H3_FRAME_UNEXPECTED.
-    for frame in invalid_request_frames() {
-        request_sequence_unexpected(|mut buf| frame.encode(&mut buf)).await;
-    }
+        // The second request is served while the first one's headers are incomplete
+        serve(fast).await;
+        served_tx.send(()).unwrap();
+        serve(slow).await;
+    };
+
+    tokio::join!(server_fut, client_fut);
 }
 
-#[tokio::test]
-async fn request_invalid_frame_after_header() {
-    for frame in invalid_request_frames() {
-        request_sequence_unexpected(|mut buf| {
-            request_encode(
-                &mut buf,
-                Request::post("http://localhost/salut").body(()).unwrap(),
-            );
-            frame.encode(&mut buf);
-        })
-        .await;
-    }
+#[test]
+fn recv_data_is_cancel_safe() {
+    let recv = RecvChunks::default();
+    let mut stream = recv.request_body();
+    let mut buf = BytesMut::new();
+    Frame::Data(Bytes::from("body")).encode_with_payload(&mut buf);
+    trailers_encode(&mut buf, HeaderMap::new());
+    let mut buf = buf.freeze();
+
+    // Dropped with a partial frame header, then with the payload still to come
+    recv.push(buf.split_to(1));
+    assert!(stream.recv_data().now_or_never().is_none());
+    recv.push(buf.split_to(1));
+    assert!(stream.recv_data().now_or_never().is_none());
+    recv.push(buf.split_to(4));
+    let mut data = stream.recv_data().now_or_never().unwrap().unwrap().unwrap();
+    assert_eq!(data.copy_to_bytes(data.remaining()), "body");
+
+    // Dropped in the middle of the trailers, which still end the body once received
+    recv.push(buf.split_to(2));
+    assert!(stream.recv_data().now_or_never().is_none());
+    recv.push(buf);
+    assert!(stream
+        .recv_data()
+        .now_or_never()
+        .unwrap()
+        .unwrap()
+        .is_none());
+    assert!(stream
+        .recv_data()
+        .now_or_never()
+        .unwrap()
+        .unwrap()
+        .is_none());
+
+    // Dropped waiting for the end of the stream
+    assert!(stream.recv_trailers().now_or_never().is_none());
+    recv.finish();
+    assert_matches!(
+        stream.recv_trailers().now_or_never(),
+        Some(Ok(Some(trailers))) if trailers.is_empty()
+    );
 }
 
-#[tokio::test]
-async fn request_invalid_frame_after_data() {
-    for frame in invalid_request_frames() {
-        request_sequence_unexpected(|mut buf| {
-            request_encode(
-                &mut buf,
-                Request::post("http://localhost/salut").body(()).unwrap(),
-            );
-            Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
-            frame.encode(&mut buf);
-        })
-        .await;
+#[test]
+fn data_frames_above_limit() {
+    let recv = RecvChunks::default();
+    let mut stream = recv.request_body();
+    stream.limit_data_frames(Some(3));
+    let mut buf = BytesMut::new();
+    for _ in 0..4 {
+        Frame::Data(Bytes::from("b")).encode_with_payload(&mut buf);
     }
-}
+    recv.push(buf.freeze());
+    recv.finish();
 
-#[tokio::test]
-async fn request_invalid_frame_after_trailers() {
-    for frame in invalid_request_frames() {
-        request_sequence_unexpected(|mut buf| {
-            request_encode(
-                &mut buf,
-                Request::post("http://localhost/salut").body(()).unwrap(),
-            );
-            Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
-            let mut trailers = HeaderMap::new();
-            trailers.insert("trailer", "value".parse().unwrap());
-            trailers_encode(buf, trailers);
-            frame.encode(&mut buf);
-        })
-        .await;
+    for _ in 0..3 {
+        let mut data = stream.recv_data().now_or_never().unwrap().unwrap().unwrap();
+        assert_eq!(data.copy_to_bytes(data.remaining()), "b");
     }
+    assert_matches!(
+        stream
+            .recv_data()
+            .now_or_never()
+            .unwrap()
+            .map(|_| ())
+            .unwrap_err()
+            .kind(),
+        Kind::Application {
+            code: Code::H3_EXCESSIVE_LOAD,
+            ..
+        }
+    );
 }
 
-#[tokio::test]
-async fn request_invalid_data_after_trailers() {
-    request_sequence_unexpected(|mut buf| {
-        request_encode(
-            &mut buf,
-            Request::post("http://localhost/salut").body(()).unwrap(),
-        );
-        let mut trailers = HeaderMap::new();
-        trailers.insert("trailer", "value".parse().unwrap());
-        trailers_encode(buf, trailers);
-        Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
-    })
-    .await;
-}
+#[test]
+fn data_frames_up_to_limit() {
+    let recv = RecvChunks::default();
+    let mut stream = recv.request_body();
+    stream.limit_data_frames(Some(3));
+    let mut buf = BytesMut::new();
+    for _ in 0..3 {
+        Frame::Data(Bytes::from("b")).encode_with_payload(&mut buf);
+    }
+    trailers_encode(&mut buf, HeaderMap::new());
+    recv.push(buf.freeze());
+    recv.finish();
 
-#[tokio::test]
-async fn request_invalid_data_first() {
-    request_sequence_unexpected(|mut buf| {
-        Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
-    })
-    .await;
+    for _ in 0..3 {
+        let mut data = stream.recv_data().now_or_never().unwrap().unwrap().unwrap();
+        assert_eq!(data.copy_to_bytes(data.remaining()), "b");
+    }
+    assert!(stream
+        .recv_data()
+        .now_or_never()
+        .unwrap()
+        .unwrap()
+        .is_none());
+    assert!(stream
+        .recv_trailers()
+        .now_or_never()
+        .unwrap()
+        .unwrap()
+        .is_some());
 }
 
-#[tokio::test]
-async fn request_invalid_two_trailers() {
-    request_sequence_unexpected(|mut buf| {
-        request_encode(
-            &mut buf,
-            Request::post("http://localhost/salut").body(()).unwrap(),
-        );
-        Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
-        let mut trailers = HeaderMap::new();
-        trailers.insert("trailer", "value".parse().unwrap());
-        trailers_encode(buf, trailers.clone());
-        trailers_encode(buf, trailers);
-    })
-    .await;
+#[test]
+fn frame_after_trailers() {
+    let recv = RecvChunks::default();
+    let mut stream = recv.request_body();
+    let mut buf = BytesMut::new();
+    Frame::Data(Bytes::from("body")).encode_with_payload(&mut buf);
+    trailers_encode(&mut buf, HeaderMap::new());
+    Frame::Data(Bytes::from("more")).encode_with_payload(&mut buf);
+    recv.push(buf.freeze());
+    recv.finish();
+
+    let mut data = stream.recv_data().now_or_never().unwrap().unwrap().unwrap();
+    assert_eq!(data.copy_to_bytes(data.remaining()), "body");
+    assert!(stream
+        .recv_data()
+        .now_or_never()
+        .unwrap()
+        .unwrap()
+        .is_none());
+    let err = stream.recv_trailers().now_or_never().unwrap().unwrap_err();
+    assert_matches!(
+        err.kind(),
+        Kind::Application {
+            code: Code::H3_FRAME_UNEXPECTED,
+            ..
+        }
+    );
+    assert!(err.to_string().contains("after trailers"), "{}", err);
 }
 
-#[tokio::test]
-async fn request_invalid_trailing_byte() {
-    request_sequence_frame_error(|mut buf| {
-        request_encode(
-            &mut buf,
-            Request::post("http://localhost/salut").body(()).unwrap(),
-        );
-        Frame::Data(Bytes::from("fada")).encode_with_payload(&mut buf);
-        let mut trailers = HeaderMap::new();
-        trailers.insert("trailer", "value".parse().unwrap());
-        trailers_encode(buf, trailers);
-
-        //= https://www.rfc-editor.org/rfc/rfc9114#section-7.1
-        //= type=test
-        //# A frame payload that contains additional bytes
-        //# after the identified fields or a frame payload that terminates before
-        //# the end of the identified fields MUST be treated as a connection
-        //# error of type H3_FRAME_ERROR.
-        buf.put_u8(255);
-    })
-    .await;
+#[test]
+fn recv_response_is_cancel_safe() {
+    let recv = RecvChunks::default();
+    let mut stream = client::RequestStream::detached(recv.request_stream());
+    let mut buf = BytesMut::new();
+    let mut block = BytesMut::new();
+    qpack::encode_stateless(
+        &mut block,
+        Header::response(StatusCode::OK, HeaderMap::new()),
+    )
+    .unwrap();
+    Frame::headers(block).encode_with_payload(&mut buf);
+    let mut buf = buf.freeze();
+
+    recv.push(buf.split_to(1));
+    assert!(stream.recv_response().now_or_never().is_none());
+    recv.push(buf.split_to(2));
+    assert!(stream.recv_response().now_or_never().is_none());
+    recv.push(buf);
+    assert_matches!(
+        stream.recv_response().now_or_never(),
+        Some(Ok(response)) if response.status() == StatusCode::OK
+    );
 }
 
-#[tokio::test]
-async fn request_invalid_data_frame_length_too_large() {
-    request_sequence_frame_error(|mut buf| {
-        request_encode(
-            &mut buf,
-            Request::post("http://localhost/salut").body(()).unwrap(),
-        );
-        FrameType::DATA.encode(&mut buf);
-
-        //= https://www.rfc-editor.org/rfc/rfc9114#section-7.1
-        //= type=test
-        //# In particular, redundant length
-        //# encodings MUST be verified to be self-consistent; see Section 10.8.
-        VarInt::from(5u32).encode(&mut buf);
-        buf.put_slice(b"fada");
-
-        let mut trailers = HeaderMap::new();
-        trailers.insert("trailer", "value".parse().unwrap());
-        trailers_encode(buf, trailers);
-    })
-    .await;
+#[test]
+fn request_handles_are_send() {
+    use h3_quinn::{BidiStream, Connection, OpenStreams, RecvStream, SendStream};
+
+    assert_send::<server::Connection<Connection, Bytes>>();
+    assert_send::<server::RequestResolver<Connection, Bytes>>();
+    assert_send::<server::RequestStream<BidiStream<Bytes>, Bytes>>();
+    assert_send::<server::RequestStream<SendStream<Bytes>, Bytes>>();
+    assert_send::<server::RequestStream<RecvStream, Bytes>>();
+
+    assert_send::<client::Connection<Connection, Bytes>>();
+    assert_send::<client::SendRequest<OpenStreams, Bytes>>();
+    assert_send::<client::RequestStream<BidiStream<Bytes>, Bytes>>();
+    assert_send::<client::RequestStream<SendStream<Bytes>, Bytes>>();
+    assert_send::<client::RequestStream<RecvStream, Bytes>>();
+
+    // Type-erased transports keep the bounds
+    assert_send::<server::RequestStream<quic::BoxedBidiStream, Bytes>>();
+    assert_send::<client::SendRequest<quic::BoxedOpenStreams, Bytes>>();
+    assert_send::<client::RequestStream<quic::BoxedBidiStream, Bytes>>();
 }
 
-#[tokio::test]
-async fn request_invalid_data_frame_length_too_short() {
-    request_sequence_frame_error(|mut buf| {
-        request_encode(
-            &mut buf,
-            Request::post("http://localhost/salut").body(()).unwrap(),
-        );
-        FrameType::DATA.encode(&mut buf);
-
-        //= https://www.rfc-editor.org/rfc/rfc9114#section-7.1
-        //= type=test
-        //# In particular, redundant length
-        //# encodings MUST be verified to be self-consistent; see Section 10.8.
-        VarInt::from(3u32).encode(&mut buf);
-        buf.put_slice(b"fada");
-    })
-    .await;
+// Never called, checks the futures a spawned task awaits to handle a request are `Send`
+#[allow(dead_code)]
+fn request_futures_are_send(
+    mut conn: server::Connection<h3_quinn::Connection, Bytes>,
+    resolver: server::RequestResolver<h3_quinn::Connection, Bytes>,
+    mut server_stream: server::RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+    driver: client::Connection<h3_quinn::Connection, Bytes>,
+    mut send_request: client::SendRequest<h3_quinn::OpenStreams, Bytes>,
+    mut client_stream: client::RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+) {
+    assert_send_future(conn.accept());
+    assert_send_future(conn.accept_resolver());
+    assert_send_future(conn.drive());
+    assert_send_future(resolver.resolve_request());
+    assert_send_future(server_stream.recv_data());
+    assert_send_future(server_stream.recv_trailers());
+    assert_send_future(server_stream.send_response(Response::new(())));
+    assert_send_future(server_stream.send_data(Bytes::new()));
+    assert_send_future(server_stream.send_trailers(HeaderMap::new()));
+    assert_send_future(server_stream.finish());
+
+    assert_send_future(driver.drive());
+    assert_send_future(
+        send_request.send_request(Request::get("http://localhost").body(()).unwrap()),
+    );
+    assert_send_future(client_stream.recv_response());
+    assert_send_future(client_stream.recv_data());
+    assert_send_future(client_stream.recv_trailers());
+    assert_send_future(client_stream.send_data(Bytes::new()));
+    assert_send_future(client_stream.finish());
 }
 
 // Helpers
@@ -1442,3 +3123,132 @@ where
     tokio::select! { res = server_fut => check(res)
     , _ = client_fut => panic!("client resolved first") };
 }
+
+/// What a request stream did to the sending side of its transport stream
+#[derive(Default)]
+struct SendActions {
+    sent: BytesMut,
+    finished: usize,
+    reset: Option<u64>,
+}
+
+#[derive(Clone, Default)]
+struct SendRecord(Arc<Mutex<SendActions>>);
+
+impl SendRecord {
+    // As the client wraps it, once the request HEADERS frame is sent
+    fn request_stream(&self) -> connection::RequestStream<SendRecord, Bytes> {
+        let mut stream = connection::RequestStream::new(
+            FrameStream::new(BufRecvStream::new(self.clone())),
+            0,
+            SharedStateRef::default(),
+            false,
+        );
+        stream.headers_sent();
+        stream
+    }
+}
+
+impl quic::SendStream<Bytes> for SendRecord {
+    type Error = Box<dyn quic::Error>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn send_data<T: Into<WriteBuf<Bytes>>>(&mut self, data: T) -> Result<(), Self::Error> {
+        self.0.lock().unwrap().sent.put(data.into());
+        Ok(())
+    }
+
+    fn poll_finish(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.lock().unwrap().finished += 1;
+        Poll::Ready(Ok(()))
+    }
+
+    fn reset(&mut self, reset_code: u64) {
+        self.0.lock().unwrap().reset = Some(reset_code);
+    }
+
+    fn send_id(&self) -> StreamId {
+        StreamId::FIRST_REQUEST
+    }
+}
+
+impl quic::RecvStream for SendRecord {
+    type Buf = Bytes;
+    type Error = Box<dyn quic::Error>;
+
+    fn poll_data(&mut self, _: &mut Context<'_>) -> Poll<Result<Option<Self::Buf>, Self::Error>> {
+        Poll::Ready(Ok(None))
+    }
+
+    fn stop_sending(&mut self, _: u64) {}
+
+    fn recv_id(&self) -> StreamId {
+        StreamId::FIRST_REQUEST
+    }
+}
+
+impl quic::BidiStream<Bytes> for SendRecord {
+    type SendStream = SendRecord;
+    type RecvStream = SendRecord;
+
+    fn split(self) -> (Self::SendStream, Self::RecvStream) {
+        (self.clone(), self)
+    }
+}
+
+/// Receives the chunks pushed to it, pending once they are exhausted until finished
+#[derive(Clone, Default)]
+struct RecvChunks(Arc<Mutex<(VecDeque<Bytes>, bool)>>);
+
+impl RecvChunks {
+    fn push(&self, chunk: Bytes) {
+        self.0.lock().unwrap().0.push_back(chunk);
+    }
+
+    fn finish(&self) {
+        self.0.lock().unwrap().1 = true;
+    }
+
+    fn request_stream(&self) -> connection::RequestStream<RecvChunks, Bytes> {
+        connection::RequestStream::new(
+            FrameStream::new(BufRecvStream::new(self.clone())),
+            1024,
+            SharedStateRef::default(),
+            false,
+        )
+    }
+
+    // As the server wraps it, once the request HEADERS frame is received
+    fn request_body(&self) -> connection::RequestStream<RecvChunks, Bytes> {
+        let mut stream = self.request_stream();
+        stream.headers_received();
+        stream
+    }
+}
+
+impl quic::RecvStream for RecvChunks {
+    type Buf = Bytes;
+    type Error = Box<dyn quic::Error>;
+
+    fn poll_data(&mut self, _: &mut Context<'_>) -> Poll<Result<Option<Self::Buf>, Self::Error>> {
+        let mut chunks = self.0.lock().unwrap();
+        match chunks.0.pop_front() {
+            Some(chunk) => Poll::Ready(Ok(Some(chunk))),
+            None if chunks.1 => Poll::Ready(Ok(None)),
+            None => Poll::Pending,
+        }
+    }
+
+    fn stop_sending(&mut self, _: u64) {}
+
+    fn recv_id(&self) -> StreamId {
+        StreamId::FIRST_REQUEST
+    }
+}
+
+fn assert_send<T: Send + 'static>() {}
+
+fn assert_send_future<F: std::future::Future + Send>(_: F) {}
@@ -0,0 +1,148 @@
+use std::task::{Context, Poll};
+
+use bytes::{Buf, Bytes, BytesMut};
+use futures_util::future;
+use http::{HeaderMap, Method, Request, Response, StatusCode};
+use tower_service::Service;
+
+use crate::{
+    client, quic,
+    server::{self, ErrorPolicy, RequestBody},
+    test,
+};
+
+use super::init_tracing;
+
+/// Responds with the request body and trailers, fails on `/fail`
+struct Echo;
+
+impl<S: quic::RecvStream> Service<Request<RequestBody<S, Bytes>>> for Echo {
+    type Response = Response<RequestBody<S, Bytes>>;
+    type Error = &'static str;
+    type Future = future::Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<RequestBody<S, Bytes>>) -> Self::Future {
+        if req.uri().path() == "/fail" {
+            return future::ready(Err("failed"));
+        }
+        future::ready(Ok(Response::new(req.into_body())))
+    }
+}
+
+#[tokio::test]
+async fn serve_echo_with_trailers() {
+    init_tracing();
+    let (client_conn, server_conn) = test::pair();
+
+    let client_fut = async {
+        let (mut driver, mut client) = client::new(client_conn).await.expect("client init");
+        let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
+        let req_fut = async {
+            let mut request_stream = client
+                .send_request(Request::post("http://localhost/echo").body(()).unwrap())
+                .await
+                .expect("request");
+            request_stream
+                .send_data("wonderful ".into())
+                .await
+                .expect("send_data");
+            request_stream
+                .send_data("hypertext".into())
+                .await
+                .expect("send_data");
+            let mut trailers = HeaderMap::new();
+            trailers.insert("trailer", "value".parse().unwrap());
+            request_stream
+                .send_trailers(trailers)
+                .await
+                .expect("send_trailers");
+            request_stream.finish().await.expect("client finish");
+
+            let response = request_stream.recv_response().await.expect("recv response");
+            assert_eq!(response.status(), StatusCode::OK);
+            let mut body = BytesMut::new();
+            while let Some(mut data) = request_stream.recv_data().await.expect("recv data") {
+                body.extend_from_slice(&data.copy_to_bytes(data.remaining()));
+            }
+            assert_eq!(&body[..], b"wonderful hypertext");
+            let trailers = request_stream
+                .recv_trailers()
+                .await
+                .expect("recv trailers")
+                .expect("trailers");
+            assert_eq!(trailers.get("trailer").unwrap(), "value");
+        };
+        tokio::select! { _ = req_fut => (), _ = drive_fut => () }
+    };
+
+    let server_fut = async {
+        let mut incoming = server::Connection::new(server_conn).await.unwrap();
+        server::Serve::new(Echo)
+            .serve(&mut incoming)
+            .await
+            .expect("serve");
+    };
+
+    tokio::join!(server_fut, client_fut);
+}
+
+#[tokio::test]
+async fn serve_error_policy_and_no_body() {
+    init_tracing();
+    let (client_conn, server_conn) = test::pair();
+
+    let client_fut = async {
+        let (mut driver, mut client) = client::new(client_conn).await.expect("client init");
+        let drive_fut = async { future::poll_fn(|cx| driver.poll_close(cx)).await };
+        let req_fut = async {
+            // The service error becomes a 500
+            let mut request_stream = client
+                .send_request(Request::get("http://localhost/fail").body(()).unwrap())
+                .await
+                .expect("request");
+            request_stream.finish().await.expect("client finish");
+            let response = request_stream.recv_response().await.expect("recv response");
+            assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+            // The echoed body is not sent in response to HEAD
+            let mut request_stream = client
+                .send_request(
+                    Request::builder()
+                        .method(Method::HEAD)
+                        .uri("http://localhost/echo")
+                        .body(())
+                        .unwrap(),
+                )
+                .await
+                .expect("request");
+            request_stream
+                .send_data("wonderful hypertext".into())
+                .await
+                .expect("send_data");
+            request_stream.finish().await.expect("client finish");
+            let response = request_stream.recv_response().await.expect("recv response");
+            assert_eq!(response.status(), StatusCode::OK);
+            assert!(request_stream
+                .recv_data()
+                .await
+                .expect("recv data")
+                .is_none());
+        };
+        tokio::select! { _ = req_fut => (), _ = drive_fut => () }
+    };
+
+    let server_fut = async {
+        let mut incoming = server::Connection::new(server_conn).await.unwrap();
+        server::Serve::new(Echo)
+            .on_error(ErrorPolicy::InternalServerError)
+            .serve(&mut incoming)
+            .await
+            .expect("serve");
+    };
+
+    tokio::join!(server_fut, client_fut);
+}
@@ -10,6 +10,8 @@ use crate::proto::{
 ///
 /// The session id is the same as the stream id of the CONNECT request.
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "u64"))]
 pub struct SessionId(u64);
 impl SessionId {
     pub(crate) fn from_varint(id: VarInt) -> SessionId {
@@ -31,6 +33,13 @@ impl TryFrom<u64> for SessionId {
     }
 }
 
+#[cfg(any(test, feature = "arbitrary"))]
+impl<'a> arbitrary::Arbitrary<'a> for SessionId {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Self::from_varint(u.arbitrary()?))
+    }
+}
+
 impl Encode for SessionId {
     fn encode<B: bytes::BufMut>(&self, buf: &mut B) {
         VarInt::from_u64(self.0).unwrap().encode(buf);